@@ -0,0 +1,146 @@
+//! Loading precompiled Truffle and Hardhat artifacts.
+//!
+//! Artifacts are JSON files with one contract each, containing at least its `abi` and `bytecode`.
+//! Contracts are named by the source they were compiled from, like `contracts/Foo.sol:Foo` for
+//! Hardhat, and `Foo.sol:Foo` for Truffle which records absolute source paths.
+
+use derive::{ContractFields, FileAst, Options, Output};
+use serde_json::{self, Value};
+use solc;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use Error;
+
+/// Load all artifacts in the given directory.
+///
+/// JSON files which aren't artifacts, like the build info and debug files of Hardhat, are skipped.
+pub fn load(path: &Path, options: &Options) -> Result<Output, Error> {
+    let mut contracts = HashMap::new();
+    let mut sources = HashMap::new();
+    let mut version = None;
+
+    for file in solc::files_by_ext(path, "json", &options.ignore)? {
+        let content = fs::read_to_string(path.join(&file))
+            .map_err(|e| format!("{}: failed to read: {}", file.display(), e))?;
+
+        let artifact: Value = serde_json::from_str(&content)
+            .map_err(|e| format!("{}: bad artifact: {}", file.display(), e))?;
+
+        let artifact = match Artifact::from_value(&artifact) {
+            Some(artifact) => artifact,
+            None => continue,
+        };
+
+        let abi = serde_json::to_string(artifact.abi)
+            .map_err(|e| format!("{}: bad abi: {}", file.display(), e))?;
+
+        let name = format!("{}:{}", artifact.source, artifact.name);
+
+        let fields = ContractFields {
+            abi,
+            bin: strip_hex_prefix(artifact.bytecode),
+            source_map: artifact.source_map.map(ToString::to_string),
+            runtime_bin: artifact.deployed_bytecode.map(strip_hex_prefix),
+            runtime_source_map: artifact.deployed_source_map.map(ToString::to_string),
+            immutable_references: artifact.immutable_references.map(ToString::to_string),
+            storage_layout: artifact.storage_layout.map(ToString::to_string),
+        };
+
+        if contracts.insert(name.clone(), fields).is_some() {
+            return Err(format!("{}: multiple artifacts for `{}`", file.display(), name).into());
+        }
+
+        if let Some(ast) = artifact.ast {
+            sources
+                .entry(artifact.source.clone())
+                .or_insert_with(|| FileAst { ast: ast.clone() });
+        }
+
+        if version.is_none() {
+            version = artifact.version.map(ToString::to_string);
+        }
+    }
+
+    Ok(Output {
+        contracts,
+        // source maps of artifacts refer to sources by index in a list which isn't recorded.
+        source_list: Vec::new(),
+        version: version.unwrap_or_else(|| String::from("artifacts")),
+        sources,
+    })
+}
+
+/// The parts of an artifact which are used.
+struct Artifact<'a> {
+    name: &'a str,
+    source: String,
+    abi: &'a Value,
+    bytecode: &'a str,
+    deployed_bytecode: Option<&'a str>,
+    source_map: Option<&'a str>,
+    deployed_source_map: Option<&'a str>,
+    ast: Option<&'a Value>,
+    version: Option<&'a str>,
+    /// Only present in artifacts which record the deployed bytecode as an object, like those of
+    /// Foundry.
+    immutable_references: Option<&'a Value>,
+    /// Only present in artifacts which were built with the storage layout, like those of Foundry.
+    storage_layout: Option<&'a Value>,
+}
+
+impl<'a> Artifact<'a> {
+    /// Decode an artifact, if the value looks like one.
+    fn from_value(value: &'a Value) -> Option<Artifact<'a>> {
+        let name = value.get("contractName")?.as_str()?;
+        let abi = value.get("abi").filter(|abi| abi.is_array())?;
+
+        // hardhat stores the bytecode directly, while older versions of truffle store it in an
+        // object.
+        let bytecode = bytecode_object(value.get("bytecode")?)?;
+        let deployed_bytecode = value.get("deployedBytecode").and_then(bytecode_object);
+
+        let source = match value.get("sourceName").and_then(|s| s.as_str()) {
+            Some(source) => source.to_string(),
+            None => {
+                let source = value.get("sourcePath")?.as_str()?;
+
+                Path::new(source)
+                    .file_name()?
+                    .to_string_lossy()
+                    .to_string()
+            }
+        };
+
+        return Some(Artifact {
+            name,
+            source,
+            abi,
+            bytecode,
+            deployed_bytecode,
+            source_map: value.get("sourceMap").and_then(|s| s.as_str()),
+            deployed_source_map: value.get("deployedSourceMap").and_then(|s| s.as_str()),
+            ast: value.get("legacyAST").or_else(|| value.get("ast")),
+            version: value
+                .get("compiler")
+                .and_then(|c| c.get("version"))
+                .and_then(|v| v.as_str()),
+            immutable_references: value
+                .get("deployedBytecode")
+                .and_then(|b| b.get("immutableReferences"))
+                .filter(|r| r.is_object()),
+            storage_layout: value.get("storageLayout").filter(|l| l.is_object()),
+        });
+
+        fn bytecode_object(value: &Value) -> Option<&str> {
+            value
+                .as_str()
+                .or_else(|| value.get("object").and_then(|o| o.as_str()))
+        }
+    }
+}
+
+/// Strip the `0x` prefix from the given bytecode.
+fn strip_hex_prefix(bytecode: &str) -> String {
+    bytecode.trim_left_matches("0x").to_string()
+}
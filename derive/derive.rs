@@ -9,41 +9,66 @@ use ethabi::{self, Constructor, Contract, Event, Function, Param, ParamType, Res
 use heck::{CamelCase, SnakeCase};
 use quote;
 use serde_json;
-use std::collections::HashMap;
+use std::collections::{hash_map, BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::path::{Path, PathBuf};
 use syn;
+use tiny_keccak;
 
 const INTERNAL_ERR: &'static str = "`parables_testing` internal error";
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct ContractFields {
-    abi: String,
-    bin: String,
+    pub abi: String,
+    pub bin: String,
     #[serde(rename = "srcmap")]
-    source_map: Option<String>,
+    pub source_map: Option<String>,
     #[serde(rename = "bin-runtime")]
-    runtime_bin: Option<String>,
+    pub runtime_bin: Option<String>,
     #[serde(rename = "srcmap-runtime")]
-    runtime_source_map: Option<String>,
+    pub runtime_source_map: Option<String>,
+    /// Regions of the runtime code reserved for immutable variables as JSON, if available.
+    #[serde(
+        rename = "immutable-references",
+        default,
+        deserialize_with = "json_string"
+    )]
+    pub immutable_references: Option<String>,
+    /// The storage layout of the contract as JSON, if requested.
+    #[serde(rename = "storage-layout", default, deserialize_with = "json_string")]
+    pub storage_layout: Option<String>,
 }
 
-#[derive(Deserialize)]
+/// Deserialize JSON which is either embedded as a string, or directly as a value.
+fn json_string<'de, D>(deserializer: D) -> ::std::result::Result<Option<String>, D::Error>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(value)) => Ok(Some(value)),
+        Some(serde_json::Value::Null) | None => Ok(None),
+        Some(value) => Ok(Some(value.to_string())),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct FileAst {
     #[serde(rename = "AST")]
-    ast: serde_json::Value,
+    pub ast: serde_json::Value,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Output {
-    contracts: HashMap<String, ContractFields>,
+    pub contracts: HashMap<String, ContractFields>,
     #[serde(rename = "sourceList")]
     #[allow(unused)]
-    source_list: Vec<String>,
+    pub source_list: Vec<String>,
     #[allow(unused)]
-    version: String,
+    pub version: String,
     #[serde(rename = "sources", default)]
-    sources: HashMap<String, FileAst>,
+    pub sources: HashMap<String, FileAst>,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
@@ -64,17 +89,41 @@ impl fmt::Display for Name {
 #[derive(Debug, Default)]
 pub struct Options {
     pub path: PathBuf,
+    /// Additional roots containing contracts, like `lib/solmate/src`.
+    pub paths: Vec<PathBuf>,
     pub contracts: Vec<ParablesContract>,
+    /// Use the standard-json interface of the compiler.
+    pub standard_json: bool,
+    /// Import remappings, like `openzeppelin-solidity/=node_modules/openzeppelin-solidity/`.
+    pub remappings: Vec<String>,
+    /// Specific version of the compiler to use, like `0.4.24`.
+    pub solc: Option<String>,
+    /// Directories which are not scanned for sources, relative to `path`.
+    pub ignore: Vec<PathBuf>,
+    /// Load precompiled Truffle or Hardhat artifacts from `path` instead of compiling sources.
+    pub artifacts: bool,
+    /// Request the storage layout of contracts from the compiler, which requires solc 0.5.13 or
+    /// later.
+    pub storage_layout: bool,
 }
 
 #[derive(Debug)]
 pub struct ParablesContract {
     pub item: String,
-    pub file: String,
+    /// The file declaring the contract, if specified.
+    ///
+    /// Without a file, the contract must only be declared in one file.
+    pub file: Option<String>,
     pub entry: String,
 }
 
+/// Name of the module which namespaces contracts by the file declaring them.
+const SOURCES_MODULE: &'static str = "sources";
+
 /// Implement a module for the given output.
+///
+/// Contracts are generated in modules namespaced by the file declaring them, like
+/// `sources::v1_token::token` for `v1/Token.sol:Token`, and each binding re-exports one of them.
 pub fn impl_module(
     path: &Path,
     output: Output,
@@ -83,6 +132,7 @@ pub fn impl_module(
     let mut result = Vec::new();
 
     let mut map = HashMap::new();
+    let mut events = Vec::new();
 
     for (name, contract) in output.contracts {
         let name = parse_name(&name)?;
@@ -93,11 +143,45 @@ pub fn impl_module(
         );
     }
 
+    // bound modules, and the contract they were bound to.
+    let mut modules = HashMap::new();
+    // modules of files, with the file they were generated for and the modules of its contracts.
+    let mut files = BTreeMap::<String, (String, BTreeMap<String, (String, quote::Tokens)>)>::new();
+    // the file and contract modules of generated contracts.
+    let mut generated = HashMap::<(String, String), (String, String)>::new();
+    // bound modules, with the file and contract module they re-export.
+    let mut bindings = Vec::new();
+
     for contract in contracts {
         let ParablesContract { item, file, entry } = contract;
 
-        let module_name = syn::Ident::from(item.as_str());
+        if item == SOURCES_MODULE {
+            return Err(format!(
+                "Module `{}` is reserved for contracts namespaced by file",
+                SOURCES_MODULE
+            ).into());
+        }
+
+        let file = match file {
+            Some(file) => file,
+            None => unique_file(&map, &entry)?,
+        };
+
         let key = (file.to_string(), entry.to_string());
+        let declared = format!("{}:{}", file, entry);
+
+        if let Some(previous) = modules.insert(item.to_string(), declared.clone()) {
+            return Err(format!(
+                "Module `{}` is declared for both `{}` and `{}`",
+                item, previous, declared
+            ).into());
+        }
+
+        // the contract is already generated for another binding.
+        if let Some(&(ref file_module, ref type_module)) = generated.get(&key) {
+            bindings.push((item, file_module.to_string(), type_module.to_string()));
+            continue;
+        }
 
         let (name, contract) = map.remove(&key).ok_or_else(|| {
             format!(
@@ -108,12 +192,109 @@ pub fn impl_module(
             )
         })?;
 
-        let contract = impl_contract_abi(&name, &contract, &contract.abi)?;
+        let abi: Contract = serde_json::from_str(&contract.abi)?;
+        events.extend(abi.events().map(new_event));
+
+        let source = output.sources.get(&name.path);
+
+        let enums = source
+            .map(|source| {
+                let enums = find_enums(&source.ast, &name.type_name);
+                find_enum_params(&source.ast, &name.type_name, enums)
+            })
+            .unwrap_or_default();
+
+        let state_variables = source
+            .map(|source| find_state_variables(&source.ast, &name.type_name))
+            .unwrap_or_default();
+
+        let natspec = source
+            .map(|source| find_natspec(&source.ast, &name.type_name))
+            .unwrap_or_default();
+
+        let contract = impl_contract_abi(
+            &name,
+            &contract,
+            &contract.abi,
+            &enums,
+            &state_variables,
+            &natspec,
+        )?;
+
+        let &mut (ref file_path, ref mut contracts) = files
+            .entry(name.module_name.to_string())
+            .or_insert_with(|| (name.path.to_string(), BTreeMap::new()));
+
+        if *file_path != name.path {
+            return Err(format!(
+                "Files `{}` and `{}` both generate module `{}::{}`, rename one of them",
+                file_path, name.path, SOURCES_MODULE, name.module_name
+            ).into());
+        }
 
-        result.push(quote! {
-            pub mod #module_name {
-                #contract
+        if let Some(&(ref previous, _)) = contracts.get(&name.type_module_name) {
+            return Err(format!(
+                "Contracts `{}:{}` and `{}` both generate module `{}::{}::{}`",
+                file_path,
+                previous,
+                name,
+                SOURCES_MODULE,
+                name.module_name,
+                name.type_module_name
+            ).into());
+        }
+
+        contracts.insert(
+            name.type_module_name.to_string(),
+            (name.type_name.to_string(), contract),
+        );
+
+        generated.insert(
+            key,
+            (name.module_name.to_string(), name.type_module_name.to_string()),
+        );
+
+        bindings.push((item, name.module_name, name.type_module_name));
+    }
+
+    let sources_module = syn::Ident::from(SOURCES_MODULE);
+
+    let file_modules = files.into_iter().map(|(file_module, (path, contracts))| {
+        let file_module = syn::Ident::from(file_module);
+        let doc = format!(" Contracts declared in `{}`.", path);
+
+        let contracts = contracts.into_iter().map(|(type_module, (_, contract))| {
+            let type_module = syn::Ident::from(type_module);
+
+            quote! {
+                pub mod #type_module {
+                    #contract
+                }
+            }
+        });
+
+        quote! {
+            #[doc = #doc]
+            pub mod #file_module {
+                #(#contracts)*
             }
+        }
+    });
+
+    result.push(quote! {
+        /// Generated contracts, namespaced by the file declaring them.
+        pub mod #sources_module {
+            #(#file_modules)*
+        }
+    });
+
+    for (item, file_module, type_module) in bindings {
+        let item = syn::Ident::from(item);
+        let file_module = syn::Ident::from(file_module);
+        let type_module = syn::Ident::from(type_module);
+
+        result.push(quote! {
+            pub use self::#sources_module::#file_module::#type_module as #item;
         });
     }
 
@@ -121,21 +302,47 @@ pub fn impl_module(
         path,
         output.source_list,
         output.sources,
+        events,
     )?);
 
     return Ok(quote!{ #(#result)* });
 
+    /// Find the only file declaring a contract with the given name.
+    fn unique_file(
+        map: &HashMap<(String, String), (Name, ContractFields)>,
+        entry: &str,
+    ) -> Result<String> {
+        let mut files: Vec<_> = map
+            .keys()
+            .filter(|&&(_, ref type_name)| type_name == entry)
+            .map(|&(ref file, _)| file.to_string())
+            .collect();
+
+        files.sort();
+
+        match files.len() {
+            0 => Err(format!("Missing solidity ABI for `{}`", entry).into()),
+            1 => Ok(files.remove(0)),
+            _ => Err(format!(
+                "Contract `{}` is declared in multiple files: {}, use `<file>:{}` to pick one",
+                entry,
+                files.join(", "),
+                entry
+            ).into()),
+        }
+    }
+
     fn parse_name(name: &str) -> Result<Name> {
         let mut parts = name.split(":");
 
         let path = parts.next().ok_or_else(|| format!("bad name: {}", name))?;
         let type_name = parts.next().ok_or_else(|| format!("bad name: {}", name))?;
 
-        let mut parts = path.split(".");
-        let base = parts.next().ok_or_else(|| format!("bad path: {}", path))?;
-
-        let module_name = base.to_snake_case();
-        let type_module_name = type_name.to_snake_case();
+        // namespace by the path of the file without its extension, like `v1_token` for
+        // `v1/Token.sol`.
+        let base = Path::new(path).with_extension("");
+        let module_name = module_ident(&base.to_string_lossy());
+        let type_module_name = module_ident(type_name);
 
         Ok(Name {
             path: path.to_string(),
@@ -149,6 +356,7 @@ pub fn impl_module(
         path: &Path,
         source_list: Vec<String>,
         sources: HashMap<String, FileAst>,
+        events: Vec<quote::Tokens>,
     ) -> Result<quote::Tokens> {
         let source_list = source_list
             .into_iter()
@@ -186,22 +394,569 @@ pub fn impl_module(
 
         Ok(quote! {
             pub fn new_context() -> ::parables_testing::abi::ContractContext {
+                #[allow(unused)]
+                use parables_testing::ethabi;
+
                 #sources
 
                 ::parables_testing::abi::ContractContext {
                     source_list: Some(vec![#(::std::path::Path::new(#source_list).to_owned(),)*]),
                     sources,
+                    events: vec![#(#events),*],
                 }
             }
         })
     }
 }
 
+/// A solidity enum, with the names of its variants in declaration order.
+struct SolidityEnum {
+    name: String,
+    /// The solidity type of the enum, like `enum Token.State`, or `enum State` if it is declared
+    /// at file level.
+    type_string: String,
+    variants: Vec<String>,
+}
+
+/// Find the enums declared in the contract with the given name, and at the top level of its file,
+/// by walking the AST of the file.
+///
+/// Enums of the contract take precedence over file-level enums with the same name.
+///
+/// Both the legacy and the compact AST format are supported.
+fn find_enums(ast: &serde_json::Value, contract: &str) -> Vec<SolidityEnum> {
+    let contract = match ast_find_contract(ast, contract) {
+        Some(contract) => contract,
+        None => return Vec::new(),
+    };
+
+    let contract_name = ast_name(contract).unwrap_or_default();
+
+    let mut out: Vec<SolidityEnum> = Vec::new();
+
+    let contract_enums = ast_children(contract).into_iter().map(|n| (Some(contract_name), n));
+    let file_enums = ast_children(ast).into_iter().map(|n| (None, n));
+
+    for (scope, node) in contract_enums.chain(file_enums) {
+        if ast_kind(node) != Some("EnumDefinition") {
+            continue;
+        }
+
+        let name = match ast_name(node) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        if out.iter().any(|e| e.name == name) {
+            continue;
+        }
+
+        let type_string = match scope {
+            Some(scope) => format!("enum {}.{}", scope, name),
+            None => format!("enum {}", name),
+        };
+
+        let members = node
+            .get("members")
+            .and_then(|m| m.as_array())
+            .map(|m| m.iter().collect::<Vec<_>>())
+            .unwrap_or_else(|| ast_children(node));
+
+        let variants = members
+            .into_iter()
+            .filter_map(|m| ast_name(m).map(|s| s.to_string()))
+            .collect();
+
+        out.push(SolidityEnum {
+            name,
+            type_string,
+            variants,
+        });
+    }
+
+    out
+}
+
+/// The names of the enums of parameters by position, or `None` if a parameter isn't declared with
+/// one.
+type ParamEnumNames = Vec<Option<String>>;
+
+/// The enums of the parameters of functions and events, for parameters declared with one of the
+/// enums of the contract.
+#[derive(Default)]
+struct EnumParams {
+    enums: Vec<SolidityEnum>,
+    /// Enums of the inputs and outputs of functions, by name and number of inputs.
+    functions: HashMap<(String, usize), (ParamEnumNames, ParamEnumNames)>,
+    /// Enums of the parameters of events, by name and number of parameters.
+    events: HashMap<(String, usize), ParamEnumNames>,
+}
+
+impl EnumParams {
+    /// The enums of the inputs of a function.
+    fn inputs(&self, function: &Function) -> Vec<Option<&SolidityEnum>> {
+        let key = (function.name.to_string(), function.inputs.len());
+        let enums = self.functions.get(&key).map(|e| &e.0);
+        self.resolve(function.inputs.iter().map(|p| &p.kind), enums)
+    }
+
+    /// The enums of the outputs of a function.
+    fn outputs(&self, function: &Function) -> Vec<Option<&SolidityEnum>> {
+        let key = (function.name.to_string(), function.inputs.len());
+        let enums = self.functions.get(&key).map(|e| &e.1);
+        self.resolve(function.outputs.iter().map(|p| &p.kind), enums)
+    }
+
+    /// The enums of the parameters of an event.
+    fn event(&self, event: &Event) -> Vec<Option<&SolidityEnum>> {
+        let key = (event.name.to_string(), event.inputs.len());
+        let enums = self.events.get(&key);
+        self.resolve(event.inputs.iter().map(|p| &p.kind), enums)
+    }
+
+    /// Resolve the enums of parameters with the given ABI types.
+    ///
+    /// Enums are encoded as `uint8`, any other type means that the AST doesn't match the ABI.
+    fn resolve<'a, K>(
+        &self,
+        kinds: K,
+        enums: Option<&ParamEnumNames>,
+    ) -> Vec<Option<&SolidityEnum>>
+    where
+        K: ExactSizeIterator<Item = &'a ParamType>,
+    {
+        let enums = match enums {
+            Some(enums) if enums.len() == kinds.len() => enums,
+            _ => return kinds.map(|_| None).collect(),
+        };
+
+        kinds
+            .zip(enums.iter())
+            .map(|(kind, name)| match (kind, name) {
+                (&ParamType::Uint(8), &Some(ref name)) => {
+                    self.enums.iter().find(|e| e.name == *name)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Find the parameters of the functions and events of the contract with the given name which are
+/// declared with one of the given enums.
+///
+/// Overloads with the same number of parameters can't be told apart, so their parameters are only
+/// typed if they agree.
+fn find_enum_params(
+    ast: &serde_json::Value,
+    contract: &str,
+    enums: Vec<SolidityEnum>,
+) -> EnumParams {
+    let mut out = EnumParams::default();
+
+    if enums.is_empty() {
+        return out;
+    }
+
+    if let Some(contract) = ast_find_contract(ast, contract) {
+        for node in ast_children(contract) {
+            let name = match ast_name(node) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            let mut lists = ast_parameter_lists(node)
+                .into_iter()
+                .map(|list| resolve(&enums, ast_parameter_types(list)));
+
+            match ast_kind(node) {
+                Some("FunctionDefinition") => {
+                    let inputs = lists.next().unwrap_or_default();
+                    let outputs = lists.next().unwrap_or_default();
+                    let key = (name, inputs.len());
+                    insert(&mut out.functions, key, (inputs, outputs));
+                }
+                Some("EventDefinition") => {
+                    let inputs = lists.next().unwrap_or_default();
+                    let key = (name, inputs.len());
+                    insert(&mut out.events, key, inputs);
+                }
+                Some("VariableDeclaration") => {
+                    // the getter of a public state variable with an enum type.
+                    let output = resolve(&enums, ast_type_string(node).into_iter().collect());
+
+                    if output.iter().any(Option::is_some) {
+                        insert(&mut out.functions, (name, 0), (Vec::new(), output));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    out.enums = enums;
+    return out;
+
+    /// Resolve the names of the enums of parameters with the given types.
+    fn resolve(enums: &[SolidityEnum], types: Vec<&str>) -> ParamEnumNames {
+        types
+            .into_iter()
+            .map(|t| {
+                enums
+                    .iter()
+                    .find(|e| e.type_string == t)
+                    .map(|e| e.name.to_string())
+            })
+            .collect()
+    }
+
+    /// Insert the enums of a declaration, clearing them if they conflict with an overload.
+    fn insert<V: PartialEq + Clone + Default>(
+        map: &mut HashMap<(String, usize), V>,
+        key: (String, usize),
+        value: V,
+    ) {
+        match map.entry(key) {
+            hash_map::Entry::Occupied(mut e) => {
+                if *e.get() != value {
+                    e.insert(V::default());
+                }
+            }
+            hash_map::Entry::Vacant(e) => {
+                e.insert(value);
+            }
+        }
+    }
+}
+
+/// A public state variable, for which the compiler generates a getter.
+struct StateVariable {
+    name: String,
+    /// The solidity type of the variable, like `mapping(address => uint256)`.
+    type_string: String,
+}
+
+/// Find the public state variables of the contract with the given name.
+fn find_state_variables(ast: &serde_json::Value, contract: &str) -> Vec<StateVariable> {
+    let contract = match ast_find_contract(ast, contract) {
+        Some(contract) => contract,
+        None => return Vec::new(),
+    };
+
+    let mut out = Vec::new();
+
+    for node in ast_children(contract) {
+        if ast_kind(node) != Some("VariableDeclaration") {
+            continue;
+        }
+
+        let state_variable = ast_attribute(node, "stateVariable")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let public = ast_attribute(node, "visibility").and_then(|v| v.as_str()) == Some("public");
+
+        if !state_variable || !public {
+            continue;
+        }
+
+        let name = match ast_name(node) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let type_string = ast_type_string(node).unwrap_or("?").to_string();
+
+        out.push(StateVariable { name, type_string });
+    }
+
+    out
+}
+
+/// Natspec documentation of a function.
+#[derive(Debug, Default)]
+struct Natspec {
+    /// Lines of `@notice`, or of untagged documentation.
+    notice: Vec<String>,
+    /// Lines of `@dev`.
+    dev: Vec<String>,
+    /// Custom tags, like `@custom:security`, and their lines.
+    custom: Vec<(String, Vec<String>)>,
+}
+
+impl Natspec {
+    /// Parse natspec from the documentation text of a declaration.
+    ///
+    /// Tags which are not used for documentation, like `@param`, are ignored.
+    fn parse(text: &str) -> Natspec {
+        let mut natspec = Natspec::default();
+
+        enum Tag {
+            Notice,
+            Dev,
+            Custom(usize),
+            Ignored,
+        }
+
+        let mut tag = Tag::Notice;
+
+        for line in text.lines() {
+            let line = line.trim().trim_left_matches('*').trim();
+
+            let line = if line.starts_with('@') {
+                let mut parts = line.splitn(2, char::is_whitespace);
+                let name = parts.next().unwrap_or("");
+                let rest = parts.next().unwrap_or("").trim();
+
+                tag = match name {
+                    "@notice" => Tag::Notice,
+                    "@dev" => Tag::Dev,
+                    name if name.starts_with("@custom:") => {
+                        natspec.custom.push((name[1..].to_string(), Vec::new()));
+                        Tag::Custom(natspec.custom.len() - 1)
+                    }
+                    _ => Tag::Ignored,
+                };
+
+                rest
+            } else {
+                line
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let line = line.to_string();
+
+            match tag {
+                Tag::Notice => natspec.notice.push(line),
+                Tag::Dev => natspec.dev.push(line),
+                Tag::Custom(index) => natspec.custom[index].1.push(line),
+                Tag::Ignored => {}
+            }
+        }
+
+        natspec
+    }
+
+    /// Convert into lines of documentation for a generated item.
+    fn doc_lines(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        out.extend(self.notice.iter().cloned());
+
+        if !self.dev.is_empty() {
+            if !out.is_empty() {
+                out.push(String::new());
+            }
+
+            out.extend(self.dev.iter().cloned());
+        }
+
+        for &(ref name, ref lines) in &self.custom {
+            if !out.is_empty() {
+                out.push(String::new());
+            }
+
+            out.push(format!("`@{}`: {}", name, lines.join(" ")));
+        }
+
+        out
+    }
+}
+
+/// Find the natspec of the functions declared in the contract with the given name, by function
+/// name.
+///
+/// Only the first of overloaded functions is documented.
+fn find_natspec(ast: &serde_json::Value, contract: &str) -> HashMap<String, Natspec> {
+    let contract = match ast_find_contract(ast, contract) {
+        Some(contract) => contract,
+        None => return HashMap::new(),
+    };
+
+    let mut out = HashMap::new();
+
+    for node in ast_children(contract) {
+        if ast_kind(node) != Some("FunctionDefinition") {
+            continue;
+        }
+
+        let name = match ast_name(node) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        // documentation is a plain string in older compilers, and a node in newer ones.
+        let text = ast_attribute(node, "documentation").and_then(|d| {
+            d.as_str()
+                .or_else(|| d.get("text").and_then(|t| t.as_str()))
+        });
+
+        if let Some(text) = text {
+            out.entry(name).or_insert_with(|| Natspec::parse(text));
+        }
+    }
+
+    out
+}
+
+/// Find the definition of the contract with the given name.
+fn ast_find_contract<'a>(
+    node: &'a serde_json::Value,
+    contract: &str,
+) -> Option<&'a serde_json::Value> {
+    if ast_kind(node) == Some("ContractDefinition") && ast_name(node) == Some(contract) {
+        return Some(node);
+    }
+
+    ast_children(node)
+        .into_iter()
+        .filter_map(|c| ast_find_contract(c, contract))
+        .next()
+}
+
+/// The kind of a node, `name` in the legacy format and `nodeType` in the compact format.
+fn ast_kind(node: &serde_json::Value) -> Option<&str> {
+    node.get("nodeType")
+        .or_else(|| node.get("name"))
+        .and_then(|n| n.as_str())
+}
+
+/// Access an attribute of a node, which are nested under `attributes` in the legacy format.
+fn ast_attribute<'a>(node: &'a serde_json::Value, name: &str) -> Option<&'a serde_json::Value> {
+    if node.get("nodeType").is_some() {
+        return node.get(name);
+    }
+
+    node.get("attributes").and_then(|a| a.get(name))
+}
+
+/// The name of the declaration of a node.
+fn ast_name(node: &serde_json::Value) -> Option<&str> {
+    ast_attribute(node, "name").and_then(|n| n.as_str())
+}
+
+/// The solidity type of a declaration, like `uint256`.
+fn ast_type_string(node: &serde_json::Value) -> Option<&str> {
+    ast_attribute(node, "type")
+        .or_else(|| {
+            node.get("typeDescriptions")
+                .and_then(|t| t.get("typeString"))
+        })
+        .and_then(|t| t.as_str())
+}
+
+/// The parameter lists of a function or event, which are its parameters followed by the return
+/// parameters of a function.
+fn ast_parameter_lists(node: &serde_json::Value) -> Vec<&serde_json::Value> {
+    match node.get("parameters") {
+        Some(parameters) => Some(parameters)
+            .into_iter()
+            .chain(node.get("returnParameters"))
+            .collect(),
+        None => ast_children(node)
+            .into_iter()
+            .filter(|c| ast_kind(c) == Some("ParameterList"))
+            .collect(),
+    }
+}
+
+/// The solidity types of the parameters in a parameter list.
+fn ast_parameter_types(list: &serde_json::Value) -> Vec<&str> {
+    let parameters = list
+        .get("parameters")
+        .and_then(|p| p.as_array())
+        .map(|p| p.iter().collect::<Vec<_>>())
+        .unwrap_or_else(|| ast_children(list));
+
+    parameters
+        .into_iter()
+        .map(|p| ast_type_string(p).unwrap_or("?"))
+        .collect()
+}
+
+/// The children of a node.
+fn ast_children(node: &serde_json::Value) -> Vec<&serde_json::Value> {
+    node.get("children")
+        .or_else(|| node.get("nodes"))
+        .and_then(|c| c.as_array())
+        .map(|c| c.iter().collect())
+        .unwrap_or_default()
+}
+
+/// Declare a rust enum for the given solidity enum.
+fn declare_enum(e: &SolidityEnum) -> quote::Tokens {
+    let name = syn::Ident::from(e.name.as_str());
+    let name_str = e.name.as_str();
+    let ref variants: Vec<_> = e
+        .variants
+        .iter()
+        .map(|v| syn::Ident::from(v.as_str()))
+        .collect();
+    let values: Vec<_> = (0..e.variants.len()).map(|i| i as u8).collect();
+
+    let names1 = ::std::iter::repeat(&name);
+
+    quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        impl #name {
+            /// Convert from the value of the variant, if valid.
+            pub fn from_u8(value: u8) -> Option<Self> {
+                match value {
+                    #(#values => Some(#names1::#variants),)*
+                    _ => None,
+                }
+            }
+
+            /// Convert from the value of the variant as returned by a contract, if valid.
+            pub fn from_u256(value: ethabi::Uint) -> Option<Self> {
+                if value > ethabi::Uint::from(u8::max_value()) {
+                    return None;
+                }
+
+                Self::from_u8(value.low_u32() as u8)
+            }
+        }
+
+        impl From<u8> for #name {
+            /// Convert from the value of the variant.
+            ///
+            /// Panics if the value doesn't correspond to a variant.
+            fn from(value: u8) -> Self {
+                match Self::from_u8(value) {
+                    Some(value) => value,
+                    None => panic!("invalid variant of `{}`: {}", #name_str, value),
+                }
+            }
+        }
+
+        impl From<#name> for u8 {
+            fn from(value: #name) -> u8 {
+                value as u8
+            }
+        }
+
+        impl From<#name> for ethabi::Uint {
+            fn from(value: #name) -> ethabi::Uint {
+                ethabi::Uint::from(value as u8)
+            }
+        }
+    }
+}
+
 /// Implement the contract ABI.
 fn impl_contract_abi(
     name: &Name,
     contract_fields: &ContractFields,
     input: &str,
+    enums: &EnumParams,
+    state_variables: &[StateVariable],
+    natspec: &HashMap<String, Natspec>,
 ) -> Result<quote::Tokens> {
     let contract: Contract = serde_json::from_str(input)?;
 
@@ -211,20 +966,70 @@ fn impl_contract_abi(
     let mut output_functions = Vec::new();
     let mut func_input_wrappers_structs = Vec::new();
 
+    let function_names: HashSet<String> = contract
+        .functions()
+        .map(|f| f.name.to_snake_case())
+        .collect();
+
     for f in contract.functions() {
-        let (static_function, impl_function) = impl_contract_function(f);
+        let (static_function, impl_function) =
+            impl_contract_function(f, natspec.get(&f.name), enums);
 
         static_functions.push(static_function);
         impl_functions.push(impl_function);
-        func_structs.push(declare_functions(f));
-        output_functions.push(declare_output_functions(f));
-        func_input_wrappers_structs.push(declare_functions_input_wrappers(f));
+
+        // don't generate queries which would shadow another function, like `getValue` if `value`
+        // is also declared.
+        if f.constant && !function_names.contains(&format!("get_{}", f.name.to_snake_case())) {
+            impl_functions.push(impl_contract_query(f, enums));
+        }
+        func_structs.push(declare_functions(f, enums));
+        output_functions.push(declare_output_functions(f, enums));
+        func_input_wrappers_structs.push(declare_functions_input_wrappers(f, enums));
     }
 
     let events_impl: Vec<_> = contract.events().map(impl_contract_event).collect();
-    let constructor_impl = impl_constructor(name, contract_fields, contract.constructor.as_ref())?;
-    let logs_structs: Vec<_> = contract.events().map(declare_logs).collect();
-    let events_structs: Vec<_> = contract.events().map(declare_events).collect();
+    // interfaces and abstract contracts don't have any code, so they can't be deployed and can
+    // only be bound to existing contracts.
+    let constructor_impl = if contract_fields.bin.is_empty() {
+        quote! {
+            /// Bind to a deployed contract implementing this interface.
+            pub fn at<'a, VM>(
+                vm: &'a VM,
+                address: ethabi::Address,
+                call: ::parables_testing::call::Call
+            ) -> Contract<'a, VM>
+                where VM: ::parables_testing::abi::Vm
+            {
+                contract(vm, address, call)
+            }
+
+            /// Set up a mock implementing this interface, whose behavior is programmed from the
+            /// test.
+            pub fn mock<'a>(evm: &'a ::parables_testing::evm::Evm)
+                -> ::std::result::Result<
+                    ::parables_testing::mock::Mock<'a>,
+                    ::parables_testing::Error,
+                >
+            {
+                ::parables_testing::mock::Mock::new(evm, ABI)
+            }
+        }
+    } else {
+        impl_constructor(name, contract_fields, contract.constructor.as_ref())?
+    };
+    let logs_structs: Vec<_> = contract.events().map(|e| declare_logs(e, enums)).collect();
+    let events_structs: Vec<_> = contract.events().map(|e| declare_events(e, enums)).collect();
+
+    // submodules refer to the generated enums as `enums`, like the contract module does.
+    let ref use_enums = if enums.enums.is_empty() {
+        quote!{}
+    } else {
+        quote! {
+            #[allow(unused)]
+            use super::enums;
+        }
+    };
 
     let events_and_logs_quote = if events_structs.is_empty() {
         quote!{}
@@ -233,6 +1038,7 @@ fn impl_contract_abi(
             pub mod events {
                 #[allow(unused)]
                 use parables_testing::ethabi;
+                #use_enums
 
                 #(#events_structs)*
 
@@ -242,14 +1048,89 @@ fn impl_contract_abi(
             pub mod logs {
                 #[allow(unused)]
                 use parables_testing::ethabi;
+                #use_enums
 
                 #(#logs_structs)*
             }
         }
     };
 
+    let mut state_functions = Vec::new();
+
+    for variable in state_variables {
+        // the getter generated by the compiler has the same name as the variable.
+        let getter = contract.functions().find(|f| f.name == variable.name);
+
+        if let Some(getter) = getter {
+            state_functions.push(impl_state_getter(getter, &variable.type_string, enums));
+        }
+    }
+
+    let state_quote = if state_functions.is_empty() {
+        quote!{}
+    } else {
+        quote! {
+            /// Getters for public state variables.
+            pub mod state {
+                #[allow(unused)]
+                use parables_testing::ethabi;
+                #use_enums
+
+                /// Access to the public state of a contract.
+                pub struct State<'a, VM: 'a> {
+                    contract: super::Contract<'a, VM>,
+                }
+
+                impl<'a, VM> State<'a, VM> where VM: ::parables_testing::abi::Vm {
+                    #(#state_functions)*
+                }
+            }
+
+            impl<'a, VM> Contract<'a, VM> {
+                /// Access the public state variables of the contract.
+                pub fn state(&self) -> state::State<'a, VM> {
+                    state::State { contract: self.clone() }
+                }
+            }
+        }
+    };
+
+    let storage_quote = match contract_fields.storage_layout {
+        Some(ref layout) => quote! {
+            /// The storage layout of the contract, as JSON.
+            pub const STORAGE_LAYOUT: &'static str = #layout;
+
+            impl<'a, VM> Contract<'a, VM> where VM: ::parables_testing::abi::Vm {
+                /// Typed access to the storage of the contract.
+                pub fn storage(&self) -> ::parables_testing::storage::Storage<'a, VM> {
+                    ::parables_testing::storage::Storage::new(self.vm, self.address, STORAGE_LAYOUT)
+                }
+            }
+        },
+        None => quote!{},
+    };
+
     let wrapper_quote = impl_wrapper(impl_functions);
 
+    let enums_quote = if enums.enums.is_empty() {
+        quote!{}
+    } else {
+        let enums: Vec<_> = enums.enums.iter().map(declare_enum).collect();
+
+        quote! {
+            /// Enums declared in the contract, or at the top level of its file.
+            ///
+            /// Functions and events use them for parameters declared with an enum, other values
+            /// can be decoded using `from_u256`.
+            pub mod enums {
+                #[allow(unused)]
+                use parables_testing::ethabi;
+
+                #(#enums)*
+            }
+        }
+    };
+
     let functions_quote = if func_structs.is_empty() {
         quote!{}
     } else {
@@ -257,6 +1138,7 @@ fn impl_contract_abi(
             pub mod functions {
                 #[allow(unused)]
                 use parables_testing::ethabi;
+                #use_enums
 
                 #(#func_structs)*
 
@@ -275,6 +1157,7 @@ fn impl_contract_abi(
             pub mod outputs {
                 #[allow(unused)]
                 use parables_testing::ethabi;
+                #use_enums
 
                 #(#output_functions)*
             }
@@ -285,14 +1168,23 @@ fn impl_contract_abi(
         #[allow(unused)]
         use parables_testing::ethabi;
 
+        /// The ABI of the contract, as JSON.
+        pub const ABI: &'static str = #input;
+
         #constructor_impl
 
         #events_and_logs_quote
 
+        #enums_quote
+
         #outputs_quote
 
         #wrapper_quote
 
+        #state_quote
+
+        #storage_quote
+
         #functions_quote
     };
 
@@ -309,61 +1201,93 @@ fn impl_contract_abi(
 
             impl<'a, VM> Clone for Contract<'a, VM> {
                 fn clone(&self) -> Self {
-                    *self
+                    Self {
+                        vm: self.vm,
+                        address: self.address,
+                        call: self.call.clone(),
+                    }
                 }
             }
 
-            impl<'a, VM> Copy for Contract<'a, VM> {
-            }
-
             impl<'a, VM> Contract<'a, VM> {
                 #(#impl_functions)*
 
-                /// Modify the call for the contract.
-                pub fn call(self, call: ::parables_testing::call::Call) -> Self {
+                /// Modify the call for a copy of the current contract.
+                pub fn call(&self, call: ::parables_testing::call::Call) -> Self {
                     Self {
                         call: call,
-                        ..self
+                        ..self.clone()
                     }
                 }
 
-                /// Modify the default sender for the contract.
-                pub fn sender(self, sender: ethabi::Address) -> Self {
+                /// Modify the default sender for a copy of the current contract.
+                pub fn sender(&self, sender: ethabi::Address) -> Self {
                     Self {
-                        call: self.call.sender(sender),
-                        ..self
+                        call: self.call.clone().sender(sender),
+                        ..self.clone()
                     }
                 }
 
                 /// Modify the default gas for a copy of the current contract.
-                pub fn gas<V>(self, gas: V) -> Self
+                pub fn gas<V>(&self, gas: V) -> Self
                     where V: Into<::parables_testing::ethereum_types::U256>
                 {
                     Self {
-                        call: self.call.gas(gas),
-                        ..self
+                        call: self.call.clone().gas(gas),
+                        ..self.clone()
                     }
                 }
 
                 /// Modify the default gas_price for a copy of the current contract.
-                pub fn gas_price<V>(self, gas_price: V) -> Self
+                pub fn gas_price<V>(&self, gas_price: V) -> Self
                     where V: Into<::parables_testing::ethereum_types::U256>
                 {
                     Self {
-                        call: self.call.gas_price(gas_price),
-                        ..self
+                        call: self.call.clone().gas_price(gas_price),
+                        ..self.clone()
                     }
                 }
 
                 /// Modify the default value for a copy of the current contract.
-                pub fn value<V>(self, value: V) -> Self
+                pub fn value<V>(&self, value: V) -> Self
                     where V: Into<::parables_testing::ethereum_types::U256>
                 {
                     Self {
-                        call: self.call.value(value),
-                        ..self
+                        call: self.call.clone().value(value),
+                        ..self.clone()
                     }
                 }
+
+                /// Call the fallback function of the contract with the given raw calldata.
+                pub fn fallback<D>(&self, data: D)
+                    -> ::std::result::Result<
+                        ::parables_testing::evm::Call<Vec<u8>>,
+                        ::parables_testing::Error,
+                    >
+                    where
+                        VM: ::parables_testing::abi::Vm,
+                        D: Into<Vec<u8>>,
+                {
+                    let function_call = ::parables_testing::abi::RawCall::new(data);
+                    self.vm.call(self.address, function_call, self.call.clone())
+                }
+
+                /// Send plain ether to the contract, without any calldata.
+                ///
+                /// This is handled by the receive function of the contract, or its fallback
+                /// function if it doesn't have one.
+                pub fn receive<V>(&self, value: V)
+                    -> ::std::result::Result<
+                        ::parables_testing::evm::Call<()>,
+                        ::parables_testing::Error,
+                    >
+                    where
+                        VM: ::parables_testing::abi::Vm,
+                        V: Into<::parables_testing::ethereum_types::U256>,
+                {
+                    let function_call = ::parables_testing::abi::Transfer;
+                    self.vm.call(self.address, function_call, self.call.clone().value(value))
+                }
             }
 
             pub fn contract<'a, VM>(
@@ -561,6 +1485,56 @@ fn from_token(kind: &ParamType, token: &quote::Tokens) -> quote::Tokens {
     }
 }
 
+/// The rust type of a parameter, which is the generated enum if it is declared with one.
+fn param_type(kind: &ParamType, e: Option<&SolidityEnum>) -> quote::Tokens {
+    match e {
+        Some(e) => {
+            let e = syn::Ident::from(e.name.as_str());
+            quote! { enums::#e }
+        }
+        None => rust_type(kind),
+    }
+}
+
+/// The generic parameter of a parameter, see `param_type`.
+fn param_template_type(kind: &ParamType, index: usize, e: Option<&SolidityEnum>) -> quote::Tokens {
+    match e {
+        Some(e) => {
+            let e = syn::Ident::from(e.name.as_str());
+            let t_ident = syn::Ident::from(format!("T{}", index));
+            quote! { #t_ident: Into<enums::#e> }
+        }
+        None => template_param_type(kind, index),
+    }
+}
+
+/// Convert a generic parameter into a token, see `param_template_type`.
+fn param_to_token(kind: &ParamType, name: &syn::Ident, e: Option<&SolidityEnum>) -> quote::Tokens {
+    match e {
+        Some(e) => {
+            let e = syn::Ident::from(e.name.as_str());
+            quote! { ethabi::Token::Uint(ethabi::Uint::from(Into::<enums::#e>::into(#name))) }
+        }
+        None => to_token(&from_template_param(kind, name), kind),
+    }
+}
+
+/// Convert a token into a parameter, see `param_type`.
+fn param_from_token(
+    kind: &ParamType,
+    e: Option<&SolidityEnum>,
+    token: &quote::Tokens,
+) -> quote::Tokens {
+    match e {
+        Some(e) => {
+            let invalid = format!("invalid variant of `{}`", e.name);
+            let e = syn::Ident::from(e.name.as_str());
+            quote! { enums::#e::from_u256(#token.to_uint().expect(#INTERNAL_ERR)).expect(#invalid) }
+        }
+        None => from_token(kind, token),
+    }
+}
+
 fn input_names(inputs: &Vec<Param>) -> Vec<syn::Ident> {
     inputs
         .iter()
@@ -583,69 +1557,92 @@ fn get_template_names(kinds: &Vec<quote::Tokens>) -> Vec<syn::Ident> {
         .collect()
 }
 
-fn get_output_kinds(outputs: &Vec<Param>) -> quote::Tokens {
+fn get_output_kinds(outputs: &Vec<Param>, enums: &[Option<&SolidityEnum>]) -> quote::Tokens {
     match outputs.len() {
         0 => quote! {()},
         1 => {
-            let t = rust_type(&outputs[0].kind);
+            let t = param_type(&outputs[0].kind, enums[0]);
             quote! { #t }
         }
         _ => {
-            let outs: Vec<_> = outputs.iter().map(|param| rust_type(&param.kind)).collect();
+            let outs: Vec<_> = outputs
+                .iter()
+                .zip(enums)
+                .map(|(param, e)| param_type(&param.kind, *e))
+                .collect();
             quote! { (#(#outs),*) }
         }
     }
 }
 
-fn impl_contract_function(function: &Function) -> (quote::Tokens, quote::Tokens) {
-    let function_input_wrapper_name =
-        syn::Ident::from(format!("{}WithInput", function.name.to_camel_case()));
-
+/// Build the generic parameters, parameters and parameter names of a function taking the given
+/// inputs.
+fn input_params(
+    inputs: &Vec<Param>,
+    enums: &[Option<&SolidityEnum>],
+) -> (Vec<quote::Tokens>, Vec<quote::Tokens>, Vec<quote::Tokens>) {
     // [param0, hello_world, param2]
-    let ref input_names: Vec<_> = input_names(&function.inputs);
+    let input_names: Vec<_> = input_names(inputs);
 
     // [T0: Into<Uint>, T1: Into<Bytes>, T2: IntoIterator<Item = U2>, U2 = Into<Uint>]
-    let ref template_params: Vec<_> = function
-        .inputs
+    let template_params: Vec<_> = inputs
         .iter()
+        .zip(enums)
         .enumerate()
-        .map(|(index, param)| template_param_type(&param.kind, index))
+        .map(|(index, (param, e))| param_template_type(&param.kind, index, *e))
         .collect();
 
     // [Uint, Bytes, Vec<Uint>]
-    let kinds: Vec<_> = function
-        .inputs
+    let kinds: Vec<_> = inputs
         .iter()
-        .map(|param| rust_type(&param.kind))
+        .zip(enums)
+        .map(|(param, e)| param_type(&param.kind, *e))
         .collect();
 
     // [T0, T1, T2]
     let template_names: Vec<_> = get_template_names(&kinds);
 
     // [param0: T0, hello_world: T1, param2: T2]
-    let ref params: Vec<_> = input_names
+    let params: Vec<_> = input_names
         .iter()
         .zip(template_names.iter())
         .map(|(param_name, template_name)| quote! { #param_name: #template_name })
         .collect();
 
     // [param0, hello_world, param2]
-    let ref param_names: Vec<_> = input_names
+    let param_names: Vec<_> = input_names
         .iter()
-        .zip(template_names.iter())
-        .map(|(param_name, _)| quote! { #param_name })
+        .map(|param_name| quote! { #param_name })
         .collect();
 
+    (template_params, params, param_names)
+}
+
+fn impl_contract_function(
+    function: &Function,
+    natspec: Option<&Natspec>,
+    enums: &EnumParams,
+) -> (quote::Tokens, quote::Tokens) {
+    let function_input_wrapper_name =
+        syn::Ident::from(format!("{}WithInput", function.name.to_camel_case()));
+
+    let ref input_enums = enums.inputs(function);
+
+    // [param0, hello_world, param2]
+    let ref input_names: Vec<_> = input_names(&function.inputs);
+
+    let (ref template_params, ref params, ref param_names) =
+        input_params(&function.inputs, input_enums);
+
     // [Token::Uint(param0.into()), Token::Bytes(hello_world.into()), Token::Array(param2.into_iter().map(Into::into).collect())]
     let usage: Vec<_> = input_names
         .iter()
         .zip(function.inputs.iter())
-        .map(|(param_name, param)| {
-            to_token(&from_template_param(&param.kind, &param_name), &param.kind)
-        })
+        .zip(input_enums)
+        .map(|((param_name, param), e)| param_to_token(&param.kind, &param_name, *e))
         .collect();
 
-    let output_kinds = get_output_kinds(&function.outputs);
+    let output_kinds = get_output_kinds(&function.outputs, &enums.outputs(function));
 
     let name = syn::Ident::from(function.name.to_snake_case());
 
@@ -657,17 +1654,34 @@ fn impl_contract_function(function: &Function) -> (quote::Tokens, quote::Tokens)
         }
     };
 
-    let impl_function_name = match function.name.as_str() {
+    // functions which would collide with the fields and methods of `Contract` are prefixed with an
+    // underscore, like `_state` for `state()`.
+    let impl_function_name = match function.name.to_snake_case().as_str() {
         "address" => syn::Ident::from("_address"),
         "sender" => syn::Ident::from("_sender"),
         "value" => syn::Ident::from("_value"),
         "gas" => syn::Ident::from("_gas"),
         "gas_price" => syn::Ident::from("_gas_price"),
-        value => syn::Ident::from(value.to_snake_case()),
+        "call" => syn::Ident::from("_call"),
+        "state" => syn::Ident::from("_state"),
+        "storage" => syn::Ident::from("_storage"),
+        "fallback" => syn::Ident::from("_fallback"),
+        "receive" => syn::Ident::from("_receive"),
+        value => syn::Ident::from(value),
+    };
+
+    let docs = match natspec.map(Natspec::doc_lines) {
+        Some(ref lines) if !lines.is_empty() => {
+            let lines = lines.iter().map(|l| format!(" {}", l));
+            quote! { #(#[doc = #lines])* }
+        }
+        _ => quote! {
+            /// Sets the input (arguments) for this contract function
+        },
     };
 
     let impl_function = quote! {
-        /// Sets the input (arguments) for this contract function
+        #docs
         pub fn #impl_function_name<#(#template_params),*>(&self, #(#params),*)
             -> ::std::result::Result<
                 ::parables_testing::evm::Call<#output_kinds>,
@@ -676,13 +1690,94 @@ fn impl_contract_function(function: &Function) -> (quote::Tokens, quote::Tokens)
             where VM: ::parables_testing::abi::Vm
         {
             let function_call = self::functions::#name(#(#param_names),*);
-            self.vm.call(self.address, function_call, self.call)
+            self.vm.call(self.address, function_call, self.call.clone())
         }
     };
 
     (static_function, impl_function)
 }
 
+/// Implement a getter for a public state variable, using the getter function generated by the
+/// compiler.
+fn impl_state_getter(function: &Function, type_string: &str, enums: &EnumParams) -> quote::Tokens {
+    let (ref input_names, ref template_params, ref params) =
+        function_params(function, &enums.inputs(function));
+    let output_kinds = get_output_kinds(&function.outputs, &enums.outputs(function));
+    let name = syn::Ident::from(function.name.to_snake_case());
+    let doc = format!(
+        "Read the public state variable `{}`, of type `{}`.",
+        function.name, type_string
+    );
+
+    quote! {
+        #[doc = #doc]
+        pub fn #name<#(#template_params),*>(&self, #(#params),*)
+            -> ::std::result::Result<#output_kinds, ::parables_testing::Error>
+        {
+            let function_call = super::functions::#name(#(#input_names),*);
+            let contract = &self.contract;
+            contract.vm.query(contract.address, function_call, contract.call.clone())
+        }
+    }
+}
+
+/// Implement a read-only query variant of a constant function, named `get_<function>`.
+fn impl_contract_query(function: &Function, enums: &EnumParams) -> quote::Tokens {
+    let (ref input_names, ref template_params, ref params) =
+        function_params(function, &enums.inputs(function));
+    let output_kinds = get_output_kinds(&function.outputs, &enums.outputs(function));
+    let name = syn::Ident::from(function.name.to_snake_case());
+    let query_name = syn::Ident::from(format!("get_{}", function.name.to_snake_case()));
+    let doc = format!(
+        "Query `{}` without committing any state, see `Vm::query`.",
+        function.name
+    );
+
+    quote! {
+        #[doc = #doc]
+        pub fn #query_name<#(#template_params),*>(&self, #(#params),*)
+            -> ::std::result::Result<#output_kinds, ::parables_testing::Error>
+            where VM: ::parables_testing::abi::Vm
+        {
+            let function_call = self::functions::#name(#(#input_names),*);
+            self.vm.query(self.address, function_call, self.call.clone())
+        }
+    }
+}
+
+/// Build the input names, template parameters, and typed parameters of a function.
+fn function_params(
+    function: &Function,
+    enums: &[Option<&SolidityEnum>],
+) -> (Vec<syn::Ident>, Vec<quote::Tokens>, Vec<quote::Tokens>) {
+    let input_names = input_names(&function.inputs);
+
+    let template_params = function
+        .inputs
+        .iter()
+        .zip(enums)
+        .enumerate()
+        .map(|(index, (param, e))| param_template_type(&param.kind, index, *e))
+        .collect();
+
+    let kinds: Vec<_> = function
+        .inputs
+        .iter()
+        .zip(enums)
+        .map(|(param, e)| param_type(&param.kind, *e))
+        .collect();
+
+    let template_names: Vec<_> = get_template_names(&kinds);
+
+    let params = input_names
+        .iter()
+        .zip(template_names.iter())
+        .map(|(param_name, template_name)| quote! { #param_name: #template_name })
+        .collect();
+
+    (input_names, template_params, params)
+}
+
 fn impl_contract_event(event: &Event) -> quote::Tokens {
     let name = syn::Ident::from(event.name.to_snake_case());
     let event_name = syn::Ident::from(event.name.to_camel_case());
@@ -748,6 +1843,7 @@ fn impl_constructor(
     let item = &name.type_name;
     let path = &name.path;
     let bin = &contract_fields.bin;
+    let payable = constructor_payable(&contract_fields.abi)?;
 
     let source_map = match contract_fields.source_map.as_ref() {
         Some(source_map) => quote!{ Some(#source_map) },
@@ -764,6 +1860,16 @@ fn impl_constructor(
         None => quote!{ None },
     };
 
+    let immutable_references = match contract_fields.immutable_references.as_ref() {
+        Some(immutable_references) => quote!{ Some(#immutable_references) },
+        None => quote!{ None },
+    };
+
+    let storage_layout = match contract_fields.storage_layout.as_ref() {
+        Some(storage_layout) => quote!{ Some(#storage_layout) },
+        None => quote!{ None },
+    };
+
     Ok(quote! {
         pub fn constructor<#(#template_params),*>(#(#params),* ) -> Constructor {
             let v: Vec<ethabi::Token> = vec![#(#usage),*];
@@ -790,7 +1896,10 @@ fn impl_constructor(
                     inputs: #constructor_inputs
                 };
 
-                let code = linker.link(<Self as ::parables_testing::abi::Constructor>::BIN)?;
+                let code = linker.link_contract(
+                    <Self as ::parables_testing::abi::Constructor>::PATH,
+                    <Self as ::parables_testing::abi::Constructor>::BIN,
+                )?;
 
                 let encoded: ethabi::Bytes = constructor
                     .encode_input(code, &self.tokens)
@@ -822,11 +1931,38 @@ fn impl_constructor(
             const SOURCE_MAP: Option<&'static str> = #source_map;
             const RUNTIME_BIN: Option<&'static str> = #runtime_bin;
             const RUNTIME_SOURCE_MAP: Option<&'static str> = #runtime_source_map;
+            const IMMUTABLE_REFERENCES: Option<&'static str> = #immutable_references;
+            const STORAGE_LAYOUT: Option<&'static str> = #storage_layout;
+            const PAYABLE: bool = #payable;
         }
     })
 }
 
-fn declare_logs(event: &Event) -> quote::Tokens {
+/// Check if the constructor in the given ABI is payable.
+///
+/// Contracts without a declared constructor have a default constructor, which is not payable.
+fn constructor_payable(abi: &str) -> Result<bool> {
+    let abi: serde_json::Value = serde_json::from_str(abi)?;
+
+    let constructor = abi
+        .as_array()
+        .into_iter()
+        .flat_map(|entries| entries.iter())
+        .find(|entry| entry.get("type").and_then(|t| t.as_str()) == Some("constructor"));
+
+    let constructor = match constructor {
+        Some(constructor) => constructor,
+        None => return Ok(false),
+    };
+
+    // older compilers use `payable`, newer ones `stateMutability`.
+    let payable = constructor.get("payable").and_then(|p| p.as_bool()) == Some(true)
+        || constructor.get("stateMutability").and_then(|s| s.as_str()) == Some("payable");
+
+    Ok(payable)
+}
+
+fn declare_logs(event: &Event, enums: &EnumParams) -> quote::Tokens {
     let name = syn::Ident::from(event.name.to_camel_case());
     let names: Vec<_> = event
         .inputs
@@ -843,7 +1979,8 @@ fn declare_logs(event: &Event) -> quote::Tokens {
     let kinds: Vec<_> = event
         .inputs
         .iter()
-        .map(|param| rust_type(&param.kind))
+        .zip(enums.event(event))
+        .map(|(param, e)| param_type(&param.kind, e))
         .collect();
     let params: Vec<_> = names
         .iter()
@@ -859,7 +1996,7 @@ fn declare_logs(event: &Event) -> quote::Tokens {
     }
 }
 
-fn declare_events(event: &Event) -> quote::Tokens {
+fn declare_events(event: &Event, enums: &EnumParams) -> quote::Tokens {
     let name: syn::Ident = event.name.to_camel_case().into();
 
     // parse log
@@ -886,7 +2023,8 @@ fn declare_events(event: &Event) -> quote::Tokens {
     let to_log: Vec<_> = event
         .inputs
         .iter()
-        .map(|param| from_token(&param.kind, &log_iter))
+        .zip(enums.event(event))
+        .map(|(param, e)| param_from_token(&param.kind, e, &log_iter))
         .collect();
 
     let log_params: Vec<_> = names
@@ -921,7 +2059,7 @@ fn declare_events(event: &Event) -> quote::Tokens {
     // [T0, T1, T2]
     let template_names: Vec<_> = get_template_names(&topic_kinds);
 
-    let params: Vec<_> = topic_names
+    let ref params: Vec<_> = topic_names
         .iter()
         .zip(template_names.iter())
         .map(|(param_name, template_name)| quote! { #param_name: #template_name })
@@ -933,7 +2071,7 @@ fn declare_events(event: &Event) -> quote::Tokens {
         .map(|_| quote! { ethabi::Topic::Any })
         .collect();
 
-    let template_params: Vec<_> = topic_kinds
+    let ref template_params: Vec<_> = topic_kinds
         .iter()
         .zip(template_names.iter())
         .map(|(kind, template_name)| quote! { #template_name: Into<ethabi::Topic<#kind>> })
@@ -942,35 +2080,29 @@ fn declare_events(event: &Event) -> quote::Tokens {
     let to_filter: Vec<_> = topic_names
         .iter()
         .zip(event.inputs.iter().filter(|p| p.indexed))
-        .enumerate()
-        .take(3)
-        .map(|(index, (param_name, param))| {
-            let topic = syn::Ident::from(format!("topic{}", index));
+        .map(|(param_name, param)| {
             let i = quote! { i };
             let to_token = to_token(&i, &param.kind);
-            quote! { #topic: #param_name.into().map(|#i| #to_token), }
+            quote! { #param_name.into().map(|#i| #to_token) }
         })
         .collect();
 
-    let event_name = &event.name;
+    let ref topic_args: Vec<_> = topic_names.iter().map(|n| quote! { #n }).collect();
+    let name_str = &event.name;
 
-    let event_inputs = &event
-        .inputs
-        .iter()
-        .map(|x| {
-            let name = &x.name;
-            let kind = to_syntax_string(&x.kind);
-            let indexed = x.indexed;
+    let new_event = new_event(event);
 
-            quote! {
-                ethabi::EventParam {
-                    name: #name.to_owned(),
-                    kind: #kind,
-                    indexed: #indexed
-                }
+    let anonymous_topics = if event.anonymous {
+        let count = topic_names.len();
+
+        quote! {
+            fn anonymous_topics(&self) -> Option<usize> {
+                Some(#count)
             }
-        })
-        .collect::<Vec<_>>();
+        }
+    } else {
+        quote!{}
+    };
 
     let parse_log = match log_params.len() {
         0 => quote! {
@@ -998,10 +2130,6 @@ fn declare_events(event: &Event) -> quote::Tokens {
         },
     };
 
-    let event_inputs = quote! { vec![ #(#event_inputs),* ] };
-
-    let event_anonymous = &event.anonymous;
-
     quote! {
         #[derive(Debug, Clone, PartialEq)]
         pub struct #name {
@@ -1011,11 +2139,7 @@ fn declare_events(event: &Event) -> quote::Tokens {
         impl Default for #name {
             fn default() -> Self {
                 #name {
-                    event: ethabi::Event {
-                        name: #event_name.to_owned(),
-                        inputs: #event_inputs,
-                        anonymous: #event_anonymous
-                    }
+                    event: #new_event
                 }
             }
         }
@@ -1031,33 +2155,81 @@ fn declare_events(event: &Event) -> quote::Tokens {
             fn wildcard_filter(&self) -> ethabi::TopicFilter {
                 self.filter(#(#any_params),*)
             }
+
+            #anonymous_topics
         }
 
         impl #name {
-            /// Creates topic filter.
+            /// Creates topic filter, with one topic for each indexed parameter.
+            ///
+            /// Panics if one of the topics can't be used, see `try_filter`.
             pub fn filter<#(#template_params),*>(&self, #(#params),*) -> ethabi::TopicFilter {
-                let raw = ethabi::RawTopicFilter {
-                    #(#to_filter)*
-                    ..Default::default()
-                };
+                match self.try_filter(#(#topic_args),*) {
+                    Ok(filter) => filter,
+                    Err(e) => panic!("bad filter for event `{}`: {}", #name_str, e),
+                }
+            }
+
+            /// Creates topic filter, with one topic for each indexed parameter.
+            ///
+            /// Errors if one of the topics can't be used.
+            pub fn try_filter<#(#template_params),*>(&self, #(#params),*)
+                -> ::std::result::Result<ethabi::TopicFilter, ::parables_testing::Error>
+            {
+                let topics = vec![#(#to_filter),*];
+                ::parables_testing::abi::topic_filter(&self.event, topics)
+            }
+        }
+    }
+}
+
+/// Build an expression constructing the given `ethabi::Event`.
+fn new_event(event: &Event) -> quote::Tokens {
+    let event_name = &event.name;
+
+    let event_inputs = &event
+        .inputs
+        .iter()
+        .map(|x| {
+            let name = &x.name;
+            let kind = to_syntax_string(&x.kind);
+            let indexed = x.indexed;
 
-                self.event.filter(raw).expect(#INTERNAL_ERR)
+            quote! {
+                ethabi::EventParam {
+                    name: #name.to_owned(),
+                    kind: #kind,
+                    indexed: #indexed
+                }
             }
+        })
+        .collect::<Vec<_>>();
+
+    let event_anonymous = &event.anonymous;
+
+    quote! {
+        ethabi::Event {
+            name: #event_name.to_owned(),
+            inputs: vec![ #(#event_inputs),* ],
+            anonymous: #event_anonymous
         }
     }
 }
 
-fn declare_functions(function: &Function) -> quote::Tokens {
+fn declare_functions(function: &Function, enums: &EnumParams) -> quote::Tokens {
     let name = syn::Ident::from(function.name.to_camel_case());
 
+    let ref input_enums = enums.inputs(function);
+    let ref output_enums = enums.outputs(function);
+
     let decode_output = {
-        let output_kinds = get_output_kinds(&function.outputs);
+        let output_kinds = get_output_kinds(&function.outputs, output_enums);
 
         let o_impl = match function.outputs.len() {
             0 => quote! { Ok(()) },
             1 => {
                 let o = quote! { out };
-                let from_first = from_token(&function.outputs[0].kind, &o);
+                let from_first = param_from_token(&function.outputs[0].kind, output_enums[0], &o);
                 quote! {
                     let out = self.function.decode_output(output)
                         .map_err(|e| format_err!("failed to decode output: {}", e))?;
@@ -1073,7 +2245,8 @@ fn declare_functions(function: &Function) -> quote::Tokens {
                 let outs: Vec<_> = function
                     .outputs
                     .iter()
-                    .map(|param| from_token(&param.kind, &o))
+                    .zip(output_enums)
+                    .map(|(param, e)| param_from_token(&param.kind, *e, &o))
                     .collect();
 
                 quote! {
@@ -1102,8 +2275,52 @@ fn declare_functions(function: &Function) -> quote::Tokens {
     let function_inputs = to_ethabi_param_vec(&function.inputs);
     let function_outputs = to_ethabi_param_vec(&function.outputs);
     let function_constant = &function.constant;
+    let strategy = declare_strategy(&function.inputs, input_enums);
+
+    let module_name = syn::Ident::from(function.name.to_snake_case());
+    let signature = function_signature(function);
+    let selector = &tiny_keccak::keccak256(signature.as_bytes())[..4];
+    let signature_doc = format!("Signature of `{}`.", function.name);
+    let selector_doc = format!("Selector of `{}`, the first four bytes of calldata.", function.name);
+    let encode_doc = format!(
+        "Encode the calldata of a call to `{}`, including the selector.",
+        function.name
+    );
+
+    let (template_params, params, param_names) = input_params(&function.inputs, input_enums);
+
+    let use_enums = if input_enums.iter().any(Option::is_some) {
+        quote! {
+            #[allow(unused)]
+            use super::enums;
+        }
+    } else {
+        quote!{}
+    };
 
     quote! {
+        /// Constants identifying the function, and encoding calls to it.
+        pub mod #module_name {
+            #[allow(unused)]
+            use parables_testing::ethabi;
+            #use_enums
+
+            #[doc = #signature_doc]
+            pub const SIGNATURE: &'static str = #signature;
+            #[doc = #selector_doc]
+            pub const SELECTOR: [u8; 4] = [#(#selector),*];
+
+            #[doc = #encode_doc]
+            ///
+            /// This is useful to build the payload of calls made by other contracts, like
+            /// multisig submissions or `delegatecall`s through a proxy.
+            pub fn encode<#(#template_params),*>(#(#params),*) -> ethabi::Bytes {
+                super::#module_name(#(#param_names),*).into_encoded_input()
+            }
+
+            #strategy
+        }
+
         #[derive(Debug, Clone, PartialEq)]
         pub struct #name {
             function: ethabi::Function
@@ -1135,10 +2352,109 @@ fn declare_functions(function: &Function) -> quote::Tokens {
     }
 }
 
-fn declare_output_functions(function: &Function) -> quote::Tokens {
+/// Build the canonical signature of a function, like `setValue(uint256)`.
+fn function_signature(function: &Function) -> String {
+    let inputs: Vec<_> = function
+        .inputs
+        .iter()
+        .map(|p| canonical_type(&p.kind))
+        .collect();
+
+    format!("{}({})", function.name, inputs.join(","))
+}
+
+/// The canonical name of a type, as used in signatures.
+fn canonical_type(kind: &ParamType) -> String {
+    match *kind {
+        ParamType::Address => "address".to_string(),
+        ParamType::Bytes => "bytes".to_string(),
+        ParamType::Int(bits) => format!("int{}", bits),
+        ParamType::Uint(bits) => format!("uint{}", bits),
+        ParamType::Bool => "bool".to_string(),
+        ParamType::String => "string".to_string(),
+        ParamType::Array(ref kind) => format!("{}[]", canonical_type(kind)),
+        ParamType::FixedBytes(size) => format!("bytes{}", size),
+        ParamType::FixedArray(ref kind, size) => format!("{}[{}]", canonical_type(kind), size),
+    }
+}
+
+/// Declare a strategy generating random input for the given parameters.
+///
+/// Since proptest only implements strategies for tuples of up to ten elements, no strategy is
+/// declared for functions with more parameters than that.
+///
+/// Parameters declared with an enum only take the values of its variants.
+fn declare_strategy(inputs: &Vec<Param>, enums: &[Option<&SolidityEnum>]) -> quote::Tokens {
+    if inputs.len() > 10 {
+        return quote!{};
+    }
+
+    let kinds: Vec<_> = inputs
+        .iter()
+        .zip(enums)
+        .map(|(param, e)| param_type(&param.kind, *e))
+        .collect();
+
+    let names: Vec<_> = (0..inputs.len())
+        .map(|index| syn::Ident::from(format!("p{}", index)))
+        .collect();
+
+    let strategies: Vec<_> = inputs
+        .iter()
+        .zip(enums)
+        .map(|(param, e)| match *e {
+            Some(e) => {
+                let count = e.variants.len() as u32;
+                let e = syn::Ident::from(e.name.as_str());
+                quote! { (0u32..#count).prop_map(|v| enums::#e::from(v as u8)) }
+            }
+            None => {
+                let kind = to_syntax_string(&param.kind);
+                quote! { ::parables_testing::fuzz::strategy(&#kind) }
+            }
+        })
+        .collect();
+
+    let from_tokens: Vec<_> = inputs
+        .iter()
+        .zip(enums)
+        .zip(names.iter())
+        .map(|((param, e), name)| match *e {
+            Some(_) => quote! { #name },
+            None => from_token(&param.kind, &quote! { #name }),
+        })
+        .collect();
+
+    let body = if inputs.is_empty() {
+        quote! { Just(()).boxed() }
+    } else {
+        quote! {
+            (#(#strategies,)*)
+                .prop_map(|(#(#names,)*)| (#(#from_tokens,)*))
+                .boxed()
+        }
+    };
+
+    // strategies depend on proptest, which is only available with the test runner.
+    quote! {
+        __parables_with_test_runner! {
+            /// Strategy generating random input for this function, based on its parameter types.
+            pub fn strategy()
+                -> ::parables_testing::proptest::strategy::BoxedStrategy<(#(#kinds,)*)>
+            {
+                #[allow(unused)]
+                use parables_testing::proptest::strategy::{Just, Strategy};
+
+                #body
+            }
+        }
+    }
+}
+
+fn declare_output_functions(function: &Function, enums: &EnumParams) -> quote::Tokens {
     let name_camel = syn::Ident::from(function.name.to_camel_case());
     let name_snake = syn::Ident::from(function.name.to_snake_case());
-    let output_kinds = get_output_kinds(&function.outputs);
+    let output_kinds = get_output_kinds(&function.outputs, &enums.outputs(function));
 
     quote! {
         /// Returns the decoded output for this contract function
@@ -1150,10 +2466,10 @@ fn declare_output_functions(function: &Function) -> quote::Tokens {
     }
 }
 
-fn declare_functions_input_wrappers(function: &Function) -> quote::Tokens {
+fn declare_functions_input_wrappers(function: &Function, enums: &EnumParams) -> quote::Tokens {
     let name = syn::Ident::from(function.name.to_camel_case());
     let name_with_input = syn::Ident::from(format!("{}WithInput", function.name.to_camel_case()));
-    let output_kinds = get_output_kinds(&function.outputs);
+    let output_kinds = get_output_kinds(&function.outputs, &enums.outputs(function));
     let output_fn_body = quote!{super::functions::#name::default().decode_output(&_output_bytes)};
 
     quote! {
@@ -1186,10 +2502,43 @@ fn declare_functions_input_wrappers(function: &Function) -> quote::Tokens {
                     encoded_input: encoded_input
                 }
             }
+
+            /// Access the encoded calldata of the call, including the selector.
+            pub fn encoded_input(&self) -> &ethabi::Bytes {
+                &self.encoded_input
+            }
+
+            /// Convert into the encoded calldata of the call, including the selector.
+            pub fn into_encoded_input(self) -> ethabi::Bytes {
+                self.encoded_input
+            }
         }
     }
 }
 
+/// Convert a name into a module name, which can't start with a digit or be a keyword.
+fn module_ident(name: &str) -> String {
+    const KEYWORDS: &'static [&'static str] = &[
+        "as", "box", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+        "where", "while",
+    ];
+
+    let name = name.to_snake_case();
+
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => {}
+        _ => return format!("_{}", name),
+    }
+
+    if KEYWORDS.contains(&name.as_str()) {
+        return format!("{}_", name);
+    }
+
+    name
+}
+
 /// Convert input into a rust variable name.
 ///
 /// Avoid using keywords by escaping them.
@@ -1200,3 +2549,368 @@ fn rust_variable(name: &str) -> String {
         other => other.to_snake_case(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{find_enum_params, find_enums, impl_module, module_ident, FileAst, Output,
+                ParablesContract};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    #[test]
+    fn test_module_ident() {
+        assert_eq!("v1_token", module_ident("v1/Token"));
+        assert_eq!("_1_token", module_ident("1_Token"));
+        assert_eq!("match_", module_ident("Match"));
+    }
+
+    #[test]
+    fn test_namespaced_modules() {
+        let output = output(&["v1/Token.sol:Token", "v2/Token.sol:Token"]);
+
+        let module = impl_module(
+            Path::new("contracts"),
+            output,
+            vec![
+                contract("token_v1", Some("v1/Token.sol"), "Token"),
+                contract("token_v2", Some("v2/Token.sol"), "Token"),
+                contract("token", Some("v2/Token.sol"), "Token"),
+            ],
+        ).expect("failed to generate module");
+
+        let module = compact(&module.to_string());
+
+        assert!(module.contains("pubmodsources{"));
+        assert!(module.contains("pubmodv1_token{pubmodtoken{"));
+        assert!(module.contains("pubmodv2_token{pubmodtoken{"));
+        assert!(module.contains("pubuseself::sources::v1_token::tokenastoken_v1;"));
+        assert!(module.contains("pubuseself::sources::v2_token::tokenastoken_v2;"));
+        assert!(module.contains("pubuseself::sources::v2_token::tokenastoken;"));
+        // every contract is only generated once.
+        assert_eq!(2, module.matches("pubmodtoken{").count());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let error = impl_module(
+            Path::new("contracts"),
+            output(&["v1/Token.sol:Token", "v2/Token.sol:Token"]),
+            vec![contract("token", None, "Token")],
+        ).expect_err("expected ambiguous contract");
+
+        assert_eq!(
+            "Contract `Token` is declared in multiple files: v1/Token.sol, v2/Token.sol, use \
+             `<file>:Token` to pick one",
+            error.to_string()
+        );
+
+        let error = impl_module(
+            Path::new("contracts"),
+            output(&["v1/Token.sol:Token", "v1_token.sol:Token"]),
+            vec![
+                contract("a", Some("v1/Token.sol"), "Token"),
+                contract("b", Some("v1_token.sol"), "Token"),
+            ],
+        ).expect_err("expected conflicting files");
+
+        assert_eq!(
+            "Files `v1/Token.sol` and `v1_token.sol` both generate module `sources::v1_token`, \
+             rename one of them",
+            error.to_string()
+        );
+
+        let error = impl_module(
+            Path::new("contracts"),
+            output(&["Token.sol:Token", "Token.sol:TOKEN"]),
+            vec![
+                contract("a", Some("Token.sol"), "Token"),
+                contract("b", Some("Token.sol"), "TOKEN"),
+            ],
+        ).expect_err("expected conflicting contracts");
+
+        assert_eq!(
+            "Contracts `Token.sol:Token` and `Token.sol:TOKEN` both generate module \
+             `sources::token::token`",
+            error.to_string()
+        );
+
+        let error = impl_module(
+            Path::new("contracts"),
+            output(&["Token.sol:Token"]),
+            vec![
+                contract("a", None, "Token"),
+                contract("a", Some("Token.sol"), "Token"),
+            ],
+        ).expect_err("expected module to be bound twice");
+
+        assert_eq!(
+            "Module `a` is declared for both `Token.sol:Token` and `Token.sol:Token`",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        let abi = json!([
+            {
+                "type": "function",
+                "name": "trade",
+                "inputs": [{"name": "side", "type": "uint8"}, {"name": "amount", "type": "uint8"}],
+                "outputs": [{"name": "", "type": "uint8"}],
+                "constant": false,
+            },
+            {
+                "type": "function",
+                "name": "state",
+                "inputs": [],
+                "outputs": [{"name": "", "type": "uint8"}],
+                "constant": true,
+            },
+            {
+                "type": "event",
+                "name": "Traded",
+                "inputs": [{"name": "side", "type": "uint8", "indexed": false}],
+                "anonymous": false,
+            },
+        ]);
+
+        let ast = json!({
+            "nodeType": "SourceUnit",
+            "nodes": [
+                {
+                    "nodeType": "EnumDefinition",
+                    "name": "Side",
+                    "members": [member("Buy"), member("Sell")],
+                },
+                {
+                    "nodeType": "ContractDefinition",
+                    "name": "Market",
+                    "nodes": [
+                        {
+                            "nodeType": "EnumDefinition",
+                            "name": "State",
+                            "members": [member("Open"), member("Closed")],
+                        },
+                        {
+                            "nodeType": "VariableDeclaration",
+                            "name": "state",
+                            "stateVariable": true,
+                            "visibility": "public",
+                            "typeDescriptions": {"typeString": "enum Market.State"},
+                        },
+                        {
+                            "nodeType": "FunctionDefinition",
+                            "name": "trade",
+                            "parameters": {
+                                "parameters": [param("enum Side"), param("uint8")],
+                            },
+                            "returnParameters": {
+                                "parameters": [param("enum Market.State")],
+                            },
+                        },
+                        {
+                            "nodeType": "EventDefinition",
+                            "name": "Traded",
+                            "parameters": {"parameters": [param("enum Side")]},
+                        },
+                    ],
+                },
+            ],
+        });
+
+        let mut output = abi_output("Market.sol:Market", abi);
+        output
+            .sources
+            .insert("Market.sol".to_string(), FileAst { ast });
+
+        let module = impl_module(
+            Path::new("contracts"),
+            output,
+            vec![contract("market", None, "Market")],
+        ).expect("failed to generate module");
+
+        let module = compact(&module.to_string());
+
+        // both the file-level and the contract enum are generated.
+        assert!(module.contains("pubenumSide{Buy,Sell,}"));
+        assert!(module.contains("pubenumState{Open,Closed,}"));
+        assert!(module.contains("usesuper::enums;"));
+
+        // function inputs and outputs.
+        assert!(module.contains("pubfntrade<T0:Into<enums::Side>,T1:Into<ethabi::Uint>>"));
+        assert!(module.contains("ethabi::Uint::from(Into::<enums::Side>::into(side))"));
+        assert!(module.contains("::parables_testing::evm::Call<enums::State>"));
+        assert!(module.contains("Result<enums::State,::parables_testing::Error>"));
+        assert!(module.contains("enums::State::from_u256(out.to_uint()"));
+        assert!(module.contains("BoxedStrategy<(enums::Side,ethabi::Uint,)>"));
+
+        // decoded event fields.
+        assert!(module.contains("pubside:enums::Side,"));
+        assert!(module.contains("side:enums::Side::from_u256("));
+
+        return;
+
+        fn member(name: &str) -> ::serde_json::Value {
+            json!({"nodeType": "EnumValue", "name": name})
+        }
+
+        fn param(type_string: &str) -> ::serde_json::Value {
+            json!({
+                "nodeType": "VariableDeclaration",
+                "typeDescriptions": {"typeString": type_string},
+            })
+        }
+    }
+
+    #[test]
+    fn test_enums_legacy_ast() {
+        let ast = json!({
+            "name": "SourceUnit",
+            "children": [
+                {
+                    "name": "EnumDefinition",
+                    "attributes": {"name": "State"},
+                    "children": [{"name": "EnumValue", "attributes": {"name": "Unused"}}],
+                },
+                {
+                    "name": "ContractDefinition",
+                    "attributes": {"name": "Market"},
+                    "children": [
+                        {
+                            "name": "EnumDefinition",
+                            "attributes": {"name": "State"},
+                            "children": [
+                                {"name": "EnumValue", "attributes": {"name": "Open"}},
+                                {"name": "EnumValue", "attributes": {"name": "Closed"}},
+                            ],
+                        },
+                        function("close", "enum Market.State"),
+                        function("close", "uint8"),
+                        function("open", "enum Market.State"),
+                        {
+                            "name": "EventDefinition",
+                            "attributes": {"name": "Closed"},
+                            "children": [
+                                {"name": "ParameterList", "children": [param("enum Market.State")]},
+                            ],
+                        },
+                    ],
+                },
+            ],
+        });
+
+        let enums = find_enums(&ast, "Market");
+
+        // the contract enum shadows the file-level one.
+        assert_eq!(1, enums.len());
+        assert_eq!("enum Market.State", enums[0].type_string);
+        assert_eq!(vec!["Open", "Closed"], enums[0].variants);
+
+        let params = find_enum_params(&ast, "Market", enums);
+        let state = Some("State".to_string());
+
+        // overloads which disagree are not typed.
+        assert_eq!(
+            Some(&(Vec::new(), Vec::new())),
+            params.functions.get(&("close".to_string(), 1))
+        );
+
+        assert_eq!(
+            Some(&(vec![state.clone()], Vec::new())),
+            params.functions.get(&("open".to_string(), 1))
+        );
+
+        assert_eq!(
+            Some(&vec![state.clone()]),
+            params.events.get(&("Closed".to_string(), 1))
+        );
+
+        return;
+
+        fn function(name: &str, type_string: &str) -> ::serde_json::Value {
+            json!({
+                "name": "FunctionDefinition",
+                "attributes": {"name": name},
+                "children": [
+                    {"name": "ParameterList", "children": [param(type_string)]},
+                    {"name": "ParameterList", "children": []},
+                    {"name": "Block", "children": []},
+                ],
+            })
+        }
+
+        fn param(type_string: &str) -> ::serde_json::Value {
+            json!({"name": "VariableDeclaration", "attributes": {"type": type_string}})
+        }
+    }
+
+    #[test]
+    fn test_colliding_function_names() {
+        let functions: Vec<_> = ["state", "storage", "gasPrice", "setValue"]
+            .iter()
+            .map(|name| {
+                json!({
+                    "type": "function",
+                    "name": name,
+                    "inputs": [],
+                    "outputs": [],
+                    "constant": false,
+                })
+            })
+            .collect();
+
+        let module = impl_module(
+            Path::new("contracts"),
+            abi_output("Token.sol:Token", json!(functions)),
+            vec![contract("token", None, "Token")],
+        ).expect("failed to generate module");
+
+        let module = compact(&module.to_string());
+
+        assert!(module.contains("pubfn_state<>(&self,)"));
+        assert!(module.contains("pubfn_storage<>(&self,)"));
+        assert!(module.contains("pubfn_gas_price<>(&self,)"));
+        assert!(module.contains("pubfnset_value<>(&self,)"));
+    }
+
+    /// Build compiler output declaring a single contract with the given ABI.
+    fn abi_output(name: &str, abi: ::serde_json::Value) -> Output {
+        let mut output = output(&[]);
+
+        let fields = json!({"abi": abi.to_string(), "bin": ""});
+        let fields = ::serde_json::from_value(fields).expect("bad contract fields");
+        output.contracts.insert(name.to_string(), fields);
+        output
+    }
+
+    /// Build compiler output declaring contracts without functions, like `Token.sol:Token`.
+    fn output(names: &[&str]) -> Output {
+        let mut contracts = HashMap::new();
+
+        for name in names {
+            let fields = json!({"abi": "[]", "bin": ""});
+            let fields = ::serde_json::from_value(fields).expect("bad contract fields");
+            contracts.insert(name.to_string(), fields);
+        }
+
+        Output {
+            contracts,
+            source_list: Vec::new(),
+            version: String::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    fn contract(item: &str, file: Option<&str>, entry: &str) -> ParablesContract {
+        ParablesContract {
+            item: item.to_string(),
+            file: file.map(|f| f.to_string()),
+            entry: entry.to_string(),
+        }
+    }
+
+    /// Remove all whitespace, to compare generated code regardless of formatting.
+    fn compact(code: &str) -> String {
+        code.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+}
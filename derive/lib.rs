@@ -1,4 +1,4 @@
-#![recursion_limit = "256"]
+#![recursion_limit = "512"]
 
 extern crate proc_macro;
 extern crate syn;
@@ -9,14 +9,17 @@ extern crate heck;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+extern crate tiny_keccak;
 
+mod artifacts;
 mod derive;
+mod solc;
 
 use std::fmt;
 use std::io;
 use std::path::PathBuf;
-use std::process::Command;
 
 #[proc_macro_derive(ParablesContracts, attributes(parables, parables_contract))]
 pub fn ethabi_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -27,8 +30,8 @@ pub fn ethabi_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 }
 
 fn get_options(attrs: &[syn::Attribute]) -> Result<derive::Options, Error> {
+    let mut options = derive::Options::default();
     let mut path = None;
-    let mut contracts = Vec::new();
 
     for attr in attrs {
         let meta = match attr.interpret_meta() {
@@ -39,21 +42,20 @@ fn get_options(attrs: &[syn::Attribute]) -> Result<derive::Options, Error> {
         };
 
         if meta.name() == "parables" {
-            path = Some(decode_parables(meta)?);
+            path = Some(decode_parables(meta, &mut options)?);
             continue;
         }
 
         if meta.name() == "parables_contract" {
-            contracts.extend(decode_parables_contract(meta)?);
+            options.contracts.extend(decode_parables_contract(meta)?);
             continue;
         }
     }
 
-    let path = path.ok_or_else(|| "Missing attribute parables(path = ...)")?;
-
-    return Ok(derive::Options { path, contracts });
+    options.path = path.ok_or_else(|| "Missing attribute parables(path = ...)")?;
+    return Ok(options);
 
-    fn decode_parables(meta: syn::Meta) -> Result<PathBuf, Error> {
+    fn decode_parables(meta: syn::Meta, options: &mut derive::Options) -> Result<PathBuf, Error> {
         let mut path = None;
 
         let values = match meta {
@@ -70,7 +72,61 @@ fn get_options(attrs: &[syn::Attribute]) -> Result<derive::Options, Error> {
             if v.name() == "path" {
                 if let syn::Meta::NameValue(ref name_value) = v {
                     if let syn::Lit::Str(ref value) = name_value.lit {
-                        path = Some(PathBuf::from(value.value()));
+                        // the first path is the primary root, the rest are additional roots.
+                        if path.is_none() {
+                            path = Some(PathBuf::from(value.value()));
+                        } else {
+                            options.paths.push(PathBuf::from(value.value()));
+                        }
+
+                        continue;
+                    }
+                }
+            }
+
+            if v.name() == "standard_json" {
+                if let syn::Meta::Word(_) = v {
+                    options.standard_json = true;
+                    continue;
+                }
+            }
+
+            if v.name() == "artifacts" {
+                if let syn::Meta::Word(_) = v {
+                    options.artifacts = true;
+                    continue;
+                }
+            }
+
+            if v.name() == "storage_layout" {
+                if let syn::Meta::Word(_) = v {
+                    options.storage_layout = true;
+                    continue;
+                }
+            }
+
+            if v.name() == "solc" {
+                if let syn::Meta::NameValue(ref name_value) = v {
+                    if let syn::Lit::Str(ref value) = name_value.lit {
+                        options.solc = Some(value.value());
+                        continue;
+                    }
+                }
+            }
+
+            if v.name() == "ignore" {
+                if let syn::Meta::NameValue(ref name_value) = v {
+                    if let syn::Lit::Str(ref value) = name_value.lit {
+                        options.ignore.push(PathBuf::from(value.value()));
+                        continue;
+                    }
+                }
+            }
+
+            if v.name() == "remapping" {
+                if let syn::Meta::NameValue(ref name_value) = v {
+                    if let syn::Lit::Str(ref value) = name_value.lit {
+                        options.remappings.push(value.value());
                         continue;
                     }
                 }
@@ -109,21 +165,17 @@ fn get_options(attrs: &[syn::Attribute]) -> Result<derive::Options, Error> {
                 _ => return Err("Bad argument to parables_contract(...)".into()),
             };
 
-            let mut parts = argument.split(":");
-
-            let file = match parts.next() {
-                Some(file) => file.to_string(),
-                _ => {
-                    return Err(format!("Bad argument to parables_contract({} = ...)", item).into())
-                }
+            // the file is optional if the contract is only declared in one file.
+            let (file, entry) = match argument.rfind(':') {
+                Some(sep) => (Some(argument[..sep].to_string()), &argument[sep + 1..]),
+                None => (None, argument.as_str()),
             };
 
-            let entry = match parts.next() {
-                Some(entry) => entry.to_string(),
-                _ => {
-                    return Err(format!("Bad argument to parables_contract({} = ...)", item).into())
-                }
-            };
+            if entry.is_empty() {
+                return Err(format!("Bad argument to parables_contract({} = ...)", item).into());
+            }
+
+            let entry = entry.to_string();
 
             contracts.push(derive::ParablesContract { item, file, entry });
         }
@@ -132,7 +184,7 @@ fn get_options(attrs: &[syn::Attribute]) -> Result<derive::Options, Error> {
     }
 }
 
-/// Compiles all solidity files in given directory.
+/// Compiles all solidity files in the given directories.
 fn compile(options: derive::Options) -> Result<quote::Tokens, Error> {
     let root = match ::std::env::var("CARGO_MANIFEST_DIR") {
         Ok(dir) => PathBuf::from(dir),
@@ -141,38 +193,14 @@ fn compile(options: derive::Options) -> Result<quote::Tokens, Error> {
 
     let path = root.join(&options.path);
 
-    let mut c = Command::new("solc");
+    let mut options = options;
+    options.paths = options.paths.iter().map(|p| root.join(p)).collect();
 
-    c.arg("--combined-json")
-        .arg("abi,bin,srcmap,srcmap-runtime,bin-runtime,ast");
-
-    for contract in &options.contracts {
-        let path = path.join(&contract.file);
-
-        if !path.is_file() {
-            panic!("No such file: {}", path.display());
-        }
-
-        c.arg(&contract.file);
-    }
-
-    let output = c
-        .current_dir(&path)
-        .output()
-        .map_err(|e| format!("error compiling contracts: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = ::std::str::from_utf8(&output.stderr)
-            .map_err(|e| format!("failed to decode stderr: {}", e))?;
-
-        return Err(format!("solcjs failed: {:?}\n{}", output.status, stderr).into());
-    }
-
-    let output = ::std::str::from_utf8(&output.stdout)
-        .map_err(|e| format!("failed to decode stdout: {}", e))?;
-
-    let output: derive::Output =
-        serde_json::from_str(&output).map_err(|e| format!("failed to decode output: {}", e))?;
+    let output = if options.artifacts {
+        artifacts::load(&path, &options)?
+    } else {
+        solc::compile(&root, &path, &options)?
+    };
 
     let result = derive::impl_module(&path, output, options.contracts)
         .map_err(|e| format!("failed to build module: {}", e))?;
@@ -0,0 +1,658 @@
+//! Invoking the solidity compiler.
+
+use derive::{ContractFields, FileAst, Options, Output};
+use serde_json::{self, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use Error;
+
+/// Base URL from which static compiler binaries, and the list of them, are downloaded.
+const BINARIES_URL: &'static str = "https://binaries.soliditylang.org/linux-amd64";
+
+/// Resolve the compiler to use.
+///
+/// Without a pinned version, this is whatever `solc` is on `PATH`. With a pinned version, `solc`
+/// on `PATH` is used if it has the expected version. Otherwise the compiler is looked up in the
+/// cache, and downloaded to it if missing.
+///
+/// The cache is `$PARABLES_SOLC_CACHE`, or `$HOME/.parables/solc` if not set.
+pub fn resolve(options: &Options) -> Result<PathBuf, Error> {
+    let version = match options.solc {
+        Some(ref version) => version.trim_left_matches('v'),
+        None => return Ok(PathBuf::from("solc")),
+    };
+
+    if path_version().map(|v| v == version).unwrap_or(false) {
+        return Ok(PathBuf::from("solc"));
+    }
+
+    let cache = match env::var_os("PARABLES_SOLC_CACHE") {
+        Some(cache) => PathBuf::from(cache),
+        None => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".parables").join("solc"),
+            None => return Err("cannot determine solc cache, set PARABLES_SOLC_CACHE".into()),
+        },
+    };
+
+    let solc = cache.join(version).join("solc");
+
+    if solc.is_file() {
+        return Ok(solc);
+    }
+
+    install(version, &solc)?;
+    Ok(solc)
+}
+
+/// Get the version of the `solc` on `PATH`, if any.
+fn path_version() -> Option<String> {
+    version(Path::new("solc"))
+}
+
+/// Get the version of the given compiler, if available.
+fn version(solc: &Path) -> Option<String> {
+    let output = Command::new(solc).arg("--version").output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+
+    // Version: 0.4.24+commit.e67f0147.Linux.g++
+    let line = output.lines().find(|l| l.starts_with("Version: "))?;
+    let version = line["Version: ".len()..].split('+').next()?;
+    Some(version.to_string())
+}
+
+/// Download the static compiler binary of the given version to the given path.
+///
+/// The binary is verified against the checksum published in the list of builds before it is
+/// installed. Nothing is downloaded if `CARGO_NET_OFFLINE` is set.
+fn install(version: &str, solc: &Path) -> Result<(), Error> {
+    if !cfg!(target_os = "linux") {
+        return Err(format!(
+            "automatic installation of solc is only supported on linux, install solc {} to: {}",
+            version,
+            solc.display()
+        ).into());
+    }
+
+    if env::var("CARGO_NET_OFFLINE").map(|v| v != "false").unwrap_or(false) {
+        return Err(format!(
+            "solc {} is not installed and CARGO_NET_OFFLINE is set, install it to: {}",
+            version,
+            solc.display()
+        ).into());
+    }
+
+    let dir = solc.parent().ok_or_else(|| "bad solc cache path")?;
+    fs::create_dir_all(dir)?;
+
+    let list = dir.join("list.json");
+    fetch(&format!("{}/list.json", BINARIES_URL), &list)?;
+    let build = find_build(&fs::read(&list)?, version);
+    fs::remove_file(&list)?;
+    let (path, expected) = build?;
+
+    let url = format!("{}/{}", BINARIES_URL, path);
+    let download = dir.join("solc.download");
+    fetch(&url, &download)?;
+
+    let actual = sha256sum(&download)?;
+
+    if actual != expected {
+        fs::remove_file(&download)?;
+
+        return Err(format!(
+            "checksum mismatch for solc {} downloaded from {}, expected {} but got {}",
+            version, url, expected, actual
+        ).into());
+    }
+
+    make_executable(&download)?;
+    fs::rename(&download, solc)?;
+    Ok(())
+}
+
+/// Download the given URL to the given path.
+fn fetch(url: &str, output: &Path) -> Result<(), Error> {
+    let status = Command::new("curl")
+        .args(&["--silent", "--show-error", "--fail", "--location", "--output"])
+        .arg(output)
+        .arg(url)
+        .status()
+        .map_err(|e| format!("failed to run curl to download {}: {}", url, e))?;
+
+    if !status.success() {
+        return Err(format!("failed to download {}: {}", url, status).into());
+    }
+
+    Ok(())
+}
+
+/// Find the path and the sha256 checksum of the release build of the given version in a list of
+/// builds.
+fn find_build(list: &[u8], version: &str) -> Result<(String, String), Error> {
+    let list: Value =
+        serde_json::from_slice(list).map_err(|e| format!("bad list of solc builds: {}", e))?;
+
+    let builds = list
+        .get("builds")
+        .and_then(|b| b.as_array())
+        .ok_or_else(|| "bad list of solc builds: missing `builds`")?;
+
+    let build = builds
+        .iter()
+        .filter(|b| b.get("prerelease").is_none())
+        .find(|b| b.get("version").and_then(|v| v.as_str()) == Some(version))
+        .ok_or_else(|| format!("no release build of solc {}", version))?;
+
+    let path = build
+        .get("path")
+        .and_then(|p| p.as_str())
+        .ok_or_else(|| format!("build of solc {} is missing `path`", version))?;
+
+    let sha256 = build
+        .get("sha256")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| format!("build of solc {} is missing `sha256`", version))?;
+
+    let sha256 = sha256.trim_left_matches("0x").to_lowercase();
+    Ok((path.to_string(), sha256))
+}
+
+/// Calculate the sha256 checksum of the given file, as lowercase hex.
+fn sha256sum(path: &Path) -> Result<String, Error> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("failed to run sha256sum: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("failed to checksum {}: {}", path.display(), output.status).into());
+    }
+
+    let output = String::from_utf8(output.stdout)
+        .map_err(|e| format!("bad output from sha256sum: {}", e))?;
+
+    let sum = output
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| "bad output from sha256sum")?;
+
+    Ok(sum.to_lowercase())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Find all files with the given extension in the given directory and its subdirectories.
+///
+/// Hidden directories, directories named `node_modules`, and directories in the ignore list are
+/// skipped. The returned paths are relative to `root`, and sorted.
+pub fn files_by_ext(root: &Path, ext: &str, ignore: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let mut queue = vec![PathBuf::new()];
+
+    while let Some(dir) = queue.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+
+                if name.starts_with('.') || name == "node_modules" || ignore.contains(&path) {
+                    continue;
+                }
+
+                queue.push(path);
+                continue;
+            }
+
+            if path.extension().map(|e| e == ext).unwrap_or(false) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Get all sources to compile.
+///
+/// This is every solidity file under the root, so that contracts can be organized in
+/// subdirectories. Sources under the primary root are relative to it, and sources under additional
+/// roots are absolute.
+fn sources(path: &Path, options: &Options) -> Result<Vec<String>, Error> {
+    for file in options.contracts.iter().filter_map(|c| c.file.as_ref()) {
+        let exists = ::std::iter::once(path)
+            .chain(options.paths.iter().map(|p| p.as_path()))
+            .any(|root| root.join(file).is_file());
+
+        if !exists {
+            return Err(format!("No such file: {}", path.join(file).display()).into());
+        }
+    }
+
+    let mut sources = Vec::new();
+
+    for file in files_by_ext(path, "sol", &options.ignore)? {
+        let file = file
+            .to_str()
+            .ok_or_else(|| format!("non utf-8 path: {}", file.display()))?
+            .replace('\\', "/");
+
+        sources.push(file);
+    }
+
+    for root in &options.paths {
+        for file in files_by_ext(root, "sol", &[])? {
+            let file = root.join(file);
+
+            let file = file
+                .to_str()
+                .ok_or_else(|| format!("non utf-8 path: {}", file.display()))?
+                .to_string();
+
+            sources.push(file);
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Paths which the compiler is permitted to import from.
+fn allow_paths(options: &Options) -> String {
+    let mut paths = vec![String::from(".")];
+    paths.extend(options.paths.iter().map(|p| p.display().to_string()));
+    paths.join(",")
+}
+
+/// Make contracts from additional roots addressable relative to their root.
+///
+/// Contracts from additional roots are compiled using absolute paths, so `ERC20.sol:ERC20` in the
+/// root `lib/solmate/src` would otherwise be named by its absolute path.
+fn relativize(output: &mut Output, options: &Options) -> Result<(), Error> {
+    if options.paths.is_empty() {
+        return Ok(());
+    }
+
+    let contracts = ::std::mem::replace(&mut output.contracts, HashMap::new());
+
+    for (name, contract) in contracts {
+        let mut relative = name.clone();
+
+        for root in &options.paths {
+            let prefix = format!("{}/", root.display());
+
+            if name.starts_with(&prefix) {
+                relative = name[prefix.len()..].to_string();
+                break;
+            }
+        }
+
+        if output.contracts.contains_key(&relative) {
+            return Err(format!("contract `{}` exists in multiple roots", relative).into());
+        }
+
+        output.contracts.insert(relative, contract);
+    }
+
+    Ok(())
+}
+
+/// Compile the contracts under the given path, using a cached result if available.
+///
+/// Results are cached under `target/parables`, keyed by a hash of the sources, the compiler
+/// version, and the compiler settings. Caching can be disabled by setting `PARABLES_NO_CACHE=1`.
+///
+/// Note that files imported from outside of the contract roots are not part of the key.
+pub fn compile(root: &Path, path: &Path, options: &Options) -> Result<Output, Error> {
+    let cache = match env::var("PARABLES_NO_CACHE") {
+        Ok(ref value) if value == "1" => None,
+        _ => Some(cache_path(root, path, options)?),
+    };
+
+    if let Some(ref cache) = cache {
+        if let Ok(content) = fs::read(cache) {
+            if let Ok(output) = serde_json::from_slice::<Output>(&content) {
+                return Ok(output);
+            }
+        }
+    }
+
+    let output = if options.standard_json {
+        standard_json(path, options)?
+    } else {
+        combined_json(path, options)?
+    };
+
+    if let Some(ref cache) = cache {
+        if let Some(dir) = cache.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // NB: write to a temporary file first, since multiple crates might compile concurrently.
+        let temp = cache.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec(&output).map_err(|e| e.to_string())?)?;
+        fs::rename(&temp, cache)?;
+    }
+
+    Ok(output)
+}
+
+/// Calculate the path of the cached compiler output for the given options.
+fn cache_path(root: &Path, path: &Path, options: &Options) -> Result<PathBuf, Error> {
+    let mut hasher = DefaultHasher::new();
+
+    version(&resolve(options)?).hash(&mut hasher);
+    options.standard_json.hash(&mut hasher);
+    options.storage_layout.hash(&mut hasher);
+    options.remappings.hash(&mut hasher);
+    options.paths.hash(&mut hasher);
+
+    for source in sources(path, options)? {
+        let content = fs::read(path.join(&source))?;
+        source.hash(&mut hasher);
+        content.hash(&mut hasher);
+    }
+
+    let target = match env::var_os("CARGO_TARGET_DIR") {
+        Some(target) => PathBuf::from(target),
+        None => {
+            // the target directory is next to the lock file, which is in the workspace root.
+            let workspace = root
+                .ancestors()
+                .find(|p| p.join("Cargo.lock").is_file())
+                .unwrap_or(root);
+
+            workspace.join("target")
+        }
+    };
+
+    Ok(target
+        .join("parables")
+        .join(format!("{:016x}.json", hasher.finish())))
+}
+
+/// Compile the contracts using the `--combined-json` interface of solc.
+pub fn combined_json(path: &Path, options: &Options) -> Result<Output, Error> {
+    let mut c = Command::new(resolve(options)?);
+
+    let mut outputs = String::from("abi,bin,srcmap,srcmap-runtime,bin-runtime,ast");
+
+    // older compilers reject outputs they don't know about.
+    if options.storage_layout {
+        outputs.push_str(",storage-layout");
+    }
+
+    c.arg("--combined-json")
+        .arg(outputs)
+        .arg("--allow-paths")
+        .arg(allow_paths(options));
+
+    for remapping in &options.remappings {
+        c.arg(remapping);
+    }
+
+    for source in sources(path, options)? {
+        c.arg(source);
+    }
+
+    let output = c
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("error compiling contracts: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = ::std::str::from_utf8(&output.stderr)
+            .map_err(|e| format!("failed to decode stderr: {}", e))?;
+
+        return Err(format!("solcjs failed: {:?}\n{}", output.status, stderr).into());
+    }
+
+    let output = ::std::str::from_utf8(&output.stdout)
+        .map_err(|e| format!("failed to decode stdout: {}", e))?;
+
+    let mut output: Output =
+        serde_json::from_str(&output).map_err(|e| format!("failed to decode output: {}", e))?;
+
+    relativize(&mut output, options)?;
+    Ok(output)
+}
+
+/// Compile the contracts using the `--standard-json` interface of solc.
+///
+/// Errors are reported with their source locations, and warnings are printed while compiling.
+pub fn standard_json(path: &Path, options: &Options) -> Result<Output, Error> {
+    let mut sources = serde_json::Map::new();
+
+    for source in self::sources(path, options)? {
+        let content = fs::read_to_string(path.join(&source))
+            .map_err(|e| format!("{}: failed to read: {}", source, e))?;
+
+        sources.insert(source, json!({ "content": content }));
+    }
+
+    let mut selection = vec![
+        "abi",
+        "evm.bytecode.object",
+        "evm.bytecode.sourceMap",
+        "evm.deployedBytecode.object",
+        "evm.deployedBytecode.sourceMap",
+        "evm.deployedBytecode.immutableReferences",
+    ];
+
+    if options.storage_layout {
+        selection.push("storageLayout");
+    }
+
+    let input = json!({
+        "language": "Solidity",
+        "sources": sources,
+        "settings": {
+            "remappings": options.remappings,
+            "outputSelection": {
+                "*": {
+                    "*": selection,
+                    "": ["legacyAST", "ast"]
+                }
+            }
+        }
+    });
+
+    let mut child = Command::new(resolve(options)?)
+        .arg("--standard-json")
+        .arg("--allow-paths")
+        .arg(allow_paths(options))
+        .current_dir(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("error compiling contracts: {}", e))?;
+
+    {
+        let stdin = child
+            .stdin
+            .as_mut()
+            .ok_or_else(|| "failed to open stdin of solc")?;
+
+        stdin.write_all(input.to_string().as_bytes())?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("error compiling contracts: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = ::std::str::from_utf8(&output.stderr)
+            .map_err(|e| format!("failed to decode stderr: {}", e))?;
+
+        return Err(format!("solc failed: {:?}\n{}", output.status, stderr).into());
+    }
+
+    let output: StandardOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("failed to decode output: {}", e))?;
+
+    let mut errors = Vec::new();
+
+    for error in output.errors {
+        let message = error.describe();
+
+        if error.severity == "error" {
+            errors.push(message);
+        } else {
+            eprintln!("{}: {}", error.severity, message);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(format!("solc failed:\n{}", errors.join("\n")).into());
+    }
+
+    let mut contracts = HashMap::new();
+
+    for (file, file_contracts) in output.contracts {
+        for (name, contract) in file_contracts {
+            let abi = serde_json::to_string(&contract.abi)
+                .map_err(|e| format!("{}:{}: bad abi: {}", file, name, e))?;
+
+            contracts.insert(
+                format!("{}:{}", file, name),
+                ContractFields {
+                    abi,
+                    bin: contract.evm.bytecode.object,
+                    source_map: contract.evm.bytecode.source_map,
+                    runtime_bin: Some(contract.evm.deployed_bytecode.object),
+                    runtime_source_map: contract.evm.deployed_bytecode.source_map,
+                    immutable_references: contract
+                        .evm
+                        .deployed_bytecode
+                        .immutable_references
+                        .map(|references| references.to_string()),
+                    storage_layout: contract.storage_layout.map(|layout| layout.to_string()),
+                },
+            );
+        }
+    }
+
+    let mut ids = Vec::new();
+    let mut sources = HashMap::new();
+
+    for (file, source) in output.sources {
+        ids.push((source.id, file.to_string()));
+
+        if let Some(ast) = source.legacy_ast.or(source.ast) {
+            sources.insert(file, FileAst { ast });
+        }
+    }
+
+    ids.sort();
+
+    let mut output = Output {
+        contracts,
+        source_list: ids.into_iter().map(|(_, file)| file).collect(),
+        version: String::from("standard-json"),
+        sources,
+    };
+
+    relativize(&mut output, options)?;
+    Ok(output)
+}
+
+#[derive(Deserialize)]
+struct StandardOutput {
+    #[serde(default)]
+    errors: Vec<StandardError>,
+    #[serde(default)]
+    sources: HashMap<String, StandardSource>,
+    #[serde(default)]
+    contracts: HashMap<String, HashMap<String, StandardContract>>,
+}
+
+#[derive(Deserialize)]
+struct StandardError {
+    severity: String,
+    message: String,
+    #[serde(rename = "formattedMessage")]
+    formatted_message: Option<String>,
+    #[serde(rename = "sourceLocation")]
+    source_location: Option<SourceLocation>,
+}
+
+impl StandardError {
+    /// Describe the error, including its location.
+    fn describe(&self) -> String {
+        if let Some(ref formatted) = self.formatted_message {
+            return formatted.trim_right().to_string();
+        }
+
+        match self.source_location {
+            Some(ref l) => format!("{}:{}-{}: {}", l.file, l.start, l.end, self.message),
+            None => self.message.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SourceLocation {
+    file: String,
+    start: i64,
+    end: i64,
+}
+
+#[derive(Deserialize)]
+struct StandardSource {
+    id: u32,
+    #[serde(rename = "legacyAST")]
+    legacy_ast: Option<Value>,
+    ast: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct StandardContract {
+    abi: Value,
+    evm: StandardEvm,
+    #[serde(rename = "storageLayout", default)]
+    storage_layout: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct StandardEvm {
+    bytecode: StandardBytecode,
+    #[serde(rename = "deployedBytecode")]
+    deployed_bytecode: StandardBytecode,
+}
+
+#[derive(Deserialize)]
+struct StandardBytecode {
+    object: String,
+    #[serde(rename = "sourceMap")]
+    source_map: Option<String>,
+    /// Only emitted for deployed code, by solc 0.6.5 and later.
+    #[serde(rename = "immutableReferences", default)]
+    immutable_references: Option<Value>,
+}
@@ -3,6 +3,7 @@ extern crate parables_testing;
 extern crate pretty_env_logger;
 
 use parables_testing::prelude::*;
+use std::env;
 
 contracts!{
     simple_contract => "SimpleContract.sol:SimpleContract",
@@ -11,7 +12,15 @@ contracts!{
 }
 
 fn main() -> Result<()> {
-    pretty_env_logger::init();
+    let mut logger = pretty_env_logger::formatted_builder()?;
+
+    if let Ok(filters) = env::var("RUST_LOG") {
+        logger.parse(&filters);
+    }
+
+    let logger = logger.build();
+    let max_level = logger.filter();
+    CaptureLogger::new(logger).init(max_level)?;
 
     let owner = Address::random();
     // template call
@@ -22,8 +31,8 @@ fn main() -> Result<()> {
     evm.add_balance(owner, wei::from_ether(1000))?;
 
     // set up simple lib
-    evm.deploy(simple_lib::constructor(), call)?.ok()?;
-    let simple = evm.deploy(simple_contract::constructor(42), call)?.ok()?;
+    evm.deploy(simple_lib::constructor(), call.clone())?.ok()?;
+    let simple = evm.deploy(simple_contract::constructor(42), call.clone())?.ok()?;
 
     let evm = Snapshot::new(evm);
 
@@ -37,7 +46,7 @@ fn main() -> Result<()> {
 
                 let evm = evm.get()?;
 
-                let contract = simple_contract::contract(&evm, simple, call);
+                let contract = simple_contract::contract(&evm, simple, call.clone());
 
                 let out = contract.get_value()?.ok()?;
                 assert_eq!(out, 42.into());
@@ -52,7 +61,7 @@ fn main() -> Result<()> {
                 }
 
                 assert_eq!(1, evm.logs(ev::value_updated()).iter()?.count());
-                assert!(!evm.has_logs()?, "there were unprocessed logs");
+                evm.assert_no_logs()?;
             }
         },
     );
@@ -61,7 +70,7 @@ fn main() -> Result<()> {
         let evm = evm.get()?;
         let mut current = 42u64;
 
-        let contract = simple_contract::contract(&evm, simple, call);
+        let contract = simple_contract::contract(&evm, simple, call.clone());
 
         let out = contract.get_value()?.ok()?;
         assert_eq!(out, current.into());
@@ -97,7 +106,7 @@ fn main() -> Result<()> {
         let evm = evm.get()?;
         let mut current = 42u64;
 
-        let contract = simple_contract::contract(&evm, simple, call);
+        let contract = simple_contract::contract(&evm, simple, call.clone());
 
         let out = contract.get_value()?.ok()?;
         assert_eq!(out, current.into());
@@ -136,7 +145,7 @@ fn main() -> Result<()> {
             .drop()?;
 
         assert_eq!(999, evm.logs(ev::value_updated()).iter()?.count());
-        assert!(!evm.has_logs()?, "there were unprocessed logs");
+        evm.assert_no_logs()?;
 
         Ok(())
     });
@@ -169,11 +178,11 @@ fn main() -> Result<()> {
         let a = Address::random();
         let b = Address::random();
 
-        let call = call.sender(a);
+        let call = call.clone().sender(a);
 
         let evm = evm.get()?;
 
-        let simple = evm.deploy(simple_ledger::constructor(), call)?.ok()?;
+        let simple = evm.deploy(simple_ledger::constructor(), call.clone())?.ok()?;
         let simple = simple_ledger::contract(&evm, simple, call.gas_price(10));
 
         let mut balances = Ledger::account_balance(&evm);
@@ -187,14 +196,12 @@ fn main() -> Result<()> {
 
         // add to a
         let res = simple.value(wei!(42 eth)).add(a)?;
-        balances.sub(a, res.gas() + wei!(42 eth))?;
-        balances.add(simple.address, wei!(42 eth))?;
+        balances.apply(&res)?;
         states.add(a, wei!(42 eth))?;
 
         // add to b
         let res = simple.value(wei!(12 eth)).add(b)?;
-        balances.sub(a, res.gas() + wei!(12 eth))?;
-        balances.add(simple.address, wei!(12 eth))?;
+        balances.apply(&res)?;
         states.add(b, wei!(12 eth))?;
 
         balances.verify()?;
@@ -214,6 +221,7 @@ fn main() -> Result<()> {
         }
 
         impl<'a> LedgerState for State<'a> {
+            type Key = Address;
             type Entry = U256;
 
             fn new_instance(&self) -> Self::Entry {
@@ -238,11 +246,15 @@ fn main() -> Result<()> {
     });
 
     let reporter = StdoutReporter::new()?;
-    runner.run(&reporter)?;
+    let summary = runner.run(&reporter)?;
     reporter.close()?;
 
-    let (count, total) = evm.get()?.calculate_visited()?;
-    println!("Contract Coverage: {}%", count * 100 / total);
+    let coverage = evm.get()?.coverage_report()?;
+    print!("Contract Coverage:\n{}", coverage);
+
+    if !summary.is_ok() {
+        bail!("{} test(s) failed", summary.failed);
+    }
 
     Ok(())
 }
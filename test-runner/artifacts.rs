@@ -0,0 +1,150 @@
+//! Artifacts recorded while a test is running, which are written to disk if it fails so that
+//! failures from CI can be debugged offline.
+//!
+//! Artifacts are only collected when the runner is configured with an artifacts directory, either
+//! through `TestRunner::artifacts_dir` or the `--artifacts=<dir>` option. Every failing test gets
+//! its own directory containing:
+//!
+//! * `failure.txt`, with the reason the test failed, including any failing proptest input.
+//! * `output.txt`, with the output captured while the test was running, if any.
+//! * Every artifact recorded by the test, like the trace of the last transaction, the storage it
+//!   wrote and the logs it emitted.
+
+use failure::Error;
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+thread_local! {
+    static COLLECTED: RefCell<Option<Vec<(String, String)>>> = const { RefCell::new(None) };
+}
+
+/// Check if artifacts are being collected on the current thread.
+///
+/// Use this to avoid building artifacts which would be discarded anyway.
+pub fn is_collecting() -> bool {
+    COLLECTED.with(|collected| collected.borrow().is_some())
+}
+
+/// Record an artifact with the given file name for the test running on the current thread.
+///
+/// An artifact recorded with the same name as an earlier one replaces it. Nothing is recorded if
+/// artifacts are not being collected.
+pub fn record(name: impl Into<String>, contents: impl Into<String>) {
+    COLLECTED.with(|collected| {
+        if let Some(ref mut artifacts) = *collected.borrow_mut() {
+            let name = name.into();
+            let contents = contents.into();
+
+            match artifacts.iter_mut().find(|a| a.0 == name) {
+                Some(artifact) => artifact.1 = contents,
+                None => artifacts.push((name, contents)),
+            }
+        }
+    });
+}
+
+/// Start collecting artifacts on the current thread.
+pub(crate) fn start() {
+    COLLECTED.with(|collected| *collected.borrow_mut() = Some(Vec::new()));
+}
+
+/// Stop collecting artifacts on the current thread, and take what was collected.
+pub(crate) fn finish() -> Vec<(String, String)> {
+    COLLECTED
+        .with(|collected| collected.borrow_mut().take())
+        .unwrap_or_default()
+}
+
+/// Write the artifacts of a failed test to its own directory under `dir`.
+///
+/// Returns the path of the directory that was written.
+pub(crate) fn write(
+    dir: &Path,
+    module: Option<&str>,
+    name: &str,
+    failure: &str,
+    output: Option<&str>,
+    artifacts: &[(String, String)],
+) -> Result<PathBuf, Error> {
+    let dir = dir.join(directory_name(module, name));
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| format_err!("failed to create directory: {}: {}", dir.display(), e))?;
+
+    let failure = ("failure.txt", failure);
+    let output = output.map(|output| ("output.txt", output));
+    let artifacts = artifacts.iter().map(|a| (a.0.as_str(), a.1.as_str()));
+
+    for (file, contents) in Some(failure).into_iter().chain(output).chain(artifacts) {
+        let path = dir.join(file_name(file));
+
+        fs::File::create(&path)
+            .and_then(|mut f| f.write_all(contents.as_bytes()))
+            .map_err(|e| format_err!("failed to write artifact: {}: {}", path.display(), e))?;
+    }
+
+    Ok(dir)
+}
+
+/// Build the name of the directory for a test, which only contains characters that are safe to use
+/// in paths.
+fn directory_name(module: Option<&str>, name: &str) -> String {
+    match module {
+        Some(module) => format!("{}__{}", file_name(module), file_name(name)),
+        None => file_name(name),
+    }
+}
+
+/// Replace every character which isn't safe to use in a file name.
+///
+/// Leading dots are replaced so that the name can't refer to a parent directory.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' => c,
+            '.' if i > 0 => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{directory_name, finish, is_collecting, record, start};
+
+    #[test]
+    fn test_record() {
+        record("ignored.json", "{}");
+        assert!(!is_collecting());
+
+        start();
+        assert!(is_collecting());
+        record("trace.json", "[]");
+        record("logs.json", "[]");
+        record("trace.json", "[{}]");
+
+        assert_eq!(
+            vec![
+                (String::from("trace.json"), String::from("[{}]")),
+                (String::from("logs.json"), String::from("[]")),
+            ],
+            finish()
+        );
+
+        assert!(!is_collecting());
+        assert!(finish().is_empty());
+    }
+
+    #[test]
+    fn test_directory_name() {
+        assert_eq!(
+            "token__transfer_to_self__0x12_",
+            directory_name(Some("token"), "transfer to self (0x12)")
+        );
+        assert_eq!("a_b..c", directory_name(None, "a/b..c"));
+        assert_eq!("_.", directory_name(None, ".."));
+    }
+}
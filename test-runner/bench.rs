@@ -0,0 +1,260 @@
+//! Benchmarks, which run the same entry many times and report statistics about how long it took
+//! and how much gas it used.
+//!
+//! ```rust,ignore
+//! runner.bench_with("transfer", 100, &evm, |evm| {
+//!     let contract = token::contract(evm, token, call.clone());
+//!     Ok(contract.transfer(to, 100)?.gas_used.as_u64())
+//! });
+//! ```
+
+use failure::Error;
+use std::cell::RefCell;
+use std::fmt;
+use std::time;
+
+thread_local! {
+    static RESULT: RefCell<Option<BenchStats>> = const { RefCell::new(None) };
+}
+
+/// Convert the result of a single benchmark iteration into the amount of gas it used, if any.
+pub trait IntoSample: Send {
+    fn into_sample(self) -> Result<Option<u64>, Error>;
+}
+
+impl IntoSample for () {
+    fn into_sample(self) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+}
+
+impl IntoSample for u64 {
+    fn into_sample(self) -> Result<Option<u64>, Error> {
+        Ok(Some(self))
+    }
+}
+
+impl IntoSample for Result<(), Error> {
+    fn into_sample(self) -> Result<Option<u64>, Error> {
+        self.map(|()| None)
+    }
+}
+
+impl IntoSample for Result<u64, Error> {
+    fn into_sample(self) -> Result<Option<u64>, Error> {
+        self.map(Some)
+    }
+}
+
+/// Statistics over all samples of a benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Statistics<T> {
+    pub mean: T,
+    pub median: T,
+    /// The 95th percentile.
+    pub p95: T,
+}
+
+impl<T> Statistics<T> {
+    fn map<U>(self, f: impl Fn(T) -> U) -> Statistics<U> {
+        Statistics {
+            mean: f(self.mean),
+            median: f(self.median),
+            p95: f(self.p95),
+        }
+    }
+}
+
+/// The result of running a benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchStats {
+    /// The number of iterations the benchmark ran for.
+    pub iterations: usize,
+    /// Time taken by every iteration.
+    pub time: Statistics<time::Duration>,
+    /// Gas used by every iteration, if the benchmark reported it.
+    pub gas: Option<Statistics<u64>>,
+}
+
+impl fmt::Display for BenchStats {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "time: mean {}, median {}, p95 {}",
+            Time(&self.time.mean),
+            Time(&self.time.median),
+            Time(&self.time.p95)
+        )?;
+
+        if let Some(ref gas) = self.gas {
+            write!(
+                fmt,
+                "; gas: mean {}, median {}, p95 {}",
+                gas.mean, gas.median, gas.p95
+            )?;
+        }
+
+        write!(fmt, " ({} iterations)", self.iterations)
+    }
+}
+
+/// Format a duration with a precision suitable for benchmarks.
+struct Time<'a>(&'a time::Duration);
+
+impl<'a> fmt::Display for Time<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let nanos = nanos(self.0);
+
+        if nanos < 1_000 {
+            write!(fmt, "{}ns", nanos)
+        } else if nanos < 1_000_000 {
+            write!(fmt, "{:.1}µs", nanos as f64 / 1_000f64)
+        } else if nanos < 1_000_000_000 {
+            write!(fmt, "{:.1}ms", nanos as f64 / 1_000_000f64)
+        } else {
+            write!(fmt, "{:.2}s", nanos as f64 / 1_000_000_000f64)
+        }
+    }
+}
+
+fn nanos(duration: &time::Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+/// Calculate statistics over the given samples.
+fn statistics(samples: &mut [u64]) -> Statistics<u64> {
+    samples.sort();
+
+    let len = samples.len();
+    let mean = samples.iter().sum::<u64>() / len as u64;
+    let median = samples[(len - 1) / 2];
+    // nearest-rank percentile.
+    let p95 = samples[(len * 95).div_ceil(100) - 1];
+
+    Statistics { mean, median, p95 }
+}
+
+/// Run a benchmark for the given number of iterations.
+///
+/// Every iteration returns the time it took, and the gas it used if any. The statistics are made
+/// available to the test runner through `take`.
+pub(crate) fn run<F>(iterations: usize, mut iteration: F) -> Result<(), Error>
+where
+    F: FnMut() -> Result<(time::Duration, Option<u64>), Error>,
+{
+    if iterations == 0 {
+        bail!("benchmark needs at least one iteration");
+    }
+
+    let mut times = Vec::with_capacity(iterations);
+    let mut gas = Vec::with_capacity(iterations);
+
+    for _ in 0..iterations {
+        let (time, sample) = iteration()?;
+        times.push(nanos(&time));
+        gas.extend(sample);
+    }
+
+    let time = statistics(&mut times).map(|n| {
+        time::Duration::new(n / 1_000_000_000, (n % 1_000_000_000) as u32)
+    });
+
+    let gas = if gas.is_empty() {
+        None
+    } else {
+        Some(statistics(&mut gas))
+    };
+
+    let stats = BenchStats {
+        iterations,
+        time,
+        gas,
+    };
+
+    RESULT.with(move |result| *result.borrow_mut() = Some(stats));
+    Ok(())
+}
+
+/// Take the statistics of the last benchmark which completed on the current thread.
+pub(crate) fn take() -> Option<BenchStats> {
+    RESULT.with(|result| result.borrow_mut().take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{statistics, BenchStats, Statistics};
+    use reporter::CollectingReporter;
+    use std::iter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time;
+    use test_runner::{Suite, TestRunner};
+
+    #[test]
+    fn test_statistics() {
+        let mut samples = (1..=100).rev().collect::<Vec<u64>>();
+
+        assert_eq!(
+            Statistics {
+                mean: 50,
+                median: 50,
+                p95: 95,
+            },
+            statistics(&mut samples)
+        );
+
+        assert_eq!(
+            Statistics {
+                mean: 7,
+                median: 7,
+                p95: 7,
+            },
+            statistics(&mut [7])
+        );
+    }
+
+    #[test]
+    fn test_bench() {
+        let gas = AtomicUsize::new(21000);
+
+        let mut runner = TestRunner::new();
+
+        runner.bench("transfer", 20, || {
+            gas.fetch_add(1, Ordering::SeqCst) as u64
+        });
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let results = reporter.take_results().expect("bad results");
+        assert_eq!(1, results.len());
+
+        let stats = results[0].bench().expect("benchmark statistics");
+        assert_eq!(20, stats.iterations);
+
+        assert_eq!(
+            Some(Statistics {
+                mean: 21009,
+                median: 21009,
+                p95: 21018,
+            }),
+            stats.gas
+        );
+
+        let stats = BenchStats {
+            iterations: 2,
+            time: Statistics {
+                mean: time::Duration::from_millis(2),
+                median: time::Duration::new(0, 1_500),
+                p95: time::Duration::new(2, 0),
+            },
+            gas: None,
+        };
+
+        assert_eq!(
+            "time: mean 2.0ms, median 1.5µs, p95 2.00s (2 iterations)",
+            stats.to_string()
+        );
+    }
+}
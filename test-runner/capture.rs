@@ -0,0 +1,324 @@
+//! Capturing of output produced by tests, so that it can be shown only for tests that fail.
+//!
+//! Output written with `test_println!` is captured for the test running on the current thread.
+//! Log records are captured the same way by installing a `CaptureLogger`, which wraps the logger
+//! that would otherwise be used:
+//!
+//! ```rust,ignore
+//! let logger = env_logger::Builder::from_default_env().build();
+//! let max_level = logger.filter();
+//! CaptureLogger::new(logger).init(max_level)?;
+//! ```
+//!
+//! Everything else written to stdout and stderr, like with `println!`, is captured by redirecting
+//! the stdout and stderr of the process. Since they are shared by every thread, this is only done
+//! when tests run one at a time, like with `--sequential`.
+//!
+//! Output produced outside of tests is passed through as-is.
+
+use failure::Error;
+use log;
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+use std::io;
+
+thread_local! {
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+    static STDIO: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Write output, which is captured if a test is running on the current thread, or printed to
+/// stdout otherwise.
+pub fn write(args: fmt::Arguments) {
+    // while stdout is captured, writing to it preserves the order with everything else written to
+    // it.
+    let stdio = STDIO.with(|stdio| stdio.get());
+
+    let args = CAPTURED.with(|captured| match *captured.borrow_mut() {
+        Some(ref mut output) if !stdio => {
+            let _ = output.write_fmt(args);
+            None
+        }
+        _ => Some(args),
+    });
+
+    if let Some(args) = args {
+        if stdio {
+            let _ = io::Write::write_fmt(&mut io::stdout(), args);
+        } else {
+            print!("{}", args);
+        }
+    }
+}
+
+/// Check if output is being captured on the current thread.
+pub fn is_capturing() -> bool {
+    CAPTURED.with(|captured| captured.borrow().is_some())
+}
+
+/// Start capturing output on the current thread.
+pub(crate) fn start() {
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(String::new()));
+}
+
+/// Stop capturing output on the current thread, and take what was captured.
+pub(crate) fn finish() -> Option<String> {
+    CAPTURED
+        .with(|captured| captured.borrow_mut().take())
+        .and_then(|output| if output.is_empty() { None } else { Some(output) })
+}
+
+/// Guard which captures everything written to stdout and stderr, see `start_stdio`.
+pub(crate) struct StdioCapture {
+    redirect: stdio::Redirect,
+}
+
+impl StdioCapture {
+    /// Stop capturing stdout and stderr, and add what was written to them to the output captured
+    /// on the current thread.
+    pub(crate) fn finish(self) {
+        STDIO.with(|stdio| stdio.set(false));
+
+        let output = match self.redirect.finish() {
+            Ok(output) => output,
+            Err(e) => format!("error in capturing stdout and stderr: {}\n", e),
+        };
+
+        CAPTURED.with(|captured| {
+            if let Some(ref mut captured) = *captured.borrow_mut() {
+                captured.push_str(&output);
+            }
+        });
+    }
+}
+
+/// Start capturing everything written to stdout and stderr, in addition to the output captured on
+/// the current thread.
+///
+/// This redirects stdout and stderr of the whole process, so it must only be used while a single
+/// test is running. Returns `None` if they couldn't be redirected, in which case they are passed
+/// through as-is.
+pub(crate) fn start_stdio() -> Option<StdioCapture> {
+    match stdio::Redirect::start() {
+        Ok(redirect) => {
+            STDIO.with(|stdio| stdio.set(true));
+            Some(StdioCapture { redirect })
+        }
+        Err(e) => {
+            eprintln!("error in capturing stdout and stderr: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+mod stdio {
+    use libc;
+    use std::env;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Stdout and stderr of the process, redirected to a temporary file.
+    pub struct Redirect {
+        file: File,
+        /// Redirected descriptors, and copies of what they originally referred to.
+        saved: Vec<(libc::c_int, libc::c_int)>,
+    }
+
+    impl Redirect {
+        pub fn start() -> io::Result<Redirect> {
+            let path = env::temp_dir().join(format!(
+                "parables-capture-{}-{}",
+                process::id(),
+                NEXT.fetch_add(1, Ordering::SeqCst)
+            ));
+
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+
+            // the file is only accessed through its descriptor from now on.
+            fs::remove_file(&path)?;
+
+            flush();
+
+            let mut redirect = Redirect {
+                file,
+                saved: Vec::new(),
+            };
+
+            for &fd in &[libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                let saved = unsafe { libc::dup(fd) };
+
+                if saved < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                redirect.saved.push((fd, saved));
+
+                if unsafe { libc::dup2(redirect.file.as_raw_fd(), fd) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(redirect)
+        }
+
+        /// Restore stdout and stderr, and read everything that was written to them.
+        pub fn finish(mut self) -> io::Result<String> {
+            self.restore();
+
+            let mut output = Vec::new();
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.read_to_end(&mut output)?;
+            Ok(String::from_utf8_lossy(&output).into_owned())
+        }
+
+        fn restore(&mut self) {
+            flush();
+
+            for (fd, saved) in self.saved.drain(..) {
+                unsafe {
+                    libc::dup2(saved, fd);
+                    libc::close(saved);
+                }
+            }
+        }
+    }
+
+    impl Drop for Redirect {
+        fn drop(&mut self) {
+            self.restore();
+        }
+    }
+
+    /// Flush output buffered by the standard library, so that it ends up where it was written.
+    fn flush() {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+    }
+}
+
+#[cfg(not(unix))]
+mod stdio {
+    use std::io;
+
+    /// Stdout and stderr can only be redirected on unix.
+    pub struct Redirect(());
+
+    impl Redirect {
+        pub fn start() -> io::Result<Redirect> {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "not supported on this platform",
+            ))
+        }
+
+        pub fn finish(self) -> io::Result<String> {
+            Ok(String::new())
+        }
+    }
+}
+
+/// A logger which captures records logged while a test is running, and passes every other record
+/// to the wrapped logger.
+pub struct CaptureLogger<L> {
+    inner: L,
+}
+
+impl<L> CaptureLogger<L>
+where
+    L: 'static + log::Log,
+{
+    /// Capture records which are enabled by the given logger.
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+
+    /// Install this as the global logger, with the given maximum level.
+    ///
+    /// The maximum level should match the one of the wrapped logger, since records above it are
+    /// never passed to the logger.
+    pub fn init(self, max_level: log::LevelFilter) -> Result<(), Error> {
+        log::set_boxed_logger(Box::new(self))
+            .map_err(|e| format_err!("failed to set logger: {}", e))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl<L> log::Log for CaptureLogger<L>
+where
+    L: log::Log,
+{
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.inner.enabled(record.metadata()) {
+            return;
+        }
+
+        if is_capturing() {
+            write(format_args!(
+                "{:<5} {}: {}\n",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+            return;
+        }
+
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{finish, is_capturing, start, start_stdio, write};
+
+    #[test]
+    fn test_capture() {
+        assert!(!is_capturing());
+
+        start();
+        write(format_args!("hello {}\n", "world"));
+        assert!(is_capturing());
+        assert_eq!(Some(String::from("hello world\n")), finish());
+
+        assert!(!is_capturing());
+
+        start();
+        assert_eq!(None, finish());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_stdio() {
+        use std::io::{self, Write};
+
+        start();
+
+        let stdio = start_stdio().expect("failed to capture stdio");
+        writeln!(io::stdout(), "to stdout").expect("failed to write");
+        writeln!(io::stderr(), "to stderr").expect("failed to write");
+        stdio.finish();
+
+        // other tests might write to stdout at the same time.
+        let output = finish().expect("no output");
+        assert!(output.contains("to stdout\n"));
+        assert!(output.contains("to stderr\n"));
+    }
+}
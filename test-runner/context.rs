@@ -0,0 +1,98 @@
+//! Ambient context for the test that is currently running.
+//!
+//! The test runner enters the context of a test on the thread which runs it, which makes the name
+//! of the test available to anything running on the same thread, like the virtual machine.
+//!
+//! The virtual machine in turn records the gas used by every transaction it runs, which is
+//! attributed to the running test.
+
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static CURRENT_TEST: RefCell<Option<String>> = const { RefCell::new(None) };
+    static GAS_USED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Access the name of the test running on the current thread, if any.
+pub fn current_test() -> Option<String> {
+    CURRENT_TEST.with(|current| current.borrow().clone())
+}
+
+/// Guard that restores the previous test context when dropped.
+pub struct TestContext {
+    previous: Option<String>,
+}
+
+impl Drop for TestContext {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_TEST.with(move |current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Enter the context of the test with the given name on the current thread.
+pub fn enter(name: impl Into<String>) -> TestContext {
+    let name = name.into();
+    let previous = CURRENT_TEST.with(move |current| current.replace(Some(name)));
+    TestContext { previous }
+}
+
+/// Record gas used by a transaction, which is attributed to the test running on the current
+/// thread.
+///
+/// Gas used outside of tests is not recorded.
+pub fn record_gas_used(gas: u64) {
+    GAS_USED.with(|used| {
+        if let Some(total) = used.get() {
+            used.set(Some(total.saturating_add(gas)));
+        }
+    });
+}
+
+/// Start accounting for gas used on the current thread.
+pub(crate) fn start_gas_accounting() {
+    GAS_USED.with(|used| used.set(Some(0)));
+}
+
+/// Stop accounting for gas used on the current thread, and take the total used.
+pub(crate) fn finish_gas_accounting() -> Option<u64> {
+    GAS_USED.with(|used| used.replace(None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        current_test, enter, finish_gas_accounting, record_gas_used, start_gas_accounting,
+    };
+
+    #[test]
+    fn test_context() {
+        assert_eq!(None, current_test());
+
+        {
+            let _outer = enter("outer");
+            assert_eq!(Some("outer".to_string()), current_test());
+
+            {
+                let _inner = enter("inner");
+                assert_eq!(Some("inner".to_string()), current_test());
+            }
+
+            assert_eq!(Some("outer".to_string()), current_test());
+        }
+
+        assert_eq!(None, current_test());
+    }
+
+    #[test]
+    fn test_gas_accounting() {
+        record_gas_used(100);
+        assert_eq!(None, finish_gas_accounting());
+
+        start_gas_accounting();
+        record_gas_used(21000);
+        record_gas_used(5000);
+        assert_eq!(Some(26000), finish_gas_accounting());
+        assert_eq!(None, finish_gas_accounting());
+    }
+}
@@ -0,0 +1,78 @@
+//! Filters used to select which tests to run.
+
+use regex::Regex;
+
+/// A single filter, matching either the name or the module of a test.
+///
+/// Filters are regular expressions, but also match if they are a part of the name of a test, so
+/// that names containing special characters can be used as-is. Filters prefixed with `!` exclude
+/// the tests they match.
+#[derive(Debug)]
+pub(crate) struct Filter {
+    pattern: String,
+    regex: Option<Regex>,
+    exclude: bool,
+}
+
+impl Filter {
+    /// Parse a filter.
+    pub fn parse(filter: &str) -> Filter {
+        let (pattern, exclude) = match filter.strip_prefix('!') {
+            Some(pattern) => (pattern, true),
+            None => (filter, false),
+        };
+
+        Filter {
+            pattern: pattern.to_string(),
+            regex: Regex::new(pattern).ok(),
+            exclude,
+        }
+    }
+
+    /// Check if the filter matches a test with the given module and name.
+    fn matches(&self, module: Option<&str>, name: &str) -> bool {
+        if name.contains(self.pattern.as_str()) || module == Some(self.pattern.as_str()) {
+            return true;
+        }
+
+        if let Some(ref regex) = self.regex {
+            return regex.is_match(name) || module.map(|m| regex.is_match(m)).unwrap_or(false);
+        }
+
+        false
+    }
+}
+
+/// Check if a test with the given module and name is selected by all filters.
+///
+/// A test is selected if it matches all filters which include tests, and none of the filters
+/// which exclude them.
+pub(crate) fn is_selected(filters: &[Filter], module: Option<&str>, name: &str) -> bool {
+    filters
+        .iter()
+        .all(|f| f.matches(module, name) != f.exclude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_selected, Filter};
+
+    #[test]
+    fn test_filters() {
+        let filters = vec![Filter::parse("ledger.*"), Filter::parse("!slow")];
+
+        assert!(is_selected(&filters, None, "ledger balances"));
+        assert!(is_selected(&filters, Some("ledger"), "transfer"));
+        assert!(!is_selected(&filters, None, "ledger balances slow"));
+        assert!(!is_selected(&filters, Some("slow"), "ledger balances"));
+        assert!(!is_selected(&filters, None, "token transfer"));
+
+        // invalid regular expressions are only matched as a part of the name.
+        let filters = vec![Filter::parse("transfer (1")];
+
+        assert!(is_selected(&filters, None, "transfer (10)"));
+        assert!(!is_selected(&filters, None, "transfer 1"));
+
+        assert!(is_selected(&[], None, "anything"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Fixtures set up the value that a test runs against, and tear it down once the test is done.
+//!
+//! ```rust,ignore
+//! let evm = Snapshot::new(evm);
+//!
+//! let mut token = runner.module("token");
+//! let mut token = token.with_fixture(&evm);
+//!
+//! token.test("transfer", |evm| {
+//!     // `evm` is a fresh snapshot for every test.
+//!     Ok(())
+//! });
+//! ```
+
+use failure::Error;
+use snapshot::{Revert, Snapshot, SnapshotPool};
+use std::borrow::Cow;
+use std::panic;
+use test_runner::{IntoResult, Suite};
+
+/// Something which sets up a value for every test using it, and tears it down afterwards.
+pub trait Fixture: Sync {
+    /// The value which is passed to tests.
+    type Value;
+
+    /// Set up the value for a single test.
+    fn setup(&self) -> Result<Self::Value, Error>;
+
+    /// Tear down the value after the test is done, regardless of if it passed.
+    fn teardown(&self, _value: Self::Value) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A function used as a fixture, which doesn't need any teardown.
+impl<F, V> Fixture for F
+where
+    F: Fn() -> Result<V, Error> + Sync,
+{
+    type Value = V;
+
+    fn setup(&self) -> Result<V, Error> {
+        (self)()
+    }
+}
+
+/// Every test gets its own clone of the snapshot.
+impl<T> Fixture for Snapshot<T>
+where
+    T: Clone + Send,
+{
+    type Value = T;
+
+    fn setup(&self) -> Result<T, Error> {
+        self.get()
+    }
+}
+
+/// Every test checks out a value from the pool, which is reverted and returned to the pool once
+/// the test is done.
+impl<T> Fixture for SnapshotPool<T>
+where
+    T: Clone + Revert + Send,
+{
+    type Value = T;
+
+    fn setup(&self) -> Result<T, Error> {
+        self.take()
+    }
+
+    fn teardown(&self, value: T) -> Result<(), Error> {
+        self.put(value)
+    }
+}
+
+/// Run the given entry with a value set up by the fixture, and tear it down afterwards.
+///
+/// Panics in the entry are propagated after the value has been torn down.
+pub(crate) fn run<X, F, T>(fixture: &X, entry: F) -> Result<(), Error>
+where
+    X: ?Sized + Fixture,
+    F: Fn(&mut X::Value) -> T,
+    T: IntoResult<()>,
+{
+    let mut value = fixture
+        .setup()
+        .map_err(|e| format_err!("fixture setup failed: {}", e))?;
+
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| entry(&mut value).into_result()));
+
+    let teardown = fixture
+        .teardown(value)
+        .map_err(|e| format_err!("fixture teardown failed: {}", e));
+
+    match res {
+        Err(payload) => panic::resume_unwind(payload),
+        Ok(Err(e)) => Err(e),
+        Ok(Ok(())) => teardown,
+    }
+}
+
+/// A suite where every test registered is passed a value from the same fixture.
+pub struct FixtureSuite<'s, 'a, S: 's, X: 'a> {
+    suite: &'s mut S,
+    fixture: &'a X,
+}
+
+impl<'s, 'a, S: 's, X: 'a> FixtureSuite<'s, 'a, S, X>
+where
+    S: Suite<'a>,
+    X: Fixture,
+{
+    pub(crate) fn new(suite: &'s mut S, fixture: &'a X) -> Self {
+        Self { suite, fixture }
+    }
+
+    /// Register a single test, with a human-readable `name`, which is passed the value of the
+    /// fixture.
+    pub fn test<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
+    where
+        F: 'a + Fn(&mut X::Value) -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.suite.test_with(name, self.fixture, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fixture;
+    use failure::Error;
+    use reporter::CollectingReporter;
+    use std::collections::HashMap;
+    use std::iter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use test_runner::{Outcome, Suite, TestRunner};
+
+    struct Counting {
+        setup: AtomicUsize,
+        teardown: AtomicUsize,
+    }
+
+    impl Fixture for Counting {
+        type Value = u32;
+
+        fn setup(&self) -> Result<u32, Error> {
+            self.setup.fetch_add(1, Ordering::SeqCst);
+            Ok(42)
+        }
+
+        fn teardown(&self, value: u32) -> Result<(), Error> {
+            assert_eq!(43, value);
+            self.teardown.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fixture() {
+        let fixture = Counting {
+            setup: AtomicUsize::new(0),
+            teardown: AtomicUsize::new(0),
+        };
+
+        let mut runner = TestRunner::new();
+
+        {
+            let mut module = runner.module("counting");
+            let mut module = module.with_fixture(&fixture);
+
+            module.test("passes", |value| {
+                *value += 1;
+            });
+
+            module.test("fails", fails);
+        }
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let result = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name.to_string(), result))
+            .collect::<HashMap<_, _>>();
+
+        match result.get("passes").map(|r| &r.outcome) {
+            Some(&Outcome::Ok) => {}
+            _ => panic!("expected `passes` to pass"),
+        }
+
+        match result.get("fails").map(|r| &r.outcome) {
+            Some(Outcome::Failed(info)) => {
+                assert_eq!(Some("broken"), info.message.as_deref());
+            }
+            _ => panic!("expected `fails` to fail"),
+        }
+
+        assert_eq!(2, fixture.setup.load(Ordering::SeqCst));
+        assert_eq!(2, fixture.teardown.load(Ordering::SeqCst));
+
+        fn fails(value: &mut u32) {
+            *value += 1;
+            panic!("broken");
+        }
+    }
+}
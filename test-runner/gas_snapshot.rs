@@ -0,0 +1,218 @@
+//! Gas snapshots, used to detect gas regressions between runs.
+//!
+//! A snapshot file contains one line per named measurement, like:
+//!
+//! ```text
+//! simple contract::set value (gas: 26922)
+//! ```
+//!
+//! In record mode, measurements are collected and written to the file when the snapshot is
+//! finished. In check mode, every measurement is compared against the file and an error is
+//! raised if gas usage increased beyond the configured threshold.
+//!
+//! The mode can be forced to record by setting the `PARABLES_GAS_SNAPSHOT=record` environment
+//! variable.
+
+use failure::Error;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The mode a snapshot is operating in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Record all measurements, and write them to the snapshot file when finished.
+    Record,
+    /// Check all measurements against the existing snapshot file.
+    Check,
+}
+
+/// A collection of named gas measurements.
+#[derive(Debug)]
+pub struct GasSnapshot {
+    path: PathBuf,
+    mode: Mode,
+    /// Permitted regression, in percent.
+    threshold: u64,
+    /// Gas from the existing snapshot file.
+    expected: BTreeMap<String, u64>,
+    /// Gas recorded during this run.
+    recorded: Mutex<BTreeMap<String, u64>>,
+}
+
+impl GasSnapshot {
+    /// Open the snapshot at the given path.
+    ///
+    /// If the file does not exist, or `PARABLES_GAS_SNAPSHOT=record` is set, the snapshot will be
+    /// opened in record mode. Otherwise it will be opened in check mode.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let record = env::var("PARABLES_GAS_SNAPSHOT")
+            .map(|v| v == "record")
+            .unwrap_or(false);
+
+        let expected = match fs::read_to_string(path) {
+            Ok(content) => parse(&content)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::new(path, Mode::Record, BTreeMap::new()));
+            }
+            Err(e) => bail!("{}: failed to read gas snapshot: {}", path.display(), e),
+        };
+
+        let mode = if record { Mode::Record } else { Mode::Check };
+        Ok(Self::new(path, mode, expected))
+    }
+
+    fn new(path: &Path, mode: Mode, expected: BTreeMap<String, u64>) -> Self {
+        Self {
+            path: path.to_owned(),
+            mode,
+            threshold: 0,
+            expected,
+            recorded: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Set the threshold, in percent, that gas is permitted to regress with before failing.
+    pub fn threshold(self, threshold: u64) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// Access the mode of the snapshot.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Record the gas used for the given name.
+    ///
+    /// In check mode, this fails if gas has regressed beyond the configured threshold.
+    pub fn gas(&self, name: impl AsRef<str>, gas: impl Into<u64>) -> Result<(), Error> {
+        let name = name.as_ref();
+        let gas = gas.into();
+
+        {
+            let mut recorded = self
+                .recorded
+                .lock()
+                .map_err(|_| format_err!("lock poisoned"))?;
+
+            recorded.insert(name.to_string(), gas);
+        }
+
+        if self.mode == Mode::Record {
+            return Ok(());
+        }
+
+        let expected = match self.expected.get(name) {
+            Some(expected) => *expected,
+            // new measurements can't regress.
+            None => return Ok(()),
+        };
+
+        let limit = expected + expected * self.threshold / 100;
+
+        if gas > limit {
+            bail!(
+                "{}: gas regressed from {} to {} (+{}), threshold is {}%",
+                name,
+                expected,
+                gas,
+                gas - expected,
+                self.threshold
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Finish the snapshot.
+    ///
+    /// In record mode, this writes all recorded measurements to the snapshot file.
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.mode != Mode::Record {
+            return Ok(());
+        }
+
+        let recorded = self
+            .recorded
+            .lock()
+            .map_err(|_| format_err!("lock poisoned"))?;
+
+        let mut f = fs::File::create(&self.path)
+            .map_err(|e| format_err!("{}: failed to create: {}", self.path.display(), e))?;
+
+        f.write_all(format(&recorded).as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Parse the content of a snapshot file.
+fn parse(content: &str) -> Result<BTreeMap<String, u64>, Error> {
+    let mut out = BTreeMap::new();
+
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let sep = line
+            .rfind(" (gas: ")
+            .ok_or_else(|| format_err!("bad gas snapshot line #{}: {}", n + 1, line))?;
+
+        let (name, rest) = line.split_at(sep);
+
+        let gas = rest[" (gas: ".len()..]
+            .trim_right_matches(')')
+            .parse::<u64>()
+            .map_err(|e| format_err!("bad gas in snapshot line #{}: {}", n + 1, e))?;
+
+        out.insert(name.to_string(), gas);
+    }
+
+    Ok(out)
+}
+
+/// Format recorded measurements.
+fn format(recorded: &BTreeMap<String, u64>) -> String {
+    let mut out = String::new();
+
+    for (name, gas) in recorded {
+        out.push_str(&format!("{} (gas: {})\n", name, gas));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, parse, GasSnapshot, Mode};
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_format() {
+        let mut recorded = BTreeMap::new();
+        recorded.insert("simple contract::set value".to_string(), 26922u64);
+        recorded.insert("transfer".to_string(), 21000u64);
+
+        assert_eq!(recorded, parse(&format(&recorded)).expect("bad parse"));
+    }
+
+    #[test]
+    fn test_regression() {
+        let mut expected = BTreeMap::new();
+        expected.insert("transfer".to_string(), 1000u64);
+
+        let snapshot = GasSnapshot::new(Path::new("unused"), Mode::Check, expected).threshold(10);
+
+        assert!(snapshot.gas("transfer", 1100u64).is_ok());
+        assert!(snapshot.gas("transfer", 1101u64).is_err());
+        assert!(snapshot.gas("new", 1000000u64).is_ok());
+    }
+}
@@ -0,0 +1,281 @@
+//! Stateful invariant testing.
+//!
+//! An invariant test declares a set of _actions_ that can be performed against some state, like
+//! calling functions on a deployed contract, and a set of _invariants_ that must hold after every
+//! action.
+//!
+//! The runner generates random sequences of actions which are applied to fresh state, as produced
+//! by the setup function. When an invariant is violated, proptest shrinks the sequence and the
+//! minimal sequence reproducing the violation is reported.
+//!
+//! ```rust,ignore
+//! let invariants = Invariants::new(|| evm.get())
+//!     .action("set value", any::<u64>(), move |evm, x| {
+//!         simple_contract::contract(evm, simple, call.clone()).set_value(x)?;
+//!         Ok(())
+//!     })
+//!     .invariant("value is never zero", move |evm| {
+//!         let value = simple_contract::contract(evm, simple, call.clone()).get_value()?.ok()?;
+//!         assert!(!value.is_zero(), "value is zero");
+//!         Ok(())
+//!     });
+//!
+//! runner.test("simple contract invariants", || invariants.run());
+//! ```
+
+use artifacts;
+use failure::Error;
+use proptest::collection;
+use proptest::strategy::{BoxedStrategy, Strategy, Union};
+use proptest::test_runner::{Config, TestCaseError, TestError, TestRunner};
+use std::fmt;
+use std::sync::Arc;
+
+/// A single action that has been generated, and which can be applied to the state.
+pub struct Step<S> {
+    /// Name of the action.
+    name: Arc<String>,
+    /// Description of the generated input.
+    input: String,
+    /// Apply the action to the state.
+    apply: Arc<Fn(&S) -> Result<(), Error> + Send + Sync>,
+}
+
+impl<S> Clone for Step<S> {
+    fn clone(&self) -> Self {
+        Self {
+            name: Arc::clone(&self.name),
+            input: self.input.clone(),
+            apply: Arc::clone(&self.apply),
+        }
+    }
+}
+
+impl<S> fmt::Debug for Step<S> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}({})", self.name, self.input)
+    }
+}
+
+/// Factory for the strategy of a single action.
+type ActionFactory<S> = Box<Fn() -> BoxedStrategy<Step<S>> + Send + Sync>;
+
+/// A single invariant.
+struct Invariant<'a, S> {
+    name: String,
+    check: Box<'a + Fn(&S) -> Result<(), Error> + Send + Sync>,
+}
+
+/// A set of actions and invariants, used to test state by applying random sequences of actions.
+pub struct Invariants<'a, S> {
+    /// Set up fresh state to test against.
+    setup: Box<'a + Fn() -> Result<S, Error> + Send + Sync>,
+    actions: Vec<ActionFactory<S>>,
+    invariants: Vec<Invariant<'a, S>>,
+    /// Maximum number of actions in a sequence.
+    depth: usize,
+    /// Number of sequences to test.
+    cases: u32,
+}
+
+impl<'a, S: 'static> Invariants<'a, S> {
+    /// Build a new set of invariants, using the given function to set up the state to test.
+    ///
+    /// The setup function is called once for every generated sequence, so it typically clones a
+    /// snapshot.
+    pub fn new<F>(setup: F) -> Self
+    where
+        F: 'a + Fn() -> Result<S, Error> + Send + Sync,
+    {
+        Self {
+            setup: Box::new(setup),
+            actions: Vec::new(),
+            invariants: Vec::new(),
+            depth: 16,
+            cases: Config::default().cases,
+        }
+    }
+
+    /// Set the maximum number of actions in a single sequence.
+    pub fn depth(self, depth: usize) -> Self {
+        Self { depth, ..self }
+    }
+
+    /// Set the number of sequences to test.
+    pub fn cases(self, cases: u32) -> Self {
+        Self { cases, ..self }
+    }
+
+    /// Register an action, with inputs generated by the given strategy.
+    ///
+    /// If the action returns an error, the sequence is considered failed.
+    pub fn action<N, T, F>(mut self, name: N, strategy: T, action: F) -> Self
+    where
+        N: Into<String>,
+        T: 'static + Strategy + Clone + Send + Sync,
+        T::Value: 'static + Clone + Send + Sync,
+        F: 'static + Fn(&S, T::Value) -> Result<(), Error> + Send + Sync,
+    {
+        let name = Arc::new(name.into());
+        let action = Arc::new(action);
+
+        self.actions.push(Box::new(move || {
+            let name = Arc::clone(&name);
+            let action = Arc::clone(&action);
+
+            strategy
+                .clone()
+                .prop_map(move |value| {
+                    let action = Arc::clone(&action);
+                    let input = format!("{:?}", value);
+
+                    Step {
+                        name: Arc::clone(&name),
+                        input,
+                        apply: Arc::new(move |state: &S| action(state, value.clone())),
+                    }
+                })
+                .boxed()
+        }));
+
+        self
+    }
+
+    /// Register an invariant, which must hold after every action.
+    pub fn invariant<N, F>(mut self, name: N, check: F) -> Self
+    where
+        N: Into<String>,
+        F: 'a + Fn(&S) -> Result<(), Error> + Send + Sync,
+    {
+        self.invariants.push(Invariant {
+            name: name.into(),
+            check: Box::new(check),
+        });
+
+        self
+    }
+
+    /// Run random sequences of actions, checking all invariants after each action.
+    ///
+    /// On failure, the error contains the minimal sequence of actions that reproduces it.
+    pub fn run(&self) -> Result<(), Error> {
+        if self.actions.is_empty() {
+            bail!("no actions registered");
+        }
+
+        let actions = Union::new(self.actions.iter().map(|f| f()));
+        let sequences = collection::vec(actions, 1..self.depth + 1);
+
+        let mut runner = TestRunner::new(Config {
+            cases: self.cases,
+            ..Config::default()
+        });
+
+        let result = runner.run(&sequences, |steps| {
+            self.run_sequence(&steps)
+                .map_err(|e| TestCaseError::fail(e.to_string()))
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(TestError::Fail(reason, steps)) => {
+                let mut sequence = String::new();
+
+                for (i, step) in steps.iter().enumerate() {
+                    sequence.push_str(&format!("\n  {}: {:?}", i + 1, step));
+                }
+
+                artifacts::record("input.txt", format!("{}\n", sequence.trim_start()));
+                bail!("{}\nminimal failing sequence:{}", reason, sequence)
+            }
+            Err(TestError::Abort(reason)) => bail!("invariant testing aborted: {}", reason),
+        }
+    }
+
+    /// Apply a single sequence of steps to fresh state.
+    fn run_sequence(&self, steps: &[Step<S>]) -> Result<(), Error> {
+        let state = (self.setup)()?;
+
+        for (i, step) in steps.iter().enumerate() {
+            (step.apply)(&state)
+                .map_err(|e| format_err!("action #{} {:?} failed: {}", i + 1, step, e))?;
+
+            for invariant in &self.invariants {
+                (invariant.check)(&state).map_err(|e| {
+                    format_err!(
+                        "invariant `{}` violated after action #{} {:?}: {}",
+                        invariant.name,
+                        i + 1,
+                        step,
+                        e
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Invariants;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_invariant_holds() {
+        let invariants = Invariants::new(|| Ok(Mutex::new(0u64)))
+            .cases(32)
+            .action("add twice", 0u64..10, |state, x| {
+                *state.lock().unwrap() += x * 2;
+                Ok(())
+            })
+            .invariant("value is even", |state| {
+                let value = *state.lock().unwrap();
+                ensure!(value % 2 == 0, "value is {}", value);
+                Ok(())
+            });
+
+        invariants.run().expect("invariant to hold");
+    }
+
+    #[test]
+    fn test_invariant_breaks() {
+        let invariants = Invariants::new(|| Ok(Mutex::new(0u64)))
+            .action("set", 0u64..100, |state, x| {
+                *state.lock().unwrap() = x;
+                Ok(())
+            })
+            .invariant("value is below 5", |state| {
+                let value = *state.lock().unwrap();
+                ensure!(value < 5, "value is {}", value);
+                Ok(())
+            });
+
+        let error = invariants.run().expect_err("invariant to break");
+
+        assert!(
+            error
+                .to_string()
+                .ends_with("minimal failing sequence:\n  1: set(5)"),
+            "unexpected error: {}",
+            error
+        );
+
+        assert!(
+            error
+                .to_string()
+                .contains("invariant `value is below 5` violated after action #1 set(5)"),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_no_actions() {
+        let invariants = Invariants::new(|| Ok(0u64));
+
+        let error = invariants.run().expect_err("run to fail");
+        assert_eq!("no actions registered", error.to_string());
+    }
+}
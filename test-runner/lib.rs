@@ -2,11 +2,23 @@
 #[macro_use]
 pub extern crate proptest;
 extern crate isatty;
+#[cfg(unix)]
+extern crate libc;
+extern crate log;
 extern crate rayon;
+extern crate regex;
 extern crate term;
 #[macro_use]
 extern crate failure;
 
+pub mod artifacts;
+pub mod bench;
+pub mod capture;
+pub mod context;
+mod filter;
+pub mod fixture;
+pub mod gas_snapshot;
+pub mod invariant;
 pub mod reporter;
 pub mod snapshot;
 pub mod test_runner;
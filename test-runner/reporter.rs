@@ -1,27 +1,25 @@
 use failure::Error;
 use isatty;
+use std::cmp;
 use std::collections::BTreeMap;
+use std::env;
 use std::fmt;
-use std::io;
+use std::io::{self, Write};
 use std::sync::Mutex;
+use std::time;
 use term;
 use test_runner::{Location, Outcome, Test, TestResult};
 use utils;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum Step {
+    #[default]
     North,
     NorthEast,
     East,
     SouthEast,
 }
 
-impl Default for Step {
-    fn default() -> Self {
-        Step::North
-    }
-}
-
 impl Step {
     /// Translate to next step.
     fn next(&mut self) {
@@ -52,7 +50,13 @@ pub struct Account {
     passed: u32,
     failed: u32,
     skipped: u32,
+    /// Tests which passed, but only after being retried.
+    flaky: u32,
     running: BTreeMap<usize, String>,
+    /// When the first test was started.
+    started: Option<time::Instant>,
+    /// Names and durations of every test that has completed.
+    durations: Vec<(String, time::Duration)>,
     step: Step,
 }
 
@@ -83,22 +87,252 @@ pub trait Reporter<'a>: Sync {
     /// Report that a number of tests have been skipped.
     fn report_skipped(&self, test: Test<'a>) -> Result<(), Error>;
 
+    /// Report a test when listing tests, instead of running them.
+    fn report_listed(&self, _test: &Test<'a>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Report the seed that tests were shuffled with, before any test is started.
+    fn report_shuffled(&self, _seed: u64) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Close the reporter.
     fn close(&self) -> Result<(), Error>;
 }
 
+/// Reporters can be shared, which makes it possible to access them after they have been used by a
+/// `MultiReporter`.
+impl<'a, R: ?Sized> Reporter<'a> for &R
+where
+    R: Reporter<'a>,
+{
+    fn supports_animation(&self) -> Result<bool, Error> {
+        (**self).supports_animation()
+    }
+
+    fn animate(&self) -> Result<(), Error> {
+        (**self).animate()
+    }
+
+    fn end(&self) -> Result<(), Error> {
+        (**self).end()
+    }
+
+    fn report_started(&self, index: usize, name: &str) -> Result<(), Error> {
+        (**self).report_started(index, name)
+    }
+
+    fn report(&self, index: usize, result: TestResult<'a>) -> Result<(), Error> {
+        (**self).report(index, result)
+    }
+
+    fn report_skipped(&self, test: Test<'a>) -> Result<(), Error> {
+        (**self).report_skipped(test)
+    }
+
+    fn report_listed(&self, test: &Test<'a>) -> Result<(), Error> {
+        (**self).report_listed(test)
+    }
+
+    fn report_shuffled(&self, seed: u64) -> Result<(), Error> {
+        (**self).report_shuffled(seed)
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        (**self).close()
+    }
+}
+
+/// A reporter which passes every event to several other reporters, like printing results for
+/// humans while also writing them as JSON.
+///
+/// ```rust,ignore
+/// let stdout = StdoutReporter::new()?;
+/// let json = JsonReporter::with_output(File::create("results.json")?);
+///
+/// let reporter = MultiReporter::new().with(&stdout).with(&json);
+/// tests.run(&reporter)?;
+/// reporter.close()?;
+/// ```
+///
+/// The first reporter receives every result as-is. The others receive copies, where errors only
+/// retain their message.
+pub struct MultiReporter<'r, 'a> {
+    reporters: Vec<Box<'r + Reporter<'a>>>,
+}
+
+impl<'r, 'a> MultiReporter<'r, 'a> {
+    /// Create a new reporter, without any reporters to pass events to.
+    pub fn new() -> Self {
+        Self {
+            reporters: Vec::new(),
+        }
+    }
+
+    /// Add a reporter to pass events to.
+    pub fn with(mut self, reporter: impl Reporter<'a> + 'r) -> Self {
+        self.reporters.push(Box::new(reporter));
+        self
+    }
+
+    /// Call the given function for every reporter, and return the first error.
+    fn each(&self, f: impl Fn(&Reporter<'a>) -> Result<(), Error>) -> Result<(), Error> {
+        let mut result = Ok(());
+
+        for reporter in &self.reporters {
+            let r = f(&**reporter);
+
+            if result.is_ok() {
+                result = r;
+            }
+        }
+
+        result
+    }
+}
+
+impl<'r, 'a> Default for MultiReporter<'r, 'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'r, 'a> Reporter<'a> for MultiReporter<'r, 'a> {
+    fn supports_animation(&self) -> Result<bool, Error> {
+        for reporter in &self.reporters {
+            if reporter.supports_animation()? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn animate(&self) -> Result<(), Error> {
+        self.each(|r| r.animate())
+    }
+
+    fn end(&self) -> Result<(), Error> {
+        self.each(|r| r.end())
+    }
+
+    fn report_started(&self, index: usize, name: &str) -> Result<(), Error> {
+        self.each(|r| r.report_started(index, name))
+    }
+
+    fn report(&self, index: usize, result: TestResult<'a>) -> Result<(), Error> {
+        let mut reporters = self.reporters.iter();
+
+        let first = match reporters.next() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        let mut errors = Vec::new();
+
+        for reporter in reporters {
+            if let Err(e) = reporter.report(index, result.duplicate()) {
+                errors.push(e);
+            }
+        }
+
+        first.report(index, result)?;
+
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn report_skipped(&self, test: Test<'a>) -> Result<(), Error> {
+        let mut reporters = self.reporters.iter();
+
+        let first = match reporters.next() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        let mut errors = Vec::new();
+
+        for reporter in reporters {
+            if let Err(e) = reporter.report_skipped(test.duplicate()) {
+                errors.push(e);
+            }
+        }
+
+        first.report_skipped(test)?;
+
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn report_listed(&self, test: &Test<'a>) -> Result<(), Error> {
+        self.each(|r| r.report_listed(test))
+    }
+
+    fn report_shuffled(&self, seed: u64) -> Result<(), Error> {
+        self.each(|r| r.report_shuffled(seed))
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        self.each(|r| r.close())
+    }
+}
+
 struct ReporterState {
     out: Terminal,
     account: Account,
 }
 
+/// How much the `StdoutReporter` prints about every test.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only print tests which failed, and the summary.
+    Quiet,
+    /// Print a line for every test, and the output of tests which failed.
+    #[default]
+    Normal,
+    /// Also print the gas used by every test, and the output of tests which passed.
+    Verbose,
+}
+
+impl Verbosity {
+    /// Read the verbosity from the `PARABLES_VERBOSITY` environment variable, which is one of
+    /// `quiet`, `normal`, or `verbose`.
+    pub fn from_env() -> Result<Option<Verbosity>, Error> {
+        let value = match env::var("PARABLES_VERBOSITY") {
+            Ok(value) => value,
+            Err(env::VarError::NotPresent) => return Ok(None),
+            Err(e) => bail!("bad value for `PARABLES_VERBOSITY`: {}", e),
+        };
+
+        let verbosity = match value.as_str() {
+            "quiet" => Verbosity::Quiet,
+            "normal" => Verbosity::Normal,
+            "verbose" => Verbosity::Verbose,
+            other => bail!("bad value for `PARABLES_VERBOSITY`: {}", other),
+        };
+
+        Ok(Some(verbosity))
+    }
+}
+
 /// A components that prints test results.
 pub struct StdoutReporter {
     state: Mutex<ReporterState>,
     report_skipped: bool,
+    /// Number of slowest tests to report when closing.
+    report_slowest: usize,
+    verbosity: Verbosity,
 }
 
 impl StdoutReporter {
+    /// Build a new reporter printing to stdout.
+    ///
+    /// The verbosity is read from the `PARABLES_VERBOSITY` environment variable if it is set.
     pub fn new() -> Result<Self, Error> {
         // make sure terminal is a tty and supports fancy features.
         let out = if isatty::stdout_isatty() {
@@ -121,9 +355,16 @@ impl StdoutReporter {
                 account: Account::default(),
             }),
             report_skipped: false,
+            report_slowest: 0,
+            verbosity: Verbosity::from_env()?.unwrap_or_default(),
         })
     }
 
+    /// Configure how much is printed about every test.
+    pub fn verbosity(self, verbosity: Verbosity) -> Self {
+        Self { verbosity, ..self }
+    }
+
     /// Configure reporter to report skipped.
     pub fn report_skipped(self) -> Self {
         Self {
@@ -132,6 +373,15 @@ impl StdoutReporter {
         }
     }
 
+    /// Configure reporter to report the `count` slowest tests, and a histogram of how long all
+    /// tests took, when closed.
+    pub fn report_slowest(self, count: usize) -> Self {
+        Self {
+            report_slowest: count,
+            ..self
+        }
+    }
+
     /// Report progress to the given terminal.
     fn report_progress(&self, out: &mut Terminal, account: &mut Account) -> Result<(), Error> {
         let mut names = account
@@ -212,6 +462,7 @@ impl<'a> Reporter<'a> for StdoutReporter {
         } = *state;
 
         account.running.insert(index, name.to_string());
+        account.started.get_or_insert_with(time::Instant::now);
 
         // only report if fancy and can clear lines.
         if out.is_fancy() {
@@ -237,11 +488,25 @@ impl<'a> Reporter<'a> for StdoutReporter {
         account.running.remove(&index);
         account.count += 1;
 
-        ColoredTestResult(&result).fmt(out)?;
+        if self.verbosity > Verbosity::Quiet || !result.outcome().is_ok() {
+            ColoredTestResult(&result, self.verbosity).fmt(out)?;
+        }
+
+        let name = match result.module {
+            Some(ref module) => format!("{} :: {}", module, result.name),
+            None => result.name.to_string(),
+        };
+
+        account.durations.push((name, result.duration));
+
+        if result.outcome().is_ok() {
+            account.passed += 1;
+        } else {
+            account.failed += 1;
+        }
 
-        match *result.outcome() {
-            Outcome::Ok => account.passed += 1,
-            _ => account.failed += 1,
+        if let Outcome::Flaky(..) = *result.outcome() {
+            account.flaky += 1;
         }
 
         if out.is_fancy() {
@@ -270,6 +535,32 @@ impl<'a> Reporter<'a> for StdoutReporter {
         Ok(())
     }
 
+    fn report_listed(&self, test: &Test) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
+
+        let ReporterState { ref mut out, .. } = *state;
+
+        if let Some(ref module) = test.module {
+            write!(out, "{} :: ", module)?;
+        }
+
+        writeln!(out, "{}: test", test.name())?;
+        Ok(())
+    }
+
+    fn report_shuffled(&self, seed: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
+
+        let ReporterState { ref mut out, .. } = *state;
+
+        writeln!(
+            out,
+            "shuffling tests with seed {}, use `--shuffle-seed={}` to reproduce",
+            seed, seed
+        )?;
+        Ok(())
+    }
+
     fn close(&self) -> Result<(), Error> {
         let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
 
@@ -292,12 +583,94 @@ impl<'a> Reporter<'a> for StdoutReporter {
         out.red(account.failed)?;
         write!(out, " failed; ")?;
         out.yellow(account.skipped)?;
-        write!(out, " skipped")?;
+        write!(out, " skipped; ")?;
+        out.yellow(account.flaky)?;
+        write!(out, " flaky")?;
         writeln!(out)?;
+
+        if let Some(started) = account.started {
+            let wall_clock = time::Instant::now().duration_since(started);
+
+            let total = account
+                .durations
+                .iter()
+                .fold(time::Duration::default(), |total, &(_, d)| total + d);
+
+            writeln!(
+                out,
+                "finished in {} ({} of test time)",
+                utils::DurationFormat(&wall_clock),
+                utils::DurationFormat(&total)
+            )?;
+        }
+
+        if self.report_slowest > 0 && !account.durations.is_empty() {
+            writeln!(out, "slowest tests:")?;
+
+            for (name, duration) in slowest(&account.durations, self.report_slowest) {
+                let duration = utils::DurationFormat(duration).to_string();
+                writeln!(out, "  {:>8} {}", duration, name)?;
+            }
+
+            let histogram = histogram(account.durations.iter().map(|(_, d)| d));
+            let max = histogram.iter().map(|&(_, count)| count).max().unwrap_or(0);
+
+            writeln!(out, "durations:")?;
+
+            for (label, count) in histogram {
+                // scale the bars so that the largest bucket is 40 characters wide.
+                let width = if max == 0 {
+                    0
+                } else {
+                    (count * 40).div_ceil(max)
+                };
+                writeln!(out, "  {:>8} {:>5} {}", label, count, "#".repeat(width))?;
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Upper bounds of the buckets in the duration histogram, in milliseconds.
+const BUCKETS: &[(u64, &str)] = &[
+    (10, "<10ms"),
+    (100, "<100ms"),
+    (1_000, "<1s"),
+    (10_000, "<10s"),
+];
+
+/// Find the `count` slowest tests, with the slowest first.
+fn slowest(durations: &[(String, time::Duration)], count: usize) -> Vec<&(String, time::Duration)> {
+    let mut durations = durations.iter().collect::<Vec<_>>();
+    durations.sort_by_key(|d| cmp::Reverse(d.1));
+    durations.truncate(count);
+    durations
+}
+
+/// Count the number of durations in every bucket of the histogram.
+fn histogram<'a>(durations: impl Iterator<Item = &'a time::Duration>) -> Vec<(&'static str, usize)> {
+    let mut histogram = BUCKETS
+        .iter()
+        .map(|&(_, label)| (label, 0))
+        .collect::<Vec<_>>();
+
+    histogram.push((">=10s", 0));
+
+    for duration in durations {
+        let millis = duration.as_secs() * 1_000 + duration.subsec_millis() as u64;
+
+        let index = BUCKETS
+            .iter()
+            .position(|&(bound, _)| millis < bound)
+            .unwrap_or(BUCKETS.len());
+
+        histogram[index].1 += 1;
+    }
+
+    histogram
+}
+
 /// A reporter that doesn't report anything.
 pub struct CollectingReporter<'a> {
     results: Mutex<Vec<TestResult<'a>>>,
@@ -312,10 +685,15 @@ impl<'a> CollectingReporter<'a> {
 
     /// Take all collected results.
     pub fn take_results(self) -> Result<Vec<TestResult<'a>>, Error> {
-        Ok(self
-            .results
+        self.results
             .into_inner()
-            .map_err(|_| format_err!("another lock is held"))?)
+            .map_err(|_| format_err!("another lock is held"))
+    }
+}
+
+impl<'a> Default for CollectingReporter<'a> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -338,6 +716,471 @@ impl<'a> Reporter<'a> for CollectingReporter<'a> {
     }
 }
 
+/// Convert a duration into fractional seconds.
+fn seconds(duration: &time::Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000f64
+}
+
+/// A reporter emitting newline-delimited JSON events, for consumption by other tools.
+///
+/// Every line is a JSON object with a `type` of either `test` or `suite`, and an `event`:
+///
+/// ```text
+/// {"type":"test","event":"started","index":0,"name":"transfer"}
+/// {"type":"test","event":"ok","index":0,"module":null,"name":"transfer","duration":0.012}
+/// {"type":"suite","event":"ok","passed":1,"failed":0,"skipped":0,"flaky":0}
+/// ```
+///
+/// Failed tests have the `event` set to `failed` or `errored`, and include a `location`, a
+/// `message`, and the captured `output` if available. Tests which failed as expected have the `event` set to
+/// `expected_failure`, and skipped tests have it set to `skipped`. Tests which passed after being
+/// retried have it set to `flaky`, and include the number of `attempts`.
+///
+/// Benchmarks include a `bench` object, with statistics of the time in seconds and the gas used by
+/// every iteration.
+///
+/// When listing tests, every test is reported with the `event` set to `listed`. If tests are
+/// shuffled, the seed is reported first in a `suite` event, with the `event` set to `shuffled`.
+pub struct JsonReporter<W = io::Stdout> {
+    state: Mutex<JsonReporterState<W>>,
+}
+
+struct JsonReporterState<W> {
+    out: W,
+    account: Account,
+}
+
+impl JsonReporter<io::Stdout> {
+    /// Create a JSON reporter writing to stdout.
+    pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+}
+
+impl Default for JsonReporter<io::Stdout> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W> JsonReporter<W>
+where
+    W: Write + Send,
+{
+    /// Create a JSON reporter writing to the given output.
+    pub fn with_output(out: W) -> Self {
+        Self {
+            state: Mutex::new(JsonReporterState {
+                out,
+                account: Account::default(),
+            }),
+        }
+    }
+
+    /// Take the output of the reporter.
+    pub fn into_output(self) -> Result<W, Error> {
+        let state = self
+            .state
+            .into_inner()
+            .map_err(|_| format_err!("another lock is held"))?;
+
+        Ok(state.out)
+    }
+
+    /// Write a single event.
+    fn event(&self, f: impl FnOnce(&mut JsonReporterState<W>) -> io::Result<()>) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
+        f(&mut *state)?;
+        state.out.flush()?;
+        Ok(())
+    }
+}
+
+impl<'a, W> Reporter<'a> for JsonReporter<W>
+where
+    W: Write + Send,
+{
+    fn report_started(&self, index: usize, name: &str) -> Result<(), Error> {
+        self.event(|state| {
+            writeln!(
+                state.out,
+                "{{\"type\":\"test\",\"event\":\"started\",\"index\":{},\"name\":{}}}",
+                index,
+                utils::JsonString(name)
+            )
+        })
+    }
+
+    fn report(&self, index: usize, result: TestResult<'a>) -> Result<(), Error> {
+        self.event(|state| {
+            let JsonReporterState {
+                ref mut out,
+                ref mut account,
+            } = *state;
+
+            account.count += 1;
+
+            let mut attempts = None;
+
+            let (event, location, message) = match result.outcome {
+                Outcome::Ok => {
+                    account.passed += 1;
+                    ("ok", None, None)
+                }
+                Outcome::Flaky(count) => {
+                    account.passed += 1;
+                    account.flaky += 1;
+                    attempts = Some(count);
+                    ("flaky", None, None)
+                }
+                Outcome::Failed(ref info) => {
+                    account.failed += 1;
+
+                    let location = info
+                        .location
+                        .as_ref()
+                        .map(|l| format!("{}:{}:{}", l.file, l.line, l.column));
+
+                    ("failed", location, info.message.clone())
+                }
+                Outcome::Errored(ref e) => {
+                    account.failed += 1;
+                    ("errored", None, Some(e.to_string()))
+                }
+                Outcome::ExpectedFailure(ref message) => {
+                    account.passed += 1;
+                    ("expected_failure", None, message.clone())
+                }
+            };
+
+            write!(
+                out,
+                "{{\"type\":\"test\",\"event\":\"{}\",\"index\":{}",
+                event, index
+            )?;
+
+            match result.module {
+                Some(ref module) => write!(out, ",\"module\":{}", utils::JsonString(module))?,
+                None => write!(out, ",\"module\":null")?,
+            }
+
+            write!(
+                out,
+                ",\"name\":{},\"duration\":{}",
+                utils::JsonString(&result.name),
+                seconds(&result.duration)
+            )?;
+
+            if let Some(ref bench) = result.bench {
+                write!(
+                    out,
+                    ",\"bench\":{{\"iterations\":{},\"time\":{{\"mean\":{},\"median\":{},\"p95\":{}}}",
+                    bench.iterations,
+                    seconds(&bench.time.mean),
+                    seconds(&bench.time.median),
+                    seconds(&bench.time.p95)
+                )?;
+
+                match bench.gas {
+                    Some(ref gas) => write!(
+                        out,
+                        ",\"gas\":{{\"mean\":{},\"median\":{},\"p95\":{}}}}}",
+                        gas.mean, gas.median, gas.p95
+                    )?,
+                    None => write!(out, ",\"gas\":null}}")?,
+                }
+            }
+
+            if let Some(attempts) = attempts {
+                write!(out, ",\"attempts\":{}", attempts)?;
+            }
+
+            if let Some(location) = location {
+                write!(out, ",\"location\":{}", utils::JsonString(&location))?;
+            }
+
+            if let Some(message) = message {
+                write!(out, ",\"message\":{}", utils::JsonString(&message))?;
+            }
+
+            if !result.outcome.is_ok() {
+                if let Some(ref output) = result.output {
+                    write!(out, ",\"output\":{}", utils::JsonString(output))?;
+                }
+            }
+
+            if let Some(ref artifacts) = result.artifacts {
+                let artifacts = artifacts.display().to_string();
+                write!(out, ",\"artifacts\":{}", utils::JsonString(&artifacts))?;
+            }
+
+            writeln!(out, "}}")
+        })
+    }
+
+    fn report_skipped(&self, test: Test<'a>) -> Result<(), Error> {
+        self.event(|state| {
+            state.account.count += 1;
+            state.account.skipped += 1;
+
+            writeln!(
+                state.out,
+                "{{\"type\":\"test\",\"event\":\"skipped\",\"name\":{}}}",
+                utils::JsonString(test.name())
+            )
+        })
+    }
+
+    fn report_listed(&self, test: &Test<'a>) -> Result<(), Error> {
+        self.event(|state| {
+            let out = &mut state.out;
+
+            write!(out, "{{\"type\":\"test\",\"event\":\"listed\"")?;
+
+            match test.module {
+                Some(ref module) => write!(out, ",\"module\":{}", utils::JsonString(module))?,
+                None => write!(out, ",\"module\":null")?,
+            }
+
+            writeln!(out, ",\"name\":{}}}", utils::JsonString(test.name()))
+        })
+    }
+
+    fn report_shuffled(&self, seed: u64) -> Result<(), Error> {
+        self.event(|state| {
+            writeln!(
+                state.out,
+                "{{\"type\":\"suite\",\"event\":\"shuffled\",\"seed\":{}}}",
+                seed
+            )
+        })
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        self.event(|state| {
+            let account = &state.account;
+
+            writeln!(
+                state.out,
+                "{{\"type\":\"suite\",\"event\":\"{}\",\"passed\":{},\"failed\":{},\"skipped\":{},\"flaky\":{}}}",
+                if account.failed == 0 { "ok" } else { "failed" },
+                account.passed,
+                account.failed,
+                account.skipped,
+                account.flaky
+            )
+        })
+    }
+}
+
+/// A reporter producing output according to the Test Anything Protocol (TAP), version 13.
+///
+/// The plan is printed last, since tests are reported as they complete:
+///
+/// ```text
+/// TAP version 13
+/// ok 1 - token :: transfer
+/// not ok 2 - approve
+///   ---
+///   message: "assertion failed"
+///   at: "src/main.rs:10:5"
+///   ...
+/// ok 3 - burn # SKIP
+/// 1..3
+/// ```
+pub struct TapReporter<W = io::Stdout> {
+    state: Mutex<TapReporterState<W>>,
+}
+
+struct TapReporterState<W> {
+    out: W,
+    /// If the version header has been written.
+    header: bool,
+    /// Number of test points written.
+    count: usize,
+}
+
+impl<W> TapReporterState<W>
+where
+    W: Write,
+{
+    /// Write the version header, unless it has already been written.
+    fn header(&mut self) -> io::Result<()> {
+        if !self.header {
+            writeln!(self.out, "TAP version 13")?;
+            self.header = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl TapReporter<io::Stdout> {
+    /// Create a TAP reporter writing to stdout.
+    pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+}
+
+impl Default for TapReporter<io::Stdout> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W> TapReporter<W>
+where
+    W: Write + Send,
+{
+    /// Create a TAP reporter writing to the given output.
+    pub fn with_output(out: W) -> Self {
+        Self {
+            state: Mutex::new(TapReporterState {
+                out,
+                header: false,
+                count: 0,
+            }),
+        }
+    }
+
+    /// Take the output of the reporter.
+    pub fn into_output(self) -> Result<W, Error> {
+        let state = self
+            .state
+            .into_inner()
+            .map_err(|_| format_err!("another lock is held"))?;
+
+        Ok(state.out)
+    }
+
+    /// Write a single test point, with the given directive and diagnostics.
+    fn point(
+        &self,
+        ok: bool,
+        description: &str,
+        directive: Option<&str>,
+        diagnostics: &[(&str, String)],
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
+        state.header()?;
+
+        let TapReporterState {
+            ref mut out,
+            ref mut count,
+            ..
+        } = *state;
+
+        *count += 1;
+
+        if !ok {
+            write!(out, "not ")?;
+        }
+
+        // `#` starts a directive, so it has to be escaped in descriptions.
+        write!(out, "ok {} - {}", count, description.replace('#', "\\#"))?;
+
+        if let Some(directive) = directive {
+            write!(out, " # {}", directive)?;
+        }
+
+        writeln!(out)?;
+
+        if !diagnostics.is_empty() {
+            writeln!(out, "  ---")?;
+
+            for &(key, ref value) in diagnostics {
+                writeln!(out, "  {}: {}", key, utils::JsonString(value))?;
+            }
+
+            writeln!(out, "  ...")?;
+        }
+
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl<'a, W> Reporter<'a> for TapReporter<W>
+where
+    W: Write + Send,
+{
+    fn report(&self, _index: usize, result: TestResult<'a>) -> Result<(), Error> {
+        let description = match result.module {
+            Some(ref module) => format!("{} :: {}", module, result.name),
+            None => result.name.to_string(),
+        };
+
+        let mut diagnostics = Vec::new();
+        let mut directive = None;
+
+        match result.outcome {
+            Outcome::Ok => {}
+            Outcome::Flaky(attempts) => {
+                diagnostics.push(("message", format!("passed after {} attempts", attempts)));
+            }
+            Outcome::ExpectedFailure(ref message) => {
+                // failures which are expected are marked as TODO, which doesn't fail the run.
+                directive = Some("TODO expected failure");
+
+                if let Some(ref message) = *message {
+                    diagnostics.push(("message", message.to_string()));
+                }
+            }
+            Outcome::Failed(ref info) => {
+                if let Some(ref message) = info.message {
+                    diagnostics.push(("message", message.to_string()));
+                }
+
+                if let Some(ref l) = info.location {
+                    diagnostics.push(("at", format!("{}:{}:{}", l.file, l.line, l.column)));
+                }
+            }
+            Outcome::Errored(ref e) => {
+                diagnostics.push(("message", e.to_string()));
+            }
+        }
+
+        if !result.outcome.is_ok() {
+            if let Some(ref output) = result.output {
+                diagnostics.push(("output", output.to_string()));
+            }
+        }
+
+        if let Some(ref artifacts) = result.artifacts {
+            diagnostics.push(("artifacts", artifacts.display().to_string()));
+        }
+
+        if let Some(ref bench) = result.bench {
+            diagnostics.push(("bench", bench.to_string()));
+        }
+
+        let ok = matches!(result.outcome, Outcome::Ok | Outcome::Flaky(..));
+
+        self.point(ok, &description, directive, &diagnostics)
+    }
+
+    fn report_skipped(&self, test: Test<'a>) -> Result<(), Error> {
+        self.point(true, test.name(), Some("SKIP"), &[])
+    }
+
+    fn report_shuffled(&self, seed: u64) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
+        state.header()?;
+        writeln!(state.out, "# shuffle seed: {}", seed)?;
+        Ok(())
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().map_err(|_| format_err!("lock poisoned"))?;
+        state.header()?;
+
+        let count = state.count;
+        writeln!(state.out, "1..{}", count)?;
+        state.out.flush()?;
+        Ok(())
+    }
+}
+
 enum Coloring {
     Colored {
         terminal: Box<term::StdoutTerminal>,
@@ -414,7 +1257,7 @@ impl Coloring {
                 terminal.delete_line().map_err(|_| fmt::Error)?;
                 Ok(())
             }
-            Coloring::Raw(..) => return Err(fmt::Error),
+            Coloring::Raw(..) => Err(fmt::Error),
         }
     }
 }
@@ -456,11 +1299,11 @@ impl Terminal {
     color!(yellow, YELLOW);
 }
 
-struct ColoredTestResult<'t, 'a: 't>(&'t TestResult<'a>);
+struct ColoredTestResult<'t, 'a: 't>(&'t TestResult<'a>, Verbosity);
 
 impl<'t, 'a: 't> ColoredTestResult<'t, 'a> {
     fn fmt(&self, fmt: &mut Terminal) -> fmt::Result {
-        let result = self.0;
+        let ColoredTestResult(result, verbosity) = *self;
 
         ColoredOutcome(&result.outcome).fmt(fmt)?;
 
@@ -475,8 +1318,33 @@ impl<'t, 'a: 't> ColoredTestResult<'t, 'a> {
             utils::DurationFormat(&result.duration)
         )?;
 
+        if let Some(ref bench) = result.bench {
+            writeln!(fmt, "  {}", bench)?;
+        }
+
+        if verbosity == Verbosity::Verbose {
+            if let Some(gas_used) = result.gas_used {
+                writeln!(fmt, "  gas used: {}", gas_used)?;
+            }
+        }
+
         ColoredOutcome(&result.outcome).fmt_errors(fmt)?;
 
+        if !result.outcome.is_ok() || verbosity == Verbosity::Verbose {
+            if let Some(ref output) = result.output {
+                writeln!(fmt, "---- captured output ----")?;
+                write!(fmt, "{}", output)?;
+
+                if !output.ends_with('\n') {
+                    writeln!(fmt)?;
+                }
+            }
+        }
+
+        if let Some(ref artifacts) = result.artifacts {
+            writeln!(fmt, "artifacts written to: {}", artifacts.display())?;
+        }
+
         Ok(())
     }
 }
@@ -491,6 +1359,11 @@ impl<'a> ColoredOutcome<'a> {
             Outcome::Failed(..) => fmt.red("FAIL")?,
             Outcome::Errored(..) => fmt.red("ERROR")?,
             Outcome::Ok => fmt.green("OK")?,
+            Outcome::ExpectedFailure(..) => fmt.yellow("XFAIL")?,
+            Outcome::Flaky(attempts) => {
+                fmt.yellow("FLAKY")?;
+                write!(fmt, " ({} attempts)", attempts)?;
+            }
         }
 
         Ok(())
@@ -506,7 +1379,7 @@ impl<'a> ColoredOutcome<'a> {
                 match info.location {
                     Some(ref location) => {
                         ColoredLocation(location).fmt(fmt)?;
-                        writeln!(fmt, "")?;
+                        writeln!(fmt)?;
                     }
                     None => {
                         writeln!(fmt, "unknown location")?;
@@ -521,9 +1394,9 @@ impl<'a> ColoredOutcome<'a> {
                 writeln!(fmt, "{}", e)?;
                 writeln!(fmt, "{:?}", e.backtrace())?;
 
-                let mut causes = e.causes().skip(1);
+                let causes = e.causes().skip(1);
 
-                while let Some(e) = causes.next() {
+                for e in causes {
                     writeln!(fmt, "caused by: {}", e)?;
                     writeln!(fmt, "{:?}", e.backtrace())?;
                 }
@@ -543,3 +1416,173 @@ impl<'a> ColoredLocation<'a> {
         write!(fmt, "{}:{}:{}", loc.file, loc.line, loc.column)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{histogram, slowest, JsonReporter, MultiReporter, Reporter, TapReporter};
+    use std::borrow::Cow;
+    use std::path::PathBuf;
+    use std::time;
+    use test_runner::{Location, Outcome, PanicInfo, TestResult};
+
+    #[test]
+    fn test_json_reporter() {
+        let reporter = JsonReporter::with_output(Vec::new());
+
+        reporter.report_started(0, "transfer").unwrap();
+        reporter
+            .report(
+                0,
+                TestResult {
+                    module: Some(Cow::from("token")),
+                    name: Cow::from("transfer"),
+                    outcome: Outcome::Failed(PanicInfo {
+                        location: Some(Location {
+                            file: "src/main.rs".to_string(),
+                            line: 10,
+                            column: 5,
+                        }),
+                        message: Some("expected \"1\"".to_string()),
+                    }),
+                    duration: time::Duration::from_millis(1500),
+                    output: Some("balance: 1\n".to_string()),
+                    bench: None,
+                    artifacts: Some(PathBuf::from("artifacts/token__transfer")),
+                    gas_used: None,
+                },
+            )
+            .unwrap();
+        reporter.close().unwrap();
+
+        let out = String::from_utf8(reporter.into_output().unwrap()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                r#"{"type":"test","event":"started","index":0,"name":"transfer"}"#,
+                r#"{"type":"test","event":"failed","index":0,"module":"token","name":"transfer","duration":1.5,"location":"src/main.rs:10:5","message":"expected \"1\"","output":"balance: 1\n","artifacts":"artifacts/token__transfer"}"#,
+                r#"{"type":"suite","event":"failed","passed":0,"failed":1,"skipped":0,"flaky":0}"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_multi_reporter() {
+        let json = JsonReporter::with_output(Vec::new());
+        let tap = TapReporter::with_output(Vec::new());
+
+        {
+            let reporter = MultiReporter::new().with(&json).with(&tap);
+
+            reporter
+                .report(
+                    0,
+                    TestResult {
+                        module: None,
+                        name: Cow::from("transfer"),
+                        outcome: Outcome::Errored(format_err!("out of gas")),
+                        duration: time::Duration::from_millis(0),
+                        output: None,
+                        bench: None,
+                        artifacts: None,
+                        gas_used: None,
+                    },
+                )
+                .unwrap();
+            reporter.close().unwrap();
+        }
+
+        let json = String::from_utf8(json.into_output().unwrap()).unwrap();
+        let tap = String::from_utf8(tap.into_output().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                r#"{"type":"test","event":"errored","index":0,"module":null,"name":"transfer","duration":0,"message":"out of gas"}"#,
+                r#"{"type":"suite","event":"failed","passed":0,"failed":1,"skipped":0,"flaky":0}"#,
+            ],
+            json.lines().collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            "TAP version 13\nnot ok 1 - transfer\n  ---\n  message: \"out of gas\"\n  ...\n1..1\n",
+            tap
+        );
+    }
+
+    #[test]
+    fn test_slowest_and_histogram() {
+        let durations = vec![
+            (String::from("fast"), time::Duration::from_millis(5)),
+            (String::from("slow"), time::Duration::from_millis(2500)),
+            (String::from("medium"), time::Duration::from_millis(50)),
+            (String::from("slower"), time::Duration::from_secs(12)),
+        ];
+
+        assert_eq!(
+            vec!["slower", "slow"],
+            slowest(&durations, 2)
+                .into_iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            vec![
+                ("<10ms", 1),
+                ("<100ms", 1),
+                ("<1s", 0),
+                ("<10s", 1),
+                (">=10s", 1),
+            ],
+            histogram(durations.iter().map(|(_, d)| d))
+        );
+    }
+
+    #[test]
+    fn test_tap_reporter() {
+        let reporter = TapReporter::with_output(Vec::new());
+
+        reporter
+            .report(
+                0,
+                TestResult {
+                    module: Some(Cow::from("token")),
+                    name: Cow::from("transfer"),
+                    outcome: Outcome::Ok,
+                    duration: time::Duration::from_millis(10),
+                    output: Some("ignored\n".to_string()),
+                    bench: None,
+                    artifacts: None,
+                    gas_used: None,
+                },
+            )
+            .unwrap();
+        reporter
+            .report(
+                1,
+                TestResult {
+                    module: None,
+                    name: Cow::from("approve #2"),
+                    outcome: Outcome::Failed(PanicInfo {
+                        location: None,
+                        message: Some("assertion failed".to_string()),
+                    }),
+                    duration: time::Duration::from_millis(10),
+                    output: None,
+                    bench: None,
+                    artifacts: None,
+                    gas_used: None,
+                },
+            )
+            .unwrap();
+        reporter.close().unwrap();
+
+        let out = String::from_utf8(reporter.into_output().unwrap()).unwrap();
+
+        assert_eq!(
+            "TAP version 13\nok 1 - token :: transfer\nnot ok 2 - approve \\#2\n  ---\n  message: \"assertion failed\"\n  ...\n1..2\n",
+            out
+        );
+    }
+}
@@ -1,7 +1,14 @@
 use failure::Error;
+use std::mem;
+use std::ops;
 use std::sync::Mutex;
+use std::thread;
 
 /// A managed instance that can be shared by cloning across threads.
+///
+/// The canonical instance can be modified with `replace` or `with`, so that setup can be built up
+/// incrementally, like deploying the contracts of every module. Everything which takes a snapshot
+/// afterwards sees the changes.
 #[derive(Debug)]
 pub struct Snapshot<T> {
     inner: Mutex<T>,
@@ -23,4 +30,256 @@ impl<T> Snapshot<T> {
         let inner = self.inner.lock().map_err(|_| format_err!("lock poisoned"))?;
         Ok(inner.clone())
     }
+
+    /// Replace the underlying value, and return the previous one.
+    pub fn replace(&self, value: T) -> Result<T, Error> {
+        let mut inner = self.inner.lock().map_err(|_| format_err!("lock poisoned"))?;
+        Ok(mem::replace(&mut *inner, value))
+    }
+
+    /// Modify the underlying value, and commit the modifications back if the given function
+    /// succeeds.
+    ///
+    /// The function operates on a clone of the value, so if it fails the underlying value is left
+    /// as it was. The lock is held while the function runs, so other snapshots can't be taken
+    /// until it is done.
+    pub fn with<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        T: Clone,
+        F: FnOnce(&mut T) -> Result<R, Error>,
+    {
+        let mut inner = self.inner.lock().map_err(|_| format_err!("lock poisoned"))?;
+        let mut value = inner.clone();
+        let output = f(&mut value)?;
+        *inner = value;
+        Ok(output)
+    }
+}
+
+/// A value which can be reverted to a checkpoint taken earlier.
+pub trait Revert {
+    /// Mark the current state of the value as the one to revert to.
+    fn checkpoint(&mut self) -> Result<(), Error>;
+
+    /// Revert the value to the last checkpoint.
+    fn revert(&mut self) -> Result<(), Error>;
+}
+
+/// A bounded pool of clones of a value, which are reverted to a checkpoint and reused instead of
+/// being cloned again.
+///
+/// Unlike `Snapshot::get`, checking out a value which is already in the pool doesn't clone it
+/// while holding a lock, which speeds up tests that take a fresh value in every iteration, like
+/// property tests.
+///
+/// ```rust,ignore
+/// let pool = SnapshotPool::new(evm, 16);
+///
+/// runner.test("set value", pt!(|x in any::<u64>()| {
+///     let evm = pool.checkout().unwrap();
+///     // `evm` is reverted and returned to the pool when dropped.
+/// }));
+/// ```
+pub struct SnapshotPool<T> {
+    snapshot: Snapshot<T>,
+    /// Values which have been reverted and are ready to be checked out.
+    idle: Mutex<Vec<T>>,
+    /// The maximum number of idle values to keep.
+    capacity: usize,
+}
+
+impl<T> SnapshotPool<T>
+where
+    T: Clone + Revert,
+{
+    /// Create a new pool of clones of the given value, keeping at most `capacity` of them.
+    pub fn new(value: T, capacity: usize) -> Self {
+        Self {
+            snapshot: Snapshot::new(value),
+            idle: Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    /// Check out a value from the pool, or clone a new one if the pool is empty.
+    ///
+    /// The value is returned to the pool when the guard is dropped, unless the thread is
+    /// panicking since the value might be in an inconsistent state.
+    pub fn checkout(&self) -> Result<PoolGuard<'_, T>, Error> {
+        Ok(PoolGuard {
+            pool: self,
+            slot: Some(self.take()?),
+        })
+    }
+
+    /// Take a value out of the pool, or clone a new one if the pool is empty.
+    pub(crate) fn take(&self) -> Result<T, Error> {
+        let idle = self
+            .idle
+            .lock()
+            .map_err(|_| format_err!("lock poisoned"))?
+            .pop();
+
+        if let Some(value) = idle {
+            return Ok(value);
+        }
+
+        let mut value = self.snapshot.get()?;
+        value.checkpoint()?;
+        Ok(value)
+    }
+
+    /// Revert the given value and put it back into the pool, if there is room for it.
+    pub(crate) fn put(&self, mut value: T) -> Result<(), Error> {
+        value.revert()?;
+
+        let mut idle = self.idle.lock().map_err(|_| format_err!("lock poisoned"))?;
+
+        if idle.len() < self.capacity {
+            idle.push(value);
+        }
+
+        Ok(())
+    }
+}
+
+/// A value checked out from a `SnapshotPool`.
+pub struct PoolGuard<'a, T: 'a>
+where
+    T: Clone + Revert,
+{
+    pool: &'a SnapshotPool<T>,
+    slot: Option<T>,
+}
+
+impl<'a, T: 'a> ops::Deref for PoolGuard<'a, T>
+where
+    T: Clone + Revert,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.slot.as_ref().expect("value checked out")
+    }
+}
+
+impl<'a, T: 'a> ops::DerefMut for PoolGuard<'a, T>
+where
+    T: Clone + Revert,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.slot.as_mut().expect("value checked out")
+    }
+}
+
+impl<'a, T: 'a> Drop for PoolGuard<'a, T>
+where
+    T: Clone + Revert,
+{
+    fn drop(&mut self) {
+        if thread::panicking() {
+            return;
+        }
+
+        if let Some(value) = self.slot.take() {
+            // values which can't be reverted are not reused.
+            let _ = self.pool.put(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Revert, Snapshot, SnapshotPool};
+    use failure::Error;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A value which counts how many times it has been cloned.
+    struct Counter {
+        clones: Arc<AtomicUsize>,
+        value: u32,
+        checkpoint: u32,
+    }
+
+    impl Clone for Counter {
+        fn clone(&self) -> Self {
+            self.clones.fetch_add(1, Ordering::SeqCst);
+
+            Self {
+                clones: Arc::clone(&self.clones),
+                value: self.value,
+                checkpoint: self.checkpoint,
+            }
+        }
+    }
+
+    impl Revert for Counter {
+        fn checkpoint(&mut self) -> Result<(), Error> {
+            self.checkpoint = self.value;
+            Ok(())
+        }
+
+        fn revert(&mut self) -> Result<(), Error> {
+            self.value = self.checkpoint;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pool() {
+        let clones = Arc::new(AtomicUsize::new(0));
+
+        let pool = SnapshotPool::new(
+            Counter {
+                clones: Arc::clone(&clones),
+                value: 42,
+                checkpoint: 0,
+            },
+            1,
+        );
+
+        for _ in 0..10 {
+            let mut counter = pool.checkout().unwrap();
+            assert_eq!(42, counter.value);
+            counter.value += 1;
+        }
+
+        assert_eq!(1, clones.load(Ordering::SeqCst));
+
+        {
+            let _a = pool.checkout().unwrap();
+            let _b = pool.checkout().unwrap();
+        }
+
+        // only one of the values is kept when they are returned.
+        assert_eq!(2, clones.load(Ordering::SeqCst));
+        assert_eq!(1, pool.idle.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_commit() {
+        let snapshot = Snapshot::new(vec![1]);
+
+        assert_eq!(vec![1], snapshot.replace(vec![2]).unwrap());
+        assert_eq!(vec![2], snapshot.get().unwrap());
+
+        let len = snapshot
+            .with(|value| {
+                value.push(3);
+                Ok(value.len())
+            })
+            .unwrap();
+
+        assert_eq!(2, len);
+        assert_eq!(vec![2, 3], snapshot.get().unwrap());
+
+        let result: Result<(), _> = snapshot.with(|value| {
+            value.push(4);
+            Err(format_err!("deployment failed"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(vec![2, 3], snapshot.get().unwrap());
+    }
 }
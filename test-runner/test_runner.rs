@@ -1,14 +1,23 @@
 //! Provides a simple test scaffolding for running tests in parallel.
+use artifacts;
+use bench::{self, BenchStats, IntoSample};
+use capture;
+use context;
 use failure::Error;
+use filter;
+use fixture::{self, Fixture, FixtureSuite};
+use rayon;
 use reporter::Reporter;
 use std::any;
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
 use std::panic;
+use std::path::{Path, PathBuf};
 use std::sync::{atomic, Arc, Mutex};
 use std::thread;
 use std::time;
+use utils;
 
 /// Convert into a result.
 pub trait IntoResult<T>: Send {
@@ -50,12 +59,25 @@ impl TestEntry for () {
     }
 }
 
+/// What a test is expected to do.
+#[derive(Debug, Clone)]
+pub(crate) enum Expect<'a> {
+    /// The test is expected to pass.
+    Pass,
+    /// The test is expected to fail, optionally with a message containing the given string.
+    Fail(Option<Cow<'a, str>>),
+}
+
 /// A single test.
 pub struct Test<'a> {
     /// Module of the test.
     pub(crate) module: Option<Cow<'a, str>>,
     /// Name of the test.
     pub(crate) name: Cow<'a, str>,
+    /// What the test is expected to do.
+    pub(crate) expect: Expect<'a>,
+    /// Number of times the test is retried if it fails.
+    pub(crate) retries: usize,
     /// Entry-point to the test. Must be guarded against panics, since that is how Rust asserts
     /// work.
     pub(crate) entry: Box<'a + TestEntry>,
@@ -66,6 +88,17 @@ impl<'a> Test<'a> {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+
+    /// Create a copy of the test for reporting, without its entry-point.
+    pub(crate) fn duplicate(&self) -> Test<'a> {
+        Test {
+            module: self.module.clone(),
+            name: self.name.clone(),
+            expect: self.expect.clone(),
+            retries: self.retries,
+            entry: Box::new(()),
+        }
+    }
 }
 
 impl<'a> fmt::Debug for Test<'a> {
@@ -107,14 +140,86 @@ pub enum Outcome {
     Errored(Error),
     /// Only indicates that the test was successful.
     Ok,
+    /// The test was expected to fail, and failed with the given message.
+    ExpectedFailure(Option<String>),
+    /// The test failed at first, but passed when retried after the given number of attempts.
+    Flaky(usize),
 }
 
 impl Outcome {
-    /// Check if outcome is OK.
+    /// Check if outcome is OK, which includes tests that failed as expected.
     pub fn is_ok(&self) -> bool {
+        matches!(
+            *self,
+            Outcome::Ok | Outcome::ExpectedFailure(..) | Outcome::Flaky(..)
+        )
+    }
+
+    /// Convert the outcome of a test which is expected to fail.
+    fn expect_failure(self, expected: Option<&str>) -> Outcome {
+        let message = match self {
+            Outcome::Ok => {
+                return Outcome::Failed(PanicInfo {
+                    location: None,
+                    message: Some(String::from("test was expected to fail, but passed")),
+                });
+            }
+            Outcome::Failed(info) => info.message,
+            Outcome::Errored(e) => Some(e.to_string()),
+            other => return other,
+        };
+
+        if let Some(expected) = expected {
+            let matches = message
+                .as_ref()
+                .map(|m| m.contains(expected))
+                .unwrap_or(false);
+
+            if !matches {
+                return Outcome::Failed(PanicInfo {
+                    location: None,
+                    message: Some(format!(
+                        "test was expected to fail with a message containing `{}`, but failed \
+                         with: {}",
+                        expected,
+                        message.as_deref().unwrap_or("no message")
+                    )),
+                });
+            }
+        }
+
+        Outcome::ExpectedFailure(message)
+    }
+
+    /// Describe why a test failed, for writing it as an artifact.
+    fn describe_failure(&self) -> String {
         match *self {
-            Outcome::Ok => true,
-            _ => false,
+            Outcome::Failed(ref info) => {
+                let mut out = format!(
+                    "panicked: {}",
+                    info.message.as_deref().unwrap_or("no message")
+                );
+
+                if let Some(ref location) = info.location {
+                    out.push_str(&format!(
+                        "\n  at {}:{}:{}",
+                        location.file, location.line, location.column
+                    ));
+                }
+
+                out.push('\n');
+                out
+            }
+            Outcome::Errored(ref e) => {
+                let mut out = format!("error: {}\n", e);
+
+                for cause in e.causes().skip(1) {
+                    out.push_str(&format!("caused by: {}\n", cause));
+                }
+
+                out
+            }
+            _ => String::new(),
         }
     }
 }
@@ -129,9 +234,41 @@ pub struct TestResult<'a> {
     pub(crate) outcome: Outcome,
     /// Duration that the test was running for.
     pub(crate) duration: time::Duration,
+    /// Output captured while the test was running, if any.
+    pub(crate) output: Option<String>,
+    /// Statistics of the benchmark, if the test was one.
+    pub(crate) bench: Option<BenchStats>,
+    /// Directory that the artifacts of the test were written to, if it failed.
+    pub(crate) artifacts: Option<PathBuf>,
+    /// Gas used by all transactions that the test performed.
+    pub(crate) gas_used: Option<u64>,
 }
 
 impl<'a> TestResult<'a> {
+    /// Create a copy of the result for reporting.
+    ///
+    /// Errors can't be copied, so the copy only retains their message.
+    pub(crate) fn duplicate(&self) -> TestResult<'a> {
+        let outcome = match self.outcome {
+            Outcome::Failed(ref info) => Outcome::Failed(info.clone()),
+            Outcome::Errored(ref e) => Outcome::Errored(format_err!("{}", e)),
+            Outcome::Ok => Outcome::Ok,
+            Outcome::ExpectedFailure(ref message) => Outcome::ExpectedFailure(message.clone()),
+            Outcome::Flaky(attempts) => Outcome::Flaky(attempts),
+        };
+
+        TestResult {
+            module: self.module.clone(),
+            name: self.name.clone(),
+            outcome,
+            duration: self.duration,
+            output: self.output.clone(),
+            bench: self.bench.clone(),
+            artifacts: self.artifacts.clone(),
+            gas_used: self.gas_used,
+        }
+    }
+
     /// Access the name of the test results.
     pub fn name(&self) -> &str {
         &self.name
@@ -146,27 +283,448 @@ impl<'a> TestResult<'a> {
     pub fn duration(&self) -> &time::Duration {
         &self.duration
     }
+
+    /// Access the output captured while the test was running.
+    pub fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+
+    /// Access the statistics of the benchmark, if the test was one.
+    pub fn bench(&self) -> Option<&BenchStats> {
+        self.bench.as_ref()
+    }
+
+    /// Access the directory that the artifacts of the test were written to, if it failed.
+    pub fn artifacts(&self) -> Option<&Path> {
+        self.artifacts.as_deref()
+    }
+
+    /// Access the gas used by all transactions that the test performed.
+    ///
+    /// This is `None` if the test didn't perform any transactions through a virtual machine.
+    pub fn gas_used(&self) -> Option<u64> {
+        self.gas_used
+    }
 }
 
 /// Helper trait to register tests.
 pub trait Suite<'a> {
     /// Register a single test, with a human-readable `name`.
-    fn test<N: Into<Cow<'a, str>>, F: 'a, T>(&mut self, name: N, entry: F)
+    fn test<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>;
+
+    /// Register a single test which is expected to fail, by panicking or returning an error.
+    ///
+    /// The test is reported as failed if it passes.
+    fn should_fail<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>;
+
+    /// Register a single test which is expected to fail with a message containing `message`.
+    fn should_fail_with<N: Into<Cow<'a, str>>, M: Into<Cow<'a, str>>, F, T>(
+        &mut self,
+        name: N,
+        message: M,
+        entry: F,
+    ) where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>;
+
+    /// Register a single test which is retried up to `retries` times until it passes.
+    ///
+    /// A test which passes after being retried is reported as flaky, instead of as failed.
+    fn test_with_retries<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, retries: usize, entry: F)
     where
-        F: Fn() -> T + Send,
+        F: 'a + Fn() -> T + Send,
         T: IntoResult<()>;
+
+    /// Register one test for every case, each named after `name` and the debug representation of
+    /// its case.
+    ///
+    /// ```rust,ignore
+    /// runner.test_each("transfer", vec![0u64, 1, 100], |amount| {
+    ///     // ...
+    /// });
+    /// ```
+    ///
+    /// Will register the tests `transfer (0)`, `transfer (1)`, and `transfer (100)`.
+    fn test_each<N: AsRef<str>, I, F, T>(&mut self, name: N, cases: I, entry: F)
+    where
+        I: IntoIterator,
+        I::Item: 'a + fmt::Debug + Send,
+        F: 'a + Fn(&I::Item) -> T + Send + Clone,
+        T: IntoResult<()>,
+    {
+        for case in cases {
+            let name = format!("{} ({:?})", name.as_ref(), case);
+            let entry = entry.clone();
+            self.test(name, move || entry(&case));
+        }
+    }
+
+    /// Register a single test which is passed a value set up by the given fixture, which is torn
+    /// down when the test is done.
+    fn test_with<N: Into<Cow<'a, str>>, X, F, T>(&mut self, name: N, fixture: &'a X, entry: F)
+    where
+        X: 'a + Fixture,
+        F: 'a + Fn(&mut X::Value) -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.test(name, move || fixture::run(fixture, &entry))
+    }
+
+    /// Register a benchmark, which runs `entry` for the given number of iterations.
+    ///
+    /// The entry can return the amount of gas it used, which is reported together with how long
+    /// it took.
+    fn bench<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, iterations: usize, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoSample,
+    {
+        self.test(name, move || {
+            bench::run(iterations, || {
+                let start = time::Instant::now();
+                let sample = entry().into_sample()?;
+                Ok((start.elapsed(), sample))
+            })
+        })
+    }
+
+    /// Register a benchmark, where every iteration is passed a fresh value set up by the given
+    /// fixture.
+    ///
+    /// Setting up and tearing down the value is not included in the time of an iteration.
+    fn bench_with<N: Into<Cow<'a, str>>, X, F, T>(
+        &mut self,
+        name: N,
+        iterations: usize,
+        fixture: &'a X,
+        entry: F,
+    ) where
+        X: 'a + Fixture,
+        F: 'a + Fn(&mut X::Value) -> T + Send,
+        T: IntoSample,
+    {
+        self.test(name, move || {
+            bench::run(iterations, || {
+                let mut value = fixture.setup()?;
+
+                let start = time::Instant::now();
+                let sample = entry(&mut value).into_sample();
+                let elapsed = start.elapsed();
+
+                fixture.teardown(value)?;
+                Ok((elapsed, sample?))
+            })
+        })
+    }
+
+    /// Register tests which are all passed a value set up by the given fixture.
+    fn with_fixture<'s, X>(&'s mut self, fixture: &'a X) -> FixtureSuite<'s, 'a, Self, X>
+    where
+        Self: Sized,
+        X: 'a + Fixture,
+    {
+        FixtureSuite::new(self, fixture)
+    }
+}
+
+/// Hooks which run once around all tests in a module.
+#[derive(Default)]
+struct ModuleHooks<'a> {
+    before_all: Option<Box<'a + TestEntry>>,
+    after_all: Option<Box<'a + TestEntry>>,
+}
+
+impl<'a> fmt::Debug for ModuleHooks<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ModuleHooks")
+            .field("before_all", &self.before_all.is_some())
+            .field("after_all", &self.after_all.is_some())
+            .finish()
+    }
+}
+
+/// The state of a module with hooks, while its tests are running.
+struct ModuleState<'a> {
+    name: Cow<'a, str>,
+    hooks: Mutex<ModuleHooks<'a>>,
+    /// The result of the `before_all` hook, once it has run.
+    before: Mutex<Option<Result<(), String>>>,
+    /// Number of tests in the module which haven't completed yet.
+    remaining: atomic::AtomicUsize,
+}
+
+impl<'a> ModuleState<'a> {
+    /// Run the `before_all` hook, unless it has already been run.
+    ///
+    /// Blocks until the hook has completed if it's being run by another test.
+    fn before(&self, catch: &Mutex<HashMap<thread::ThreadId, PanicInfo>>) -> Result<(), String> {
+        let mut before = self.before.lock().expect("poisoned lock");
+
+        if let Some(ref result) = *before {
+            return result.clone();
+        }
+
+        let hooks = self.hooks.lock().expect("poisoned lock");
+
+        let result = match hooks.before_all {
+            Some(ref hook) => run_hook(&**hook, catch),
+            None => Ok(()),
+        };
+
+        *before = Some(result.clone());
+        result
+    }
+
+    /// Mark a test in the module as completed, and run the `after_all` hook if it was the last
+    /// one.
+    ///
+    /// Returns a result to report if the hook failed.
+    fn after(&self, catch: &Mutex<HashMap<thread::ThreadId, PanicInfo>>) -> Option<TestResult<'a>> {
+        if self.remaining.fetch_sub(1, atomic::Ordering::AcqRel) != 1 {
+            return None;
+        }
+
+        let hooks = self.hooks.lock().expect("poisoned lock");
+
+        let hook = match hooks.after_all {
+            Some(ref hook) => hook,
+            None => return None,
+        };
+
+        let start = time::Instant::now();
+        let result = run_hook(&**hook, catch);
+        let duration = time::Instant::now().duration_since(start);
+
+        match result {
+            Ok(()) => None,
+            Err(message) => Some(TestResult {
+                module: Some(self.name.clone()),
+                name: Cow::from("after_all"),
+                outcome: Outcome::Errored(format_err!("after_all hook failed: {}", message)),
+                duration,
+                output: None,
+                bench: None,
+                artifacts: None,
+                gas_used: None,
+            }),
+        }
+    }
+}
+
+/// Run a hook, and convert any panic or error into a message.
+fn run_hook(hook: &TestEntry, catch: &Mutex<HashMap<thread::ThreadId, PanicInfo>>) -> Result<(), String> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| hook.run())) {
+        Err(_) => {
+            let id = thread::current().id();
+
+            let mut catch = catch.lock().expect("poisoned lock");
+            let catch = catch.remove(&id).unwrap_or_default();
+
+            Err(catch.message.unwrap_or_else(|| String::from("hook panicked")))
+        }
+        Ok(Err(e)) => Err(e.to_string()),
+        Ok(Ok(())) => Ok(()),
+    }
+}
+
+/// A summary of all tests which were run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Number of tests which passed, including flaky tests and tests which failed as expected.
+    pub passed: u32,
+    /// Number of tests which failed or errored.
+    pub failed: u32,
+    /// Number of tests which were skipped.
+    pub skipped: u32,
+    /// Number of tests which only passed after being retried.
+    pub flaky: u32,
+}
+
+impl Summary {
+    /// Check if no tests failed.
+    pub fn is_ok(&self) -> bool {
+        self.failed == 0
+    }
+
+    /// The exit code to use for the process, which is non-zero if any test failed.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_ok() {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Add the outcome of a single test to the summary.
+    fn record(&mut self, outcome: &Outcome) {
+        if outcome.is_ok() {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+
+        if let Outcome::Flaky(..) = *outcome {
+            self.flaky += 1;
+        }
+    }
+}
+
+/// Check that the given shard is valid.
+fn check_shard(shard_index: usize, total_shards: usize) -> Result<(), Error> {
+    if shard_index >= total_shards {
+        bail!(
+            "shard index must be less than the total number of shards, but was {}/{}",
+            shard_index,
+            total_shards
+        );
+    }
+
+    Ok(())
+}
+
+/// Parse a shard on the form `<index>/<total>`.
+fn parse_shard(value: &str) -> Result<(usize, usize), Error> {
+    let mut parts = value.splitn(2, '/');
+
+    let shard = match (parts.next(), parts.next()) {
+        (Some(index), Some(total)) => match (index.parse::<usize>(), total.parse::<usize>()) {
+            (Ok(index), Ok(total)) => (index, total),
+            _ => bail!("bad value for `--shard`, expected <index>/<total>: {}", value),
+        },
+        _ => bail!("bad value for `--shard`, expected <index>/<total>: {}", value),
+    };
+
+    check_shard(shard.0, shard.1)?;
+    Ok(shard)
+}
+
+/// The order in which tests are started.
+///
+/// Tests which run in parallel can complete in any order, so ordering is only strict when running
+/// sequentially.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// The order that the tests were registered in.
+    #[default]
+    Registration,
+    /// Alphabetical order of the module and name of every test.
+    Alphabetical,
+    /// A random order, determined by the given seed or a random one if not given.
+    ///
+    /// The seed used is reported, so that the order can be reproduced.
+    Shuffled(Option<u64>),
+}
+
+impl Order {
+    /// Sort the given tests in this order.
+    ///
+    /// Returns the seed used, if the tests were shuffled.
+    fn sort(self, tests: &mut Vec<Test>) -> Option<u64> {
+        match self {
+            Order::Registration => None,
+            Order::Alphabetical => {
+                tests.sort_by(|a, b| (&a.module, &a.name).cmp(&(&b.module, &b.name)));
+                None
+            }
+            Order::Shuffled(seed) => {
+                let seed = seed.unwrap_or_else(random_seed);
+                utils::shuffle(tests, seed);
+                Some(seed)
+            }
+        }
+    }
+}
+
+/// Generate a random seed from the current time.
+fn random_seed() -> u64 {
+    let now = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    utils::SplitMix64::new(now.as_secs() ^ now.subsec_nanos() as u64).next_u64()
+}
+
+/// Options which apply to every test in a run.
+#[derive(Debug, Clone, Copy)]
+struct RunOptions<'r> {
+    /// Directory to write artifacts of failing tests to.
+    artifacts_dir: Option<&'r Path>,
+    /// Capture everything written to stdout and stderr, which requires that tests run one at a
+    /// time.
+    capture_stdio: bool,
 }
 
 /// A scaffolding that runs tests very efficiently.
 #[derive(Debug)]
 pub struct TestRunner<'a> {
     tests: Vec<Test<'a>>,
+    /// Hooks registered for modules, by name of the module.
+    hooks: HashMap<Cow<'a, str>, ModuleHooks<'a>>,
+    /// The maximum number of tests to run in parallel.
+    max_parallelism: Option<usize>,
+    /// The index of the shard to run, and the total number of shards.
+    shard: Option<(usize, usize)>,
+    /// The order to start tests in.
+    order: Order,
+    /// Directory to write artifacts of failing tests to.
+    artifacts_dir: Option<PathBuf>,
 }
 
 impl<'a> TestRunner<'a> {
     /// Build a new test runner.
     pub fn new() -> Self {
-        Self { tests: Vec::new() }
+        Self {
+            tests: Vec::new(),
+            hooks: HashMap::new(),
+            max_parallelism: None,
+            shard: None,
+            order: Order::default(),
+            artifacts_dir: None,
+        }
+    }
+
+    /// Write artifacts of every failing test to its own directory under `dir`.
+    ///
+    /// See the `artifacts` module for what is written. Can also be set with the
+    /// `--artifacts=<dir>` option.
+    pub fn artifacts_dir(self, dir: impl AsRef<Path>) -> Self {
+        Self {
+            artifacts_dir: Some(dir.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Set the order to start tests in.
+    ///
+    /// Can also be set with the `--order=<registration|alphabetical|shuffled>` and
+    /// `--shuffle-seed=<seed>` options.
+    pub fn order(self, order: Order) -> Self {
+        Self { order, ..self }
+    }
+
+    /// Limit the number of tests which are run in parallel, which limits the number of virtual
+    /// machines that are cloned at the same time.
+    ///
+    /// Can also be set with the `--test-threads=<n>` option.
+    pub fn max_parallelism(self, max_parallelism: usize) -> Self {
+        Self {
+            max_parallelism: Some(max_parallelism),
+            ..self
+        }
+    }
+
+    /// Run tests one at a time, in order.
+    ///
+    /// Running one test at a time also captures everything they write to stdout and stderr, like
+    /// with `println!`. Can also be set with the `--sequential` option.
+    pub fn sequential(self) -> Self {
+        self.max_parallelism(1)
     }
 
     /// Create a module runner.
@@ -178,21 +736,82 @@ impl<'a> TestRunner<'a> {
     }
 
     /// Run by reading filters from argv.
-    pub fn run(self, reporter: &Reporter<'a>) -> Result<(), Error> {
+    ///
+    /// Returns a summary of the tests that were run, which can be used to check if any failed.
+    pub fn run(self, reporter: &Reporter<'a>) -> Result<Summary, Error> {
         use std::env;
 
         let mut args = env::args();
         args.next();
 
-        self.run_with_filters(args.collect::<HashSet<String>>(), reporter)
+        self.run_with_filters(args.collect::<Vec<String>>(), reporter)
+    }
+
+    /// Run by reading filters from argv, close the reporter, and exit the process.
+    ///
+    /// The process exits with a non-zero exit code if any test failed, or if the tests couldn't be
+    /// run.
+    pub fn exit(self, reporter: &Reporter<'a>) -> ! {
+        use std::process;
+
+        let result = self.run(reporter).and_then(|summary| {
+            reporter.close()?;
+            Ok(summary)
+        });
+
+        match result {
+            Ok(summary) => process::exit(summary.exit_code()),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+
+    /// List all registered tests to the reporter, without running them.
+    ///
+    /// Can also be done with the `--list` option, which also applies any filters.
+    pub fn list(self, reporter: &Reporter<'a>) -> Result<(), Error> {
+        self.run_with_filters(vec![String::from("--list")], reporter)?;
+        Ok(())
+    }
+
+    /// Run a single shard of the registered tests, by reading filters from argv.
+    ///
+    /// Tests are partitioned by the order they are registered in, so every test is part of
+    /// exactly one shard as long as all shards register the same tests. Shards are numbered from
+    /// 0, and tests in other shards are not reported.
+    ///
+    /// Can also be set with the `--shard=<index>/<total>` option.
+    pub fn run_sharded(
+        self,
+        shard_index: usize,
+        total_shards: usize,
+        reporter: &Reporter<'a>,
+    ) -> Result<Summary, Error> {
+        check_shard(shard_index, total_shards)?;
+
+        Self {
+            shard: Some((shard_index, total_shards)),
+            ..self
+        }.run(reporter)
     }
 
-    fn run_in_parallel(reporter: &Reporter<'a>, tests: Vec<Test<'a>>, done: impl FnOnce()) {
+    fn run_in_parallel(
+        reporter: &Reporter<'a>,
+        tests: Vec<Test<'a>>,
+        modules: &HashMap<Cow<'a, str>, ModuleState<'a>>,
+        options: RunOptions,
+        done: impl FnOnce(),
+    ) -> Summary {
         use rayon::prelude::*;
 
         let catch = Arc::new(Mutex::new(HashMap::new()));
         let local_catch = catch.clone();
 
+        // the previous hook is restored once all tests have run.
+        let previous_hook = panic::take_hook();
+
         panic::set_hook(Box::new(move |info| {
             let id = thread::current().id();
 
@@ -203,27 +822,66 @@ impl<'a> TestRunner<'a> {
             catch.message = payload_to_message(info.payload());
         }));
 
-        let index = atomic::AtomicUsize::new(0usize);
+        let next = atomic::AtomicUsize::new(0usize);
+
+        let results = tests.into_par_iter().flat_map(|test| {
+            let index = next.fetch_add(1usize, atomic::Ordering::Relaxed);
+
+            if let Err(e) = reporter.report_started(index, &test.name) {
+                println!("error in reporting: {}", e);
+            }
 
-        let results = tests.into_par_iter().map(|test| {
-            let index = index.fetch_add(1usize, atomic::Ordering::Relaxed);
+            let module = match test.module {
+                Some(ref module) => modules.get(module),
+                None => None,
+            };
 
-            match reporter.report_started(index, &test.name) {
-                Err(e) => println!("error in reporting: {}", e),
-                Ok(()) => {}
+            let module = match module {
+                Some(module) => module,
+                None => {
+                    let result = Self::run_one_test(test, catch.clone(), options);
+                    return vec![(index, result)];
+                }
+            };
+
+            let result = match module.before(&catch) {
+                Ok(()) => Self::run_one_test(test, catch.clone(), options),
+                Err(message) => TestResult {
+                    module: test.module,
+                    name: test.name,
+                    outcome: Outcome::Errored(format_err!("before_all hook failed: {}", message)),
+                    duration: time::Duration::default(),
+                    output: None,
+                    bench: None,
+                    artifacts: None,
+                    gas_used: None,
+                },
+            };
+
+            let mut results = vec![(index, result)];
+
+            if let Some(result) = module.after(&catch) {
+                let index = next.fetch_add(1usize, atomic::Ordering::Relaxed);
+                results.push((index, result));
             }
 
-            (index, Self::run_one_test(test, catch.clone()))
+            results
         });
 
-        results.for_each(|(index, r)| match reporter.report(index, r) {
-            Err(e) => println!("error in reporting: {}", e),
-            Ok(()) => {}
+        let summary = Mutex::new(Summary::default());
+
+        results.for_each(|(index, r)| {
+            summary.lock().expect("poisoned lock").record(&r.outcome);
+
+            if let Err(e) = reporter.report(index, r) {
+                println!("error in reporting: {}", e);
+            }
         });
 
-        let _ = panic::take_hook();
+        panic::set_hook(previous_hook);
 
         done();
+        return summary.into_inner().expect("poisoned lock");
 
         /// downcast the info payload to a string message.
         fn payload_to_message(any: &any::Any) -> Option<String> {
@@ -239,74 +897,276 @@ impl<'a> TestRunner<'a> {
         }
     }
 
+    /// Run the tests in the given thread pool, or in the global one if none is given.
+    fn run_in_pool(
+        pool: Option<&rayon::ThreadPool>,
+        reporter: &Reporter<'a>,
+        tests: Vec<Test<'a>>,
+        modules: &HashMap<Cow<'a, str>, ModuleState<'a>>,
+        options: RunOptions,
+        done: impl FnOnce() + Send,
+    ) -> Summary {
+        match pool {
+            Some(pool) => {
+                pool.install(move || Self::run_in_parallel(reporter, tests, modules, options, done))
+            }
+            None => Self::run_in_parallel(reporter, tests, modules, options, done),
+        }
+    }
+
     /// Run all registered tests, while applying the given filter on their name.
     ///
-    /// All strings specified in the filter must be apart of the name of a test to include it.
+    /// A test is included if every filter matches either its name or its module. Filters are
+    /// regular expressions, which also match if they are a part of the name of the test. Filters
+    /// prefixed with `!` instead exclude every test that they match, like `ledger.*` `!slow`.
+    ///
+    /// Filters starting with `--` are options, which are:
     ///
-    /// Note: this installs a panic hook, so mixing this with another component that fiddles with
-    /// the hook will cause unexpected results.
-    pub fn run_with_filters<F>(self, filters: F, reporter: &Reporter<'a>) -> Result<(), Error>
+    /// * `--sequential`, to run tests one at a time, in order. Can't be used together with
+    ///   `--test-threads`.
+    /// * `--order=<registration|alphabetical|shuffled>`, to set the order to start tests in.
+    /// * `--shuffle-seed=<seed>`, to shuffle tests using the given seed, which implies
+    ///   `--order=shuffled`.
+    /// * `--test-threads=<n>`, to run at most `n` tests in parallel.
+    /// * `--shard=<index>/<total>`, to only run a single shard of the tests.
+    /// * `--list`, to list the tests which would run instead of running them.
+    /// * `--artifacts=<dir>`, to write artifacts of failing tests to the given directory.
+    ///
+    /// Note: this installs a panic hook while tests are running, and restores the previous one
+    /// afterwards. Panics on other threads while tests are running are not reported by the
+    /// previous hook.
+    pub fn run_with_filters<F>(self, filters: F, reporter: &Reporter<'a>) -> Result<Summary, Error>
     where
         F: IntoIterator<Item = String>,
     {
-        use rayon;
+        let TestRunner {
+            tests: all_tests,
+            hooks,
+            mut max_parallelism,
+            mut shard,
+            mut order,
+            mut artifacts_dir,
+        } = self;
+
+        let mut patterns = Vec::new();
+        let mut list = false;
+        let mut order_option = None;
+        let mut shuffle_seed = None;
+        let mut sequential = false;
+        let mut test_threads = None;
+
+        for filter in filters {
+            if filter == "--list" {
+                list = true;
+                continue;
+            }
+
+            if filter == "--sequential" {
+                sequential = true;
+                continue;
+            }
+
+            if let Some(value) = filter.strip_prefix("--test-threads=") {
+                let value = value
+                    .parse::<usize>()
+                    .map_err(|e| format_err!("bad value for `--test-threads`: {}: {}", value, e))?;
+
+                test_threads = Some(value);
+                continue;
+            }
+
+            if let Some(value) = filter.strip_prefix("--order=") {
+                order_option = Some(match value {
+                    "registration" => Order::Registration,
+                    "alphabetical" => Order::Alphabetical,
+                    "shuffled" => Order::Shuffled(None),
+                    other => bail!("bad value for `--order`: {}", other),
+                });
+
+                continue;
+            }
+
+            if let Some(value) = filter.strip_prefix("--shuffle-seed=") {
+                let seed = value
+                    .parse::<u64>()
+                    .map_err(|e| format_err!("bad value for `--shuffle-seed`: {}: {}", value, e))?;
+
+                shuffle_seed = Some(seed);
+                continue;
+            }
 
-        let filters = filters.into_iter().collect::<HashSet<_>>();
+            if let Some(value) = filter.strip_prefix("--shard=") {
+                shard = Some(parse_shard(value)?);
+                continue;
+            }
+
+            if let Some(value) = filter.strip_prefix("--artifacts=") {
+                artifacts_dir = Some(PathBuf::from(value));
+                continue;
+            }
+
+            if filter.starts_with("--") {
+                bail!("unsupported option: {}", filter);
+            }
+
+            patterns.push(filter::Filter::parse(&filter));
+        }
+
+        match (sequential, test_threads) {
+            (true, Some(_)) => bail!("`--sequential` can't be used together with `--test-threads`"),
+            (true, None) => max_parallelism = Some(1),
+            (false, Some(value)) => max_parallelism = Some(value),
+            (false, None) => {}
+        }
+
+        // the seed is resolved after the order, so that options can be given in any order.
+        match (order_option, shuffle_seed) {
+            (None, Some(seed)) | (Some(Order::Shuffled(_)), Some(seed)) => {
+                order = Order::Shuffled(Some(seed));
+            }
+            (Some(_), Some(_)) => bail!("`--shuffle-seed` can only be used with `--order=shuffled`"),
+            (Some(option), None) => order = option,
+            (None, None) => {}
+        }
+
+        let filters = patterns;
 
         let mut tests = Vec::new();
+        let mut skipped = 0;
 
-        for test in self.tests {
-            let matches_module =
-                |test: &Test, f| test.module.as_ref().map(|m| m == f).unwrap_or(false);
+        for (index, test) in all_tests.into_iter().enumerate() {
+            if let Some((shard_index, total_shards)) = shard {
+                if index % total_shards != shard_index {
+                    continue;
+                }
+            }
 
-            if filters
-                .iter()
-                .all(|f| test.name.contains(f) || matches_module(&test, f))
-            {
+            let selected = {
+                let module = test.module.as_ref().map(|m| m.as_ref());
+                filter::is_selected(&filters, module, &test.name)
+            };
+
+            if selected {
                 tests.push(test);
-            } else {
+            } else if !list {
+                skipped += 1;
                 reporter.report_skipped(test)?;
             }
         }
 
-        let done = atomic::AtomicBool::new(false);
+        if let Some(seed) = order.sort(&mut tests) {
+            reporter.report_shuffled(seed)?;
+        }
 
-        if reporter.supports_animation()? {
-            rayon::scope(|s| {
-                s.spawn(|s| {
-                    s.spawn(|_| {
-                        while !done.load(atomic::Ordering::Acquire) {
-                            thread::sleep(time::Duration::from_millis(100));
-                            reporter.animate().expect("animation failed");
-                        }
-                    });
+        if list {
+            for test in &tests {
+                reporter.report_listed(test)?;
+            }
 
-                    Self::run_in_parallel(reporter, tests, || {
-                        done.store(true, atomic::Ordering::Release)
-                    });
-                });
+            return Ok(Summary::default());
+        }
+
+        let mut modules = HashMap::new();
+
+        for (name, hooks) in hooks {
+            let remaining = tests
+                .iter()
+                .filter(|test| test.module.as_ref() == Some(&name))
+                .count();
+
+            // hooks of modules without any tests to run are never run.
+            if remaining == 0 {
+                continue;
+            }
+
+            modules.insert(
+                name.clone(),
+                ModuleState {
+                    name,
+                    hooks: Mutex::new(hooks),
+                    before: Mutex::new(None),
+                    remaining: atomic::AtomicUsize::new(remaining),
+                },
+            );
+        }
+
+        let pool = match max_parallelism {
+            Some(max_parallelism) => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(max_parallelism)
+                    .build()
+                    .map_err(|e| format_err!("failed to build thread pool: {}", e))?,
+            ),
+            None => None,
+        };
+
+        let options = RunOptions {
+            artifacts_dir: artifacts_dir.as_deref(),
+            // stdout and stderr are shared by every thread, so they can only be attributed to a
+            // test if it's the only one running.
+            capture_stdio: max_parallelism == Some(1),
+        };
+
+        let done = atomic::AtomicBool::new(false);
+
+        // animating would write to the captured stdout.
+        let summary = if !options.capture_stdio && reporter.supports_animation()? {
+            let summary = Mutex::new(Summary::default());
+
+            rayon::scope(|s| {
+                s.spawn(|s| {
+                    s.spawn(|_| {
+                        while !done.load(atomic::Ordering::Acquire) {
+                            thread::sleep(time::Duration::from_millis(100));
+                            reporter.animate().expect("animation failed");
+                        }
+                    });
+
+                    let result = Self::run_in_pool(
+                        pool.as_ref(),
+                        reporter,
+                        tests,
+                        &modules,
+                        options,
+                        || done.store(true, atomic::Ordering::Release),
+                    );
+
+                    *summary.lock().expect("poisoned lock") = result;
+                });
             });
+
+            summary
+                .into_inner()
+                .map_err(|_| format_err!("another lock is held"))?
         } else {
-            Self::run_in_parallel(reporter, tests, || {});
-        }
+            Self::run_in_pool(pool.as_ref(), reporter, tests, &modules, options, || {})
+        };
 
         reporter.end()?;
-        return Ok(());
+
+        Ok(Summary {
+            skipped,
+            ..summary
+        })
     }
 
     /// Internal function to register a test.
-    fn internal_test<N: Into<Cow<'a, str>>, F: 'a, T>(
+    fn internal_test<N: Into<Cow<'a, str>>, F, T>(
         &mut self,
         module: Option<Cow<'a, str>>,
         name: N,
+        expect: Expect<'a>,
+        retries: usize,
         entry: F,
     ) where
-        F: Fn() -> T + Send,
+        F: 'a + Fn() -> T + Send,
         T: IntoResult<()>,
     {
         self.tests.push(Test {
             module,
             name: name.into(),
+            expect,
+            retries,
             entry: Box::new(entry),
         })
     }
@@ -315,57 +1175,167 @@ impl<'a> TestRunner<'a> {
     fn run_one_test(
         test: Test<'a>,
         catch: Arc<Mutex<HashMap<thread::ThreadId, PanicInfo>>>,
+        options: RunOptions,
     ) -> TestResult<'a> {
         let Test {
             module,
             name,
+            expect,
+            retries,
             entry,
             ..
         } = test;
+
+        let _context = match module {
+            Some(ref module) => context::enter(format!("{}::{}", module, name)),
+            None => context::enter(name.as_ref()),
+        };
+
         let start = time::Instant::now();
-        let res = panic::catch_unwind(panic::AssertUnwindSafe(move || entry.run()));
+        let mut attempts = 0;
+
+        let outcome = loop {
+            attempts += 1;
+
+            // every attempt starts over, so that only the output, gas, and artifacts of the last
+            // attempt are reported.
+            capture::start();
+            context::start_gas_accounting();
+            bench::take();
+
+            if options.artifacts_dir.is_some() {
+                artifacts::start();
+            }
+
+            let stdio = if options.capture_stdio {
+                capture::start_stdio()
+            } else {
+                None
+            };
+
+            let res = panic::catch_unwind(panic::AssertUnwindSafe(|| entry.run()));
+
+            if let Some(stdio) = stdio {
+                stdio.finish();
+            }
+
+            let outcome = match res {
+                Err(_) => {
+                    let id = thread::current().id();
+
+                    let mut catch = catch.lock().expect("poisoned lock");
+                    let catch = catch.remove(&id).unwrap_or_default();
+
+                    Outcome::Failed(catch)
+                }
+                Ok(Err(e)) => Outcome::Errored(e),
+                Ok(Ok(())) => Outcome::Ok,
+            };
+
+            let outcome = match expect {
+                Expect::Pass => outcome,
+                Expect::Fail(ref expected) => {
+                    outcome.expect_failure(expected.as_ref().map(|e| e.as_ref()))
+                }
+            };
+
+            if outcome.is_ok() || attempts > retries {
+                break outcome;
+            }
+        };
+
         let end = time::Instant::now();
         let duration = end.duration_since(start);
 
-        let out = match res {
-            Err(_) => {
-                let id = thread::current().id();
-
-                let mut catch = catch.lock().expect("poisoned lock");
-                let mut catch = catch.remove(&id).unwrap_or_else(PanicInfo::default);
+        let output = capture::finish();
+        let bench = bench::take();
+        let gas_used = context::finish_gas_accounting().and_then(|gas| match gas {
+            0 => None,
+            gas => Some(gas),
+        });
+        let recorded = artifacts::finish();
+
+        let artifacts = match options.artifacts_dir {
+            Some(dir) if !outcome.is_ok() => {
+                let written = artifacts::write(
+                    dir,
+                    module.as_ref().map(|m| m.as_ref()),
+                    &name,
+                    &outcome.describe_failure(),
+                    output.as_deref(),
+                    &recorded,
+                );
 
-                TestResult {
-                    module,
-                    name,
-                    outcome: Outcome::Failed(catch),
-                    duration,
+                match written {
+                    Ok(path) => Some(path),
+                    Err(e) => {
+                        println!("error in writing artifacts: {}", e);
+                        None
+                    }
                 }
             }
-            Ok(Err(e)) => TestResult {
-                module,
-                name,
-                outcome: Outcome::Errored(e),
-                duration,
-            },
-            Ok(Ok(())) => TestResult {
-                module,
-                name,
-                outcome: Outcome::Ok,
-                duration,
-            },
+            _ => None,
         };
 
-        return out;
+        let outcome = match outcome {
+            Outcome::Ok if attempts > 1 => Outcome::Flaky(attempts),
+            outcome => outcome,
+        };
+
+        TestResult {
+            module,
+            name,
+            outcome,
+            duration,
+            output,
+            bench,
+            artifacts,
+            gas_used,
+        }
+    }
+}
+
+impl<'a> Default for TestRunner<'a> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl<'a> Suite<'a> for TestRunner<'a> {
-    fn test<N: Into<Cow<'a, str>>, F: 'a, T>(&mut self, name: N, entry: F)
+    fn test<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.internal_test(None, name, Expect::Pass, 0, entry)
+    }
+
+    fn should_fail<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.internal_test(None, name, Expect::Fail(None), 0, entry)
+    }
+
+    fn should_fail_with<N: Into<Cow<'a, str>>, M: Into<Cow<'a, str>>, F, T>(
+        &mut self,
+        name: N,
+        message: M,
+        entry: F,
+    ) where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.internal_test(None, name, Expect::Fail(Some(message.into())), 0, entry)
+    }
+
+    fn test_with_retries<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, retries: usize, entry: F)
     where
-        F: Fn() -> T + Send,
+        F: 'a + Fn() -> T + Send,
         T: IntoResult<()>,
     {
-        self.internal_test(None, name, entry)
+        self.internal_test(None, name, Expect::Pass, retries, entry)
     }
 }
 
@@ -374,24 +1344,94 @@ pub struct ModuleRunner<'m, 'a: 'm> {
     name: Cow<'a, str>,
 }
 
+impl<'m, 'a: 'm> ModuleRunner<'m, 'a> {
+    /// Register a hook which runs once before any test in the module, replacing any previously
+    /// registered hook.
+    ///
+    /// Tests in other modules keep running in parallel with the hook. If the hook fails, all tests
+    /// in the module are reported as errored.
+    pub fn before_all<F, T>(&mut self, hook: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.hooks().before_all = Some(Box::new(hook));
+    }
+
+    /// Register a hook which runs once after all tests in the module have completed, replacing any
+    /// previously registered hook.
+    ///
+    /// If the hook fails, it's reported as an errored test named `after_all`.
+    pub fn after_all<F, T>(&mut self, hook: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.hooks().after_all = Some(Box::new(hook));
+    }
+
+    /// Access the hooks of the module.
+    fn hooks(&mut self) -> &mut ModuleHooks<'a> {
+        self.test_runner.hooks.entry(self.name.clone()).or_default()
+    }
+}
+
 impl<'m, 'a: 'm> Suite<'a> for ModuleRunner<'m, 'a> {
-    fn test<N: Into<Cow<'a, str>>, F: 'a, T>(&mut self, name: N, entry: F)
+    fn test<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
     where
-        F: Fn() -> T + Send,
+        F: 'a + Fn() -> T + Send,
         T: IntoResult<()>,
     {
         self.test_runner
-            .internal_test(Some(self.name.clone()), name, entry)
+            .internal_test(Some(self.name.clone()), name, Expect::Pass, 0, entry)
+    }
+
+    fn should_fail<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.test_runner
+            .internal_test(Some(self.name.clone()), name, Expect::Fail(None), 0, entry)
+    }
+
+    fn should_fail_with<N: Into<Cow<'a, str>>, M: Into<Cow<'a, str>>, F, T>(
+        &mut self,
+        name: N,
+        message: M,
+        entry: F,
+    ) where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        let expect = Expect::Fail(Some(message.into()));
+
+        self.test_runner
+            .internal_test(Some(self.name.clone()), name, expect, 0, entry)
+    }
+
+    fn test_with_retries<N: Into<Cow<'a, str>>, F, T>(&mut self, name: N, retries: usize, entry: F)
+    where
+        F: 'a + Fn() -> T + Send,
+        T: IntoResult<()>,
+    {
+        self.test_runner
+            .internal_test(Some(self.name.clone()), name, Expect::Pass, retries, entry)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Suite;
-    use super::{Outcome, TestRunner};
-    use reporter::CollectingReporter;
+    use super::{Order, Outcome, Summary, TestRunner};
+    use artifacts;
+    use capture;
+    use context;
+    use reporter::{CollectingReporter, JsonReporter};
     use std::collections::HashMap;
     use std::iter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
     #[test]
     pub fn test_runner() {
@@ -400,9 +1440,21 @@ mod tests {
         runner.test("my success", my_success);
 
         let reporter = CollectingReporter::new();
-        runner
+        let summary = runner
             .run_with_filters(iter::empty(), &reporter)
             .expect("tests to run");
+
+        assert_eq!(
+            Summary {
+                passed: 1,
+                failed: 1,
+                skipped: 0,
+                flaky: 0,
+            },
+            summary
+        );
+        assert_eq!(1, summary.exit_code());
+
         let result = reporter.take_results().expect("bad results");
 
         let result = result
@@ -416,22 +1468,486 @@ mod tests {
         );
 
         match result.get("my failure").map(|r| &r.outcome) {
-            Some(&Outcome::Failed(ref info)) => {
+            Some(Outcome::Failed(info)) => {
                 assert!(info.location.is_some());
                 assert_eq!(
                     Some("my_failure_message"),
-                    info.message.as_ref().map(|m| m.as_str())
+                    info.message.as_deref()
                 );
             }
             _ => panic!("expected failure outcome"),
         }
 
         fn my_failure() {
-            assert!(false, "my_failure_message");
+            panic!("my_failure_message");
+        }
+
+        fn my_success() {}
+    }
+
+    #[test]
+    pub fn test_should_fail() {
+        let mut runner = TestRunner::new();
+        runner.should_fail("expected failure", broken);
+        runner.should_fail_with("expected message", "broken", broken);
+        runner.should_fail_with("wrong message", "other", broken);
+        runner.should_fail("unexpected pass", || {});
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let result = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name.to_string(), result))
+            .collect::<HashMap<_, _>>();
+
+        for name in &["expected failure", "expected message"] {
+            match result.get(*name).map(|r| &r.outcome) {
+                Some(Outcome::ExpectedFailure(message)) => {
+                    assert_eq!(Some("broken"), message.as_ref().map(|m| m.as_str()));
+                }
+                _ => panic!("expected `{}` to fail as expected", name),
+            }
+        }
+
+        for name in &["wrong message", "unexpected pass"] {
+            match result.get(*name).map(|r| &r.outcome) {
+                Some(&Outcome::Failed(..)) => {}
+                _ => panic!("expected `{}` to fail", name),
+            }
+        }
+
+        fn broken() {
+            panic!("broken");
+        }
+    }
+
+    #[test]
+    pub fn test_retries() {
+        let flaky = AtomicUsize::new(0);
+        let broken = AtomicUsize::new(0);
+
+        let mut runner = TestRunner::new();
+
+        runner.test_with_retries("flaky", 2, || {
+            let attempt = flaky.fetch_add(1, Ordering::SeqCst);
+            capture::write(format_args!("attempt {}\n", attempt));
+            context::record_gas_used(100);
+            assert!(attempt >= 1, "first attempt fails");
+        });
+
+        runner.test_with_retries("broken", 2, || {
+            let attempt = broken.fetch_add(1, Ordering::SeqCst);
+            assert!(attempt > 2, "always fails");
+        });
+
+        runner.test_with_retries("stable", 2, || {});
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let result = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name.to_string(), result))
+            .collect::<HashMap<_, _>>();
+
+        match result.get("flaky").map(|r| &r.outcome) {
+            Some(&Outcome::Flaky(2)) => {}
+            _ => panic!("expected `flaky` to pass after two attempts"),
+        }
+
+        // only the last attempt is reported.
+        let flaky_result = result.get("flaky").expect("flaky result");
+        assert_eq!(Some("attempt 1\n"), flaky_result.output.as_deref());
+        assert_eq!(Some(100), flaky_result.gas_used);
+
+        match result.get("broken").map(|r| &r.outcome) {
+            Some(&Outcome::Failed(..)) => {}
+            _ => panic!("expected `broken` to fail"),
         }
 
-        fn my_success() {
-            assert!(true);
+        match result.get("stable").map(|r| &r.outcome) {
+            Some(&Outcome::Ok) => {}
+            _ => panic!("expected `stable` to pass"),
+        }
+
+        assert_eq!(2, flaky.load(Ordering::SeqCst));
+        assert_eq!(3, broken.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    pub fn test_each() {
+        let mut runner = TestRunner::new();
+        runner.test_each("positive", vec![1i32, -2, 3], |case| {
+            assert!(*case > 0, "not positive");
+        });
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let result = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name.to_string(), result.outcome.is_ok()))
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(3, result.len());
+        assert_eq!(Some(&true), result.get("positive (1)"));
+        assert_eq!(Some(&false), result.get("positive (-2)"));
+        assert_eq!(Some(&true), result.get("positive (3)"));
+    }
+
+    #[test]
+    pub fn test_module_hooks() {
+        let events = Mutex::new(Vec::new());
+
+        let mut runner = TestRunner::new();
+
+        {
+            let mut module = runner.module("hooked");
+            module.before_all(|| events.lock().unwrap().push("before"));
+            module.after_all(|| events.lock().unwrap().push("after"));
+            module.test("first", || events.lock().unwrap().push("test"));
+            module.test("second", || events.lock().unwrap().push("test"));
+        }
+
+        {
+            let mut module = runner.module("broken");
+            module.before_all(no_setup);
+            module.test("first", || {});
+        }
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let results = reporter.take_results().expect("bad results");
+        assert_eq!(3, results.len());
+
+        for result in &results {
+            let module = result.module.as_ref().map(|m| m.as_ref());
+
+            match (module, &result.outcome) {
+                (Some("hooked"), &Outcome::Ok) => {}
+                (Some("broken"), Outcome::Errored(e)) => {
+                    assert_eq!("before_all hook failed: no setup", e.to_string());
+                }
+                _ => panic!("unexpected result for `{}`", result.name),
+            }
+        }
+
+        assert_eq!(
+            vec!["before", "test", "test", "after"],
+            events.into_inner().unwrap()
+        );
+
+        fn no_setup() {
+            panic!("no setup");
+        }
+    }
+
+    #[test]
+    pub fn test_sequential() {
+        let order = Mutex::new(Vec::new());
+
+        let mut runner = TestRunner::new();
+
+        for index in 0..16 {
+            let order = &order;
+            runner.test(format!("test {}", index), move || {
+                order.lock().unwrap().push(index)
+            });
+        }
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(vec![String::from("--sequential")], &reporter)
+            .expect("tests to run");
+
+        assert_eq!((0..16).collect::<Vec<_>>(), order.into_inner().unwrap());
+
+        let runner = TestRunner::new();
+        let reporter = CollectingReporter::new();
+
+        assert!(
+            runner
+                .run_with_filters(vec![String::from("--test-threads=many")], &reporter)
+                .is_err()
+        );
+
+        let runner = TestRunner::new();
+        let reporter = CollectingReporter::new();
+
+        assert!(
+            runner
+                .run_with_filters(
+                    vec![
+                        String::from("--test-threads=4"),
+                        String::from("--sequential"),
+                    ],
+                    &reporter
+                ).is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn test_capture_stdio() {
+        use std::io::{self, Write};
+
+        let mut runner = TestRunner::new().sequential();
+
+        runner.test("prints", || {
+            writeln!(io::stdout(), "raw output").expect("failed to write");
+            capture::write(format_args!("captured output\n"));
+        });
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let results = reporter.take_results().expect("bad results");
+        let output = results[0].output.as_ref().expect("no output");
+
+        assert!(output.contains("raw output\n"));
+        assert!(output.contains("captured output\n"));
+    }
+
+    #[test]
+    pub fn test_sharded() {
+        let mut seen = Vec::new();
+
+        for shard in 0..3 {
+            let mut runner = TestRunner::new();
+
+            for index in 0..10 {
+                runner.test(format!("test {}", index), || {});
+            }
+
+            let reporter = CollectingReporter::new();
+            runner
+                .run_with_filters(vec![format!("--shard={}/3", shard)], &reporter)
+                .expect("tests to run");
+
+            let results = reporter.take_results().expect("bad results");
+            assert!(results.len() >= 3);
+
+            seen.extend(results.into_iter().map(|r| r.name.to_string()));
+        }
+
+        // every test is run by exactly one shard.
+        assert_eq!(10, seen.len());
+        seen.sort();
+        seen.dedup();
+        assert_eq!(10, seen.len());
+
+        assert!(super::parse_shard("3/3").is_err());
+        assert!(super::parse_shard("1").is_err());
+        assert_eq!((1, 4), super::parse_shard("1/4").unwrap());
+    }
+
+    #[test]
+    pub fn test_list() {
+        let mut runner = TestRunner::new();
+        runner.test("transfer", should_not_run);
+        runner.module("token").test("approve", should_not_run);
+        runner.test("burn", should_not_run);
+
+        let reporter = JsonReporter::with_output(Vec::new());
+        runner
+            .run_with_filters(vec![String::from("--list"), String::from("!burn")], &reporter)
+            .expect("tests to list");
+
+        let out = String::from_utf8(reporter.into_output().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                r#"{"type":"test","event":"listed","module":null,"name":"transfer"}"#,
+                r#"{"type":"test","event":"listed","module":"token","name":"approve"}"#,
+            ],
+            out.lines().collect::<Vec<_>>()
+        );
+
+        fn should_not_run() {
+            panic!("should not run");
+        }
+    }
+
+    #[test]
+    pub fn test_captured_output() {
+        let mut runner = TestRunner::new();
+
+        runner.test("quiet", || {
+            capture::write(format_args!("passing\n"));
+        });
+
+        runner.test("noisy", noisy);
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let result = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name.to_string(), result.output))
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(Some(&Some(String::from("passing\n"))), result.get("quiet"));
+        assert_eq!(Some(&Some(String::from("failing\n"))), result.get("noisy"));
+        assert!(!capture::is_capturing());
+
+        fn noisy() {
+            capture::write(format_args!("failing\n"));
+            panic!("broken");
+        }
+    }
+
+    #[test]
+    pub fn test_order() {
+        let names = |order: Order| {
+            let order_mutex = Mutex::new(Vec::new());
+
+            {
+                let mut runner = TestRunner::new().sequential().order(order);
+
+                for name in &["b", "c", "a", "e", "d"] {
+                    let order = &order_mutex;
+                    runner.test(*name, move || order.lock().unwrap().push(*name));
+                }
+
+                let reporter = CollectingReporter::new();
+                runner
+                    .run_with_filters(iter::empty(), &reporter)
+                    .expect("tests to run");
+            }
+
+            order_mutex.into_inner().unwrap()
+        };
+
+        assert_eq!(vec!["b", "c", "a", "e", "d"], names(Order::Registration));
+        assert_eq!(vec!["a", "b", "c", "d", "e"], names(Order::Alphabetical));
+
+        let shuffled = names(Order::Shuffled(Some(42)));
+        assert_eq!(shuffled, names(Order::Shuffled(Some(42))));
+
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        assert_eq!(vec!["a", "b", "c", "d", "e"], sorted);
+    }
+
+    #[test]
+    pub fn test_order_options() {
+        let names = |filters: &[&str]| {
+            let order_mutex = Mutex::new(Vec::new());
+
+            {
+                let mut runner = TestRunner::new().sequential();
+
+                for index in 0..16 {
+                    let order = &order_mutex;
+                    runner.test(format!("test {}", index), move || {
+                        order.lock().unwrap().push(index)
+                    });
+                }
+
+                let filters = filters.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+
+                let reporter = CollectingReporter::new();
+                runner
+                    .run_with_filters(filters, &reporter)
+                    .expect("tests to run");
+            }
+
+            order_mutex.into_inner().unwrap()
+        };
+
+        let seeded = names(&["--shuffle-seed=42"]);
+        assert_ne!((0..16).collect::<Vec<_>>(), seeded);
+        assert_eq!(seeded, names(&["--order=shuffled", "--shuffle-seed=42"]));
+        assert_eq!(seeded, names(&["--shuffle-seed=42", "--order=shuffled"]));
+
+        let runner = TestRunner::new();
+        let reporter = CollectingReporter::new();
+
+        assert!(
+            runner
+                .run_with_filters(
+                    vec![
+                        String::from("--shuffle-seed=42"),
+                        String::from("--order=alphabetical"),
+                    ],
+                    &reporter
+                ).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_artifacts() {
+        use std::env;
+        use std::fs;
+        use std::process;
+
+        let dir = env::temp_dir().join(format!("parables-artifacts-{}", process::id()));
+
+        let mut runner = TestRunner::new().artifacts_dir(&dir);
+
+        {
+            let mut module = runner.module("token");
+
+            module.test("passes", || {
+                artifacts::record("trace.json", "[]");
+            });
+
+            module.test("fails", fails);
+        }
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let result = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name.to_string(), result.artifacts))
+            .collect::<HashMap<_, _>>();
+
+        let written = dir.join("token__fails");
+
+        assert_eq!(Some(&None), result.get("passes"));
+        assert_eq!(Some(&Some(written.clone())), result.get("fails"));
+        assert!(!dir.join("token__passes").exists());
+
+        let read = |file: &str| fs::read_to_string(written.join(file)).expect("artifact");
+
+        assert!(read("failure.txt").starts_with("panicked: broken\n"));
+        assert_eq!("balance: 1\n", read("output.txt"));
+        assert_eq!("[{\"depth\":0}]", read("trace.json"));
+        assert!(!artifacts::is_collecting());
+
+        fs::remove_dir_all(&dir).expect("remove artifacts");
+
+        fn fails() {
+            artifacts::record("trace.json", "[{\"depth\":0}]");
+            capture::write(format_args!("balance: {}\n", 1));
+            panic!("broken");
         }
     }
 
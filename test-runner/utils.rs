@@ -1,6 +1,57 @@
 use std::fmt;
 use std::time;
 
+/// Format a string as a quoted JSON string.
+pub struct JsonString<'a>(pub &'a str);
+
+impl<'a> fmt::Display for JsonString<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "\"")?;
+
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(fmt, "\\\"")?,
+                '\\' => write!(fmt, "\\\\")?,
+                '\n' => write!(fmt, "\\n")?,
+                '\r' => write!(fmt, "\\r")?,
+                '\t' => write!(fmt, "\\t")?,
+                c if (c as u32) < 0x20 => write!(fmt, "\\u{:04x}", c as u32)?,
+                c => write!(fmt, "{}", c)?,
+            }
+        }
+
+        write!(fmt, "\"")
+    }
+}
+
+/// A small deterministic random number generator (SplitMix64), used to shuffle tests in a way that
+/// can be reproduced from the seed.
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Shuffle the given items using the given seed.
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64::new(seed);
+
+    for i in (1..items.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
 /// Format a duration as a human-readable time duration.
 pub struct DurationFormat<'a>(pub &'a time::Duration);
 
@@ -18,3 +69,23 @@ impl<'a> fmt::Display for DurationFormat<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::shuffle;
+
+    #[test]
+    fn test_shuffle() {
+        let mut a = (0..32).collect::<Vec<_>>();
+        let mut b = a.clone();
+
+        shuffle(&mut a, 42);
+        shuffle(&mut b, 42);
+
+        assert_eq!(a, b);
+        assert_ne!((0..32).collect::<Vec<_>>(), a);
+
+        a.sort();
+        assert_eq!((0..32).collect::<Vec<_>>(), a);
+    }
+}
@@ -1,11 +1,13 @@
 //! Contract ABI helpers.
 
-use ethabi::{Bytes, RawLog, TopicFilter};
-use ethereum_types::Address;
+use ethabi::{self, Bytes, Event, EventParam, Hash, ParamType, RawLog, Token, Topic, TopicFilter};
+use ethereum_types::{Address, H256, U256};
 use failure::Error;
 use linker::Linker;
+use serde_json;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 use {call, evm};
 
 #[derive(Debug)]
@@ -20,6 +22,8 @@ pub struct ContractContext {
     pub source_list: Option<Vec<PathBuf>>,
     /// AST of sources.
     pub sources: HashMap<&'static str, FileSource>,
+    /// All events declared by loaded contracts.
+    pub events: Vec<Event>,
 }
 
 /// Contract functions generated by parables_build.
@@ -34,9 +38,156 @@ pub trait ContractFunction {
     fn output(&self, output_bytes: Bytes) -> Result<Self::Output, Error>;
 }
 
+/// A call with raw calldata, like a call to the fallback function of a contract.
+///
+/// The output of the call is returned as is.
+#[derive(Debug, Clone)]
+pub struct RawCall {
+    data: Bytes,
+}
+
+impl RawCall {
+    /// Build a new call with the given calldata.
+    pub fn new(data: impl Into<Bytes>) -> Self {
+        Self { data: data.into() }
+    }
+}
+
+impl ContractFunction for RawCall {
+    type Output = Bytes;
+
+    fn encoded(&self, _linker: &Linker) -> Result<Bytes, Error> {
+        Ok(self.data.clone())
+    }
+
+    fn output(&self, output_bytes: Bytes) -> Result<Self::Output, Error> {
+        Ok(output_bytes)
+    }
+}
+
+/// A call to a function with raw tokens as input.
+pub(crate) struct TokenCall<'a> {
+    pub function: &'a ethabi::Function,
+    pub tokens: Vec<Token>,
+}
+
+impl<'a> ContractFunction for TokenCall<'a> {
+    type Output = Vec<Token>;
+
+    fn encoded(&self, _linker: &Linker) -> Result<Bytes, Error> {
+        self.function
+            .encode_input(&self.tokens)
+            .map_err(|e| format_err!("failed to encode input: {}", e))
+    }
+
+    fn output(&self, output_bytes: Bytes) -> Result<Self::Output, Error> {
+        self.function
+            .decode_output(&output_bytes)
+            .map_err(|e| format_err!("failed to decode output: {}", e))
+    }
+}
+
+/// A plain transfer of ether, without any calldata.
+#[derive(Debug, Clone, Copy)]
+pub struct Transfer;
+
+impl ContractFunction for Transfer {
+    type Output = ();
+
+    fn encoded(&self, _linker: &Linker) -> Result<Bytes, Error> {
+        Ok(Bytes::new())
+    }
+
+    fn output(&self, _output_bytes: Bytes) -> Result<Self::Output, Error> {
+        Ok(())
+    }
+}
+
 /// Helpers for building log filters.
 pub trait LogFilter {
     fn wildcard_filter(&self) -> TopicFilter;
+
+    /// The number of topics of the event, if it is anonymous.
+    ///
+    /// Anonymous events don't have a first topic identifying them, so they are matched on their
+    /// number of topics instead.
+    fn anonymous_topics(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Build a topic filter for the given event.
+///
+/// `topics` are the filters for each indexed parameter of the event, in order. Parameters that are
+/// not specified match anything.
+///
+/// Errors if more topics are specified than the event has indexed parameters, or if filtering on
+/// a parameter which is stored as a hash is attempted.
+pub fn topic_filter(event: &Event, topics: Vec<Topic<Token>>) -> Result<TopicFilter, Error> {
+    let params: Vec<_> = event.inputs.iter().filter(|p| p.indexed).collect();
+
+    if topics.len() > params.len() {
+        bail!(
+            "event `{}` has {} indexed parameters, but {} topics were specified",
+            event.name,
+            params.len(),
+            topics.len()
+        );
+    }
+
+    let mut out = Vec::new();
+
+    if !event.anonymous {
+        out.push(Topic::This(event.signature()));
+    }
+
+    for (param, topic) in params.into_iter().zip(topics) {
+        let topic = match topic {
+            Topic::Any => Topic::Any,
+            Topic::This(token) => Topic::This(topic_hash(param, token)?),
+            Topic::OneOf(tokens) => Topic::OneOf(
+                tokens
+                    .into_iter()
+                    .map(|token| topic_hash(param, token))
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
+        out.push(topic);
+    }
+
+    if out.len() > 4 {
+        bail!(
+            "event `{}` has {} topics, but logs have at most 4",
+            event.name,
+            out.len()
+        );
+    }
+
+    let mut out = out.into_iter();
+
+    Ok(TopicFilter {
+        topic0: out.next().unwrap_or(Topic::Any),
+        topic1: out.next().unwrap_or(Topic::Any),
+        topic2: out.next().unwrap_or(Topic::Any),
+        topic3: out.next().unwrap_or(Topic::Any),
+    })
+}
+
+/// Encode the given token as the topic for an indexed parameter.
+fn topic_hash(param: &EventParam, token: Token) -> Result<Hash, Error> {
+    match param.kind {
+        ParamType::Bytes | ParamType::String | ParamType::Array(..) | ParamType::FixedArray(..) => {
+            bail!(
+                "cannot filter on parameter `{}` of type {:?}, since it is indexed as a hash",
+                param.name,
+                param.kind
+            );
+        }
+        _ => {}
+    }
+
+    Ok(Hash::from_slice(&ethabi::encode(&[token])))
 }
 
 /// Log parsing implementation.
@@ -66,6 +217,165 @@ pub trait Constructor {
 
     /// Access the runtime source map for the type this constructor is associated with.
     const RUNTIME_SOURCE_MAP: Option<&'static str>;
+
+    /// Regions of the runtime code which are reserved for immutable variables, as JSON.
+    const IMMUTABLE_REFERENCES: Option<&'static str> = None;
+
+    /// The storage layout of the contract, as JSON.
+    const STORAGE_LAYOUT: Option<&'static str> = None;
+
+    /// If the constructor accepts value.
+    const PAYABLE: bool = false;
+}
+
+/// A contract which is bound at runtime from its ABI, instead of being generated from its source.
+///
+/// This permits interacting with contracts that are not part of the compiled sources, like
+/// contracts deployed from bytecode or forked from a live network.
+///
+/// ```rust,ignore
+/// let token = DynamicContract::new(&evm, ERC20_ABI, address, call)?;
+/// let balance = token.query("balanceOf", &[Token::Address(owner)])?;
+/// token.call("transfer", &[Token::Address(to), Token::Uint(100.into())])?.ok()?;
+/// let transfers = evm.logs(token.event("Transfer")?).drain()?;
+/// ```
+pub struct DynamicContract<'a, VM: 'a> {
+    vm: &'a VM,
+    contract: Arc<ethabi::Contract>,
+    pub address: Address,
+    call: call::Call,
+}
+
+impl<'a, VM> Clone for DynamicContract<'a, VM> {
+    fn clone(&self) -> Self {
+        Self {
+            vm: self.vm,
+            contract: Arc::clone(&self.contract),
+            address: self.address,
+            call: self.call.clone(),
+        }
+    }
+}
+
+impl<'a, VM> DynamicContract<'a, VM>
+where
+    VM: Vm,
+{
+    /// Bind the contract with the given ABI, deployed at the given address.
+    ///
+    /// All calls are performed using the given template call.
+    pub fn new(vm: &'a VM, abi: &str, address: Address, call: call::Call) -> Result<Self, Error> {
+        let contract: ethabi::Contract =
+            serde_json::from_str(abi).map_err(|e| format_err!("failed to parse ABI: {}", e))?;
+
+        Ok(Self {
+            vm,
+            contract: Arc::new(contract),
+            address,
+            call,
+        })
+    }
+
+    /// Access the ABI of the contract.
+    pub fn abi(&self) -> &ethabi::Contract {
+        &self.contract
+    }
+
+    /// Modify the call for the contract.
+    pub fn with_call(self, call: call::Call) -> Self {
+        Self { call, ..self }
+    }
+
+    /// Modify the default sender for the contract.
+    pub fn sender(self, sender: Address) -> Self {
+        let call = self.call.sender(sender);
+        Self { call, ..self }
+    }
+
+    /// Modify the default value for a copy of the current contract.
+    pub fn value<V: Into<U256>>(self, value: V) -> Self {
+        let call = self.call.value(value);
+        Self { call, ..self }
+    }
+
+    /// Call the function with the given name and inputs.
+    pub fn call(&self, function: &str, tokens: &[Token]) -> Result<evm::Call<Vec<Token>>, Error> {
+        let function = self.function(function)?;
+        let tokens = tokens.to_vec();
+
+        self.vm
+            .call(self.address, TokenCall { function, tokens }, self.call.clone())
+    }
+
+    /// Perform a read-only query against the function with the given name, see `Vm::query`.
+    pub fn query(&self, function: &str, tokens: &[Token]) -> Result<Vec<Token>, Error> {
+        let function = self.function(function)?;
+        let tokens = tokens.to_vec();
+
+        self.vm
+            .query(self.address, TokenCall { function, tokens }, self.call.clone())
+    }
+
+    /// Access the event with the given name, which can be used to drain its logs.
+    pub fn event(&self, name: &str) -> Result<DynamicEvent, Error> {
+        let event = self
+            .contract
+            .event(name)
+            .map_err(|e| format_err!("no event `{}`: {}", name, e))?;
+
+        Ok(DynamicEvent {
+            event: event.clone(),
+        })
+    }
+
+    /// Access the function with the given name.
+    fn function(&self, name: &str) -> Result<&ethabi::Function, Error> {
+        self.contract
+            .function(name)
+            .map_err(|e| format_err!("no function `{}`: {}", name, e))
+    }
+}
+
+/// An event which is decoded at runtime, as returned by `DynamicContract::event`.
+#[derive(Debug, Clone)]
+pub struct DynamicEvent {
+    event: Event,
+}
+
+impl DynamicEvent {
+    /// Build a new dynamic event.
+    pub fn new(event: Event) -> Self {
+        Self { event }
+    }
+
+    /// Creates topic filter, with one topic for each indexed parameter.
+    pub fn filter(&self, topics: Vec<Topic<Token>>) -> Result<TopicFilter, Error> {
+        topic_filter(&self.event, topics)
+    }
+}
+
+impl ParseLog for DynamicEvent {
+    type Log = ethabi::Log;
+
+    fn parse_log(&self, log: RawLog) -> Result<Self::Log, Error> {
+        self.event
+            .parse_log(log)
+            .map_err(|e| format_err!("failed to parse log: {}", e))
+    }
+}
+
+impl LogFilter for DynamicEvent {
+    fn wildcard_filter(&self) -> TopicFilter {
+        topic_filter(&self.event, Vec::new()).expect("wildcard filter is always valid")
+    }
+
+    fn anonymous_topics(&self) -> Option<usize> {
+        if !self.event.anonymous {
+            return None;
+        }
+
+        Some(self.event.inputs.iter().filter(|p| p.indexed).count())
+    }
 }
 
 /// Virtual machine abstraction.
@@ -79,4 +389,61 @@ pub trait Vm {
     ) -> Result<evm::Call<F::Output>, Error>
     where
         F: ContractFunction;
+
+    /// Perform a read-only query against the given contract function.
+    ///
+    /// Nothing is committed by the query: no gas is deducted, the nonce of the sender is not
+    /// bumped, and no logs are recorded.
+    fn query<F>(&self, address: Address, f: F, call: call::Call) -> Result<F::Output, Error>
+    where
+        F: ContractFunction;
+
+    /// Read the value of a storage slot of the given contract.
+    fn storage_at(&self, address: Address, slot: H256) -> Result<H256, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::topic_filter;
+    use ethabi::{Event, EventParam, ParamType, Token, Topic};
+
+    fn event(anonymous: bool, kinds: Vec<ParamType>) -> Event {
+        Event {
+            name: "Test".to_string(),
+            inputs: kinds
+                .into_iter()
+                .enumerate()
+                .map(|(index, kind)| EventParam {
+                    name: format!("param{}", index),
+                    kind,
+                    indexed: true,
+                })
+                .collect(),
+            anonymous,
+        }
+    }
+
+    #[test]
+    fn test_topic_filter() {
+        let uint = |v: u64| Topic::This(Token::Uint(v.into()));
+
+        let e = event(true, vec![ParamType::Uint(256); 4]);
+        let filter = topic_filter(&e, vec![uint(1), Topic::Any, uint(3), uint(4)]).unwrap();
+        assert_eq!(Topic::This(1.into()), filter.topic0);
+        assert_eq!(Topic::Any, filter.topic1);
+        assert_eq!(Topic::This(4.into()), filter.topic3);
+
+        let e = event(false, vec![ParamType::Uint(256); 3]);
+        let filter = topic_filter(&e, vec![Topic::Any, uint(2)]).unwrap();
+        assert_eq!(Topic::This(e.signature()), filter.topic0);
+        assert_eq!(Topic::This(2.into()), filter.topic2);
+        assert_eq!(Topic::Any, filter.topic3);
+
+        assert!(topic_filter(&e, vec![Topic::Any; 4]).is_err());
+
+        let e = event(false, vec![ParamType::String]);
+        let string = Topic::This(Token::String("hello".to_string()));
+        assert!(topic_filter(&e, vec![string]).is_err());
+        assert!(topic_filter(&e, vec![Topic::Any]).is_ok());
+    }
 }
@@ -1,11 +1,29 @@
 use crypto::{keccak256, Crypto};
+use ethcore_transaction::{Action, Transaction};
 use ethereum_types::{Address, H160, H256, U256};
+use rand::Rng;
+use rlp::RlpStream;
+use rust_crypto::aes::{self, KeySize};
 use rust_crypto::digest::Digest;
+use rust_crypto::hmac::Hmac;
+use rust_crypto::pbkdf2::pbkdf2;
+use rust_crypto::scrypt::{scrypt, ScryptParams};
+use rust_crypto::sha2::Sha256;
 use rust_crypto::sha3::Sha3;
+use rust_crypto::symmetriccipher::SynchronousStreamCipher;
 use secp256k1::{self, key};
+use serde_json;
 use std::fmt;
 use std::sync::Arc;
 
+/// Scrypt parameters used when exporting keystores.
+///
+/// These correspond to the _light_ parameters used by geth, since keystores written by tests
+/// don't need to resist brute-forcing, but should be quick to read back.
+const SCRYPT_LOG_N: u8 = 12;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 6;
+
 #[derive(Debug, Fail)]
 pub enum AccountError {
     #[fail(display = "failed to derive public key: {}", error)]
@@ -16,8 +34,25 @@ pub enum AccountError {
     MessageError { error: secp256k1::Error },
     #[fail(display = "failed to borrow")]
     BorrowError,
+    #[fail(display = "invalid secret key: {}", reason)]
+    InvalidSecret { reason: String },
+    #[fail(display = "bad keystore: {}", reason)]
+    KeystoreError { reason: String },
+}
+
+impl AccountError {
+    fn keystore(reason: impl Into<String>) -> Self {
+        AccountError::KeystoreError {
+            reason: reason.into(),
+        }
+    }
 }
 
+/// An account which can sign transactions.
+///
+/// Accounts are cheap to clone since the signing context is shared, so they can be kept in
+/// fixtures which are snapshotted for every test.
+#[derive(Clone)]
 pub struct Account {
     secp: Arc<secp256k1::Secp256k1>,
     pub address: Address,
@@ -33,20 +68,189 @@ impl Account {
             ref mut rng,
         } = *crypto;
 
-        let (secret, public, address) = {
-            let secret = key::SecretKey::new(secp, rng);
-            let public = key::PublicKey::from_secret_key(secp, &secret)
-                .map_err(|error| AccountError::DerivePublicKeyError { error })?;
+        let secret = key::SecretKey::new(secp, rng);
+        Self::from_secret_key(secp, secret)
+    }
+
+    /// Import an account from a raw private key.
+    ///
+    /// The key can be provided as 32 bytes, or as a hex string with an optional `0x` prefix, like
+    /// the well-known default accounts of other development tooling.
+    pub fn from_secret(crypto: &Crypto, secret: impl AsSecret) -> Result<Account, AccountError> {
+        let secret = secret.as_secret()?;
+
+        let secret = key::SecretKey::from_slice(&crypto.secp, &secret[..]).map_err(|error| {
+            AccountError::InvalidSecret {
+                reason: error.to_string(),
+            }
+        })?;
+
+        Self::from_secret_key(&crypto.secp, secret)
+    }
+
+    /// Import an account from a Web3 Secret Storage keystore, as written by geth and parity.
+    ///
+    /// Both the scrypt and the pbkdf2 key derivation functions are supported.
+    pub fn from_keystore(
+        crypto: &Crypto,
+        keystore: &str,
+        password: &str,
+    ) -> Result<Account, AccountError> {
+        let keystore: Keystore = serde_json::from_str(keystore)
+            .map_err(|e| AccountError::keystore(format!("bad json: {}", e)))?;
+
+        if keystore.version != 3 {
+            return Err(AccountError::keystore(format!(
+                "unsupported version: {}",
+                keystore.version
+            )));
+        }
+
+        let c = keystore.crypto;
+
+        if c.cipher != "aes-128-ctr" {
+            return Err(AccountError::keystore(format!(
+                "unsupported cipher: {}",
+                c.cipher
+            )));
+        }
 
-            let address = {
-                let serialized = public.serialize_vec(secp, false);
-                // NB: important that we convert from H256 since `H256 -> H160` trims the leading bits.
-                // i.e.: 00 00 00 af ff ff ff ff -> af ff ff ff ff
-                let hash = H256::from(keccak256(&serialized[1..]));
-                Address::from(H160::from(hash))
-            };
+        let salt = decode_hex(&c.kdfparams.salt)?;
+        let ciphertext = decode_hex(&c.ciphertext)?;
+        let mac = decode_hex(&c.mac)?;
+        let iv = decode_hex(&c.cipherparams.iv)?;
 
-            (secret, public, address)
+        if iv.len() != 16 {
+            return Err(AccountError::keystore(format!(
+                "iv must be 16 bytes, but is {}",
+                iv.len()
+            )));
+        }
+
+        let mut derived = vec![0u8; c.kdfparams.dklen];
+
+        if derived.len() < 32 {
+            return Err(AccountError::keystore("derived key must be at least 32 bytes"));
+        }
+
+        match c.kdf.as_str() {
+            "scrypt" => {
+                let n = c.kdfparams.n.ok_or_else(|| AccountError::keystore("missing n"))?;
+                let r = c.kdfparams.r.ok_or_else(|| AccountError::keystore("missing r"))?;
+                let p = c.kdfparams.p.ok_or_else(|| AccountError::keystore("missing p"))?;
+
+                let params = scrypt_params(n, r, p)?;
+                scrypt(password.as_bytes(), &salt, &params, &mut derived);
+            }
+            "pbkdf2" => {
+                let rounds = c.kdfparams.c.ok_or_else(|| AccountError::keystore("missing c"))?;
+
+                if rounds == 0 {
+                    return Err(AccountError::keystore("c must be greater than zero"));
+                }
+
+                match c.kdfparams.prf.as_ref().map(|s| s.as_str()) {
+                    Some("hmac-sha256") => {}
+                    prf => {
+                        return Err(AccountError::keystore(format!(
+                            "unsupported prf: {:?}",
+                            prf
+                        )))
+                    }
+                }
+
+                let mut mac = Hmac::new(Sha256::new(), password.as_bytes());
+                pbkdf2(&mut mac, &salt, rounds, &mut derived);
+            }
+            kdf => return Err(AccountError::keystore(format!("unsupported kdf: {}", kdf))),
+        }
+
+        if keystore_mac(&derived, &ciphertext) != mac {
+            return Err(AccountError::keystore("MAC mismatch, wrong password?"));
+        }
+
+        let mut secret = vec![0u8; ciphertext.len()];
+        aes::ctr(KeySize::KeySize128, &derived[..16], &iv).process(&ciphertext, &mut secret);
+
+        Self::from_secret(crypto, &secret[..])
+    }
+
+    /// Export the account as a Web3 Secret Storage keystore, encrypted with the given password.
+    ///
+    /// The key is derived using scrypt.
+    pub fn to_keystore(&self, crypto: &mut Crypto, password: &str) -> Result<String, AccountError> {
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        let mut id = [0u8; 16];
+
+        crypto.rng.fill_bytes(&mut salt);
+        crypto.rng.fill_bytes(&mut iv);
+        crypto.rng.fill_bytes(&mut id);
+
+        let mut derived = [0u8; 32];
+        let params = ScryptParams::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P);
+        scrypt(password.as_bytes(), &salt, &params, &mut derived);
+
+        let mut ciphertext = [0u8; 32];
+        aes::ctr(KeySize::KeySize128, &derived[..16], &iv)
+            .process(&self.secret[..], &mut ciphertext);
+
+        let mac = keystore_mac(&derived, &ciphertext);
+
+        // uuid version 4.
+        id[6] = (id[6] & 0x0f) | 0x40;
+        id[8] = (id[8] & 0x3f) | 0x80;
+        let id = encode_hex(&id);
+
+        let keystore = Keystore {
+            version: 3,
+            id: format!(
+                "{}-{}-{}-{}-{}",
+                &id[0..8],
+                &id[8..12],
+                &id[12..16],
+                &id[16..20],
+                &id[20..32]
+            ),
+            address: Some(encode_hex(&self.address)),
+            crypto: KeystoreCrypto {
+                cipher: "aes-128-ctr".to_string(),
+                ciphertext: encode_hex(&ciphertext),
+                cipherparams: CipherParams {
+                    iv: encode_hex(&iv),
+                },
+                kdf: "scrypt".to_string(),
+                kdfparams: KdfParams {
+                    dklen: 32,
+                    salt: encode_hex(&salt),
+                    n: Some(1u32 << SCRYPT_LOG_N),
+                    r: Some(SCRYPT_R),
+                    p: Some(SCRYPT_P),
+                    c: None,
+                    prf: None,
+                },
+                mac: encode_hex(&mac),
+            },
+        };
+
+        serde_json::to_string_pretty(&keystore)
+            .map_err(|e| AccountError::keystore(format!("failed to serialize: {}", e)))
+    }
+
+    /// Build an account from the given secret key.
+    fn from_secret_key(
+        secp: &Arc<secp256k1::Secp256k1>,
+        secret: key::SecretKey,
+    ) -> Result<Account, AccountError> {
+        let public = key::PublicKey::from_secret_key(secp, &secret)
+            .map_err(|error| AccountError::DerivePublicKeyError { error })?;
+
+        let address = {
+            let serialized = public.serialize_vec(secp, false);
+            // NB: important that we convert from H256 since `H256 -> H160` trims the leading bits.
+            // i.e.: 00 00 00 af ff ff ff ff -> af ff ff ff ff
+            let hash = H256::from(keccak256(&serialized[1..]));
+            Address::from(H160::from(hash))
         };
 
         Ok(Self {
@@ -61,6 +265,49 @@ impl Account {
     pub fn sign<'a>(&'a self) -> Signer<'a> {
         Signer::new(self)
     }
+
+    /// Sign a precomputed 32-byte digest as-is, without applying any envelope.
+    pub fn sign_digest(&self, digest: impl Into<H256>) -> Result<Signature, AccountError> {
+        let digest = digest.into();
+        Signer::to_secp_signature(self, &digest[..])
+    }
+
+    /// Sign the given transaction with replay protection for the given chain id, as specified in
+    /// EIP-155.
+    ///
+    /// The result is the RLP-encoded signed transaction, which can be executed through
+    /// `Evm::send_raw_transaction`.
+    pub fn sign_transaction(
+        &self,
+        tx: &Transaction,
+        chain_id: u64,
+    ) -> Result<Vec<u8>, AccountError> {
+        let hash = tx.hash(Some(chain_id));
+        let signature = self.sign_digest(hash)?;
+        let signature = signature.as_ref();
+
+        let r = U256::from(&signature[0..32]);
+        let s = U256::from(&signature[32..64]);
+        let v = u64::from(signature[64]) + 35 + chain_id * 2;
+
+        let mut stream = RlpStream::new_list(9);
+        stream.append(&tx.nonce);
+        stream.append(&tx.gas_price);
+        stream.append(&tx.gas);
+
+        match tx.action {
+            Action::Create => stream.append_empty_data(),
+            Action::Call(ref address) => stream.append(address),
+        };
+
+        stream.append(&tx.value);
+        stream.append(&tx.data);
+        stream.append(&v);
+        stream.append(&r);
+        stream.append(&s);
+
+        Ok(stream.out())
+    }
 }
 
 impl fmt::Debug for Account {
@@ -106,6 +353,22 @@ impl<'a> Signer<'a> {
         Self::to_secp_signature(account, &hash)
     }
 
+    /// Finish the signature, signing the bare keccak256 hash of the input.
+    ///
+    /// Unlike `finish`, this doesn't apply the `\x19Ethereum Signed Message` envelope. This is
+    /// useful for contracts verifying bare hashes, or which apply their own envelope.
+    pub fn finish_raw(self) -> Result<Signature, AccountError> {
+        let Signer {
+            account,
+            mut checksum,
+        } = self;
+
+        let mut hash = [0u8; 32];
+        checksum.result(&mut hash);
+
+        Self::to_secp_signature(account, &hash)
+    }
+
     /// Convert the given message into an rpc hash, with the expected envelope.
     fn to_rpc_hash(message: &[u8]) -> Vec<u8> {
         let mut checksum = Sha3::keccak256();
@@ -153,6 +416,156 @@ impl ::std::convert::AsRef<[u8]> for Signature {
     }
 }
 
+/// A Web3 Secret Storage keystore.
+#[derive(Debug, Serialize, Deserialize)]
+struct Keystore {
+    version: u32,
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    crypto: KeystoreCrypto,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeystoreCrypto {
+    cipher: String,
+    ciphertext: String,
+    cipherparams: CipherParams,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+/// Parameters for either the scrypt or the pbkdf2 key derivation function.
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    dklen: usize,
+    salt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    r: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    p: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    c: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prf: Option<String>,
+}
+
+/// Build scrypt parameters, checking the constraints that `ScryptParams::new` panics on.
+fn scrypt_params(n: u32, r: u32, p: u32) -> Result<ScryptParams, AccountError> {
+    if n < 2 || !n.is_power_of_two() {
+        return Err(AccountError::keystore(format!(
+            "n must be a power of two greater than one, but is {}",
+            n
+        )));
+    }
+
+    if r == 0 || p == 0 {
+        return Err(AccountError::keystore("r and p must be greater than zero"));
+    }
+
+    let log_n = n.trailing_zeros();
+    let (r, p) = (u64::from(r), u64::from(p));
+
+    // the memory used by scrypt, which must be addressable.
+    let memory = (r * 128).checked_mul(u64::from(n));
+
+    // n < 2^(128 * r / 8), and p <= ((2^32 - 1) * 32) / (128 * r).
+    if u64::from(log_n) >= r * 16
+        || r * p >= 0x4000_0000
+        || memory.map_or(true, |m| m > usize::max_value() as u64)
+    {
+        return Err(AccountError::keystore(format!(
+            "unsupported scrypt parameters: n = {}, r = {}, p = {}",
+            n, r, p
+        )));
+    }
+
+    Ok(ScryptParams::new(log_n as u8, r as u32, p as u32))
+}
+
+/// Calculate the MAC of a keystore.
+fn keystore_mac(derived: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut input = Vec::with_capacity(16 + ciphertext.len());
+    input.extend(&derived[16..32]);
+    input.extend(ciphertext);
+    keccak256(&input).to_vec()
+}
+
+/// Encode the given bytes as hex, without a prefix.
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode hex, with an optional `0x` prefix.
+fn decode_hex(hex: &str) -> Result<Vec<u8>, AccountError> {
+    let digits = hex
+        .trim_left_matches("0x")
+        .chars()
+        .map(|c| {
+            c.to_digit(16)
+                .ok_or_else(|| AccountError::keystore(format!("bad hex digit: {:?}", c)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if digits.len() % 2 != 0 {
+        return Err(AccountError::keystore("odd number of hex digits"));
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|d| ((d[0] << 4) | d[1]) as u8)
+        .collect())
+}
+
+/// Trait for things which can be used as a raw private key.
+pub trait AsSecret {
+    /// Convert into the 32 bytes of a private key.
+    fn as_secret(&self) -> Result<H256, AccountError>;
+}
+
+impl<'a> AsSecret for &'a str {
+    fn as_secret(&self) -> Result<H256, AccountError> {
+        let hex = self.trim_left_matches("0x");
+
+        hex.parse::<H256>()
+            .map_err(|e| AccountError::InvalidSecret {
+                reason: format!("bad hex `{}`: {}", self, e),
+            })
+    }
+}
+
+impl<'a> AsSecret for &'a [u8] {
+    fn as_secret(&self) -> Result<H256, AccountError> {
+        if self.len() != 32 {
+            return Err(AccountError::InvalidSecret {
+                reason: format!("expected 32 bytes, but got {}", self.len()),
+            });
+        }
+
+        Ok(H256::from(*self))
+    }
+}
+
+impl AsSecret for [u8; 32] {
+    fn as_secret(&self) -> Result<H256, AccountError> {
+        Ok(H256::from(*self))
+    }
+}
+
+impl AsSecret for H256 {
+    fn as_secret(&self) -> Result<H256, AccountError> {
+        Ok(*self)
+    }
+}
+
 /// Trait for things which can be digested.
 pub trait Digestable {
     /// Digest the given type.
@@ -194,3 +607,186 @@ impl<'a> Digestable for &'a Signature {
         checksum.input(self.as_ref());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Account, Signature};
+    use crypto::{keccak256, Crypto};
+    use ethereum_types::{Address, H256};
+    use secp256k1::{self, key, RecoverableSignature, RecoveryId};
+
+    #[test]
+    fn test_from_secret() {
+        let crypto = Crypto::new();
+
+        let account = Account::from_secret(
+            &crypto,
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+        ).expect("bad secret");
+
+        let expected: Address = "f39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+            .parse()
+            .expect("bad address");
+
+        assert_eq!(expected, account.address);
+        assert!(Account::from_secret(&crypto, &[0u8; 31][..]).is_err());
+    }
+
+    #[test]
+    fn test_keystore() {
+        // test vector for pbkdf2 from the web3 secret storage definition.
+        let keystore = r#"{
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": {"iv": "6087dab2f9fdbbfaddc31a909735c1e6"},
+                "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf": "pbkdf2",
+                "kdfparams": {
+                    "c": 262144,
+                    "dklen": 32,
+                    "prf": "hmac-sha256",
+                    "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        }"#;
+
+        let mut crypto = Crypto::new();
+
+        let account =
+            Account::from_keystore(&crypto, keystore, "testpassword").expect("bad keystore");
+
+        let expected = Account::from_secret(
+            &crypto,
+            "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d",
+        ).expect("bad secret");
+
+        assert_eq!(expected.address, account.address);
+        assert!(Account::from_keystore(&crypto, keystore, "wrong").is_err());
+
+        let exported = account
+            .to_keystore(&mut crypto, "password")
+            .expect("bad export");
+
+        let imported =
+            Account::from_keystore(&crypto, &exported, "password").expect("bad import");
+
+        assert_eq!(account.address, imported.address);
+    }
+
+    #[test]
+    fn test_malformed_keystore() {
+        let crypto = Crypto::new();
+        let iv = "6087dab2f9fdbbfaddc31a909735c1e6";
+
+        let error = rejected(&crypto, &keystore("pbkdf2", &pbkdf2(1, "aé"), iv));
+        assert!(error.contains("bad hex digit: 'é'"), "{}", error);
+
+        let error = rejected(&crypto, &keystore("pbkdf2", &pbkdf2(1, "ae"), "6087"));
+        assert!(error.contains("iv must be 16 bytes, but is 2"), "{}", error);
+
+        let error = rejected(&crypto, &keystore("pbkdf2", &pbkdf2(0, "ae"), iv));
+        assert!(error.contains("c must be greater than zero"), "{}", error);
+
+        for &(n, r, p, expected) in &[
+            (1, 8, 1, "n must be a power of two greater than one, but is 1"),
+            (1000, 8, 1, "n must be a power of two greater than one, but is 1000"),
+            (1024, 0, 1, "r and p must be greater than zero"),
+            (1024, 8, 0, "r and p must be greater than zero"),
+            (1 << 20, 1, 1, "unsupported scrypt parameters"),
+            (2, 1 << 16, 1 << 14, "unsupported scrypt parameters"),
+        ] {
+            let kdfparams = format!(
+                r#"{{"n": {}, "r": {}, "p": {}, "dklen": 32, "salt": "ae"}}"#,
+                n, r, p
+            );
+
+            let error = rejected(&crypto, &keystore("scrypt", &kdfparams, iv));
+            assert!(error.contains(expected), "{}", error);
+        }
+
+        /// Parameters for pbkdf2 with the given number of rounds and salt.
+        fn pbkdf2(c: u32, salt: &str) -> String {
+            format!(
+                r#"{{"c": {}, "dklen": 32, "prf": "hmac-sha256", "salt": "{}"}}"#,
+                c, salt
+            )
+        }
+
+        /// Build a keystore using the given key derivation function and iv.
+        fn keystore(kdf: &str, kdfparams: &str, iv: &str) -> String {
+            format!(
+                r#"{{
+                    "crypto": {{
+                        "cipher": "aes-128-ctr",
+                        "cipherparams": {{"iv": "{}"}},
+                        "ciphertext": "00",
+                        "kdf": "{}",
+                        "kdfparams": {},
+                        "mac": "00"
+                    }},
+                    "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                    "version": 3
+                }}"#,
+                iv, kdf, kdfparams
+            )
+        }
+
+        /// Import the given keystore, expecting it to be rejected.
+        fn rejected(crypto: &Crypto, keystore: &str) -> String {
+            Account::from_keystore(crypto, keystore, "testpassword")
+                .expect_err("keystore should be rejected")
+                .to_string()
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let crypto = Crypto::new();
+
+        let account = Account::from_secret(
+            &crypto,
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+        ).expect("bad secret");
+
+        let digest = H256::from(keccak256(b"hello"));
+
+        let signature = account.sign_digest(digest).expect("failed to sign");
+        assert_eq!(65, signature.as_ref().len());
+        assert_eq!(account.public, recover(&account, &digest, &signature));
+
+        // the bare hash of the input is signed.
+        let raw = account
+            .sign()
+            .input("hello")
+            .finish_raw()
+            .expect("failed to sign");
+        assert_eq!(signature.as_ref(), raw.as_ref());
+
+        // the hash of the input is signed in the `\x19Ethereum Signed Message` envelope.
+        let mut message = b"\x19Ethereum Signed Message:\n32".to_vec();
+        message.extend(&digest[..]);
+        let enveloped = H256::from(keccak256(&message));
+
+        let signed = account.sign().input("hello").finish().expect("failed to sign");
+        assert_ne!(signature.as_ref(), signed.as_ref());
+        assert_eq!(account.public, recover(&account, &enveloped, &signed));
+
+        /// Recover the public key which made the given signature of the given digest.
+        fn recover(account: &Account, digest: &H256, signature: &Signature) -> key::PublicKey {
+            let signature = signature.as_ref();
+            let message = secp256k1::Message::from_slice(&digest[..]).expect("bad digest");
+            let id = RecoveryId::from_i32(i32::from(signature[64])).expect("bad recovery id");
+
+            let signature = RecoverableSignature::from_compact(&account.secp, &signature[..64], id)
+                .expect("bad signature");
+
+            account
+                .secp
+                .recover(&message, &signature)
+                .expect("failed to recover")
+        }
+    }
+}
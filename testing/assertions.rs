@@ -0,0 +1,120 @@
+//! Support for assertions on common Ethereum types, like `assert_eq_u256!`, `assert_balance!` and
+//! `assert_addr_eq!`.
+//!
+//! Instead of the `Debug` output of `assert_eq!`, mismatching values are printed in hex and in
+//! human units, with the hex digits which differ marked:
+//!
+//! ```text
+//! assertion failed: `(left == right)`
+//!   left: 0xde0b6b3a7640000 (1 ether, 1000000000000000000 wei)
+//!  right: 0xde0b6b3a7640001 (1.000000000000000001 ether, 1000000000000000001 wei)
+//!                         ^
+//! ```
+
+use ethereum_types::{Address, U256};
+use std::cmp;
+use wei;
+
+/// Describe the difference between two `U256` values, labeled by `labels`.
+pub fn u256_diff(labels: (&str, &str), left: U256, right: U256) -> String {
+    let left_hex = format!("{:x}", left);
+    let right_hex = format!("{:x}", right);
+    let width = cmp::max(left_hex.len(), right_hex.len());
+
+    diff(
+        labels,
+        (
+            &format!("0x{:0>1$}", left_hex, width),
+            &format!("0x{:0>1$}", right_hex, width),
+        ),
+        (&units(left), &units(right)),
+    )
+}
+
+/// Describe the difference between two addresses, labeled by `labels`.
+pub fn address_diff(labels: (&str, &str), left: Address, right: Address) -> String {
+    diff(
+        labels,
+        (&format!("{:?}", left), &format!("{:?}", right)),
+        ("", ""),
+    )
+}
+
+/// Format a value in ether and in wei.
+fn units(value: U256) -> String {
+    format!("{} ether, {} wei", wei::format_ether(value), value)
+}
+
+/// Describe the difference between two hex values of the same width, and mark the digits which
+/// differ.
+fn diff(labels: (&str, &str), hex: (&str, &str), units: (&str, &str)) -> String {
+    let width = cmp::max(labels.0.len(), labels.1.len());
+
+    let mut out = String::new();
+
+    for &(label, hex, units) in &[(labels.0, hex.0, units.0), (labels.1, hex.1, units.1)] {
+        out.push_str(&format!("{:>1$}: {2}", label, width + 1, hex));
+
+        if !units.is_empty() {
+            out.push_str(&format!(" ({})", units));
+        }
+
+        out.push('\n');
+    }
+
+    let markers = hex
+        .0
+        .chars()
+        .zip(hex.1.chars())
+        .map(|(a, b)| if a == b { ' ' } else { '^' })
+        .collect::<String>();
+
+    out.push_str(&format!("{:1$}{2}", "", width + 3, markers.trim_right()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{address_diff, u256_diff};
+    use ethereum_types::{Address, U256};
+
+    #[test]
+    fn test_u256_diff() {
+        let one = U256::from(1_000_000_000_000_000_000u64);
+        let diff = u256_diff(("left", "right"), one, one + U256::from(1));
+
+        assert_eq!(
+            vec![
+                "  left: 0xde0b6b3a7640000 (1 ether, 1000000000000000000 wei)",
+                " right: 0xde0b6b3a7640001 (1.000000000000000001 ether, 1000000000000000001 wei)",
+                "                        ^",
+            ],
+            diff.lines().collect::<Vec<_>>()
+        );
+
+        let diff = u256_diff(("actual", "expected"), 255.into(), 1.into());
+
+        assert_eq!(
+            vec![
+                "   actual: 0xff (0.000000000000000255 ether, 255 wei)",
+                " expected: 0x01 (0.000000000000000001 ether, 1 wei)",
+                "             ^^",
+            ],
+            diff.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_address_diff() {
+        let diff = address_diff(("left", "right"), Address::from(1), Address::from(2));
+
+        assert_eq!(
+            vec![
+                "  left: 0x0000000000000000000000000000000000000001",
+                " right: 0x0000000000000000000000000000000000000002",
+                "                                                 ^",
+            ],
+            diff.lines().collect::<Vec<_>>()
+        );
+    }
+}
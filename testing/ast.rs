@@ -36,6 +36,9 @@ macro_rules! ast {
         )*
         }
 
+        /// All known kinds of AST nodes.
+        const KINDS: &'static [&'static str] = &[$(stringify!($variant)),*];
+
         impl Ast {
             /// Access the kind of the ast.
             pub fn kind(&self) -> &'static str {
@@ -78,6 +81,23 @@ impl fmt::Debug for Src {
     }
 }
 
+impl Src {
+    /// Byte offset where the element starts.
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Length of the element in bytes.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Index of the file the element is in.
+    pub fn file_index(&self) -> u32 {
+        self.file_index
+    }
+}
+
 impl<'de> de::Deserialize<'de> for Src {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -126,6 +146,18 @@ pub struct FunctionCallAttributes {
 #[derive(Debug, Deserialize)]
 pub struct FunctionDefinitionAttributes {
     pub name: String,
+    /// Natspec documentation of the function, if any.
+    #[serde(default)]
+    pub documentation: Option<String>,
+    /// If the function has a body, which is not the case in interfaces.
+    #[serde(default)]
+    pub implemented: Option<bool>,
+}
+
+#[serde(rename_all = "camelCase")]
+#[derive(Debug, Deserialize)]
+pub struct ModifierDefinitionAttributes {
+    pub name: String,
 }
 
 #[serde(rename_all = "camelCase")]
@@ -134,7 +166,8 @@ pub struct IdentifierAttributes {
     #[serde(rename = "type")]
     pub ty: String,
     pub value: String,
-    pub referenced_declaration: u32,
+    /// Negative for built-in declarations, like `msg`.
+    pub referenced_declaration: i64,
 }
 
 #[serde(rename_all = "camelCase")]
@@ -185,6 +218,12 @@ pub struct ElementaryTypeNameExpressionAttributes {
     pub value: String,
 }
 
+#[serde(rename_all = "camelCase")]
+#[derive(Debug, Deserialize)]
+pub struct ContractDefinitionAttributes {
+    pub name: String,
+}
+
 #[serde(rename_all = "camelCase")]
 #[derive(Debug, Deserialize)]
 pub struct EnumDefinitionAttributes {
@@ -198,6 +237,13 @@ pub struct EnumValueAttributes {
     pub name: String,
 }
 
+#[serde(rename_all = "camelCase")]
+#[derive(Debug, Deserialize)]
+pub struct StructDefinitionAttributes {
+    pub canonical_name: String,
+    pub name: String,
+}
+
 ast!{
     ArrayTypeName { },
     Assignment {
@@ -208,7 +254,9 @@ ast!{
     Break { },
     Conditional { },
     Continue { },
-    ContractDefinition { },
+    ContractDefinition {
+        attributes: ContractDefinitionAttributes,
+    },
     ElementaryTypeName {
         attributes: ElementaryTypeNameAttributes,
     },
@@ -252,15 +300,21 @@ ast!{
         id: u32,
         attributes: MemberAccessAttributes,
     },
-    ModifierDefinition { },
+    ModifierDefinition {
+        attributes: ModifierDefinitionAttributes,
+    },
     ModifierInvocation { },
     NewExpression { },
+    Other { },
     ParameterList { },
     PlaceholderStatement { },
     PragmaDirective { },
     Return { },
     SourceUnit { },
-    StructDefinition { },
+    StructDefinition {
+        id: u32,
+        attributes: StructDefinitionAttributes,
+    },
     TupleExpression { },
     UnaryOperation { },
     UserDefinedTypeName { },
@@ -276,6 +330,206 @@ ast!{
 pub struct Function {
     pub src: Src,
     pub name: String,
+    /// Lines of natspec documentation of the function.
+    pub docs: Vec<String>,
+}
+
+/// The kind of a definition which contains code.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DefinitionKind {
+    Function,
+    Modifier,
+}
+
+/// A function or a modifier with a body.
+#[derive(Debug)]
+pub struct Definition {
+    pub kind: DefinitionKind,
+    /// Name of the contract the definition belongs to, if known.
+    pub contract: Option<String>,
+    pub name: String,
+    pub src: Src,
+}
+
+impl Definition {
+    /// Check if the given location is inside of the definition.
+    pub fn contains(&self, src: &Src) -> bool {
+        src.file_index == self.src.file_index
+            && src.start >= self.src.start
+            && src.start + src.length <= self.src.start + self.src.length
+    }
+}
+
+/// Normalize an AST into the legacy format, which is what `Ast` is decoded from.
+///
+/// Nodes of kinds which are not known are decoded as `Ast::Other`.
+fn normalize(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    let mut node = match value {
+        Value::Object(node) => node,
+        other => return other,
+    };
+
+    if node.contains_key("nodeType") {
+        return Value::Object(normalize_compact(node));
+    }
+
+    let children = match node.remove("children") {
+        Some(Value::Array(children)) => children.into_iter().map(normalize).collect(),
+        _ => Vec::new(),
+    };
+
+    let known = node
+        .get("name")
+        .and_then(|n| n.as_str())
+        .map(|n| KINDS.contains(&n))
+        .unwrap_or(false);
+
+    if !known {
+        node.insert("name".to_string(), Value::from("Other"));
+    }
+
+    node.insert("children".to_string(), Value::Array(children));
+    Value::Object(node)
+}
+
+/// Normalize a node of the compact AST format, as produced by solc 0.5 and later.
+///
+/// In the compact format the kind of a node is stored in `nodeType`, attributes are stored
+/// directly in the node, and children are stored in named fields.
+fn normalize_compact(
+    mut node: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    use serde_json::{Map, Value};
+
+    let kind = match node.remove("nodeType") {
+        Some(Value::String(kind)) => kind,
+        _ => String::new(),
+    };
+
+    let mut out = Map::new();
+
+    if let Some(src) = node.remove("src") {
+        out.insert("src".to_string(), src);
+    }
+
+    if let Some(id) = node.remove("id") {
+        out.insert("id".to_string(), id);
+    }
+
+    let mut attributes = Map::new();
+
+    let ty = node
+        .get("typeDescriptions")
+        .and_then(|t| t.get("typeString"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    attributes.insert("type".to_string(), Value::from(ty));
+
+    // documentation is a node in newer compilers.
+    if let Some(documentation) = node.remove("documentation") {
+        let documentation = match documentation {
+            Value::Object(mut d) => d.remove("text").unwrap_or(Value::Null),
+            other => other,
+        };
+
+        attributes.insert("documentation".to_string(), documentation);
+    }
+
+    match kind.as_str() {
+        "Identifier" => {
+            if let Some(name) = node.get("name").cloned() {
+                attributes.insert("value".to_string(), name);
+            }
+        }
+        "MemberAccess" => {
+            if let Some(member_name) = node.remove("memberName") {
+                attributes.insert("member_name".to_string(), member_name);
+            }
+        }
+        "ElementaryTypeNameExpression" => {
+            // the type name is a plain string in older compilers, and a node in newer ones.
+            let value = match node.remove("typeName") {
+                Some(Value::Object(type_name)) => type_name.get("name").cloned(),
+                other => other,
+            };
+
+            if let Some(value) = value {
+                attributes.insert("value".to_string(), value);
+            }
+        }
+        _ => {}
+    }
+
+    // fields holding children whose order is significant.
+    let ordered: &[&str] = match kind.as_str() {
+        "Assignment" => &["leftHandSide", "rightHandSide"],
+        "FunctionCall" => &["expression", "arguments"],
+        "FunctionDefinition" => &["parameters", "returnParameters", "modifiers", "body"],
+        "IndexAccess" => &["baseExpression", "indexExpression"],
+        "MemberAccess" => &["expression"],
+        "VariableDeclaration" => &["typeName", "value"],
+        _ => &[],
+    };
+
+    let mut children = Vec::new();
+
+    for field in ordered {
+        if let Some(value) = node.remove(*field) {
+            push_children(value, &mut children);
+        }
+    }
+
+    for (key, value) in node {
+        if is_node(&value) || value.as_array().map(|a| a.iter().any(is_node)) == Some(true) {
+            push_children(value, &mut children);
+        } else {
+            attributes.insert(key, value);
+        }
+    }
+
+    let name = if KINDS.contains(&kind.as_str()) {
+        kind
+    } else {
+        String::from("Other")
+    };
+
+    out.insert("name".to_string(), Value::from(name));
+    out.insert("attributes".to_string(), Value::Object(attributes));
+    out.insert("children".to_string(), Value::Array(children));
+    return out;
+
+    fn is_node(value: &Value) -> bool {
+        value.get("nodeType").is_some()
+    }
+
+    fn push_children(value: Value, children: &mut Vec<Value>) {
+        match value {
+            Value::Array(values) => {
+                for value in values {
+                    push_children(value, children);
+                }
+            }
+            value => {
+                if is_node(&value) {
+                    children.push(normalize(value));
+                }
+            }
+        }
+    }
+}
+
+/// Split documentation into trimmed, non-empty lines.
+fn doc_lines(documentation: &str) -> Vec<String> {
+    documentation
+        .lines()
+        .map(|l| l.trim().trim_left_matches('*').trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -289,6 +543,50 @@ pub struct Enum {
     pub variants: Vec<Variant>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub ty: Type,
+    /// Number of bytes the member occupies in storage, if known.
+    pub size: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Struct {
+    pub name: String,
+    pub members: Vec<Member>,
+}
+
+impl Struct {
+    /// Calculate the storage location of every member, as a slot relative to the struct and an
+    /// offset into that slot.
+    ///
+    /// Members following one with an unknown size can't be located and are excluded.
+    fn storage_locations(&self) -> Vec<(&Member, U256, usize)> {
+        let mut out = Vec::new();
+
+        let mut slot = 0u64;
+        let mut offset = 0usize;
+
+        for member in &self.members {
+            let size = match member.size {
+                Some(size) => size,
+                None => break,
+            };
+
+            if offset + size > 32 {
+                slot += 1;
+                offset = 0;
+            }
+
+            out.push((member, U256::from(slot), offset));
+            offset += size;
+        }
+
+        out
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Registry {
     /// ASTs indexed by source location.
@@ -299,14 +597,23 @@ pub struct Registry {
     functions: HashMap<u32, BTreeMap<u32, Arc<Function>>>,
     /// Enums, to lookup variant names.
     enums: HashMap<String, Arc<Enum>>,
+    /// Structs, to lookup member names and types.
+    structs: HashMap<String, Arc<Struct>>,
+    /// Functions and modifiers with a body.
+    definitions: Vec<Definition>,
 }
 
 impl Registry {
     /// Parse AST.
+    ///
+    /// Both the legacy and the compact AST format are supported.
     pub fn parse(input: &str) -> Result<Registry, Error> {
-        let ast: Ast =
+        let ast: serde_json::Value =
             serde_json::from_str(input).map_err(|e| format_err!("failed to parse AST: {}", e))?;
 
+        let ast: Ast = serde_json::from_value(normalize(ast))
+            .map_err(|e| format_err!("failed to parse AST: {}", e))?;
+
         let ast = Arc::new(ast);
 
         let mut index = HashMap::new();
@@ -315,6 +622,11 @@ impl Registry {
         let mut functions = HashMap::new();
         // mapping from enum variants to struct to figure out name.
         let mut enums = HashMap::new();
+        // struct members and their types, which are decoded once all enums are known.
+        let mut structs = Vec::new();
+        // functions and modifiers, which are assigned to contracts once all contracts are known.
+        let mut definitions = Vec::new();
+        let mut contracts = Vec::new();
 
         let mut current = ::std::collections::VecDeque::new();
         current.push_back(&ast);
@@ -325,15 +637,40 @@ impl Registry {
 
             match next.as_ref() {
                 Ast::FunctionDefinition { ref attributes, .. } => {
+                    let docs = attributes
+                        .documentation
+                        .as_ref()
+                        .map(|d| doc_lines(d))
+                        .unwrap_or_default();
+
                     let function = Arc::new(Function {
                         src: src.clone(),
                         name: attributes.name.to_string(),
+                        docs,
                     });
 
                     functions
                         .entry(src.file_index)
                         .or_insert_with(BTreeMap::new)
                         .insert(src.start, function);
+
+                    if attributes.implemented != Some(false) {
+                        definitions.push((
+                            DefinitionKind::Function,
+                            attributes.name.to_string(),
+                            src.clone(),
+                        ));
+                    }
+                }
+                Ast::ModifierDefinition { ref attributes, .. } => {
+                    definitions.push((
+                        DefinitionKind::Modifier,
+                        attributes.name.to_string(),
+                        src.clone(),
+                    ));
+                }
+                Ast::ContractDefinition { ref attributes, .. } => {
+                    contracts.push((attributes.name.to_string(), src.clone()));
                 }
                 Ast::EnumDefinition {
                     ref attributes,
@@ -357,6 +694,21 @@ impl Registry {
 
                     enums.insert(attributes.canonical_name.to_string(), enum_);
                 }
+                Ast::StructDefinition {
+                    ref attributes,
+                    ref children,
+                    ..
+                } => {
+                    let mut members = Vec::new();
+
+                    for c in children {
+                        if let Ast::VariableDeclaration { ref attributes, .. } = *c.as_ref() {
+                            members.push((attributes.name.to_string(), attributes.ty.to_string()));
+                        }
+                    }
+
+                    structs.push((attributes.canonical_name.to_string(), members));
+                }
                 _ => {}
             }
 
@@ -368,12 +720,52 @@ impl Registry {
             current.extend(next.children());
         }
 
-        Ok(Registry {
+        let mut registry = Registry {
             index,
             statements,
             functions,
             enums,
-        })
+            structs: HashMap::new(),
+            definitions: Vec::new(),
+        };
+
+        for (kind, name, src) in definitions {
+            let contract = contracts
+                .iter()
+                .find(|&&(_, ref c)| {
+                    c.file_index == src.file_index
+                        && c.start <= src.start
+                        && src.start + src.length <= c.start + c.length
+                })
+                .map(|&(ref name, _)| name.to_string());
+
+            registry.definitions.push(Definition {
+                kind,
+                contract,
+                name,
+                src,
+            });
+        }
+
+        for (name, members) in structs {
+            let members = members
+                .into_iter()
+                .map(|(name, ty)| Member {
+                    name,
+                    size: storage_size(&ty),
+                    ty: registry.decode_type(&ty),
+                })
+                .collect();
+
+            let struct_ = Arc::new(Struct {
+                name: name.to_string(),
+                members,
+            });
+
+            registry.structs.insert(name, struct_);
+        }
+
+        Ok(registry)
     }
 
     /// Find the function the encapsulated the specified mapping.
@@ -410,6 +802,11 @@ impl Registry {
         self.index.get(&src).map(|a| a.as_ref())
     }
 
+    /// Find all functions and modifiers with a body.
+    pub fn definitions(&self) -> impl Iterator<Item = &Definition> {
+        self.definitions.iter()
+    }
+
     /// Find the location of all statements in registry.
     pub fn statements(&self) -> impl Iterator<Item = &Src> {
         self.statements.iter()
@@ -571,13 +968,16 @@ impl Registry {
                     _ => return Unknown(ty.into()),
                 };
 
-                let kind = match it.next() {
-                    Some("pointer") => Kind::Pointer,
-                    Some("ref") => Kind::Ref,
+                let kind = match (it.next(), &storage) {
+                    (Some("pointer"), _) => Kind::Pointer,
+                    (Some("ref"), _) => Kind::Ref,
+                    // structs in memory are always referenced through pointers.
+                    (None, &Storage::Memory) => Kind::Pointer,
                     _ => return Unknown(ty.into()),
                 };
 
-                return Struct(name.to_string(), storage, kind);
+                let struct_ = self.structs.get(name).map(Arc::clone);
+                return Struct(name.to_string(), storage, kind, struct_);
             }
             Some("function") => {
                 let mut it = ty.splitn(2, " ");
@@ -598,6 +998,27 @@ impl Registry {
                 Some("memory") => Bytes(Storage::Memory),
                 _ => return Unknown(ty.into()),
             },
+            Some("string") => match it.next() {
+                Some("calldata") => String(Storage::CallData),
+                Some("memory") => String(Storage::Memory),
+                _ => return Unknown(ty.into()),
+            },
+            Some(array) if array.ends_with("[]") => {
+                let storage = match it.next() {
+                    Some("calldata") => Storage::CallData,
+                    Some("memory") => Storage::Memory,
+                    _ => return Unknown(ty.into()),
+                };
+
+                let element = self.decode_type(&array[..array.len() - 2]);
+
+                // only arrays of value types can be decoded from a single word per element.
+                if !element.is_word() {
+                    return Unknown(ty.into());
+                }
+
+                Array(Box::new(element), storage)
+            }
             Some("bytes32") => Bytes32,
             Some("uint256") => Uint256,
             Some("bool") => Bool,
@@ -656,18 +1077,87 @@ pub enum Type {
     Bool,
     Address,
     Enum(String, Option<Arc<Enum>>),
-    Struct(String, Storage, Kind),
+    Struct(String, Storage, Kind, Option<Arc<Struct>>),
     Function(String),
     Mapping(Box<Type>, Box<Type>),
+    String(Storage),
+    /// A dynamic array of the given element type.
+    Array(Box<Type>, Storage),
     Unknown(String),
 }
 
 impl Type {
+    /// Maximum number of array elements which are decoded.
+    const MAX_ELEMENTS: usize = 32;
+
+    /// Test if the type is a value type, which is represented by a single word.
+    fn is_word(&self) -> bool {
+        use self::Type::*;
+
+        match *self {
+            Bytes32 | Uint256 | Bool | Address | Enum(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Decode a value type from a single word.
+    pub fn from_word(&self, value: U256) -> Option<Value> {
+        use self::Type::*;
+
+        let value = match *self {
+            Bytes32 => Value::Bytes32(<[u8; 32]>::from(value)),
+            Uint256 => Value::Uint256(value),
+            Bool => Value::Bool(value != U256::from(0)),
+            Address => Value::Address(self::Address::from(H160::from(H256::from(value)))),
+            Enum(ref name, ref enum_) => {
+                let variant = enum_.as_ref().and_then(|enum_| {
+                    enum_
+                        .variants
+                        .get(value.as_usize())
+                        .map(|v| v.name.to_string())
+                });
+
+                Value::Enum {
+                    name: name.to_string(),
+                    value,
+                    variant,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Decode the elements of an array from the given data, which starts with the first element.
+    fn elements(&self, data: &[u8], length: usize) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::new();
+
+        for index in 0..length.min(Self::MAX_ELEMENTS) {
+            let word = slice(data, index * 32, 32)?;
+
+            let value = self
+                .from_word(U256::from(word))
+                .ok_or_else(|| format_err!("cannot decode element of type: {}", self))?;
+
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
     /// Decode the value from the type, returning the decoded value and the amount of stack that
     /// was used to decode it.
     pub fn value(self, ctx: &mut Context) -> Result<Value, Error> {
         use self::Type::*;
 
+        if self.is_word() {
+            let value = ctx.pop()?;
+            return self
+                .from_word(value)
+                .ok_or_else(|| format_err!("cannot decode value of type: {}", self));
+        }
+
         match self {
             Bytes(Storage::CallData) => {
                 let length = ctx.pop()?.as_u32() as usize;
@@ -686,40 +1176,92 @@ impl Type {
 
                 Ok(Value::Bytes(data.to_vec()))
             }
-            Bytes32 => {
-                let value = ctx.pop()?;
-                Ok(Value::Bytes32(<[u8; 32]>::from(value)))
+            Mapping(key, value) => Ok(Value::Mapping(*key, *value)),
+            String(Storage::CallData) => {
+                let length = ctx.pop()?.as_u32() as usize;
+                let address = ctx.pop()?.as_u32() as usize;
+
+                let data = slice(ctx.call_data, address, length)?;
+                Ok(Value::String(
+                    ::std::string::String::from_utf8_lossy(data).to_string(),
+                ))
             }
-            Uint256 => {
-                let value = ctx.pop()?;
-                Ok(Value::Uint256(value))
+            String(Storage::Memory) => {
+                let address = ctx.pop()?.as_u32() as usize;
+
+                let length = U256::from(slice(ctx.memory, address, 32)?).as_u32() as usize;
+                let data = slice(ctx.memory, address + 32, length)?;
+                Ok(Value::String(
+                    ::std::string::String::from_utf8_lossy(data).to_string(),
+                ))
             }
-            Bool => {
-                let value = ctx.pop()?;
-                Ok(Value::Bool(value != U256::from(0)))
+            Array(element, Storage::CallData) => {
+                let length = ctx.pop()?.as_u32() as usize;
+                let address = ctx.pop()?.as_u32() as usize;
+
+                let data = &ctx.call_data[address.min(ctx.call_data.len())..];
+                let values = element.elements(data, length)?;
+                Ok(Value::Array { values, length })
             }
-            Address => {
-                let value = ctx.pop()?;
-                let address = self::Address::from(H160::from(H256::from(value)));
-                Ok(Value::Address(address))
+            Array(element, Storage::Memory) => {
+                let address = ctx.pop()?.as_u32() as usize;
+
+                let length = U256::from(slice(ctx.memory, address, 32)?).as_u32() as usize;
+                let data = &ctx.memory[(address + 32).min(ctx.memory.len())..];
+                let values = element.elements(data, length)?;
+                Ok(Value::Array { values, length })
             }
-            Mapping(key, value) => Ok(Value::Mapping(*key, *value)),
-            Enum(name, enum_) => {
-                let value = ctx.pop()?;
-                let variant = enum_.as_ref().and_then(|enum_| {
-                    enum_
-                        .variants
-                        .get(value.as_usize())
-                        .map(|v| v.name.to_string())
-                });
+            Struct(name, Storage::Storage, Kind::Pointer, Some(struct_)) => {
+                let slot = ctx.pop()?;
+
+                let mut members = Vec::new();
 
-                Ok(Value::Enum {
+                for (member, relative, offset) in struct_.storage_locations() {
+                    let value = ctx
+                        .sload(slot.overflowing_add(relative).0)
+                        .and_then(|word| {
+                            let size = member.size.unwrap_or(32);
+                            let word = word >> (offset * 8);
+
+                            let word = if size < 32 {
+                                word & ((U256::from(1) << (size * 8)) - U256::from(1))
+                            } else {
+                                word
+                            };
+
+                            member.ty.from_word(word)
+                        })
+                        .unwrap_or_else(|| Value::Unknown(member.ty.to_string()));
+
+                    members.push((member.name.to_string(), value));
+                }
+
+                Ok(Value::Struct(
                     name,
-                    value,
-                    variant,
-                })
+                    Storage::Storage,
+                    Kind::Pointer,
+                    members,
+                ))
+            }
+            Struct(name, Storage::Memory, kind, Some(struct_)) => {
+                let address = ctx.pop()?.as_u32() as usize;
+
+                let mut members = Vec::new();
+
+                // every member of a struct in memory occupies a full word, where dynamic members
+                // are pointers.
+                for (index, member) in struct_.members.iter().enumerate() {
+                    let value = slice(ctx.memory, address + index * 32, 32)
+                        .ok()
+                        .and_then(|word| member.ty.from_word(U256::from(word)))
+                        .unwrap_or_else(|| Value::Unknown(member.ty.to_string()));
+
+                    members.push((member.name.to_string(), value));
+                }
+
+                Ok(Value::Struct(name, Storage::Memory, kind, members))
             }
-            Struct(name, storage, kind) => Ok(Value::Struct(name, storage, kind)),
+            Struct(name, storage, kind, _) => Ok(Value::Struct(name, storage, kind, vec![])),
             Function(params) => Ok(Value::Function(params)),
             Unknown(ty) => Ok(Value::Unknown(ty)),
             _ => Ok(Value::Unknown("unknown".into())),
@@ -738,8 +1280,10 @@ impl fmt::Display for Type {
             Bool => write!(fmt, "bool"),
             Address => write!(fmt, "address"),
             Mapping(ref key, ref value) => write!(fmt, "mapping({} => {})", key, value),
+            String(ref storage) => write!(fmt, "string {}", storage),
+            Array(ref element, ref storage) => write!(fmt, "{}[] {}", element, storage),
             Enum(ref name, ..) => write!(fmt, "enum {}", name),
-            Struct(ref name, ref storage, ref kind) => {
+            Struct(ref name, ref storage, ref kind, _) => {
                 write!(fmt, "struct {} {} {}", name, storage, kind)
             }
             Function(ref params) => write!(fmt, "function {}", params),
@@ -762,19 +1306,43 @@ pub enum Value {
     Address(Address),
     /// Only store the types of a mapping since we can't discover all values.
     Mapping(Type, Type),
+    /// A string.
+    String(String),
+    /// A dynamic array, where only the first elements are decoded.
+    Array {
+        values: Vec<Value>,
+        length: usize,
+    },
     /// An enum value.
     Enum {
         name: String,
         value: U256,
         variant: Option<String>,
     },
-    /// A struct and its name.
-    Struct(String, Storage, Kind),
+    /// A struct and its name, with the members which could be decoded.
+    Struct(String, Storage, Kind, Vec<(String, Value)>),
     /// A function.
     Function(String),
     Unknown(String),
 }
 
+impl Value {
+    /// Encode a value type into the word it is represented by, like when it's used as a mapping
+    /// key.
+    pub fn to_word(&self) -> Option<U256> {
+        use self::Value::*;
+
+        match *self {
+            Bytes32(ref value) => Some(U256::from(&value[..])),
+            Uint256(value) => Some(value),
+            Bool(value) => Some(U256::from(value as u8)),
+            Address(ref value) => Some(U256::from(&H256::from(*value)[..])),
+            Enum { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use self::Value::*;
@@ -786,6 +1354,24 @@ impl fmt::Display for Value {
             Bool(ref value) => write!(fmt, "bool({})", value),
             Address(ref value) => write!(fmt, "address({:?})", value),
             Mapping(ref key, ref value) => write!(fmt, "mapping({} => {})", key, value),
+            String(ref value) => write!(fmt, "string({:?})", value),
+            Array { ref values, length } => {
+                write!(fmt, "[")?;
+
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(fmt, ", ")?;
+                    }
+
+                    write!(fmt, "{}", value)?;
+                }
+
+                if length > values.len() {
+                    write!(fmt, ", ... {} more", length - values.len())?;
+                }
+
+                write!(fmt, "]")
+            }
             Enum {
                 ref name,
                 ref value,
@@ -798,8 +1384,22 @@ impl fmt::Display for Value {
                     write!(fmt, "enum {}({})", name, value)
                 }
             }
-            Struct(ref name, ref storage, ref kind) => {
-                write!(fmt, "struct {} {} {}", name, storage, kind)
+            Struct(ref name, ref storage, ref kind, ref members) => {
+                if members.is_empty() {
+                    return write!(fmt, "struct {} {} {}", name, storage, kind);
+                }
+
+                write!(fmt, "struct {} {{ ", name)?;
+
+                for (index, &(ref name, ref value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(fmt, ", ")?;
+                    }
+
+                    write!(fmt, "{}: {}", name, value)?;
+                }
+
+                write!(fmt, " }}")
             }
             Function(ref params) => write!(fmt, "function {}", params),
             Unknown(ref ty) => write!(fmt, "{}(*unknown*)", ty),
@@ -812,6 +1412,8 @@ pub struct Context<'a> {
     stack: &'a [U256],
     memory: &'a [u8],
     call_data: &'a Bytes,
+    /// Storage slots which are known, if any.
+    storage: Option<&'a HashMap<U256, U256>>,
 }
 
 impl<'a> Context<'a> {
@@ -821,9 +1423,23 @@ impl<'a> Context<'a> {
             stack,
             memory,
             call_data,
+            storage: None,
+        }
+    }
+
+    /// Use the given storage slots to decode values from storage.
+    pub fn with_storage(self, storage: &'a HashMap<U256, U256>) -> Context<'a> {
+        Context {
+            storage: Some(storage),
+            ..self
         }
     }
 
+    /// Load a storage slot, if it's known.
+    fn sload(&self, slot: U256) -> Option<U256> {
+        self.storage.and_then(|storage| storage.get(&slot).cloned())
+    }
+
     /// Pop a value from the stack.
     pub fn pop(&mut self) -> Result<U256, Error> {
         if self.stack.is_empty() {
@@ -846,6 +1462,8 @@ pub enum Expr {
     MemberAccess { key: Box<Expr>, value: String },
     /// name(args) function calls.
     FunctionCall { name: Box<Expr>, args: Vec<Expr> },
+    /// An expression which has been replaced with the value it evaluated to.
+    Resolved { value: String },
 }
 
 impl fmt::Display for Expr {
@@ -864,10 +1482,46 @@ impl fmt::Display for Expr {
                     .join(", ");
                 write!(fmt, "{}({})", name, args)
             }
+            Resolved { ref value } => value.fmt(fmt),
         }
     }
 }
 
+/// Calculate the number of bytes a value of the given type occupies in storage.
+///
+/// Types which occupy a full slot regardless of their content, like mappings and dynamic arrays,
+/// are 32 bytes.
+fn storage_size(ty: &str) -> Option<usize> {
+    let first = match ty.split(" ").next() {
+        Some(first) => first,
+        None => return None,
+    };
+
+    if first.starts_with("mapping") || first.ends_with("[]") {
+        return Some(32);
+    }
+
+    let size = match first {
+        "bool" | "enum" => 1,
+        "address" | "contract" => 20,
+        "string" | "bytes" => 32,
+        other if other.starts_with("uint") => other[4..].parse::<usize>().ok()? / 8,
+        other if other.starts_with("int") => other[3..].parse::<usize>().ok()? / 8,
+        other if other.starts_with("bytes") => other[5..].parse::<usize>().ok()?,
+        _ => return None,
+    };
+
+    Some(size)
+}
+
+/// Access a slice of the given data, or error if it's out of bounds.
+fn slice(data: &[u8], start: usize, length: usize) -> Result<&[u8], Error> {
+    match start.checked_add(length) {
+        Some(end) if end <= data.len() => Ok(&data[start..end]),
+        _ => bail!("{} bytes at {} out of bounds", length, start),
+    }
+}
+
 struct Hex<'a>(&'a [u8]);
 
 impl<'a> fmt::Display for Hex<'a> {
@@ -884,7 +1538,13 @@ impl<'a> fmt::Display for Hex<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Kind, Registry, Storage, Type};
+    use super::{
+        doc_lines, storage_size, Ast, Context, DefinitionKind, Kind, Member, Registry, Storage,
+        Struct, Type, Value,
+    };
+    use ethereum_types::{Address, U256};
+    use std::collections::HashMap;
+    use std::sync::Arc;
 
     #[test]
     fn test_decode() {
@@ -903,7 +1563,7 @@ mod tests {
         );
 
         assert_eq!(
-            Type::Struct("Foo".to_string(), Storage::Storage, Kind::Pointer),
+            Type::Struct("Foo".to_string(), Storage::Storage, Kind::Pointer, None),
             registry.decode_type("struct Foo storage pointer")
         );
 
@@ -911,5 +1571,238 @@ mod tests {
             Type::Function("(uint256)".to_string()),
             registry.decode_type("function (uint256)")
         );
+
+        assert_eq!(
+            Type::String(Storage::Memory),
+            registry.decode_type("string memory")
+        );
+
+        assert_eq!(
+            Type::Array(Box::new(Type::Uint256), Storage::CallData),
+            registry.decode_type("uint256[] calldata")
+        );
+
+        assert_eq!(
+            Type::Unknown("uint256[] storage ref".to_string()),
+            registry.decode_type("uint256[] storage ref")
+        );
+    }
+
+    #[test]
+    fn test_decode_dynamic() {
+        let mut memory = vec![0u8; 32 * 6];
+        // string "foo" at 0
+        memory[31] = 3;
+        memory[32..35].copy_from_slice(b"foo");
+        // uint256[] [1, 2] at 64
+        memory[64 + 31] = 2;
+        memory[96 + 31] = 1;
+        memory[128 + 31] = 2;
+
+        let call_data = Vec::new();
+
+        let stack = vec![U256::from(64), U256::from(0)];
+        let mut ctx = Context::new(&stack, &memory, &call_data);
+
+        let value = Type::String(Storage::Memory).value(&mut ctx).unwrap();
+        assert_eq!("string(\"foo\")", value.to_string());
+
+        let value = Type::Array(Box::new(Type::Uint256), Storage::Memory)
+            .value(&mut ctx)
+            .unwrap();
+        assert_eq!("[uint256(1), uint256(2)]", value.to_string());
+
+        let mut ctx = Context::new(&stack, &memory[..70], &call_data);
+        ctx.pop().unwrap();
+        assert!(Type::Array(Box::new(Type::Uint256), Storage::Memory)
+            .value(&mut ctx)
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_struct() {
+        let member = |name: &str, ty: Type, size: usize| Member {
+            name: name.to_string(),
+            ty,
+            size: Some(size),
+        };
+
+        let order = Arc::new(Struct {
+            name: "Exchange.Order".to_string(),
+            members: vec![
+                member("amount", Type::Uint256, 32),
+                member("owner", Type::Address, 20),
+                member("open", Type::Bool, 1),
+                member("price", Type::Uint256, 32),
+            ],
+        });
+
+        let ty = Type::Struct(
+            "Exchange.Order".to_string(),
+            Storage::Storage,
+            Kind::Pointer,
+            Some(order),
+        );
+
+        let mut storage = HashMap::new();
+        storage.insert(U256::from(5), U256::from(100));
+        storage.insert(U256::from(6), U256::from(0x1234) | (U256::from(1) << 160));
+
+        let stack = vec![U256::from(5)];
+        let call_data = Vec::new();
+        let mut ctx = Context::new(&stack, &[], &call_data).with_storage(&storage);
+
+        let members = match ty.value(&mut ctx).unwrap() {
+            Value::Struct(_, _, _, members) => members,
+            other => panic!("expected struct, but got: {}", other),
+        };
+
+        assert_eq!(
+            vec![
+                ("amount".to_string(), Value::Uint256(100.into())),
+                ("owner".to_string(), Value::Address(Address::from(0x1234))),
+                ("open".to_string(), Value::Bool(true)),
+                ("price".to_string(), Value::Unknown("uint256".to_string())),
+            ],
+            members
+        );
+
+        assert_eq!(Some(1), storage_size("bool"));
+        assert_eq!(Some(20), storage_size("address payable"));
+        assert_eq!(Some(4), storage_size("uint32"));
+        assert_eq!(Some(32), storage_size("mapping(address => uint256)"));
+        assert_eq!(None, storage_size("struct Exchange.Order storage ref"));
+    }
+
+    #[test]
+    fn test_doc_lines() {
+        assert_eq!(
+            vec![
+                "@notice Withdraw funds.",
+                "@dev Only the owner may withdraw."
+            ],
+            doc_lines("@notice Withdraw funds.\n * @dev Only the owner may withdraw.\n")
+        );
+    }
+
+    #[test]
+    fn test_parse_compact() {
+        let registry = Registry::parse(
+            r#"{
+                "nodeType": "SourceUnit",
+                "src": "0:100:0",
+                "id": 1,
+                "nodes": [{
+                    "nodeType": "FunctionDefinition",
+                    "src": "10:80:0",
+                    "id": 2,
+                    "name": "get",
+                    "documentation": {"nodeType": "StructuredDocumentation", "text": "@notice Get."},
+                    "body": {
+                        "nodeType": "IndexAccess",
+                        "src": "20:10:0",
+                        "id": 3,
+                        "typeDescriptions": {"typeString": "uint256"},
+                        "baseExpression": {
+                            "nodeType": "Identifier",
+                            "src": "20:6:0",
+                            "id": 4,
+                            "name": "values",
+                            "referencedDeclaration": -1,
+                            "typeDescriptions": {"typeString": "uint256[] storage ref"}
+                        },
+                        "indexExpression": {
+                            "nodeType": "Identifier",
+                            "src": "27:2:0",
+                            "id": 5,
+                            "name": "i",
+                            "referencedDeclaration": 6,
+                            "typeDescriptions": {"typeString": "uint256"}
+                        }
+                    }
+                }]
+            }"#,
+        ).expect("compact ast to parse");
+
+        let function = registry
+            .functions
+            .get(&0)
+            .and_then(|f| f.get(&10))
+            .expect("function to be registered");
+
+        assert_eq!("get", function.name);
+        assert_eq!(vec!["@notice Get."], function.docs);
+
+        let index = registry.index.get(&(20, 10)).expect("index access");
+
+        match **index {
+            Ast::IndexAccess { ref children, .. } => {
+                assert_eq!(2, children.len());
+
+                match *children[0].as_ref() {
+                    Ast::Identifier { ref attributes, .. } => {
+                        assert_eq!("values", attributes.value)
+                    }
+                    ref other => panic!("unexpected base: {:?}", other),
+                }
+            }
+            ref other => panic!("unexpected ast: {:?}", other),
+        }
+
+        assert!(registry.index.get(&(0, 100)).is_some());
+    }
+
+    #[test]
+    fn test_definitions() {
+        let registry = Registry::parse(
+            r#"{
+                "nodeType": "SourceUnit",
+                "src": "0:200:0",
+                "id": 1,
+                "nodes": [{
+                    "nodeType": "ContractDefinition",
+                    "src": "0:150:0",
+                    "id": 2,
+                    "name": "Token",
+                    "nodes": [{
+                        "nodeType": "ModifierDefinition",
+                        "src": "10:20:0",
+                        "id": 3,
+                        "name": "onlyOwner"
+                    }, {
+                        "nodeType": "FunctionDefinition",
+                        "src": "40:50:0",
+                        "id": 4,
+                        "name": "burn",
+                        "implemented": true
+                    }]
+                }, {
+                    "nodeType": "ContractDefinition",
+                    "src": "150:50:0",
+                    "id": 5,
+                    "name": "IToken",
+                    "nodes": [{
+                        "nodeType": "FunctionDefinition",
+                        "src": "160:20:0",
+                        "id": 6,
+                        "name": "burn",
+                        "implemented": false
+                    }]
+                }]
+            }"#,
+        ).expect("compact ast to parse");
+
+        let definitions = registry
+            .definitions()
+            .map(|d| (d.kind, d.contract.as_ref().map(|c| c.as_str()), d.name.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (DefinitionKind::Modifier, Some("Token"), "onlyOwner"),
+                (DefinitionKind::Function, Some("Token"), "burn"),
+            ],
+            definitions
+        );
     }
 }
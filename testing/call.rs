@@ -1,6 +1,7 @@
 use ethereum_types::{Address, U256};
+use wei;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Call {
     /// The sender of the call.
     pub sender: Address,
@@ -10,6 +11,12 @@ pub struct Call {
     pub gas_price: U256,
     /// The amount of ethereum attached to the call (in WEI).
     pub value: U256,
+    /// Data appended after the ABI-encoded input of the call.
+    pub extra_data: Vec<u8>,
+    /// Data used instead of the ABI-encoded input of the call.
+    pub data_override: Option<Vec<u8>>,
+    /// Estimate the gas of the call, instead of using `gas`.
+    pub auto_gas: bool,
 }
 
 impl Call {
@@ -20,6 +27,9 @@ impl Call {
             gas: 0.into(),
             gas_price: 0.into(),
             value: 0.into(),
+            extra_data: Vec::new(),
+            data_override: None,
+            auto_gas: false,
         }
     }
 
@@ -47,6 +57,16 @@ impl Call {
         }
     }
 
+    /// Estimate the amount of gas needed for the call, instead of using a fixed amount.
+    ///
+    /// The estimate is multiplied by the headroom configured with `Evm::set_gas_headroom`.
+    pub fn auto_gas(self) -> Self {
+        Self {
+            auto_gas: true,
+            ..self
+        }
+    }
+
     /// Set the call to have the specified value.
     pub fn value<E: Into<U256>>(self, value: E) -> Self {
         Self {
@@ -54,4 +74,105 @@ impl Call {
             ..self
         }
     }
+
+    /// Append the given data after the ABI-encoded input of the call.
+    ///
+    /// This is useful for testing contracts that parse trailing calldata, like EIP-2771 trusted
+    /// forwarders which append the address of the original sender.
+    pub fn extra_data(self, data: impl AsRef<[u8]>) -> Self {
+        Self {
+            extra_data: data.as_ref().to_vec(),
+            ..self
+        }
+    }
+
+    /// Use the given data as the full input of the call, instead of the ABI-encoded input.
+    ///
+    /// The override only applies to transactions calling a contract. Deployments and queries
+    /// performed with the same call still use their own input.
+    pub fn data_override(self, data: impl AsRef<[u8]>) -> Self {
+        Self {
+            data_override: Some(data.as_ref().to_vec()),
+            ..self
+        }
+    }
+
+    /// Build the input of a message call from the given ABI-encoded input.
+    pub(crate) fn data(&self, encoded: Vec<u8>) -> Vec<u8> {
+        if let Some(ref data) = self.data_override {
+            return data.clone();
+        }
+
+        self.extend_data(encoded)
+    }
+
+    /// Build the input of a deployment or a query from the given ABI-encoded input.
+    ///
+    /// These are not affected by `data_override`.
+    pub(crate) fn extend_data(&self, encoded: Vec<u8>) -> Vec<u8> {
+        let mut data = encoded;
+        data.extend(&self.extra_data);
+        data
+    }
+}
+
+/// A preset of gas settings, which can be applied to all calls performed by a virtual machine.
+///
+/// See `Evm::use_profile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallProfile {
+    /// The amount of gas to include in calls.
+    pub gas: U256,
+    /// The price to pay for gas during calls (in WEI).
+    pub gas_price: U256,
+}
+
+impl CallProfile {
+    /// Build a new profile with the given gas and gas price.
+    pub fn new<G: Into<U256>, P: Into<U256>>(gas: G, gas_price: P) -> Self {
+        Self {
+            gas: gas.into(),
+            gas_price: gas_price.into(),
+        }
+    }
+
+    /// A profile with plenty of gas which is free, so that balances are unaffected by gas.
+    pub fn cheap() -> Self {
+        Self::new(10_000_000, 0)
+    }
+
+    /// A profile with a gas limit and gas price typical for the main network.
+    pub fn realistic_mainnet() -> Self {
+        Self::new(8_000_000, wei::from_gwei(20))
+    }
+
+    /// Apply the profile to the given call.
+    pub fn apply(&self, call: Call) -> Call {
+        Call {
+            gas: self.gas,
+            gas_price: self.gas_price,
+            ..call
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Call;
+    use ethereum_types::Address;
+
+    #[test]
+    fn test_data() {
+        let call = Call::new(Address::from(0x10));
+        assert_eq!(vec![1, 2], call.data(vec![1, 2]));
+
+        let call = call.extra_data(vec![3u8, 4]);
+        assert_eq!(vec![1, 2, 3, 4], call.data(vec![1, 2]));
+
+        let call = call.data_override(vec![5u8]);
+        assert_eq!(vec![5], call.data(vec![1, 2]));
+
+        // deployments and queries are not affected by the override.
+        assert_eq!(vec![1, 2, 3, 4], call.extend_data(vec![1, 2]));
+    }
 }
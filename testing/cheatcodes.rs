@@ -0,0 +1,190 @@
+//! Support for a subset of the Foundry (forge-std) cheatcodes.
+//!
+//! Cheatcodes are calls made to a well-known address, which holds stub code (see `stub_code`).
+//! Calls to it are detected by the tracer, and the corresponding cheat is applied by the `Evm` once
+//! the transaction that issued it has completed. Cheats issued by calls which fail are discarded
+//! together with the rest of their effects.
+//!
+//! Since parity does not permit us to modify the environment of an in-flight transaction, cheats
+//! only affect _subsequent_ transactions. E.g. `vm.warp(t)` sets the timestamp of the next
+//! transaction.
+//!
+//! Only `warp`, `roll`, and `deal` are supported. Cheats which need to affect the next call in the
+//! same transaction, like `prank` and `expectRevert`, can't be implemented this way, so the stub
+//! code reverts when they are called. Like any other revert, this undoes the effects of the
+//! transaction which issued them, unless the revert is caught.
+
+use crypto::keccak256;
+use ethabi::{self, ParamType};
+use ethereum_types::{Address, U256};
+use mock::{op, revert_reason, Assembler};
+
+const PRANK: &'static str =
+    "vm.prank can't affect calls in the same transaction, set the sender of the call instead";
+
+const EXPECT_REVERT: &'static str =
+    "vm.expectRevert can't affect calls in the same transaction, check the outcome of the call instead";
+
+/// How a known cheatcode is supported.
+enum Support {
+    /// The cheatcode is supported, and needs at least the given number of bytes of input.
+    Supported(usize),
+    /// The cheatcode is not supported, for the given reason.
+    Unsupported(&'static str),
+}
+
+/// All known cheatcodes, by signature.
+const CHEATCODES: &'static [(&'static str, Support)] = &[
+    ("warp(uint256)", Support::Supported(4 + 32)),
+    ("roll(uint256)", Support::Supported(4 + 32)),
+    ("deal(address,uint256)", Support::Supported(4 + 64)),
+    ("prank(address)", Support::Unsupported(PRANK)),
+    ("startPrank(address)", Support::Unsupported(PRANK)),
+    ("expectRevert()", Support::Unsupported(EXPECT_REVERT)),
+    ("expectRevert(bytes)", Support::Unsupported(EXPECT_REVERT)),
+];
+
+/// The address which forge-std sends all cheatcode calls to.
+pub fn cheatcode_address() -> Address {
+    // 0x7109709ECfa91a80626fF3989D68f67F5b1DD12D
+    Address::from([
+        0x71, 0x09, 0x70, 0x9e, 0xcf, 0xa9, 0x1a, 0x80, 0x62, 0x6f, 0xf3, 0x98, 0x9d, 0x68, 0xf6,
+        0x7f, 0x5b, 0x1d, 0xd1, 0x2d,
+    ])
+}
+
+/// Generate the code to put at the cheatcode address.
+///
+/// Supported cheatcodes do nothing, they are applied once the transaction has completed. All other
+/// calls revert with the reason they are not supported.
+pub fn stub_code() -> Vec<u8> {
+    let mut asm = Assembler::default();
+
+    for &(signature, ref support) in CHEATCODES {
+        let next = asm.label();
+        asm.jump_unless_selector(&keccak256(signature.as_bytes())[..4], next);
+
+        match *support {
+            Support::Supported(size) => {
+                let malformed = asm.label();
+                asm.push(size);
+                asm.op(op::CALLDATASIZE);
+                asm.op(op::LT);
+                asm.jumpi(malformed);
+                asm.op(op::STOP);
+
+                asm.bind(malformed);
+                let reason = format!("malformed input to cheatcode {}", signature);
+                asm.exit(&revert_reason(&reason), op::REVERT);
+            }
+            Support::Unsupported(reason) => {
+                asm.exit(&revert_reason(reason), op::REVERT);
+            }
+        }
+
+        asm.bind(next);
+    }
+
+    asm.exit(&revert_reason("unknown cheatcode"), op::REVERT);
+    asm.finish()
+}
+
+/// A single decoded cheatcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cheatcode {
+    /// `vm.warp(timestamp)`, set the block timestamp.
+    Warp(U256),
+    /// `vm.roll(number)`, set the block number.
+    Roll(U256),
+    /// `vm.deal(who, balance)`, set the balance of an account.
+    Deal(Address, U256),
+    /// A cheatcode which is not supported, with a description of why.
+    Unsupported(String),
+}
+
+/// Decode the input of a call to the cheatcode address.
+pub fn decode(data: &[u8]) -> Cheatcode {
+    if let Some(cheat) = decode_supported(data) {
+        return cheat;
+    }
+
+    match data.get(..4) {
+        Some(selector) => Cheatcode::Unsupported(format!(
+            "unknown cheatcode with selector 0x{}",
+            selector
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        )),
+        None => Cheatcode::Unsupported(String::from("call without a selector")),
+    }
+}
+
+/// Decode a supported cheatcode, or one which we know to be unsupported.
+fn decode_supported(data: &[u8]) -> Option<Cheatcode> {
+    if data.len() < 4 {
+        return None;
+    }
+
+    let (selector, input) = data.split_at(4);
+    let matches = |signature: &str| &keccak256(signature.as_bytes())[..4] == selector;
+
+    if matches("warp(uint256)") {
+        let mut it = ethabi::decode(&[ParamType::Uint(256)], input)
+            .ok()?
+            .into_iter();
+        return Some(Cheatcode::Warp(it.next()?.to_uint()?));
+    }
+
+    if matches("roll(uint256)") {
+        let mut it = ethabi::decode(&[ParamType::Uint(256)], input)
+            .ok()?
+            .into_iter();
+        return Some(Cheatcode::Roll(it.next()?.to_uint()?));
+    }
+
+    if matches("deal(address,uint256)") {
+        let mut it = ethabi::decode(&[ParamType::Address, ParamType::Uint(256)], input)
+            .ok()?
+            .into_iter();
+        let who = it.next()?.to_address()?;
+        let balance = it.next()?.to_uint()?;
+        return Some(Cheatcode::Deal(who, balance));
+    }
+
+    for &(signature, ref support) in CHEATCODES {
+        if let Support::Unsupported(reason) = *support {
+            if matches(signature) {
+                return Some(Cheatcode::Unsupported(String::from(reason)));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, Cheatcode};
+    use crypto::keccak256;
+    use ethabi::{self, Token};
+
+    #[test]
+    fn test_decode() {
+        let mut data = keccak256(b"warp(uint256)")[..4].to_vec();
+        data.extend(ethabi::encode(&[Token::Uint(1000.into())]));
+        assert_eq!(Cheatcode::Warp(1000.into()), decode(&data));
+
+        let data = keccak256(b"expectRevert()")[..4].to_vec();
+
+        match decode(&data) {
+            Cheatcode::Unsupported(ref reason) => assert!(reason.contains("vm.expectRevert")),
+            other => panic!("expected unsupported cheatcode, got {:?}", other),
+        }
+
+        assert_eq!(
+            Cheatcode::Unsupported(String::from("unknown cheatcode with selector 0x00010203")),
+            decode(&[0, 1, 2, 3])
+        );
+    }
+}
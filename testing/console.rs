@@ -0,0 +1,160 @@
+//! Decoding of calls made through hardhat's `console.sol`.
+//!
+//! `console.log` is implemented as a static call to a well-known address which has no code, the
+//! arguments of the call are ABI-encoded according to the overload being used.
+
+use crypto::keccak256;
+use ethabi::{self, ParamType, Token};
+use ethereum_types::{Address, U256};
+use std::fmt;
+
+/// The address which `console.sol` sends all of its calls to.
+pub fn console_address() -> Address {
+    // 0x000000000000000000636F6e736F6c652e6c6f67
+    Address::from([
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x63, 0x6f, 0x6e, 0x73, 0x6f, 0x6c,
+        0x65, 0x2e, 0x6c, 0x6f, 0x67,
+    ])
+}
+
+/// Types which can be combined into the overloads of `log(...)`.
+fn log_types() -> Vec<(&'static str, ParamType)> {
+    vec![
+        ("uint256", ParamType::Uint(256)),
+        ("string", ParamType::String),
+        ("bool", ParamType::Bool),
+        ("address", ParamType::Address),
+        // older versions of console.sol computed selectors using `uint`.
+        ("uint", ParamType::Uint(256)),
+    ]
+}
+
+/// Single-argument overloads that are not named `log`.
+fn named_signatures() -> Vec<(String, Vec<ParamType>)> {
+    let mut out = vec![
+        ("log()".to_string(), vec![]),
+        ("log(int256)".to_string(), vec![ParamType::Int(256)]),
+        ("logInt(int256)".to_string(), vec![ParamType::Int(256)]),
+        ("logInt(int)".to_string(), vec![ParamType::Int(256)]),
+        ("logUint(uint256)".to_string(), vec![ParamType::Uint(256)]),
+        ("logUint(uint)".to_string(), vec![ParamType::Uint(256)]),
+        ("logString(string)".to_string(), vec![ParamType::String]),
+        ("logBool(bool)".to_string(), vec![ParamType::Bool]),
+        ("logAddress(address)".to_string(), vec![ParamType::Address]),
+        ("logBytes(bytes)".to_string(), vec![ParamType::Bytes]),
+    ];
+
+    for n in 1..33 {
+        out.push((
+            format!("logBytes{}(bytes{})", n, n),
+            vec![ParamType::FixedBytes(n)],
+        ));
+    }
+
+    out
+}
+
+/// Find the parameters for the given selector.
+fn find_params(selector: &[u8]) -> Option<Vec<ParamType>> {
+    let matches = |signature: &str| &keccak256(signature.as_bytes())[..4] == selector;
+
+    for (signature, params) in named_signatures() {
+        if matches(&signature) {
+            return Some(params);
+        }
+    }
+
+    let types = log_types();
+
+    // all combinations of up to four arguments.
+    for len in 1..5u32 {
+        for mut n in 0..types.len().pow(len) {
+            let mut names = Vec::new();
+            let mut params = Vec::new();
+
+            for _ in 0..len {
+                let (name, ref param) = types[n % types.len()];
+                names.push(name);
+                params.push(param.clone());
+                n /= types.len();
+            }
+
+            if matches(&format!("log({})", names.join(","))) {
+                return Some(params);
+            }
+        }
+    }
+
+    None
+}
+
+/// Decode the input to a `console.log` call into a printable line.
+///
+/// Returns `None` if the input does not correspond to a known overload.
+pub fn decode(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+
+    let params = find_params(&data[..4])?;
+    let tokens = ethabi::decode(&params, &data[4..]).ok()?;
+
+    let line = tokens
+        .iter()
+        .map(|t| ConsoleToken(t).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Some(line)
+}
+
+/// Helper to format a single token the way it would be printed by console.log.
+struct ConsoleToken<'a>(&'a Token);
+
+impl<'a> fmt::Display for ConsoleToken<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Token::Address(ref address) => write!(fmt, "{:?}", address),
+            Token::Bytes(ref bytes) | Token::FixedBytes(ref bytes) => {
+                write!(fmt, "0x")?;
+
+                for b in bytes {
+                    write!(fmt, "{:02x}", b)?;
+                }
+
+                Ok(())
+            }
+            Token::Int(ref value) => {
+                // two's complement.
+                if value.bit(255) {
+                    write!(fmt, "-{}", (!*value).overflowing_add(U256::one()).0)
+                } else {
+                    write!(fmt, "{}", value)
+                }
+            }
+            Token::Uint(ref value) => write!(fmt, "{}", value),
+            Token::Bool(ref value) => write!(fmt, "{}", value),
+            Token::String(ref value) => write!(fmt, "{}", value),
+            ref other => write!(fmt, "{:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use crypto::keccak256;
+    use ethabi::{self, Token};
+
+    #[test]
+    fn test_decode() {
+        let mut data = keccak256(b"log(string,uint256)")[..4].to_vec();
+        data.extend(ethabi::encode(&[
+            Token::String("value is".to_string()),
+            Token::Uint(42.into()),
+        ]));
+
+        assert_eq!(Some("value is 42".to_string()), decode(&data));
+        assert_eq!(None, decode(&[0, 1, 2, 3]));
+    }
+}
@@ -0,0 +1,363 @@
+//! Statement coverage, and the ways code can be excluded from it.
+//!
+//! Single lines are excluded with comments in the source:
+//!
+//! ```solidity
+//! require(msg.sender == owner); // parables-ignore
+//!
+//! // parables-ignore-next-line
+//! selfdestruct(owner);
+//! ```
+//!
+//! Whole files, like `Migrations.sol`, are excluded with `Evm::exclude_from_coverage`.
+//!
+//! Besides statements, coverage is aggregated by function and modifier through
+//! `Evm::coverage_report`, which finds functions that are never called.
+
+use ast;
+use linker;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+pub use ast::DefinitionKind;
+
+/// Comment excluding the line it's on from coverage.
+const IGNORE: &'static str = "parables-ignore";
+/// Comment excluding the line following it from coverage.
+const IGNORE_NEXT_LINE: &'static str = "parables-ignore-next-line";
+
+/// Coverage of a single function or modifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub kind: DefinitionKind,
+    /// Name of the contract the function belongs to, if known.
+    pub contract: Option<String>,
+    /// Name of the function, which is empty for constructors and fallback functions in older
+    /// compilers.
+    pub name: String,
+    /// Path of the file the function is defined in, and the line it starts on, numbered from 1.
+    pub location: Option<(PathBuf, usize)>,
+    /// Number of statements in the function which were visited.
+    pub visited: u32,
+    /// Number of statements in the function.
+    pub total: u32,
+    /// If the function was called, or if the modifier was used.
+    pub called: bool,
+}
+
+impl fmt::Display for FunctionCoverage {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            DefinitionKind::Function => write!(fmt, "function ")?,
+            DefinitionKind::Modifier => write!(fmt, "modifier ")?,
+        }
+
+        if let Some(ref contract) = self.contract {
+            write!(fmt, "{}.", contract)?;
+        }
+
+        if self.name.is_empty() {
+            write!(fmt, "<unnamed>")?;
+        } else {
+            write!(fmt, "{}", self.name)?;
+        }
+
+        if let Some((ref path, line)) = self.location {
+            write!(fmt, " ({}:{})", path.display(), line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Coverage of all known sources, by statement and by function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Number of statements which were visited.
+    pub visited: u32,
+    /// Number of statements.
+    pub total: u32,
+    /// Coverage of every function and modifier.
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl CoverageReport {
+    /// Coverage of functions, excluding modifiers.
+    pub fn functions(&self) -> impl Iterator<Item = &FunctionCoverage> {
+        self.functions
+            .iter()
+            .filter(|f| f.kind == DefinitionKind::Function)
+    }
+
+    /// Coverage of modifiers.
+    pub fn modifiers(&self) -> impl Iterator<Item = &FunctionCoverage> {
+        self.functions
+            .iter()
+            .filter(|f| f.kind == DefinitionKind::Modifier)
+    }
+
+    /// Functions which were never called, and modifiers which were never used.
+    pub fn never_called(&self) -> impl Iterator<Item = &FunctionCoverage> {
+        self.functions.iter().filter(|f| !f.called)
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |count: usize, total: usize| {
+            if total == 0 {
+                100
+            } else {
+                count * 100 / total
+            }
+        };
+
+        writeln!(
+            fmt,
+            "Statements: {}/{} ({}%)",
+            self.visited,
+            self.total,
+            percent(self.visited as usize, self.total as usize)
+        )?;
+
+        let functions = self.functions().count();
+        let called = self.functions().filter(|f| f.called).count();
+
+        writeln!(
+            fmt,
+            "Functions: {}/{} called ({}%)",
+            called,
+            functions,
+            percent(called, functions)
+        )?;
+
+        let modifiers = self.modifiers().count();
+        let used = self.modifiers().filter(|f| f.called).count();
+
+        writeln!(
+            fmt,
+            "Modifiers: {}/{} used ({}%)",
+            used,
+            modifiers,
+            percent(used, modifiers)
+        )?;
+
+        let mut never_called = self.never_called().peekable();
+
+        if never_called.peek().is_some() {
+            writeln!(fmt, "Never called:")?;
+
+            for function in never_called {
+                writeln!(fmt, "  {}", function)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Build a coverage report for all known sources from the statements which have been visited.
+pub(crate) fn report(
+    linker: &linker::Linker,
+    visited_statements: &HashSet<ast::Src>,
+    filter: &mut Filter,
+) -> CoverageReport {
+    let mut report = CoverageReport::default();
+
+    for registry in linker.all_asts() {
+        let statements = registry
+            .statements()
+            .filter(|src| !filter.is_ignored(src))
+            .collect::<Vec<_>>();
+
+        report.total += statements.len() as u32;
+        report.visited += statements
+            .iter()
+            .filter(|src| visited_statements.contains(**src))
+            .count() as u32;
+
+        for definition in registry.definitions() {
+            if filter.is_ignored(&definition.src) {
+                continue;
+            }
+
+            let mut visited = 0u32;
+            let mut total = 0u32;
+            let mut called = visited_statements.contains(&definition.src);
+
+            for src in &statements {
+                if **src == definition.src || !definition.contains(src) {
+                    continue;
+                }
+
+                total += 1;
+
+                if visited_statements.contains(*src) {
+                    visited += 1;
+                    called = true;
+                }
+            }
+
+            report.functions.push(FunctionCoverage {
+                kind: definition.kind,
+                contract: definition.contract.clone(),
+                name: definition.name.to_string(),
+                location: filter.location(&definition.src),
+                visited,
+                total,
+                called,
+            });
+        }
+    }
+
+    report.functions.sort_by(|a, b| a.location.cmp(&b.location));
+    report
+}
+
+/// Decides which statements are excluded from coverage.
+pub(crate) struct Filter<'a> {
+    linker: &'a linker::Linker,
+    /// Files excluded from coverage.
+    excluded: &'a [PathBuf],
+    /// Lines excluded by comments, by file index.
+    ignored_lines: HashMap<u32, HashSet<usize>>,
+}
+
+impl<'a> Filter<'a> {
+    /// Create a new filter, excluding the given files.
+    pub fn new(linker: &'a linker::Linker, excluded: &'a [PathBuf]) -> Self {
+        Self {
+            linker,
+            excluded,
+            ignored_lines: HashMap::new(),
+        }
+    }
+
+    /// Check if the statement at the given location is excluded from coverage.
+    pub fn is_ignored(&mut self, src: &ast::Src) -> bool {
+        let linker = self.linker;
+
+        let path = match linker.find_file(src.file_index()) {
+            Some(path) => path,
+            None => return false,
+        };
+
+        if self.excluded.iter().any(|excluded| path.ends_with(excluded)) {
+            return true;
+        }
+
+        let ignored_lines = self
+            .ignored_lines
+            .entry(src.file_index())
+            .or_insert_with(|| match linker.source_cache().file(path) {
+                Ok(file) => ignored_lines(file.content()),
+                Err(e) => {
+                    warn!("failed to read source for coverage: {}", e);
+                    HashSet::new()
+                }
+            });
+
+        if ignored_lines.is_empty() {
+            return false;
+        }
+
+        let span = (
+            src.start() as usize,
+            (src.start() + src.length()) as usize,
+        );
+
+        match linker.source_cache().find_line(path, span) {
+            Ok((_, line)) => ignored_lines.contains(&line),
+            Err(_) => false,
+        }
+    }
+
+    /// Find the path and line, numbered from 1, of the given location.
+    pub fn location(&self, src: &ast::Src) -> Option<(PathBuf, usize)> {
+        let path = self.linker.find_file(src.file_index())?;
+        let span = (
+            src.start() as usize,
+            (src.start() + src.length()) as usize,
+        );
+
+        let (_, line) = self.linker.source_cache().find_line(path, span).ok()?;
+        Some((path.to_owned(), line + 1))
+    }
+}
+
+/// Find the lines excluded from coverage by comments, numbered from 0.
+fn ignored_lines(content: &str) -> HashSet<usize> {
+    let mut lines = HashSet::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let comment = match line.find("//") {
+            Some(start) => &line[start + 2..],
+            None => continue,
+        };
+
+        match comment.split_whitespace().next() {
+            Some(IGNORE) => {
+                lines.insert(index);
+            }
+            Some(IGNORE_NEXT_LINE) => {
+                lines.insert(index + 1);
+            }
+            _ => {}
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ignored_lines, CoverageReport, DefinitionKind, FunctionCoverage};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_ignored_lines() {
+        let content = "contract Migrations {\n  a = 1; // parables-ignore\n  // parables-ignore-next-line\n  b = 2;\n  c = 3; // parables-ignored\n}\n";
+
+        let mut lines = ignored_lines(content).into_iter().collect::<Vec<_>>();
+        lines.sort();
+
+        assert_eq!(vec![1, 3], lines);
+    }
+
+    #[test]
+    fn test_report() {
+        let function = |kind, name: &str, line, called| FunctionCoverage {
+            kind,
+            contract: Some("Token".to_string()),
+            name: name.to_string(),
+            location: Some((PathBuf::from("contracts/Token.sol"), line)),
+            visited: if called { 1 } else { 0 },
+            total: 2,
+            called,
+        };
+
+        let report = CoverageReport {
+            visited: 3,
+            total: 4,
+            functions: vec![
+                function(DefinitionKind::Modifier, "onlyOwner", 3, true),
+                function(DefinitionKind::Function, "transfer", 7, true),
+                function(DefinitionKind::Function, "burn", 12, false),
+            ],
+        };
+
+        assert_eq!(
+            vec!["burn"],
+            report
+                .never_called()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            "Statements: 3/4 (75%)\nFunctions: 1/2 called (50%)\nModifiers: 1/1 used (100%)\nNever called:\n  function Token.burn (contracts/Token.sol:12)\n",
+            report.to_string()
+        );
+    }
+}
@@ -7,18 +7,39 @@ use ethcore::receipt;
 use ethcore::spec;
 use ethcore::state;
 use ethcore::state_db;
+#[cfg(feature = "account")]
+use ethcore_transaction::UnverifiedTransaction;
 use ethcore_transaction::{Action, SignedTransaction, Transaction};
-use ethereum_types::{Address, U256};
+use ethereum_types::{Address, H256, U256};
 use failure::Error;
 use kvdb::KeyValueDB;
+#[cfg(feature = "persist")]
+use kvdb_rocksdb;
 use parity_vm;
+#[cfg(feature = "account")]
+use rlp;
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::{hash_map, HashMap, HashSet};
 use std::fmt;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use trace;
-use {abi, account, ast, call, crypto, journaldb, kvdb, kvdb_memorydb, linker, matcher};
+use {
+    abi, account, ast, call, cheatcodes, coverage, crypto, gas, journaldb, kvdb, kvdb_memorydb, linker,
+    matcher, proxy, storage,
+};
+
+/// Gas costs and other parameters of the active specification.
+pub use parity_vm::Schedule;
+/// How values are formatted in errors.
+pub use trace::ValueFormat;
+
+/// Key in the extra column under which the persisted state root is stored.
+const PERSISTED_ROOT_KEY: &'static [u8] = b"parables_state_root";
+
+/// Default headroom applied to estimated gas, in percent.
+const DEFAULT_GAS_HEADROOM: u64 = 150;
 
 /// The outcome of a transaction.
 ///
@@ -56,6 +77,18 @@ impl<T> Outcome<T> {
             _ => false,
         }
     }
+
+    /// Convert the outcome into a result.
+    pub fn ok(self) -> Result<T, Error> {
+        use self::Outcome::*;
+
+        match self {
+            Ok(value) => Result::Ok(value),
+            Reverted { errors } => bail!("Reverted at:\n{}", errors),
+            Errored { errors } => bail!("Errored at:\n{}", errors),
+            Status { status } => bail!("Call returned status at:\n{}", status),
+        }
+    }
 }
 
 /// The result of executing a call transaction.
@@ -72,6 +105,20 @@ pub struct Call<T> {
     pub value: U256,
     /// The sender of the transaction.
     pub sender: Address,
+    /// The receiver of the transaction, or the address of the created contract.
+    pub to: Address,
+    /// Output from `console.log` calls made during the transaction.
+    pub console: Vec<String>,
+    /// The test that performed the call, if it was performed by the test runner.
+    pub test: Option<String>,
+    /// Gas used by the call, by function, if profiling is enabled with `Evm::set_gas_profile`.
+    pub gas_profile: Option<gas::GasProfile>,
+    /// Writes to storage which took effect, in the order they were performed.
+    storage_writes: Vec<storage::StorageWrite>,
+    /// All calls performed, in the order they were made.
+    calls: Vec<trace::CallTrace>,
+    /// Logs produced by the transaction, in the order they were emitted.
+    logs: Vec<LogEntry>,
 }
 
 impl<T> Call<T> {
@@ -148,16 +195,89 @@ impl<T> Call<T> {
         return Result::Ok(());
     }
 
-    /// Convert the outcome into a result.
-    pub fn ok(self) -> Result<T, Error> {
+    /// Test that the call reverted, for any reason.
+    ///
+    /// If the assertion doesn't hold, return an error indicating what actually happened.
+    pub fn assert_reverted(self) -> Result<(), Error> {
         use self::Outcome::*;
 
         match self.outcome {
-            Ok(value) => Result::Ok(value),
-            Reverted { errors } => bail!("Reverted at:\n{}", errors),
-            Errored { errors } => bail!("Errored at:\n{}", errors),
-            Status { status } => bail!("Call returned status at:\n{}", status),
+            Reverted { .. } => {}
+            Errored { ref errors } => bail!("Expected call to revert.\nBut call errored:\n{}", errors),
+            Status { ref status } => {
+                bail!("Expected call to revert.\nBut call returned status:\n{}", status)
+            }
+            Ok(_) => bail!("Expected call to revert.\nBut call succeeded"),
+        }
+
+        Result::Ok(())
+    }
+
+    /// Test that the call reverted with data matching the given matcher.
+    ///
+    /// This matches against what the call reverted with, like
+    /// `RevertMatch::reason("not allowed")` or `RevertMatch::error_selector(selector)`, rather than
+    /// against the source.
+    pub fn assert_reverted_with(self, matcher: impl matcher::RevertMatcher) -> Result<(), Error> {
+        use self::Outcome::*;
+
+        match self.outcome {
+            Reverted { ref errors } => {
+                if !errors.is_reverted_with(matcher) {
+                    bail!("Expected {}.\nBut call reverted:\n{}", matcher, errors);
+                }
+            }
+            Errored { ref errors } => {
+                bail!("Expected {}.\nBut call errored:\n{}", matcher, errors);
+            }
+            Status { ref status } => {
+                bail!("Expected {}.\nBut call returned status:\n{}", matcher, status);
+            }
+            Ok(_) => bail!("Expected {}.\nBut call succeeded", matcher),
         }
+
+        Result::Ok(())
+    }
+
+    /// Convert the outcome into a result.
+    pub fn ok(self) -> Result<T, Error> {
+        self.outcome.ok()
+    }
+
+    /// Access the writes to storage which took effect during the call.
+    ///
+    /// Writes are mapped back to the state variables they belong to for contracts compiled with
+    /// `#[parables(storage_layout)]`. Writes performed by calls that reverted are not included.
+    pub fn storage_writes(&self) -> &[storage::StorageWrite] {
+        &self.storage_writes
+    }
+
+    /// Access all calls performed during the transaction, including the transaction itself, in
+    /// the order they were made.
+    ///
+    /// Use `trace::diff` to compare the calls of two transactions.
+    pub fn calls(&self) -> &[trace::CallTrace] {
+        &self.calls
+    }
+
+    /// Access the logs produced by the transaction, in the order they were emitted.
+    ///
+    /// This includes logs which were passed to subscriptions.
+    pub fn logs(&self) -> &[LogEntry] {
+        &self.logs
+    }
+
+    /// The names of all state variables written to during the call, sorted and deduplicated.
+    pub fn written_variables(&self) -> Vec<&str> {
+        let mut variables = self
+            .storage_writes
+            .iter()
+            .flat_map(|w| w.variables.iter().map(|v| v.as_str()))
+            .collect::<Vec<_>>();
+
+        variables.sort();
+        variables.dedup();
+        variables
     }
 }
 
@@ -176,17 +296,85 @@ where
 // Evm.
 #[derive(Clone)]
 pub struct Evm {
-    env_info: parity_vm::EnvInfo,
+    env_info: RefCell<parity_vm::EnvInfo>,
     state: RefCell<state::State<state_db::StateDB>>,
     engine: Arc<engines::EthEngine>,
+    /// Id of the chain, which signed transactions must be signed for.
+    chain_id: u64,
     /// Logs collected by topic.
     logs: RefCell<HashMap<ethabi::Hash, Vec<LogEntry>>>,
+    /// Known events, by signature.
+    events: Arc<HashMap<ethabi::Hash, ethabi::Event>>,
     /// Linker used, if available it can be used to perform source-map lookups.
     linker: RefCell<linker::Linker>,
     /// Default crypto implementation.
     crypto: RefCell<crypto::Crypto>,
     /// Local set of visited statements.
     visited_statements: Arc<Mutex<HashSet<ast::Src>>>,
+    /// Files excluded from coverage.
+    coverage_excluded: Vec<PathBuf>,
+    /// How values are formatted in errors.
+    value_format: ValueFormat,
+    /// Headroom applied to estimated gas, in percent.
+    gas_headroom: u64,
+    /// If transactions should be profiled for gas.
+    gas_profile: bool,
+    /// If gas used should be accumulated by line of source.
+    line_gas_report: bool,
+    /// Gas used by file index and source offset, accumulated over all transactions.
+    line_gas: Arc<Mutex<HashMap<(u32, u32), u64>>>,
+    /// Registered call profiles, by name.
+    profiles: HashMap<String, call::CallProfile>,
+    /// Call profile applied to all calls, if any.
+    profile: Option<call::CallProfile>,
+    /// Persistent database backing the state, if opened using `Evm::open`.
+    db: Option<Arc<KeyValueDB>>,
+    /// Checkpoint to revert to, if one has been taken.
+    checkpoint: Option<Box<Checkpoint>>,
+    /// Callbacks invoked with matching logs as they are produced.
+    subscriptions: Subscriptions,
+}
+
+/// Metadata of a log delivered to a subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogMeta {
+    /// The contract which emitted the log.
+    pub sender: Address,
+    /// The sender of the transaction which produced the log.
+    pub origin: Address,
+    /// The number of the block the transaction was executed in.
+    pub block_number: u64,
+}
+
+/// A callback subscribed to logs matching a filter.
+struct Subscription {
+    filter: ethabi::TopicFilter,
+    anonymous: Option<usize>,
+    /// Invoked with every matching log, returns `false` if the log turned out to not belong to the
+    /// subscription.
+    callback: Box<FnMut(&LogEntry, LogMeta) -> Result<bool, Error> + Send>,
+}
+
+/// Subscriptions registered with a virtual machine.
+///
+/// Subscriptions belong to the instance they were registered with, so they are not cloned.
+#[derive(Default)]
+struct Subscriptions(RefCell<Vec<Subscription>>);
+
+impl Clone for Subscriptions {
+    fn clone(&self) -> Self {
+        Subscriptions::default()
+    }
+}
+
+/// The parts of a virtual machine which are restored when reverting to a checkpoint.
+#[derive(Clone)]
+struct Checkpoint {
+    env_info: parity_vm::EnvInfo,
+    state: state::State<state_db::StateDB>,
+    logs: HashMap<ethabi::Hash, Vec<LogEntry>>,
+    linker: linker::Linker,
+    crypto: crypto::Crypto,
 }
 
 impl fmt::Debug for Evm {
@@ -198,9 +386,173 @@ impl fmt::Debug for Evm {
 impl Evm {
     /// Create a new ethereum virtual machine abstraction.
     pub fn new(spec: &spec::Spec, context: abi::ContractContext) -> Result<Self, Error> {
+        let db = Arc::new(kvdb_memorydb::create(
+            db::NUM_COLUMNS.expect("We use column-based DB; qed"),
+        ));
+
+        let state = Self::state_from_spec(spec, db, journaldb::Algorithm::EarlyMerge)?;
+        Self::from_state(spec, context, state, None)
+    }
+
+    /// Open a virtual machine whose state is stored in a database at the given path.
+    ///
+    /// If the database already contains state that has been persisted using `Evm::persist`, the
+    /// virtual machine will continue from it. Otherwise it will be set up from the genesis of the
+    /// given spec.
+    ///
+    /// Note that only account state is persisted. Logs, block environment, and linker information
+    /// about deployed contracts are not.
+    #[cfg(feature = "persist")]
+    pub fn open(
+        spec: &spec::Spec,
+        context: abi::ContractContext,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| format_err!("{}: database path is not utf-8", path.display()))?;
+
+        let config = kvdb_rocksdb::DatabaseConfig::with_columns(db::NUM_COLUMNS);
+
+        let db: Arc<KeyValueDB> = Arc::new(
+            kvdb_rocksdb::Database::open(&config, path_str)
+                .map_err(|e| format_err!("{}: failed to open database: {}", path.display(), e))?,
+        );
+
+        let root = db
+            .get(db::COL_EXTRA, PERSISTED_ROOT_KEY)
+            .map_err(|e| format_err!("failed to read state root: {}", e))?;
+
+        let state = match root {
+            Some(root) => {
+                let journal_db =
+                    journaldb::new(db.clone(), journaldb::Algorithm::Archive, db::COL_STATE);
+                let state_db = state_db::StateDB::new(journal_db, 5 * 1024 * 1024);
+
+                state::State::from_existing(
+                    state_db,
+                    H256::from_slice(&root),
+                    spec.engine.account_start_nonce(0),
+                    Default::default(),
+                ).map_err(|e| format_err!("error opening persisted state: {}", e))?
+            }
+            None => Self::state_from_spec(spec, db.clone(), journaldb::Algorithm::Archive)?,
+        };
+
+        Self::from_state(spec, context, state, Some(db))
+    }
+
+    /// Persist the current state to the database that the virtual machine was opened with.
+    ///
+    /// Fails if the virtual machine was not opened using `Evm::open`.
+    pub fn persist(&self) -> Result<(), Error> {
+        let db = match self.db {
+            Some(ref db) => db,
+            None => bail!("virtual machine is not backed by a persistent database"),
+        };
+
+        let mut state = self.borrow_mut_state()?;
+
+        state
+            .commit()
+            .map_err(|e| format_err!("failed to commit state: {}", e))?;
+
+        let (root, mut state_db) = state.clone().drop();
+
+        let mut batch = kvdb::DBTransaction::new();
+
+        state_db
+            .journal_under(&mut batch, self.get_block_number(), &root)
+            .map_err(|e| format_err!("failed to journal state: {}", e))?;
+
+        batch.put(db::COL_EXTRA, PERSISTED_ROOT_KEY, &root);
+
+        db.write(batch)
+            .map_err(|e| format_err!("failed to write database: {}", e))?;
+
+        *state = state::State::from_existing(
+            state_db,
+            root,
+            self.engine.account_start_nonce(0),
+            Default::default(),
+        ).map_err(|e| format_err!("error setting up state: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Run the given function against the virtual machine, and revert the state, logs and
+    /// coverage it modified once it returns, even if it panics.
+    ///
+    /// This makes it safe to check the outcome of hypothetical transactions in the middle of a
+    /// test:
+    ///
+    /// ```rust,ignore
+    /// let balance = evm.scoped(|evm| {
+    ///     contract.withdraw(100)?.ok()?;
+    ///     evm.balance(owner)
+    /// })?;
+    /// ```
+    ///
+    /// Scopes can be nested, every scope reverts what was modified inside of it.
+    pub fn scoped<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Evm) -> Result<T, Error>,
+    {
+        let visited_statements = self
+            .visited_statements
+            .lock()
+            .map_err(|_| format_err!("lock poisoned"))?
+            .clone();
+
+        let guard = ScopeGuard {
+            evm: self,
+            checkpoint: self.take_checkpoint()?,
+            visited_statements,
+        };
+
+        let output = f(self);
+        mem::drop(guard);
+        output
+    }
+
+    /// Take a checkpoint of the parts of the virtual machine which are modified by transactions.
+    fn take_checkpoint(&self) -> Result<Checkpoint, Error> {
+        let mut state = self.borrow_mut_state()?;
+
+        state
+            .commit()
+            .map_err(|e| format_err!("failed to commit state: {}", e))?;
+
+        Ok(Checkpoint {
+            env_info: self.borrow_env_info()?.clone(),
+            state: state.clone(),
+            logs: self.borrow_logs()?.clone(),
+            linker: self.borrow_linker()?.clone(),
+            crypto: self.borrow_crypto()?.clone(),
+        })
+    }
+
+    /// Restore the virtual machine to a checkpoint.
+    fn restore_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        self.borrow_mut_env_info()?.clone_from(&checkpoint.env_info);
+        *self.borrow_mut_state()? = checkpoint.state.clone();
+        self.borrow_mut_logs()?.clone_from(&checkpoint.logs);
+        self.borrow_mut_linker()?.clone_from(&checkpoint.linker);
+        self.borrow_mut_crypto()?.clone_from(&checkpoint.crypto);
+        Ok(())
+    }
+
+    /// Construct a virtual machine from existing state.
+    fn from_state(
+        spec: &spec::Spec,
+        context: abi::ContractContext,
+        state: state::State<state_db::StateDB>,
+        db: Option<Arc<KeyValueDB>>,
+    ) -> Result<Self, Error> {
         let env_info = Self::env_info(Address::random());
         let engine = Arc::clone(&spec.engine);
-        let state = Self::state_from_spec(spec)?;
 
         let mut linker = linker::Linker::new();
 
@@ -213,16 +565,38 @@ impl Evm {
             linker.register_ast(path, registry);
         }
 
+        let events = context
+            .events
+            .into_iter()
+            .map(|event| (event.signature(), event))
+            .collect();
+
         let evm = Evm {
-            env_info,
+            env_info: RefCell::new(env_info),
             state: RefCell::new(state),
+            chain_id: engine.params().chain_id,
             engine,
             logs: RefCell::new(HashMap::new()),
+            events: Arc::new(events),
             linker: RefCell::new(linker),
             crypto: RefCell::new(crypto::Crypto::new()),
             visited_statements: Arc::new(Mutex::new(HashSet::new())),
+            coverage_excluded: Vec::new(),
+            value_format: ValueFormat::default(),
+            gas_headroom: DEFAULT_GAS_HEADROOM,
+            gas_profile: false,
+            line_gas_report: false,
+            line_gas: Arc::new(Mutex::new(HashMap::new())),
+            profiles: Self::default_profiles(),
+            profile: None,
+            db,
+            checkpoint: None,
+            subscriptions: Subscriptions::default(),
         };
 
+        // high-level calls to the cheatcode address require it to have code, which also rejects
+        // unsupported cheatcodes.
+        evm.set_code(cheatcodes::cheatcode_address(), cheatcodes::stub_code())?;
         Ok(evm)
     }
 
@@ -233,28 +607,211 @@ impl Evm {
             .map_err(|e| format_err!("failed to setup account: {}", e))
     }
 
+    /// Create the given number of accounts, each funded with the given initial balance.
+    pub fn create_accounts<W: Into<U256>>(
+        &self,
+        n: usize,
+        initial_balance: W,
+    ) -> Result<Vec<account::Account>, Error> {
+        let initial_balance = initial_balance.into();
+        let mut accounts = Vec::with_capacity(n);
+
+        for _ in 0..n {
+            let account = self.account()?;
+            self.add_balance(account.address, initial_balance)?;
+            accounts.push(account);
+        }
+
+        Ok(accounts)
+    }
+
+    /// Import an account from a raw private key.
+    pub fn account_from_secret(
+        &self,
+        secret: impl account::AsSecret,
+    ) -> Result<account::Account, Error> {
+        let crypto = self.borrow_mut_crypto()?;
+        account::Account::from_secret(&crypto, secret)
+            .map_err(|e| format_err!("failed to import account: {}", e))
+    }
+
+    /// Get the id of the chain, which signed transactions must be signed for.
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Set the id of the chain, which signed transactions must be signed for.
+    ///
+    /// Defaults to the chain id of the spec the virtual machine was set up with.
+    pub fn set_chain_id(&mut self, chain_id: u64) {
+        self.chain_id = chain_id;
+    }
+
     /// Get the current block number.
     pub fn get_block_number(&self) -> u64 {
-        self.env_info.number
+        self.env_info.borrow().number
     }
 
     /// Set the current block number.
     pub fn set_block_number(&mut self, number: u64) {
-        self.env_info.number = number;
+        self.env_info.get_mut().number = number;
+    }
+
+    /// Access the name of the test which is currently using the virtual machine, if it is being
+    /// run by the test runner.
+    #[cfg(feature = "test-runner")]
+    pub fn current_test() -> Option<String> {
+        ::parables_test_runner::context::current_test()
+    }
+
+    /// Access the name of the test which is currently using the virtual machine, if it is being
+    /// run by the test runner.
+    #[cfg(not(feature = "test-runner"))]
+    pub fn current_test() -> Option<String> {
+        None
+    }
+
+    /// Attribute gas used by a transaction to the current test, if it is being run by the test
+    /// runner.
+    #[cfg(feature = "test-runner")]
+    fn record_gas_used(gas_used: U256) {
+        ::parables_test_runner::context::record_gas_used(gas_used.low_u64());
+    }
+
+    /// Attribute gas used by a transaction to the current test, if it is being run by the test
+    /// runner.
+    #[cfg(not(feature = "test-runner"))]
+    fn record_gas_used(_gas_used: U256) {}
+
+    /// Record the trace, storage writes and logs of a transaction as artifacts of the current
+    /// test, which are written out if it fails.
+    ///
+    /// Every transaction replaces the artifacts of the one before it, so that a failing test
+    /// keeps the artifacts of the last transaction it performed.
+    #[cfg(feature = "test-runner")]
+    fn record_artifacts(
+        calls: &[trace::CallTrace],
+        storage_writes: &[storage::StorageWrite],
+        logs: &[LogEntry],
+    ) {
+        use parables_test_runner::artifacts;
+
+        if !artifacts::is_collecting() {
+            return;
+        }
+
+        for (name, value) in trace::artifacts(calls, storage_writes, logs) {
+            match ::serde_json::to_string_pretty(&value) {
+                Ok(json) => artifacts::record(name, json),
+                Err(e) => warn!("failed to serialize artifact `{}`: {}", name, e),
+            }
+        }
+    }
+
+    /// Record the trace, storage writes and logs of a transaction as artifacts of the current
+    /// test, which are written out if it fails.
+    #[cfg(not(feature = "test-runner"))]
+    fn record_artifacts(
+        _calls: &[trace::CallTrace],
+        _storage_writes: &[storage::StorageWrite],
+        _logs: &[LogEntry],
+    ) {
+    }
+
+    /// Set how `uint256` values of variables are formatted in errors.
+    ///
+    /// Use `ValueFormat::Auto` to render values that are likely wei amounts in ether.
+    pub fn set_value_format(&mut self, value_format: ValueFormat) {
+        self.value_format = value_format;
+    }
+
+    /// Set the headroom, in percent, applied to gas estimated for calls using `Call::auto_gas`.
+    ///
+    /// The headroom needs to cover gas which is refunded at the end of a transaction, and gas
+    /// withheld from nested calls. Defaults to 150%.
+    pub fn set_gas_headroom(&mut self, percent: u64) {
+        self.gas_headroom = percent;
+    }
+
+    /// Profile the gas used by every transaction, by function.
+    ///
+    /// The profile is available through `Call::gas_profile`, and can be exported as folded stacks
+    /// or a speedscope profile.
+    pub fn set_gas_profile(&mut self, gas_profile: bool) {
+        self.gas_profile = gas_profile;
+    }
+
+    /// Accumulate the gas used by every line of source over all transactions, which is available
+    /// through `Evm::line_gas_report`.
+    ///
+    /// Like visited statements, the gas is shared with all clones of the virtual machine.
+    pub fn set_line_gas_report(&mut self, line_gas_report: bool) {
+        self.line_gas_report = line_gas_report;
+    }
+
+    /// Register a call profile under the given name, which can be activated with
+    /// `Evm::use_profile`.
+    ///
+    /// The profiles `cheap` and `realistic_mainnet` are registered by default.
+    pub fn register_profile(&mut self, name: impl AsRef<str>, profile: call::CallProfile) {
+        self.profiles.insert(name.as_ref().to_string(), profile);
+    }
+
+    /// Apply the registered profile with the given name to all calls.
+    pub fn use_profile(&mut self, name: impl AsRef<str>) -> Result<(), Error> {
+        let name = name.as_ref();
+
+        let profile = match self.profiles.get(name) {
+            Some(profile) => *profile,
+            None => bail!("no such call profile: {}", name),
+        };
+
+        self.profile = Some(profile);
+        Ok(())
+    }
+
+    /// Set the profile to apply to all calls.
+    ///
+    /// While a profile is set, it overrides the gas and gas price of every call.
+    pub fn set_profile(&mut self, profile: Option<call::CallProfile>) {
+        self.profile = profile;
+    }
+
+    /// Access the profile currently applied to all calls, if any.
+    pub fn profile(&self) -> Option<call::CallProfile> {
+        self.profile
+    }
+
+    /// Profiles registered by default.
+    fn default_profiles() -> HashMap<String, call::CallProfile> {
+        let mut profiles = HashMap::new();
+        profiles.insert("cheap".to_string(), call::CallProfile::cheap());
+        profiles.insert(
+            "realistic_mainnet".to_string(),
+            call::CallProfile::realistic_mainnet(),
+        );
+        profiles
+    }
+
+    /// Access the schedule of the current block, containing the gas cost of all operations.
+    ///
+    /// This permits writing gas assertions in terms of the active specification, like
+    /// `schedule.tx_gas + 2 * schedule.sstore_set_gas`.
+    pub fn schedule(&self) -> Schedule {
+        self.engine.machine().schedule(self.get_block_number())
     }
 
     /// Convert the spec into a state.
     /// Converted from parity:
     /// https://github.com/paritytech/parity/blob/98b7c07171cd320f32877dfa5aa528f585dc9a72/ethcore/src/client/evm_test_client.rs#L136
-    fn state_from_spec(spec: &spec::Spec) -> Result<state::State<state_db::StateDB>, Error> {
+    fn state_from_spec(
+        spec: &spec::Spec,
+        db: Arc<KeyValueDB>,
+        algorithm: journaldb::Algorithm,
+    ) -> Result<state::State<state_db::StateDB>, Error> {
         let factories = Default::default();
 
-        let db = Arc::new(kvdb_memorydb::create(
-            db::NUM_COLUMNS.expect("We use column-based DB; qed"),
-        ));
-
-        let journal_db =
-            journaldb::new(db.clone(), journaldb::Algorithm::EarlyMerge, db::COL_STATE);
+        let journal_db = journaldb::new(db.clone(), algorithm, db::COL_STATE);
 
         let mut state_db = state_db::StateDB::new(journal_db, 5 * 1024 * 1024);
 
@@ -300,16 +857,39 @@ impl Evm {
     }
 
     /// Deploy the contract with the given code.
+    ///
+    /// Errors if the call has a value, but the constructor isn't payable.
     pub fn deploy<C>(&self, constructor: C, call: call::Call) -> Result<Call<Address>, Error>
     where
         C: abi::ContractFunction<Output = Address> + abi::Constructor,
     {
+        if !C::PAYABLE && !call.value.is_zero() {
+            bail!(
+                "{}: constructor is not payable, but deployment has a value of {} wei",
+                C::ITEM,
+                call.value
+            );
+        }
+
         let mut linker = self.borrow_mut_linker()?;
 
         let code = constructor
             .encoded(&linker)
             .map_err(|e| format_err!("{}: failed to encode deployment: {}", C::ITEM, e))?;
 
+        let object = linker::Object {
+            path: C::PATH.to_string(),
+            item: C::ITEM.to_string(),
+        };
+
+        // registered up front, so that writes performed by the constructor can be decoded.
+        if let Some(layout) = C::STORAGE_LAYOUT {
+            let layout = storage::StorageLayout::parse(layout)
+                .map_err(|e| format_err!("{}: {}", C::ITEM, e))?;
+
+            linker.register_storage_layout(object.clone(), layout);
+        }
+
         // when deploying, special source information should be used.
         let entry_source = match (C::BIN.clone(), C::SOURCE_MAP.clone()) {
             (bin, Some(source_map)) => {
@@ -326,16 +906,21 @@ impl Evm {
 
         // Register all linker information used for debugging.
         if let Outcome::Ok(ref address) = result.outcome {
-            let object = linker::Object {
-                path: C::PATH.to_string(),
-                item: C::ITEM.to_string(),
-            };
-
             if let (Some(bin), Some(source_map)) =
                 (C::RUNTIME_BIN.clone(), C::RUNTIME_SOURCE_MAP.clone())
             {
+                // immutable variables are filled in by the constructor, so use the deployed code
+                // for those regions.
+                let bin = match (C::IMMUTABLE_REFERENCES, self.code(*address)?) {
+                    (Some(references), Some(deployed)) => {
+                        linker::patch_immutables(bin, references, &deployed)
+                            .map_err(|e| format_err!("{}: {}", C::ITEM, e))?
+                    }
+                    _ => bin.to_string(),
+                };
+
                 let source = linker
-                    .source(C::PATH, C::ITEM, bin, source_map)
+                    .source(C::PATH, C::ITEM, &bin, source_map)
                     .map_err(|e| format_err!("{}: {}", C::ITEM, e))?;
 
                 linker.register_runtime_source(object.clone(), source);
@@ -347,6 +932,58 @@ impl Evm {
         Ok(result)
     }
 
+    /// Deploy one instance of the contract, and `n` EIP-1167 minimal proxies delegating to it.
+    ///
+    /// Returns the addresses of the clones, the address of the implementation is not included.
+    pub fn deploy_clones<C>(
+        &self,
+        constructor: C,
+        n: usize,
+        call: call::Call,
+    ) -> Result<Vec<Address>, Error>
+    where
+        C: abi::ContractFunction<Output = Address> + abi::Constructor,
+    {
+        let implementation = self
+            .deploy(constructor, call.clone())?
+            .ok()
+            .map_err(|e| format_err!("{}: failed to deploy implementation: {}", C::ITEM, e))?;
+
+        let mut out = Vec::with_capacity(n);
+
+        for _ in 0..n {
+            let address = self
+                .deploy_clone(implementation, call.clone())
+                .map_err(|e| format_err!("{}: {}", C::ITEM, e))?;
+
+            out.push(address);
+        }
+
+        Ok(out)
+    }
+
+    /// Deploy an EIP-1167 minimal proxy delegating to the given implementation.
+    ///
+    /// Source maps of the implementation are used for the proxy as well.
+    pub fn deploy_clone(
+        &self,
+        implementation: Address,
+        call: call::Call,
+    ) -> Result<Address, Error> {
+        let address = {
+            let linker = self.borrow_linker()?;
+
+            self.deploy_code(proxy::minimal_proxy_code(implementation), call, None, &linker)?
+                .ok()
+                .map_err(|e| format_err!("failed to deploy clone: {}", e))?
+        };
+
+        self.borrow_mut_linker()?
+            .register_clone(address, implementation);
+
+        Ok(address)
+    }
+
     /// Deploy the contract with the given code.
     pub fn deploy_code(
         &self,
@@ -361,49 +998,291 @@ impl Evm {
             call,
             entry_source,
             linker,
-            |evm, tx, _| {
-                let scheme = evm
-                    .engine
-                    .machine()
-                    .create_address_scheme(evm.env_info.number);
-
-                let address =
-                    executive::contract_address(scheme, &tx.sender(), &tx.nonce, &tx.data).0;
-                Ok(address)
-            },
+            |evm, tx, _| Ok(evm.contract_address(tx.sender(), tx.nonce, &tx.data)),
         )
     }
 
-    /// Perform a call against the given address' fallback function.
+    /// Link the given code in hex against the given library addresses, and deploy it with the given
+    /// constructor arguments.
     ///
-    /// This is the same as a straight up transfer.
-    pub fn call_default(&self, address: Address, call: call::Call) -> Result<Call<()>, Error> {
+    /// Libraries are identified by name, like `SafeMath`, or by path and name, like
+    /// `lib/SafeMath.sol:SafeMath`. This permits linking against libraries which weren't deployed
+    /// through parables, like ones in a forked state. Libraries which aren't given are linked
+    /// against the ones deployed through parables.
+    pub fn deploy_linked(
+        &self,
+        bin: &str,
+        libraries: &HashMap<String, Address>,
+        args: &[ethabi::Token],
+        call: call::Call,
+    ) -> Result<Call<Address>, Error> {
         let linker = self.borrow_linker()?;
 
-        self.action(
-            Action::Call(address),
-            Vec::new(),
-            call,
-            None,
-            &linker,
-            |_evm, _tx, _output| Ok(()),
-        )
+        let mut code = linker
+            .link_with(bin, libraries)
+            .map_err(|e| format_err!("failed to link: {}", e))?;
+
+        code.extend(ethabi::encode(args));
+        self.deploy_code(code, call, None, &linker)
     }
 
-    /// Setup a log drainer that drains the specified logs.
-    pub fn logs<'a, P>(&'a self, log: P) -> LogDrainer<'a, P>
-    where
-        P: abi::ParseLog + abi::LogFilter,
-    {
-        LogDrainer::new(self, log)
+    /// Calculate the address of a contract created by `sender` with the given nonce and code.
+    pub fn contract_address(&self, sender: Address, nonce: U256, code: &[u8]) -> Address {
+        let scheme = self
+            .engine
+            .machine()
+            .create_address_scheme(self.get_block_number());
+
+        executive::contract_address(scheme, &sender, &nonce, code).0
     }
 
-    /// Access raw underlying logs.
+    /// Perform a call with raw input data, returning the raw output.
     ///
-    /// Note: it is important that the Ref is released as soon as possible since this would
-    /// otherwise cause borrowing issues for other operations.
-    pub fn raw_logs(&self) -> Result<Ref<HashMap<ethabi::Hash, Vec<LogEntry>>>, Error> {
-        self.borrow_logs()
+    /// If `address` is `None`, the data is deployed as a new contract.
+    pub fn call_raw(
+        &self,
+        address: Option<Address>,
+        data: Vec<u8>,
+        call: call::Call,
+    ) -> Result<Call<Vec<u8>>, Error> {
+        let linker = self.borrow_linker()?;
+
+        let action = match address {
+            Some(address) => Action::Call(address),
+            None => Action::Create,
+        };
+
+        self.action(action, data, call, None, &linker, |_evm, _tx, output| {
+            Ok(output)
+        })
+    }
+
+    /// Perform a read-only query with raw input data, returning the raw output.
+    ///
+    /// If `address` is `None`, the data is run as the code of a new contract. Like `Vm::query`, the
+    /// query is performed against a copy of the state, so it doesn't modify state, collect logs,
+    /// or notify subscriptions.
+    pub fn query_raw(
+        &self,
+        address: Option<Address>,
+        data: Vec<u8>,
+        call: call::Call,
+    ) -> Result<Outcome<Vec<u8>>, Error> {
+        let linker = self.borrow_linker()?;
+
+        let action = match address {
+            Some(address) => Action::Call(address),
+            None => Action::Create,
+        };
+
+        self.query_action(action, data, call, &linker, |_evm, _tx, output| Ok(output))
+    }
+
+    /// Estimate the gas needed to perform a call with raw input data, like `Call::auto_gas` does.
+    ///
+    /// If `address` is `None`, the gas needed to deploy the data as a new contract is estimated.
+    pub fn estimate_gas_raw(
+        &self,
+        address: Option<Address>,
+        data: Vec<u8>,
+        call: call::Call,
+    ) -> Result<U256, Error> {
+        let state = self.borrow_state()?;
+
+        let nonce = state
+            .nonce(&call.sender)
+            .map_err(|_| format_err!("error building nonce"))?;
+
+        let (action, data) = match address {
+            Some(address) => (Action::Call(address), call.data(data)),
+            None => (Action::Create, call.extend_data(data)),
+        };
+
+        let tx = Transaction {
+            nonce,
+            gas_price: call.gas_price,
+            gas: call.gas,
+            action,
+            value: call.value,
+            data,
+        };
+
+        self.estimate_gas(&state, &tx, call.sender)
+    }
+
+    /// Perform several calls in order, and revert all of them if any of them doesn't succeed.
+    ///
+    /// Calls are executed as separate transactions from the same sender, so each of them uses
+    /// gas and a nonce of its own. Calls to different functions can be made by encoding them
+    /// up front:
+    ///
+    /// ```rust,ignore
+    /// use token::functions as f;
+    ///
+    /// let calls = evm.multicall(
+    ///     vec![
+    ///         (token, RawCall::new(f::approve::encode(spender, 100))),
+    ///         (spender, RawCall::new(f::deposit::encode(token, 100))),
+    ///     ],
+    ///     call,
+    /// )?;
+    /// ```
+    ///
+    /// If a call doesn't succeed, the state, logs and environment are reverted to what they were
+    /// before the first call, and an error describing the failing call is returned. Subscriptions
+    /// are notified of logs as they are produced, even if they are later reverted.
+    pub fn multicall<F>(
+        &self,
+        calls: impl IntoIterator<Item = (Address, F)>,
+        call: call::Call,
+    ) -> Result<Vec<Call<F::Output>>, Error>
+    where
+        F: abi::ContractFunction,
+    {
+        let checkpoint = self.take_checkpoint()?;
+        let mut out = Vec::new();
+
+        for (index, (address, f)) in calls.into_iter().enumerate() {
+            let result = match abi::Vm::call(self, address, f, call.clone()) {
+                Ok(result) => result,
+                Err(e) => {
+                    self.restore_checkpoint(&checkpoint)?;
+                    return Err(e);
+                }
+            };
+
+            if result.is_ok() {
+                out.push(result);
+                continue;
+            }
+
+            self.restore_checkpoint(&checkpoint)?;
+
+            match result.ok() {
+                Err(e) => bail!("call #{} to {:?} did not succeed: {}", index, address, e),
+                Ok(_) => unreachable!("outcome is not ok"),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Run a transaction which has already been signed, returning the raw output.
+    pub(crate) fn transact(&self, tx: SignedTransaction) -> Result<Call<Vec<u8>>, Error> {
+        let linker = self.borrow_linker()?;
+        let mut state = self.borrow_mut_state()?;
+
+        self.run_transaction(&mut state, tx, None, &linker, |_evm, _tx, output| {
+            Ok(output)
+        })
+    }
+
+    /// Execute a signed, RLP-encoded transaction, like the ones produced by
+    /// `Account::sign_transaction`.
+    #[cfg(feature = "account")]
+    pub fn send_raw_transaction(&self, raw: &[u8]) -> Result<Call<Vec<u8>>, Error> {
+        let tx: UnverifiedTransaction =
+            rlp::decode(raw).map_err(|e| format_err!("bad transaction: {}", e))?;
+
+        let tx = SignedTransaction::new(tx).map_err(|e| format_err!("bad signature: {}", e))?;
+
+        self.transact(tx)
+    }
+
+    /// Perform a call against the given address' fallback function.
+    ///
+    /// This is the same as a straight up transfer.
+    pub fn call_default(&self, address: Address, call: call::Call) -> Result<Call<()>, Error> {
+        let linker = self.borrow_linker()?;
+
+        self.action(
+            Action::Call(address),
+            Vec::new(),
+            call,
+            None,
+            &linker,
+            |_evm, _tx, _output| Ok(()),
+        )
+    }
+
+    /// Setup a log drainer that drains the specified logs.
+    pub fn logs<'a, P>(&'a self, log: P) -> LogDrainer<'a, P>
+    where
+        P: abi::ParseLog + abi::LogFilter,
+    {
+        LogDrainer::new(self, log)
+    }
+
+    /// Subscribe to logs matching the given event.
+    ///
+    /// The callback is invoked as soon as a transaction produces a matching log, instead of the
+    /// log being queued up to be drained. This is useful to maintain derived state over long
+    /// scenarios, like feeding a ledger:
+    ///
+    /// ```rust,ignore
+    /// let updates = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// evm.subscribe(ev::value_updated(), {
+    ///     let updates = Arc::clone(&updates);
+    ///
+    ///     move |log, meta| {
+    ///         updates.lock().unwrap().push((meta.sender, log.value));
+    ///         Ok(())
+    ///     }
+    /// })?;
+    /// ```
+    ///
+    /// Errors raised by the callback are returned from the transaction which produced the log,
+    /// once the transaction has been committed and all of its logs have been processed. A log
+    /// which a callback failed for is queued up to be drained like any other log.
+    /// Subscriptions are not carried over to clones of the virtual machine.
+    pub fn subscribe<P, F>(&self, log: P, mut callback: F) -> Result<(), Error>
+    where
+        P: 'static + Send + abi::ParseLog + abi::LogFilter,
+        F: 'static + Send + FnMut(P::Log, LogMeta) -> Result<(), Error>,
+    {
+        let filter = log.wildcard_filter();
+        let anonymous = log.anonymous_topics();
+
+        let callback = move |entry: &LogEntry, meta: LogMeta| {
+            let raw = ethabi::RawLog {
+                topics: entry.topics.clone(),
+                data: entry.data.clone(),
+            };
+
+            let parsed = match log.parse_log(raw) {
+                Ok(parsed) => parsed,
+                // logs of other events might have the same number of topics as an anonymous
+                // event.
+                Err(_) if anonymous.is_some() => return Ok(false),
+                Err(e) => return Err(format_err!("failed to parse log entry: {}", e)),
+            };
+
+            callback(parsed, meta)?;
+            Ok(true)
+        };
+
+        let mut subscriptions = self
+            .subscriptions
+            .0
+            .try_borrow_mut()
+            .map_err(|e| format_err!("cannot borrow subscriptions mutably: {}", e))?;
+
+        subscriptions.push(Subscription {
+            filter,
+            anonymous,
+            callback: Box::new(callback),
+        });
+
+        Ok(())
+    }
+
+    /// Access raw underlying logs.
+    ///
+    /// Note: it is important that the Ref is released as soon as possible since this would
+    /// otherwise cause borrowing issues for other operations.
+    pub fn raw_logs(&self) -> Result<Ref<HashMap<ethabi::Hash, Vec<LogEntry>>>, Error> {
+        self.borrow_logs()
     }
 
     /// Check if we still have unclaimed logs.
@@ -412,6 +1291,79 @@ impl Evm {
         Ok(logs.values().any(|v| !v.is_empty()))
     }
 
+    /// Summarize all unclaimed logs.
+    ///
+    /// Logs are grouped by their first topic, and named after the corresponding event if it is
+    /// known.
+    pub fn pending_logs_summary(&self) -> Result<Vec<LogSummary>, Error> {
+        let logs = self.borrow_logs()?;
+
+        // format the parameters of the given log entry.
+        let format_params = |event: &ethabi::Event, entry: &LogEntry| {
+            let log = event
+                .parse_log((entry.topics.clone(), entry.data.clone()).into())
+                .ok()?;
+
+            let params = log
+                .params
+                .into_iter()
+                .map(|p| format!("{}: {}", p.name, p.value))
+                .collect::<Vec<_>>();
+
+            Some(params.join(", "))
+        };
+
+        let mut out = Vec::new();
+
+        for (topic, entries) in logs.iter() {
+            let (first, last) = match (entries.first(), entries.last()) {
+                (Some(first), Some(last)) => (first, last),
+                _ => continue,
+            };
+
+            let event = self.events.get(topic);
+
+            out.push(LogSummary {
+                topic: *topic,
+                name: event.map(|e| e.name.to_string()),
+                count: entries.len(),
+                first_sender: first.address,
+                first_params: event.and_then(|e| format_params(e, first)),
+                last_sender: last.address,
+                last_params: event.and_then(|e| format_params(e, last)),
+            });
+        }
+
+        out.sort_by(|a, b| (&a.name, &a.topic).cmp(&(&b.name, &b.topic)));
+        Ok(out)
+    }
+
+    /// Assert that there are no unclaimed logs.
+    ///
+    /// If there are, the returned error lists all of them.
+    pub fn assert_no_logs(&self) -> Result<(), Error> {
+        let summary = self.pending_logs_summary()?;
+
+        if summary.is_empty() {
+            return Ok(());
+        }
+
+        let summary = summary
+            .iter()
+            .map(|s| format!("  {}", s))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        bail!("expected no logs, but found:\n{}", summary);
+    }
+
+    /// Resolve the program counter of code running at the given address to a source location.
+    ///
+    /// Only contracts deployed through parables, with a runtime source map, can be resolved.
+    pub fn resolve_pc(&self, address: Address, pc: usize) -> Option<linker::SourceLocation> {
+        self.borrow_linker().ok()?.resolve_pc(address, pc)
+    }
+
     /// Query the balance of the given account.
     pub fn balance(&self, address: Address) -> Result<U256, Error> {
         let state = self.borrow_state()?;
@@ -420,6 +1372,34 @@ impl Evm {
             .map_err(|_| format_err!("failed to access balance"))?)
     }
 
+    /// Query the nonce of the given account.
+    pub fn nonce(&self, address: Address) -> Result<U256, Error> {
+        let state = self.borrow_state()?;
+        Ok(state
+            .nonce(&address)
+            .map_err(|_| format_err!("failed to access nonce"))?)
+    }
+
+    /// Query the code of the given account, if it has any.
+    pub fn code(&self, address: Address) -> Result<Option<Vec<u8>>, Error> {
+        let state = self.borrow_state()?;
+
+        let code = state
+            .code(&address)
+            .map_err(|_| format_err!("failed to access code"))?;
+
+        Ok(code.map(|code| code.as_ref().clone()))
+    }
+
+    /// Replace the code of the given account.
+    pub fn set_code(&self, address: Address, code: Vec<u8>) -> Result<(), Error> {
+        let mut state = self.borrow_mut_state()?;
+
+        Ok(state
+            .reset_code(&address, code)
+            .map_err(|_| format_err!("failed to modify code"))?)
+    }
+
     /// Add the given number of wei to the provided account.
     pub fn add_balance<W: Into<U256>>(&self, address: Address, wei: W) -> Result<(), Error> {
         let mut state = self.borrow_mut_state()?;
@@ -429,27 +1409,60 @@ impl Evm {
             .map_err(|_| format_err!("failed to modify balance"))?)
     }
 
+    /// Exclude the given file from coverage, like `Migrations.sol`.
+    ///
+    /// The path is matched against the end of the paths of sources.
+    pub fn exclude_from_coverage(&mut self, path: impl AsRef<Path>) {
+        self.coverage_excluded.push(path.as_ref().to_owned());
+    }
+
     /// Access the visited statement statistics.
+    ///
+    /// Statements in excluded files, and on lines marked with `// parables-ignore` or following
+    /// `// parables-ignore-next-line` are not counted.
     pub fn calculate_visited(&self) -> Result<(u32, u32), Error> {
-        let mut total = 0u32;
-        let mut count = 0u32;
+        let report = self.coverage_report()?;
+        Ok((report.visited, report.total))
+    }
 
+    /// Build a report of the coverage of all known sources, by statement and by function.
+    ///
+    /// This is subject to the same exclusions as `calculate_visited`.
+    pub fn coverage_report(&self) -> Result<coverage::CoverageReport, Error> {
         let visited_statements = self
             .visited_statements
             .lock()
             .map_err(|_| format_err!("lock poisoned"))?;
 
         let linker = self.borrow_linker()?;
+        let mut filter = coverage::Filter::new(&linker, &self.coverage_excluded);
 
-        for src in linker.all_asts().flat_map(ast::Registry::statements) {
-            total += 1;
+        Ok(coverage::report(&linker, &visited_statements, &mut filter))
+    }
 
-            if visited_statements.contains(src) {
-                count += 1;
-            }
+    /// Build a report of the gas used by every line of source, accumulated over all transactions
+    /// since `Evm::set_line_gas_report` was enabled.
+    pub fn line_gas_report(&self) -> Result<gas::LineGasReport, Error> {
+        let line_gas = self
+            .line_gas
+            .lock()
+            .map_err(|_| format_err!("lock poisoned"))?;
+
+        let linker = self.borrow_linker()?;
+        let mut report = gas::LineGasReport::new();
+
+        for (&(file_index, start), gas) in line_gas.iter() {
+            let path = match linker.find_file(file_index) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let file = linker.source_cache().file(path)?;
+            let (_, line) = file.find_line((start as usize, start as usize));
+            report.add(path, line + 1, *gas);
         }
 
-        Ok((count, total))
+        Ok(report)
     }
 
     /// Execute the given action.
@@ -464,23 +1477,111 @@ impl Evm {
     ) -> Result<Call<T>, Error> {
         let mut state = self.borrow_mut_state()?;
 
+        let call = match self.profile {
+            Some(ref profile) => profile.apply(call),
+            None => call,
+        };
+
         let nonce = state
             .nonce(&call.sender)
             .map_err(|_| format_err!("error building nonce"))?;
 
-        let tx = Transaction {
+        let data = match action {
+            Action::Call(_) => call.data(data),
+            Action::Create => call.extend_data(data),
+        };
+
+        let mut tx = Transaction {
             nonce,
             gas_price: call.gas_price,
             gas: call.gas,
             action: action,
             value: call.value,
-            data: data,
+            data,
         };
 
+        if call.auto_gas {
+            tx.gas = self.estimate_gas(&state, &tx, call.sender)?;
+        }
+
         let tx = tx.fake_sign(call.sender.into());
         self.run_transaction(&mut state, tx, entry_source, linker, decode)
     }
 
+    /// Run a call against a copy of the state, discarding any changes it makes.
+    ///
+    /// The call is performed with the gas limit of the block and a zero gas price.
+    fn query_action<T>(
+        &self,
+        action: Action,
+        data: Vec<u8>,
+        call: call::Call,
+        linker: &linker::Linker,
+        decode: impl FnOnce(&Evm, &SignedTransaction, Vec<u8>) -> Result<T, Error>,
+    ) -> Result<Outcome<T>, Error> {
+        let mut state = self.borrow_state()?.clone();
+        let env_info = self.borrow_env_info()?;
+
+        let nonce = state
+            .nonce(&call.sender)
+            .map_err(|_| format_err!("error building nonce"))?;
+
+        let tx = Transaction {
+            nonce,
+            gas_price: U256::zero(),
+            gas: env_info.gas_limit,
+            action,
+            value: call.value,
+            data: call.extend_data(data),
+        };
+
+        let tx = tx.fake_sign(call.sender.into());
+        let shared = Mutex::new(trace::Shared::new(self.value_format));
+
+        let result = state
+            .apply_with_tracing(
+                &env_info,
+                self.engine.machine(),
+                &tx,
+                trace::Tracer::new(linker, None, &shared),
+                trace::VmTracer::new(linker, None, &shared),
+            )
+            .map_err(|e| format_err!("vm: {}", e))?;
+
+        self.outcome(result, tx, decode)
+    }
+
+    /// Estimate the gas needed for the given transaction, including the configured headroom.
+    ///
+    /// The transaction is run against a copy of the state using the gas limit of the block and a
+    /// zero gas price, so that the sender doesn't need to afford the full gas limit.
+    fn estimate_gas(
+        &self,
+        state: &state::State<state_db::StateDB>,
+        tx: &Transaction,
+        sender: Address,
+    ) -> Result<U256, Error> {
+        let env_info = self.borrow_env_info()?;
+
+        let mut probe = tx.clone();
+        probe.gas = env_info.gas_limit;
+        probe.gas_price = U256::zero();
+
+        let mut state = state.clone();
+
+        let result = state
+            .apply(
+                &env_info,
+                self.engine.machine(),
+                &probe.fake_sign(sender.into()),
+                false,
+            )
+            .map_err(|e| format_err!("failed to estimate gas: {}", e))?;
+
+        let gas = result.receipt.gas_used * U256::from(self.gas_headroom) / U256::from(100);
+        Ok(::std::cmp::min(gas, env_info.gas_limit))
+    }
+
     /// Run the specified transaction.
     fn run_transaction<T>(
         &self,
@@ -491,24 +1592,69 @@ impl Evm {
         decode: impl FnOnce(&Evm, &SignedTransaction, Vec<u8>) -> Result<T, Error>,
     ) -> Result<Call<T>, Error> {
         // Verify transaction
-        tx.verify_basic(true, None, false)
+        tx.verify_basic(true, Some(self.chain_id()), false)
             .map_err(|e| format_err!("verify failed: {}", e))?;
 
-        let shared = Mutex::new(trace::Shared::new());
+        let to = match tx.action {
+            Action::Call(address) => address,
+            Action::Create => self.contract_address(tx.sender(), tx.nonce, &tx.data),
+        };
+
+        let mut shared = trace::Shared::new(self.value_format);
+
+        if self.gas_profile {
+            shared.gas_profile = Some(gas::GasProfile::new());
+        }
+
+        if self.line_gas_report {
+            shared.line_gas = Some(HashMap::new());
+        }
+
+        let shared = Mutex::new(shared);
 
         // Apply transaction
-        let result = state.apply_with_tracing(
-            &self.env_info,
-            self.engine.machine(),
-            &tx,
-            trace::Tracer::new(linker, entry_source.clone(), &shared),
-            trace::VmTracer::new(linker, entry_source.clone(), &shared),
-        );
+        let result = {
+            let env_info = self.borrow_env_info()?;
+
+            state.apply_with_tracing(
+                &env_info,
+                self.engine.machine(),
+                &tx,
+                trace::Tracer::new(linker, entry_source.clone(), &shared),
+                trace::VmTracer::new(linker, entry_source.clone(), &shared),
+            )
+        };
 
         let mut result = result.map_err(|e| format_err!("vm: {}", e))?;
 
+        let trace::Shared {
+            console,
+            cheatcodes,
+            gas_profile,
+            line_gas,
+            storage_writes,
+            calls,
+            ..
+        } = shared
+            .into_inner()
+            .map_err(|_| format_err!("lock poisoned"))?;
+
+        // the stub code reverts calls to unsupported cheatcodes, so this only happens if the code at
+        // the cheatcode address has been replaced.
+        for cheat in &cheatcodes {
+            if let cheatcodes::Cheatcode::Unsupported(ref reason) = *cheat {
+                bail!("unsupported cheatcode: {}", reason);
+            }
+        }
+
         state.commit().ok();
-        self.add_logs(result.receipt.logs.drain(..))?;
+        self.apply_cheatcodes(state, cheatcodes)?;
+        Self::record_artifacts(&calls, &storage_writes, &result.receipt.logs);
+        Self::record_gas_used(result.receipt.gas_used);
+
+        // logs are added once the rest of the transaction is accounted for, since subscriptions
+        // might fail.
+        let logs = result.receipt.logs.drain(..).collect::<Vec<_>>();
 
         let gas_used = result.receipt.gas_used;
         let gas_price = tx.gas_price;
@@ -524,7 +1670,27 @@ impl Evm {
             visited_statements.extend(vm_trace.visited_statements.drain());
         }
 
-        let outcome = self.outcome(result, tx, decode)?;
+        if let Some(line_gas) = line_gas {
+            let mut accumulated = self
+                .line_gas
+                .lock()
+                .map_err(|_| format_err!("lock poisoned"))?;
+
+            for (key, gas) in line_gas {
+                *accumulated.entry(key).or_insert(0) += gas;
+            }
+        }
+
+        let outcome = self.outcome(result, tx, decode);
+        let added = self.add_logs(sender, logs.iter().cloned());
+        let outcome = outcome?;
+        added?;
+
+        let test = Self::current_test();
+
+        if let Some(ref test) = test {
+            debug!("{}: transaction from {:?}, gas used {}", test, sender, gas_used);
+        }
 
         Ok(Call {
             outcome,
@@ -532,9 +1698,61 @@ impl Evm {
             gas_price,
             value,
             sender,
+            to,
+            console,
+            test,
+            gas_profile,
+            storage_writes,
+            calls,
+            logs,
         })
     }
 
+    /// Apply cheatcodes issued by a transaction.
+    fn apply_cheatcodes(
+        &self,
+        state: &mut state::State<state_db::StateDB>,
+        cheats: Vec<cheatcodes::Cheatcode>,
+    ) -> Result<(), Error> {
+        use cheatcodes::Cheatcode::*;
+
+        for cheat in cheats {
+            match cheat {
+                Warp(timestamp) => self.borrow_mut_env_info()?.timestamp = timestamp.low_u64(),
+                Roll(number) => self.borrow_mut_env_info()?.number = number.low_u64(),
+                Deal(address, balance) => {
+                    let current = state
+                        .balance(&address)
+                        .map_err(|_| format_err!("failed to access balance"))?;
+
+                    if balance > current {
+                        state
+                            .add_balance(
+                                &address,
+                                &(balance - current),
+                                state::CleanupMode::ForceCreate,
+                            )
+                            .map_err(|_| format_err!("failed to modify balance"))?;
+                    } else {
+                        state
+                            .sub_balance(
+                                &address,
+                                &(current - balance),
+                                &mut state::CleanupMode::NoEmpty,
+                            )
+                            .map_err(|_| format_err!("failed to modify balance"))?;
+                    }
+
+                    state.commit().ok();
+                }
+                // checked before the transaction is committed.
+                Unsupported(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
     /// Convert into an outcome.
     fn outcome<T>(
         &self,
@@ -547,7 +1765,7 @@ impl Evm {
 
             if reverted {
                 return Ok(Outcome::Reverted {
-                    errors: trace::Errors::new(result.trace),
+                    errors: trace::Errors::new(result.trace).with_output(result.output),
                 });
             } else {
                 return Ok(Outcome::Errored {
@@ -567,19 +1785,70 @@ impl Evm {
     }
 
     /// Add logs, partitioned by topic.
-    fn add_logs(&self, new_logs: impl Iterator<Item = LogEntry>) -> Result<(), Error> {
+    ///
+    /// Logs matching a subscription are passed to it instead of being added. If a subscription
+    /// fails, the log is added so that it can still be drained, the remaining logs are processed,
+    /// and the first error is returned.
+    fn add_logs(
+        &self,
+        origin: Address,
+        new_logs: impl Iterator<Item = LogEntry>,
+    ) -> Result<(), Error> {
         let mut logs = self.borrow_mut_logs()?;
 
+        let mut subscriptions = self
+            .subscriptions
+            .0
+            .try_borrow_mut()
+            .map_err(|e| format_err!("cannot borrow subscriptions mutably: {}", e))?;
+
+        let block_number = self.get_block_number();
+        let mut error = None;
+
         for log in new_logs {
-            let topic = match log.topics.iter().next() {
-                Some(first) => *first,
-                None => return Err(format_err!("expected at least one topic")),
+            let meta = LogMeta {
+                sender: log.address,
+                origin,
+                block_number,
             };
 
+            let mut claimed = false;
+            let mut failed = false;
+
+            for subscription in subscriptions.iter_mut() {
+                if !log_matches(&log, &subscription.filter, subscription.anonymous) {
+                    continue;
+                }
+
+                match (subscription.callback)(&log, meta) {
+                    Ok(true) => claimed = true,
+                    Ok(false) => {}
+                    Err(e) => {
+                        failed = true;
+                        error = error.or(Some(e));
+                    }
+                }
+            }
+
+            if claimed && !failed {
+                continue;
+            }
+
+            // anonymous events without indexed parameters don't have any topics.
+            let topic = log
+                .topics
+                .iter()
+                .next()
+                .cloned()
+                .unwrap_or_else(ethabi::Hash::zero);
+
             logs.entry(topic).or_insert_with(Vec::new).push(log);
         }
 
-        Ok(())
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 
     /// Access all raw logs.
@@ -596,6 +1865,20 @@ impl Evm {
             .map_err(|e| format_err!("cannot borrow logs mutably: {}", e))
     }
 
+    /// Access the environment.
+    fn borrow_env_info(&self) -> Result<Ref<parity_vm::EnvInfo>, Error> {
+        self.env_info
+            .try_borrow()
+            .map_err(|e| format_err!("cannot borrow environment: {}", e))
+    }
+
+    /// Mutably access the environment.
+    fn borrow_mut_env_info(&self) -> Result<RefMut<parity_vm::EnvInfo>, Error> {
+        self.env_info
+            .try_borrow_mut()
+            .map_err(|e| format_err!("cannot borrow environment mutably: {}", e))
+    }
+
     /// Access linker.
     fn borrow_linker(&self) -> Result<Ref<linker::Linker>, Error> {
         self.linker
@@ -625,6 +1908,13 @@ impl Evm {
     }
 
     /// Access underlying crypto.
+    fn borrow_crypto(&self) -> Result<Ref<crypto::Crypto>, Error> {
+        self.crypto
+            .try_borrow()
+            .map_err(|e| format_err!("cannot borrow crypto: {}", e))
+    }
+
+    /// Mutably access underlying crypto.
     fn borrow_mut_crypto(&self) -> Result<RefMut<crypto::Crypto>, Error> {
         self.crypto
             .try_borrow_mut()
@@ -632,6 +1922,58 @@ impl Evm {
     }
 }
 
+/// Reverting restores the state, environment, logs, linker and accounts of the virtual machine.
+///
+/// Coverage and line gas are accumulated across reverts, like they are across snapshots.
+#[cfg(feature = "test-runner")]
+impl ::parables_test_runner::snapshot::Revert for Evm {
+    fn checkpoint(&mut self) -> Result<(), Error> {
+        let checkpoint = self.take_checkpoint()?;
+        self.checkpoint = Some(Box::new(checkpoint));
+        Ok(())
+    }
+
+    fn revert(&mut self) -> Result<(), Error> {
+        let checkpoint = match self.checkpoint {
+            Some(ref checkpoint) => checkpoint,
+            None => bail!("no checkpoint to revert to"),
+        };
+
+        self.restore_checkpoint(checkpoint)
+    }
+}
+
+/// Reverts a virtual machine to the checkpoint taken when a scope was entered, once it is dropped.
+struct ScopeGuard<'a> {
+    evm: &'a Evm,
+    checkpoint: Checkpoint,
+    visited_statements: HashSet<ast::Src>,
+}
+
+impl<'a> ScopeGuard<'a> {
+    /// Revert the virtual machine to the checkpoint.
+    fn revert(&self) -> Result<(), Error> {
+        self.evm.restore_checkpoint(&self.checkpoint)?;
+
+        let mut visited_statements = self
+            .evm
+            .visited_statements
+            .lock()
+            .map_err(|_| format_err!("lock poisoned"))?;
+
+        visited_statements.clone_from(&self.visited_statements);
+        Ok(())
+    }
+}
+
+impl<'a> Drop for ScopeGuard<'a> {
+    fn drop(&mut self) {
+        if let Err(e) = self.revert() {
+            warn!("failed to revert scoped changes: {}", e);
+        }
+    }
+}
+
 impl abi::Vm for Evm {
     fn call<F>(&self, address: Address, f: F, call: call::Call) -> Result<Call<F::Output>, Error>
     where
@@ -655,6 +1997,76 @@ impl abi::Vm for Evm {
             },
         )
     }
+
+    fn query<F>(&self, address: Address, f: F, call: call::Call) -> Result<F::Output, Error>
+    where
+        F: abi::ContractFunction,
+    {
+        let linker = self.borrow_linker()?;
+
+        let params = f
+            .encoded(&linker)
+            .map_err(|e| format_err!("failed to encode input: {}", e))?;
+
+        let action = Action::Call(address);
+
+        self.query_action(action, params, call, &linker, move |_evm, _tx, output| {
+            f.output(output)
+                .map_err(|e| format_err!("VM output conversion failed: {}", e))
+        })?
+        .ok()
+    }
+
+    fn storage_at(&self, address: Address, slot: H256) -> Result<H256, Error> {
+        let state = self.borrow_state()?;
+        Ok(state
+            .storage_at(&address, &slot)
+            .map_err(|_| format_err!("failed to access storage"))?)
+    }
+}
+
+/// Summary of unclaimed logs with the same first topic.
+#[derive(Debug, Clone)]
+pub struct LogSummary {
+    /// The first topic of the logs.
+    pub topic: ethabi::Hash,
+    /// The name of the event, if known.
+    pub name: Option<String>,
+    /// Number of unclaimed logs.
+    pub count: usize,
+    /// Sender of the first log.
+    pub first_sender: Address,
+    /// Parameters of the first log, if they could be decoded.
+    pub first_params: Option<String>,
+    /// Sender of the last log.
+    pub last_sender: Address,
+    /// Parameters of the last log, if they could be decoded.
+    pub last_params: Option<String>,
+}
+
+impl fmt::Display for LogSummary {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(fmt, "{}", name)?,
+            None => write!(fmt, "{:?}", self.topic)?,
+        }
+
+        write!(fmt, " x{} (first from {:?}", self.count, self.first_sender)?;
+
+        if let Some(ref params) = self.first_params {
+            write!(fmt, ": {}", params)?;
+        }
+
+        if self.count > 1 {
+            write!(fmt, ", last from {:?}", self.last_sender)?;
+
+            if let Some(ref params) = self.last_params {
+                write!(fmt, ": {}", params)?;
+            }
+        }
+
+        write!(fmt, ")")
+    }
 }
 
 #[derive(Debug)]
@@ -662,6 +2074,8 @@ pub struct LogDrainer<'a, P> {
     evm: &'a Evm,
     log: P,
     filter: ethabi::TopicFilter,
+    /// Only drain logs emitted by this address, if set.
+    address: Option<Address>,
 }
 
 impl<'a, P> LogDrainer<'a, P>
@@ -671,7 +2085,23 @@ where
     pub fn new(evm: &'a Evm, log: P) -> Self {
         let filter = log.wildcard_filter();
 
-        Self { evm, log, filter }
+        Self {
+            evm,
+            log,
+            filter,
+            address: None,
+        }
+    }
+
+    /// Only drain logs emitted by the contract at the given address.
+    ///
+    /// This is recommended when draining anonymous events, since these can't be told apart from
+    /// other logs with the same number of topics.
+    pub fn address(self, address: Address) -> Self {
+        Self {
+            address: Some(address),
+            ..self
+        }
     }
 
     /// Modify the current drainer with a new filter.
@@ -699,6 +2129,44 @@ where
         Ok(())
     }
 
+    /// Drain logs matching the given filter, and error if there are none.
+    ///
+    /// The error lists all logs which are still unclaimed, to show what was emitted instead.
+    pub fn assert_emitted(self) -> Result<Vec<P::Log>, Error>
+    where
+        P: abi::ParseLog,
+    {
+        let evm = self.evm;
+
+        let expected = match self.filter.topic0 {
+            ethabi::Topic::This(ref topic) => match evm.events.get(topic) {
+                Some(event) => format!("event `{}`", event.name),
+                None => format!("event {:?}", topic),
+            },
+            _ => String::from("anonymous event"),
+        };
+
+        let logs = self.drain()?;
+
+        if !logs.is_empty() {
+            return Ok(logs);
+        }
+
+        let summary = evm.pending_logs_summary()?;
+
+        if summary.is_empty() {
+            bail!("expected {} to be emitted, but no logs were emitted", expected);
+        }
+
+        let summary = summary
+            .iter()
+            .map(|s| format!("  {}", s))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        bail!("expected {} to be emitted, but found:\n{}", expected, summary);
+    }
+
     /// Drain logs matching the given filter that has been registered so far.
     pub fn drain(self) -> Result<Vec<P::Log>, Error>
     where
@@ -721,88 +2189,134 @@ where
     {
         let mut out = Vec::new();
 
-        let LogDrainer { evm, log, filter } = self;
+        let LogDrainer {
+            evm,
+            log,
+            filter,
+            address,
+        } = self;
 
-        let topic = extract_this_topic(&filter.topic0)?;
+        let anonymous = log.anonymous_topics();
 
-        let matches = move |log: &LogEntry| {
-            let mut top = log.topics.iter();
-
-            // topics to match in order.
-            let mut mat = vec![
-                &filter.topic0,
-                &filter.topic1,
-                &filter.topic2,
-                &filter.topic3,
-            ].into_iter();
-
-            while let Some(t) = top.next() {
-                let m = match mat.next() {
-                    Some(m) => m,
-                    None => return false,
-                };
-
-                match m {
-                    ethabi::Topic::Any => continue,
-                    ethabi::Topic::OneOf(ids) => {
-                        if ids.contains(t) {
-                            continue;
-                        }
-                    }
-                    ethabi::Topic::This(id) => {
-                        if id == t {
-                            continue;
-                        }
-                    }
+        let matches = |entry: &LogEntry| {
+            if let Some(address) = address {
+                if entry.address != address {
+                    return false;
                 }
-
-                return false;
             }
 
-            // rest must match any
-            mat.all(|m| *m == ethabi::Topic::Any)
+            log_matches(entry, &filter, anonymous)
         };
 
         let mut logs = evm.borrow_mut_logs()?;
 
-        match logs.entry(topic) {
-            hash_map::Entry::Vacant(_) => return Ok(out),
-            hash_map::Entry::Occupied(mut e) => {
-                let remove = {
-                    let mut keep = Vec::new();
-                    let logs = e.get_mut();
+        let topics = match anonymous {
+            // anonymous events aren't identified by their first topic, so any log might match.
+            Some(_) => logs.keys().cloned().collect::<Vec<_>>(),
+            None => vec![extract_this_topic(&filter.topic0)?],
+        };
+
+        for topic in topics {
+            let mut e = match logs.entry(topic) {
+                hash_map::Entry::Vacant(_) => continue,
+                hash_map::Entry::Occupied(e) => e,
+            };
+
+            let remove = {
+                let mut keep = Vec::new();
+                let logs = e.get_mut();
 
-                    for entry in logs.drain(..) {
-                        if !matches(&entry) {
+                for entry in logs.drain(..) {
+                    if !matches(&entry) {
+                        keep.push(entry);
+                        continue;
+                    }
+
+                    let raw = ethabi::RawLog {
+                        topics: entry.topics.clone(),
+                        data: entry.data.clone(),
+                    };
+
+                    let parsed = match log.parse_log(raw) {
+                        Ok(parsed) => parsed,
+                        // logs of other events might have the same number of topics as an
+                        // anonymous event.
+                        Err(_) if anonymous.is_some() => {
                             keep.push(entry);
                             continue;
                         }
+                        Err(e) => return Err(format_err!("failed to parse log entry: {}", e)),
+                    };
 
-                        let sender = entry.address;
+                    out.push(map(entry.address, parsed));
+                }
 
-                        let entry = log
-                            .parse_log((entry.topics, entry.data).into())
-                            .map_err(|e| format_err!("failed to parse log entry: {}", e))?;
+                if !keep.is_empty() {
+                    mem::replace(logs, keep);
+                    false
+                } else {
+                    true
+                }
+            };
 
-                        out.push(map(sender, entry));
-                    }
+            if remove {
+                e.remove_entry();
+            }
+        }
 
-                    if !keep.is_empty() {
-                        mem::replace(logs, keep);
-                        false
-                    } else {
-                        true
-                    }
-                };
+        Ok(out)
+    }
+}
 
-                if remove {
-                    e.remove_entry();
+/// Test if the given log entry matches a topic filter.
+///
+/// `anonymous` is the number of topics of the event if it is anonymous.
+fn log_matches(
+    entry: &LogEntry,
+    filter: &ethabi::TopicFilter,
+    anonymous: Option<usize>,
+) -> bool {
+    if let Some(count) = anonymous {
+        if entry.topics.len() != count {
+            return false;
+        }
+    }
+
+    let mut top = entry.topics.iter();
+
+    // topics to match in order.
+    let mut mat = vec![
+        &filter.topic0,
+        &filter.topic1,
+        &filter.topic2,
+        &filter.topic3,
+    ].into_iter();
+
+    while let Some(t) = top.next() {
+        let m = match mat.next() {
+            Some(m) => m,
+            None => return false,
+        };
+
+        match m {
+            ethabi::Topic::Any => continue,
+            ethabi::Topic::OneOf(ids) => {
+                if ids.contains(t) {
+                    continue;
+                }
+            }
+            ethabi::Topic::This(id) => {
+                if id == t {
+                    continue;
                 }
             }
         }
 
-        Ok(out)
+        return false;
     }
+
+    // rest must match any
+    mat.all(|m| *m == ethabi::Topic::Any)
 }
 
 /// Extract the exact topic or fail.
@@ -812,3 +2326,325 @@ pub fn extract_this_topic(topic: &ethabi::Topic<ethabi::Hash>) -> Result<ethabi:
         ref other => return Err(format_err!("not an exact topic: {:?}", other)),
     }
 }
+
+#[cfg(all(test, feature = "account"))]
+mod tests {
+    use super::{Call, Evm, Outcome};
+    use abi::{ContractContext, Vm};
+    use account::Account;
+    use call;
+    use cheatcodes::cheatcode_address;
+    use crypto::{keccak256, Crypto};
+    use ethabi::{self, Token};
+    use ethcore::spec::Spec;
+    use ethcore_transaction::{Action, Transaction};
+    use ethereum_types::{Address, H256, U256};
+    use std::collections::HashMap;
+    use wei;
+
+    #[test]
+    fn test_send_raw_transaction() {
+        let crypto = Crypto::new();
+
+        let account = Account::from_secret(
+            &crypto,
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+        ).expect("bad secret");
+
+        let spec = Spec::new_null();
+
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        let evm = Evm::new(&spec, context).expect("failed to set up evm");
+        evm.add_balance(account.address, wei::from_ether(1))
+            .expect("failed to add balance");
+
+        let nonce = evm.nonce(account.address).expect("failed to get nonce");
+
+        let tx = Transaction {
+            nonce,
+            gas_price: U256::zero(),
+            gas: U256::from(21_000),
+            action: Action::Call(Address::from(0x42)),
+            value: U256::from(1000),
+            data: Vec::new(),
+        };
+
+        let raw = account
+            .sign_transaction(&tx, spec.chain_id())
+            .expect("failed to sign transaction");
+
+        let call = evm.send_raw_transaction(&raw).expect("failed to send transaction");
+
+        assert!(call.is_ok());
+        assert_eq!(account.address, call.sender);
+        assert_eq!(
+            nonce + U256::from(1),
+            evm.nonce(account.address).expect("failed to get nonce")
+        );
+        assert_eq!(
+            U256::from(1000),
+            evm.balance(Address::from(0x42)).expect("failed to get balance")
+        );
+
+        // transactions signed for another chain are rejected.
+        let tx = Transaction {
+            nonce: nonce + U256::from(1),
+            ..tx
+        };
+
+        let raw = account
+            .sign_transaction(&tx, spec.chain_id() + 1)
+            .expect("failed to sign transaction");
+
+        assert!(evm.send_raw_transaction(&raw).is_err());
+    }
+
+    #[test]
+    fn test_auto_gas_with_gas_price() {
+        let spec = Spec::new_null();
+
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        let evm = Evm::new(&spec, context).expect("failed to set up evm");
+
+        // enough to pay for the transfer, but not for the gas limit of the block.
+        let sender = Address::from(0x10);
+        let gas_price = U256::from(1000);
+        let balance = U256::from(100_000) * gas_price;
+
+        evm.add_balance(sender, balance)
+            .expect("failed to add balance");
+
+        let call = call::Call::new(sender).gas_price(gas_price).auto_gas();
+
+        let result = evm
+            .call_raw(Some(Address::from(0x42)), Vec::new(), call)
+            .expect("failed to call");
+
+        assert!(result.is_ok());
+        assert_eq!(U256::from(21_000), result.gas_used);
+        assert_eq!(
+            balance - result.gas_used * gas_price,
+            evm.balance(sender).expect("failed to get balance")
+        );
+    }
+
+    #[test]
+    fn test_data_override() {
+        let spec = Spec::new_null();
+
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        let evm = Evm::new(&spec, context).expect("failed to set up evm");
+
+        let call = call::Call::new(Address::from(0x10))
+            .gas(1_000_000)
+            .data_override(vec![0xfe]);
+
+        // deployments use their own code, instead of the INVALID in the override.
+        let result = evm
+            .call_raw(None, vec![0x00], call.clone())
+            .expect("failed to deploy");
+        assert!(result.is_ok());
+
+        // code returning CALLDATASIZE.
+        let code = vec![0x36, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+        evm.set_code(Address::from(0x42), code)
+            .expect("failed to set code");
+
+        let output = evm
+            .call_raw(Some(Address::from(0x42)), vec![1, 2, 3], call)
+            .expect("failed to call")
+            .ok()
+            .expect("call failed");
+        assert_eq!(U256::from(1), U256::from(&output[..]));
+    }
+
+    #[test]
+    fn test_cheatcodes() {
+        let spec = Spec::new_null();
+
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        let evm = Evm::new(&spec, context).expect("failed to set up evm");
+        let call = call::Call::new(Address::from(0x10)).gas(1_000_000);
+
+        let warp = cheat("warp(uint256)", &[Token::Uint(1000.into())]);
+        let deal = cheat(
+            "deal(address,uint256)",
+            &[
+                Token::Address(Address::from(0x42)),
+                Token::Uint(1000.into()),
+            ],
+        );
+
+        // cheats issued by a failing contract are discarded.
+        let result = evm
+            .call_raw(None, calls_cheatcode(&deal, true), call.clone())
+            .expect("failed to call");
+        assert!(!result.is_ok());
+        assert_eq!(
+            U256::zero(),
+            evm.balance(Address::from(0x42)).expect("failed to get balance")
+        );
+
+        // cheats issued by a contract, which checks that the cheatcode address has code.
+        let result = evm
+            .call_raw(None, calls_cheatcode(&deal, false), call.clone())
+            .expect("failed to call");
+        assert!(result.is_ok());
+        assert_eq!(
+            U256::from(1000),
+            evm.balance(Address::from(0x42)).expect("failed to get balance")
+        );
+
+        // cheats issued by calling the cheatcode address directly.
+        let result = evm
+            .call_raw(Some(cheatcode_address()), warp.clone(), call.clone())
+            .expect("failed to call");
+        assert!(result.is_ok());
+        assert_eq!(1000, evm.env_info.borrow().timestamp);
+
+        // unsupported cheats revert with the reason they aren't supported.
+        let prank = cheat("prank(address)", &[Token::Address(Address::from(0x42))]);
+        let result = evm
+            .call_raw(Some(cheatcode_address()), prank.clone(), call.clone())
+            .expect("failed to call");
+        assert!(result.is_reverted());
+        assert!(reason(&result).contains("vm.prank"));
+
+        let result = evm
+            .call_raw(Some(cheatcode_address()), vec![1, 2, 3, 4], call.clone())
+            .expect("failed to call");
+        assert_eq!("unknown cheatcode", reason(&result));
+
+        let result = evm
+            .call_raw(Some(cheatcode_address()), warp[..4].to_vec(), call.clone())
+            .expect("failed to call");
+        assert_eq!("malformed input to cheatcode warp(uint256)", reason(&result));
+
+        // a rejected cheat reverts the transaction issuing it, together with earlier cheats.
+        let warp = cheat("warp(uint256)", &[Token::Uint(2000.into())]);
+        let contract = Address::from(0x50);
+
+        evm.set_code(contract, issues_cheatcodes(&[&warp[..], &deal[..], &prank[..]]))
+            .expect("failed to set code");
+
+        let result = evm
+            .call_raw(Some(contract), Vec::new(), call)
+            .expect("failed to call");
+        assert!(result.is_reverted());
+        assert!(reason(&result).contains("vm.prank"));
+
+        assert_eq!(H256::zero(), evm.storage_at(contract, H256::zero()).expect("no storage"));
+        assert_eq!(1000, evm.env_info.borrow().timestamp);
+        assert_eq!(
+            U256::from(1000),
+            evm.balance(Address::from(0x42)).expect("failed to get balance")
+        );
+
+        /// Get the reason a call reverted with.
+        fn reason(result: &Call<Vec<u8>>) -> String {
+            match result.outcome {
+                Outcome::Reverted { ref errors } => errors.reason().expect("no reason"),
+                ref outcome => panic!("expected call to revert, but got {:?}", outcome),
+            }
+        }
+
+        /// Encode a call to the given cheatcode.
+        fn cheat(signature: &str, args: &[Token]) -> Vec<u8> {
+            let mut data = keccak256(signature.as_bytes())[..4].to_vec();
+            data.extend(ethabi::encode(args));
+            data
+        }
+
+        /// Build code which calls the cheatcode address with the given data, and then fails if
+        /// `fail` is set.
+        ///
+        /// Like high-level calls in solidity, it fails if the cheatcode address has no code.
+        fn calls_cheatcode(data: &[u8], fail: bool) -> Vec<u8> {
+            let mut code = Vec::new();
+
+            // MSTORE the data in chunks of 32 bytes.
+            for (i, chunk) in data.chunks(32).enumerate() {
+                code.push(0x7f);
+                code.extend(chunk);
+                code.extend(vec![0u8; 32 - chunk.len()]);
+                code.extend(&[0x60, (i * 32) as u8, 0x52]);
+            }
+
+            // JUMPI to the INVALID at the end if EXTCODESIZE is zero.
+            code.push(0x73);
+            code.extend(cheatcode_address().iter());
+            code.extend(&[0x3b, 0x15, 0x60, 0x00, 0x57]);
+            let jump = code.len() - 2;
+
+            // CALL(gas, address, 0, 0, len, 0, 0), and POP the result.
+            code.extend(&[0x60, 0x00, 0x60, 0x00]);
+            code.extend(&[0x60, data.len() as u8, 0x60, 0x00, 0x60, 0x00]);
+            code.push(0x73);
+            code.extend(cheatcode_address().iter());
+            code.extend(&[0x5a, 0xf1, 0x50]);
+
+            code.push(if fail { 0xfe } else { 0x00 });
+            code[jump] = code.len() as u8;
+            code.extend(&[0x5b, 0xfe]);
+            code
+        }
+
+        /// Build code which stores 1 in its first slot and then calls the cheatcode address with
+        /// each of the given inputs.
+        ///
+        /// Like high-level calls in solidity, it reverts with the same data as a failing call.
+        fn issues_cheatcodes(inputs: &[&[u8]]) -> Vec<u8> {
+            // SSTORE(0, 1)
+            let mut code = vec![0x60, 0x01, 0x60, 0x00, 0x55];
+            let mut jumps = Vec::new();
+
+            for data in inputs {
+                for (i, chunk) in data.chunks(32).enumerate() {
+                    code.push(0x7f);
+                    code.extend(chunk);
+                    code.extend(vec![0u8; 32 - chunk.len()]);
+                    code.extend(&[0x60, (i * 32) as u8, 0x52]);
+                }
+
+                // CALL(gas, address, 0, 0, len, 0, 0), and JUMPI to the revert if it failed.
+                code.extend(&[0x60, 0x00, 0x60, 0x00]);
+                code.extend(&[0x60, data.len() as u8, 0x60, 0x00, 0x60, 0x00]);
+                code.push(0x73);
+                code.extend(cheatcode_address().iter());
+                code.extend(&[0x5a, 0xf1, 0x15, 0x61, 0x00, 0x00, 0x57]);
+                jumps.push(code.len() - 3);
+            }
+
+            code.push(0x00);
+
+            for jump in jumps {
+                code[jump] = (code.len() >> 8) as u8;
+                code[jump + 1] = code.len() as u8;
+            }
+
+            // RETURNDATACOPY(0, 0, RETURNDATASIZE), REVERT(0, RETURNDATASIZE)
+            code.extend(&[0x5b, 0x3d, 0x60, 0x00, 0x60, 0x00, 0x3e, 0x3d, 0x60, 0x00, 0xfd]);
+            code
+        }
+    }
+}
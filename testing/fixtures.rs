@@ -0,0 +1,179 @@
+//! Precompiled fixture contracts.
+//!
+//! These are useful when testing how a contract handles sending ether to recipients that behave
+//! in a particular way, like reverting or consuming all provided gas.
+//!
+//! Every fixture is exposed through the same kind of bindings that are generated for regular
+//! contracts, so they can be deployed using `Evm::deploy`.
+//!
+//! ```rust,ignore
+//! use parables_testing::fixtures::{accepter, rejector};
+//!
+//! let accepter = evm.deploy(accepter::constructor(), call.clone())?.ok()?;
+//! let rejector = evm.deploy(rejector::constructor(rejector::Mode::Revert), call)?.ok()?;
+//! ```
+
+use ethabi;
+use failure::Error;
+
+/// Decode the output of a constructor.
+fn constructor_output(output_bytes: ethabi::Bytes) -> Result<ethabi::Address, Error> {
+    let out = ethabi::decode(&vec![ethabi::ParamType::Address], &output_bytes)
+        .map_err(|e| format_err!("failed to decode output: {}", e))?;
+
+    let out = out
+        .into_iter()
+        .next()
+        .ok_or_else(|| format_err!("expected one parameter"))?;
+
+    let out = out
+        .to_address()
+        .ok_or_else(|| format_err!("failed to convert output to address"))?;
+
+    Ok(out)
+}
+
+/// A contract that accepts any call and any amount of ether.
+pub mod accepter {
+    use abi;
+    use ethabi;
+    use failure::Error;
+    use linker::Linker;
+
+    /// Construct a new accepter.
+    pub fn constructor() -> Constructor {
+        Constructor
+    }
+
+    pub struct Constructor;
+
+    impl abi::ContractFunction for Constructor {
+        type Output = ethabi::Address;
+
+        fn encoded(&self, linker: &Linker) -> Result<ethabi::Bytes, Error> {
+            linker.link(<Self as abi::Constructor>::BIN)
+        }
+
+        fn output(&self, output_bytes: ethabi::Bytes) -> Result<ethabi::Address, Error> {
+            super::constructor_output(output_bytes)
+        }
+    }
+
+    impl abi::Constructor for Constructor {
+        const ITEM: &'static str = "Accepter";
+        const PATH: &'static str = "<fixtures>";
+        // copies the runtime code and returns it.
+        const BIN: &'static str = "600180600b6000396000f300";
+        const SOURCE_MAP: Option<&'static str> = None;
+        // STOP
+        const RUNTIME_BIN: Option<&'static str> = Some("00");
+        const RUNTIME_SOURCE_MAP: Option<&'static str> = None;
+    }
+}
+
+/// A contract that rejects any call made to it.
+pub mod rejector {
+    use abi;
+    use ethabi;
+    use failure::Error;
+    use linker::Linker;
+
+    /// How the rejector should reject calls.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Mode {
+        /// Revert the call, refunding any remaining gas.
+        Revert,
+        /// Execute an invalid instruction, consuming all provided gas.
+        ConsumeAllGas,
+    }
+
+    impl Mode {
+        fn to_token(self) -> ethabi::Token {
+            let value = match self {
+                Mode::Revert => 0u64,
+                Mode::ConsumeAllGas => 1u64,
+            };
+
+            ethabi::Token::Uint(value.into())
+        }
+    }
+
+    /// Construct a new rejector with the given mode.
+    pub fn constructor(mode: Mode) -> Constructor {
+        Constructor { mode }
+    }
+
+    pub struct Constructor {
+        mode: Mode,
+    }
+
+    impl abi::ContractFunction for Constructor {
+        type Output = ethabi::Address;
+
+        fn encoded(&self, linker: &Linker) -> Result<ethabi::Bytes, Error> {
+            let constructor = ethabi::Constructor {
+                inputs: vec![ethabi::Param {
+                    name: "mode".to_owned(),
+                    kind: ethabi::ParamType::Uint(8),
+                }],
+            };
+
+            let code = linker.link(<Self as abi::Constructor>::BIN)?;
+
+            constructor
+                .encode_input(code, &[self.mode.to_token()])
+                .map_err(|e| format_err!("failed to encode rejector: {}", e))
+        }
+
+        fn output(&self, output_bytes: ethabi::Bytes) -> Result<ethabi::Address, Error> {
+            super::constructor_output(output_bytes)
+        }
+    }
+
+    impl abi::Constructor for Constructor {
+        const ITEM: &'static str = "Rejector";
+        const PATH: &'static str = "<fixtures>";
+        // stores the trailing constructor argument in slot 0, then copies the runtime code and
+        // returns it.
+        const BIN: &'static str =
+            "602060203803600039600051600055600d80601a6000396000f3600054600b5760006000fd5bfe";
+        const SOURCE_MAP: Option<&'static str> = None;
+        // if slot 0 is zero REVERT, otherwise INVALID.
+        const RUNTIME_BIN: Option<&'static str> = Some("600054600b5760006000fd5bfe");
+        const RUNTIME_SOURCE_MAP: Option<&'static str> = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accepter, rejector};
+    use abi::Constructor;
+    use linker::Linker;
+
+    #[test]
+    fn test_fixtures_decode() {
+        let linker = Linker::new();
+
+        for (bin, runtime_bin) in vec![
+            (
+                accepter::Constructor::BIN,
+                accepter::Constructor::RUNTIME_BIN,
+            ),
+            (
+                rejector::Constructor::BIN,
+                rejector::Constructor::RUNTIME_BIN,
+            ),
+        ] {
+            let code = linker.link(bin).expect("bad link");
+            let runtime = linker
+                .link(runtime_bin.expect("runtime bin"))
+                .expect("bad runtime link");
+
+            assert!(code.ends_with(&runtime), "init code must end with runtime");
+
+            linker
+                .decode_offsets(runtime_bin.expect("runtime bin"))
+                .expect("bad decode");
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Automatic fuzzing of all functions of a deployed contract.
+//!
+//! Inputs for every function are generated from the contract ABI, and each call is performed
+//! against fresh state as produced by a setup function. Calls that panic, error, or revert
+//! unexpectedly are reported together with their minimal failing input, as are calls after which
+//! a registered invariant no longer holds.
+//!
+//! ```rust,ignore
+//! let fuzzer = Fuzzer::new(simple_contract::ABI, simple, call.clone())?
+//!     .allow_revert("setValue")
+//!     .invariant("value is below limit", move |evm| {
+//!         let value = simple_contract::contract(evm, simple, call.clone()).get_value()?.ok()?;
+//!         assert!(value < 1000000.into());
+//!         Ok(())
+//!     });
+//!
+//! runner.test("fuzz simple contract", || fuzzer.run(|| evm.get()));
+//! ```
+
+use abi::{TokenCall, Vm};
+use call;
+use ethabi::{self, ParamType, Token};
+use ethereum_types::{Address, U256};
+use evm::{Evm, Outcome};
+use failure::Error;
+use parables_test_runner::artifacts;
+use proptest::collection;
+use proptest::prelude::{any, BoxedStrategy, Just, Strategy};
+use proptest::test_runner::{Config, TestCaseError, TestError, TestRunner};
+use serde_json;
+use std::collections::HashSet;
+
+/// Build a strategy generating random tokens of the given kind.
+pub fn strategy(kind: &ParamType) -> BoxedStrategy<Token> {
+    match *kind {
+        ParamType::Address => any::<[u8; 20]>()
+            .prop_map(|b| Token::Address(Address::from(b)))
+            .boxed(),
+        ParamType::Bytes => collection::vec(any::<u8>(), 0..64)
+            .prop_map(Token::Bytes)
+            .boxed(),
+        ParamType::FixedBytes(size) => collection::vec(any::<u8>(), size..size + 1)
+            .prop_map(Token::FixedBytes)
+            .boxed(),
+        ParamType::Int(bits) => any::<[u8; 32]>()
+            .prop_map(move |b| {
+                let value = truncate(U256::from(&b[..]), bits);
+
+                // sign extend.
+                if bits < 256 && value.bit(bits - 1) {
+                    return Token::Int(value | !mask(bits));
+                }
+
+                Token::Int(value)
+            })
+            .boxed(),
+        ParamType::Uint(bits) => any::<[u8; 32]>()
+            .prop_map(move |b| Token::Uint(truncate(U256::from(&b[..]), bits)))
+            .boxed(),
+        ParamType::Bool => any::<bool>().prop_map(Token::Bool).boxed(),
+        ParamType::String => any::<String>().prop_map(Token::String).boxed(),
+        ParamType::Array(ref kind) => collection::vec(strategy(kind), 0..8)
+            .prop_map(Token::Array)
+            .boxed(),
+        ParamType::FixedArray(ref kind, size) => collection::vec(strategy(kind), size..size + 1)
+            .prop_map(Token::FixedArray)
+            .boxed(),
+    }
+}
+
+/// Mask covering the given number of bits.
+fn mask(bits: usize) -> U256 {
+    if bits >= 256 {
+        return !U256::zero();
+    }
+
+    (U256::one() << bits) - U256::one()
+}
+
+/// Truncate the value to the given number of bits.
+fn truncate(value: U256, bits: usize) -> U256 {
+    value & mask(bits)
+}
+
+/// A single invariant.
+struct Invariant<'a> {
+    name: String,
+    check: Box<'a + Fn(&Evm) -> Result<(), Error> + Send + Sync>,
+}
+
+/// Fuzzes every function of a deployed contract.
+pub struct Fuzzer<'a> {
+    contract: ethabi::Contract,
+    address: Address,
+    call: call::Call,
+    /// Functions which are permitted to revert.
+    allow_revert: HashSet<String>,
+    invariants: Vec<Invariant<'a>>,
+    /// Number of cases to test for each function.
+    cases: u32,
+}
+
+impl<'a> Fuzzer<'a> {
+    /// Build a new fuzzer for the contract with the given ABI, deployed at the given address.
+    ///
+    /// All calls are performed using the given template call.
+    pub fn new(abi: &str, address: Address, call: call::Call) -> Result<Self, Error> {
+        let contract: ethabi::Contract =
+            serde_json::from_str(abi).map_err(|e| format_err!("failed to parse ABI: {}", e))?;
+
+        Ok(Self {
+            contract,
+            address,
+            call,
+            allow_revert: HashSet::new(),
+            invariants: Vec::new(),
+            cases: Config::default().cases,
+        })
+    }
+
+    /// Permit the function with the given name to revert.
+    pub fn allow_revert(mut self, function: impl Into<String>) -> Self {
+        self.allow_revert.insert(function.into());
+        self
+    }
+
+    /// Set the number of cases to test for each function.
+    pub fn cases(self, cases: u32) -> Self {
+        Self { cases, ..self }
+    }
+
+    /// Register an invariant, which must hold after every call.
+    pub fn invariant<N, F>(mut self, name: N, check: F) -> Self
+    where
+        N: Into<String>,
+        F: 'a + Fn(&Evm) -> Result<(), Error> + Send + Sync,
+    {
+        self.invariants.push(Invariant {
+            name: name.into(),
+            check: Box::new(check),
+        });
+
+        self
+    }
+
+    /// Fuzz all functions, using the given function to set up fresh state for every call.
+    ///
+    /// All functions are fuzzed, even if an earlier one fails. The error lists every failing
+    /// function together with its minimal failing input.
+    pub fn run<S>(&self, setup: S) -> Result<(), Error>
+    where
+        S: Fn() -> Result<Evm, Error>,
+    {
+        let mut failures = Vec::new();
+        let mut inputs = String::new();
+
+        for function in self.contract.functions() {
+            let inputs = function
+                .inputs
+                .iter()
+                .fold(Just(Vec::new()).boxed(), |inputs, p| {
+                    (inputs, strategy(&p.kind))
+                        .prop_map(|(mut inputs, token)| {
+                            inputs.push(token);
+                            inputs
+                        })
+                        .boxed()
+                });
+
+            let mut runner = TestRunner::new(Config {
+                cases: self.cases,
+                ..Config::default()
+            });
+
+            let result = runner.run(&inputs, |tokens| {
+                self.run_one(&setup, function, tokens)
+                    .map_err(|e| TestCaseError::fail(e.to_string()))
+            });
+
+            match result {
+                Ok(()) => {}
+                Err(TestError::Fail(reason, tokens)) => {
+                    inputs.push_str(&format!("{}: {:?}\n", function.name, tokens));
+
+                    failures.push(format!(
+                        "{}: {}\n  minimal failing input: {:?}",
+                        function.name, reason, tokens
+                    ));
+                }
+                Err(TestError::Abort(reason)) => {
+                    failures.push(format!("{}: aborted: {}", function.name, reason));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            artifacts::record("input.txt", inputs);
+            bail!("fuzzing failed:\n{}", failures.join("\n"));
+        }
+
+        Ok(())
+    }
+
+    /// Call a single function with the given input against fresh state.
+    fn run_one<S>(
+        &self,
+        setup: &S,
+        function: &ethabi::Function,
+        tokens: Vec<Token>,
+    ) -> Result<(), Error>
+    where
+        S: Fn() -> Result<Evm, Error>,
+    {
+        let evm = setup()?;
+
+        let result = evm.call(self.address, TokenCall { function, tokens }, self.call.clone())?;
+
+        match result.outcome {
+            Outcome::Ok(_) => {}
+            Outcome::Reverted { .. } if self.allow_revert.contains(&function.name) => {}
+            Outcome::Reverted { errors } => bail!("unexpected revert:\n{}", errors),
+            Outcome::Errored { errors } => bail!("errored:\n{}", errors),
+            Outcome::Status { status } => bail!("call returned status: {}", status),
+        }
+
+        for invariant in &self.invariants {
+            (invariant.check)(&evm)
+                .map_err(|e| format_err!("invariant `{}` violated: {}", invariant.name, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strategy, Fuzzer};
+    use abi::ContractContext;
+    use call;
+    use ethabi::{ParamType, Token};
+    use ethcore::spec::Spec;
+    use ethereum_types::{Address, U256};
+    use evm::Evm;
+    use mock::Mock;
+    use proptest::strategy::{Strategy, ValueTree};
+    use proptest::test_runner::TestRunner;
+    use std::collections::HashMap;
+
+    const ABI: &'static str = r#"[
+        {
+            "type": "function",
+            "name": "flag",
+            "inputs": [{"name": "value", "type": "bool"}],
+            "outputs": [],
+            "constant": false,
+            "payable": false
+        },
+        {
+            "type": "function",
+            "name": "value",
+            "inputs": [{"name": "value", "type": "uint8"}],
+            "outputs": [],
+            "constant": false,
+            "payable": false
+        }
+    ]"#;
+
+    #[test]
+    fn test_strategy() {
+        let mut runner = TestRunner::default();
+
+        for _ in 0..64 {
+            match generate(&mut runner, &ParamType::Int(8)) {
+                Token::Int(value) => assert!(
+                    value < U256::from(0x80) || value >= !U256::from(0x7f),
+                    "not sign extended: {:?}",
+                    value
+                ),
+                token => panic!("unexpected token: {:?}", token),
+            }
+
+            match generate(&mut runner, &ParamType::Uint(8)) {
+                Token::Uint(value) => assert!(value < U256::from(0x100), "too large: {:?}", value),
+                token => panic!("unexpected token: {:?}", token),
+            }
+
+            match generate(&mut runner, &ParamType::FixedBytes(4)) {
+                Token::FixedBytes(bytes) => assert_eq!(4, bytes.len()),
+                token => panic!("unexpected token: {:?}", token),
+            }
+
+            match generate(
+                &mut runner,
+                &ParamType::FixedArray(Box::new(ParamType::Bool), 3),
+            ) {
+                Token::FixedArray(tokens) => {
+                    assert_eq!(3, tokens.len());
+
+                    for token in tokens {
+                        match token {
+                            Token::Bool(_) => {}
+                            token => panic!("unexpected token: {:?}", token),
+                        }
+                    }
+                }
+                token => panic!("unexpected token: {:?}", token),
+            }
+        }
+
+        /// Generate a single token of the given kind.
+        fn generate(runner: &mut TestRunner, kind: &ParamType) -> Token {
+            strategy(kind)
+                .new_tree(runner)
+                .expect("failed to generate token")
+                .current()
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let spec = Spec::new_null();
+
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        let evm = Evm::new(&spec, context).expect("failed to set up evm");
+        let call = call::Call::new(Address::from(0x10)).gas(1_000_000);
+
+        let mock = Mock::new(&evm, ABI).expect("failed to set up mock");
+        mock.expect("flag")
+            .returns_all(Vec::new())
+            .expect("failed to program mock");
+        mock.expect("flag")
+            .with_args(vec![Token::Bool(true)])
+            .reverts()
+            .expect("failed to program mock");
+        mock.expect("value")
+            .returns_all(Vec::new())
+            .expect("failed to program mock");
+
+        // reverts are reported together with the input shrunk to the one that reverts.
+        let error = Fuzzer::new(ABI, mock.address, call.clone())
+            .expect("failed to set up fuzzer")
+            .cases(64)
+            .run(|| Ok(evm.clone()))
+            .expect_err("fuzzing should fail");
+
+        let error = error.to_string();
+        assert!(error.starts_with("fuzzing failed:\nflag: unexpected revert"), "{}", error);
+        assert!(error.ends_with("\n  minimal failing input: [Bool(true)]"), "{}", error);
+        assert!(!error.contains("\nvalue:"), "{}", error);
+
+        Fuzzer::new(ABI, mock.address, call.clone())
+            .expect("failed to set up fuzzer")
+            .cases(64)
+            .allow_revert("flag")
+            .run(|| Ok(evm.clone()))
+            .expect("fuzzing should pass when reverts are allowed");
+
+        // broken invariants are reported for every function, with inputs shrunk to the simplest
+        // ones.
+        let error = Fuzzer::new(ABI, mock.address, call)
+            .expect("failed to set up fuzzer")
+            .cases(64)
+            .allow_revert("flag")
+            .invariant("never holds", |_| Err(format_err!("broken")))
+            .run(|| Ok(evm.clone()))
+            .expect_err("fuzzing should fail");
+
+        let error = error.to_string();
+        assert!(
+            error.contains(
+                "flag: invariant `never holds` violated: broken\n  \
+                 minimal failing input: [Bool(false)]"
+            ),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("value: invariant `never holds` violated: broken"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Profiles of where gas is used, which can be exported for visualization.
+
+use failure::Error;
+use serde_json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Gas used by a transaction, attributed to stacks of functions.
+///
+/// Every frame in a stack is the contract and function being executed in a call, outermost
+/// first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasProfile {
+    stacks: BTreeMap<Vec<String>, u64>,
+}
+
+impl GasProfile {
+    /// Create a new, empty profile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attribute gas to the given stack.
+    pub fn add(&mut self, stack: &[String], gas: u64) {
+        if gas == 0 {
+            return;
+        }
+
+        if let Some(existing) = self.stacks.get_mut(stack) {
+            *existing += gas;
+            return;
+        }
+
+        self.stacks.insert(stack.to_vec(), gas);
+    }
+
+    /// Total amount of gas in the profile.
+    pub fn total(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+
+    /// Iterate over all stacks and the gas attributed to them.
+    pub fn stacks(&self) -> impl Iterator<Item = (&[String], u64)> {
+        self.stacks
+            .iter()
+            .map(|(stack, gas)| (stack.as_slice(), *gas))
+    }
+
+    /// Format the profile as folded stacks, which is the input format of `flamegraph.pl` and
+    /// `inferno-flamegraph`.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+
+        for (stack, gas) in &self.stacks {
+            out.push_str(&stack.join(";"));
+            out.push_str(&format!(" {}\n", gas));
+        }
+
+        out
+    }
+
+    /// Format the profile as a speedscope profile, which can be opened at
+    /// https://www.speedscope.app.
+    pub fn speedscope(&self, name: &str) -> String {
+        let mut frames = Vec::new();
+        let mut indexes = HashMap::new();
+
+        let mut samples = Vec::new();
+        let mut weights = Vec::new();
+
+        for (stack, gas) in &self.stacks {
+            let mut sample = Vec::new();
+
+            for frame in stack {
+                let index = *indexes.entry(frame.as_str()).or_insert_with(|| {
+                    frames.push(json!({ "name": frame }));
+                    frames.len() - 1
+                });
+
+                sample.push(index);
+            }
+
+            samples.push(sample);
+            weights.push(*gas);
+        }
+
+        let profile = json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "name": name,
+            "exporter": "parables",
+            "shared": { "frames": frames },
+            "profiles": [{
+                "type": "sampled",
+                "name": name,
+                "unit": "none",
+                "startValue": 0,
+                "endValue": self.total(),
+                "samples": samples,
+                "weights": weights,
+            }],
+        });
+
+        serde_json::to_string(&profile).expect("profile to serialize")
+    }
+
+    /// Write the profile as folded stacks to the given path.
+    pub fn write_folded(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        fs::write(path, self.folded())
+            .map_err(|e| format_err!("{}: failed to write profile: {}", path.display(), e))
+    }
+
+    /// Write the profile as a speedscope profile to the given path.
+    pub fn write_speedscope(&self, path: impl AsRef<Path>, name: &str) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        fs::write(path, self.speedscope(name))
+            .map_err(|e| format_err!("{}: failed to write profile: {}", path.display(), e))
+    }
+}
+
+/// Gas used by every line of source, accumulated over many transactions.
+///
+/// Lines are numbered from 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineGasReport {
+    files: BTreeMap<PathBuf, BTreeMap<usize, u64>>,
+}
+
+impl LineGasReport {
+    /// Create a new, empty report.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attribute gas to the given line.
+    pub fn add(&mut self, path: &Path, line: usize, gas: u64) {
+        if gas == 0 {
+            return;
+        }
+
+        *self
+            .files
+            .entry(path.to_owned())
+            .or_insert_with(BTreeMap::new)
+            .entry(line)
+            .or_insert(0) += gas;
+    }
+
+    /// Access the gas used by the given line.
+    pub fn get(&self, path: impl AsRef<Path>, line: usize) -> Option<u64> {
+        self.files
+            .get(path.as_ref())
+            .and_then(|lines| lines.get(&line).cloned())
+    }
+
+    /// Total amount of gas in the report.
+    pub fn total(&self) -> u64 {
+        self.files.values().flat_map(|lines| lines.values()).sum()
+    }
+
+    /// Iterate over all lines which have used gas, in order of file and line.
+    pub fn lines(&self) -> impl Iterator<Item = (&Path, usize, u64)> {
+        self.files.iter().flat_map(|(path, lines)| {
+            lines
+                .iter()
+                .map(move |(line, gas)| (path.as_path(), *line, *gas))
+        })
+    }
+
+    /// The lines which used the most gas, with the most expensive first.
+    pub fn top(&self, count: usize) -> Vec<(&Path, usize, u64)> {
+        let mut lines = self.lines().collect::<Vec<_>>();
+        lines.sort_by(|a, b| b.2.cmp(&a.2));
+        lines.truncate(count);
+        lines
+    }
+
+    /// Format every file in the report, with each line prefixed by the gas it used.
+    pub fn annotated(&self) -> Result<String, Error> {
+        let mut out = String::new();
+
+        for (path, lines) in &self.files {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format_err!("{}: failed to read: {}", path.display(), e))?;
+
+            out.push_str(&annotate(path, &content, lines));
+        }
+
+        Ok(out)
+    }
+}
+
+/// Annotate the content of a single file with the gas used by each line.
+fn annotate(path: &Path, content: &str, lines: &BTreeMap<usize, u64>) -> String {
+    let width = lines
+        .values()
+        .map(|gas| gas.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let mut out = format!("{}:\n", path.display());
+
+    for (index, line) in content.lines().enumerate() {
+        match lines.get(&(index + 1)) {
+            Some(gas) => out.push_str(&format!("{:>width$} | {}\n", gas, line, width = width)),
+            None => out.push_str(&format!("{:>width$} | {}\n", "", line, width = width)),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{annotate, GasProfile, LineGasReport};
+    use serde_json;
+    use std::path::Path;
+
+    fn stack(frames: &[&str]) -> Vec<String> {
+        frames.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_folded() {
+        let mut profile = GasProfile::new();
+        profile.add(&stack(&["Token::transfer"]), 100);
+        profile.add(&stack(&["Token::transfer", "Hook::notify"]), 50);
+        profile.add(&stack(&["Token::transfer"]), 20);
+        profile.add(&stack(&["Token::approve"]), 0);
+
+        assert_eq!(170, profile.total());
+        assert_eq!(
+            "Token::transfer 120\nToken::transfer;Hook::notify 50\n",
+            profile.folded()
+        );
+    }
+
+    #[test]
+    fn test_speedscope() {
+        let mut profile = GasProfile::new();
+        profile.add(&stack(&["Token::transfer"]), 100);
+        profile.add(&stack(&["Token::transfer", "Hook::notify"]), 50);
+
+        let out: serde_json::Value = serde_json::from_str(&profile.speedscope("transfer")).unwrap();
+
+        assert_eq!(
+            json!([{"name": "Token::transfer"}, {"name": "Hook::notify"}]),
+            out["shared"]["frames"]
+        );
+        assert_eq!(json!([[0], [0, 1]]), out["profiles"][0]["samples"]);
+        assert_eq!(json!([100, 50]), out["profiles"][0]["weights"]);
+        assert_eq!(json!(150), out["profiles"][0]["endValue"]);
+    }
+
+    #[test]
+    fn test_line_gas_report() {
+        let path = Path::new("contracts/Token.sol");
+
+        let mut report = LineGasReport::new();
+        report.add(path, 3, 20000);
+        report.add(path, 2, 300);
+        report.add(path, 3, 5000);
+
+        assert_eq!(Some(25000), report.get(path, 3));
+        assert_eq!(None, report.get(path, 1));
+        assert_eq!(25300, report.total());
+        assert_eq!(vec![(path, 3, 25000)], report.top(1));
+
+        let lines = report.files.get(path).unwrap();
+
+        assert_eq!(
+            "contracts/Token.sol:\n      | contract Token {\n  300 |   uint a;\n25000 |   a = 1;\n      | }\n",
+            annotate(path, "contract Token {\n  uint a;\n  a = 1;\n}\n", lines)
+        );
+    }
+}
@@ -1,11 +1,17 @@
 //! A ledger is used to keep track of the books for multiple accounts.
 //!
 //! For testing, this permits us to perform a kind of double booking.
+//!
+//! Entries are keyed by `LedgerState::Key`, which is typically an `Address`, but can be anything
+//! identifying an entry in a contract mapping, like `(Address, u64)` for per-tranche balances or
+//! an `H256` order id.
 
 use ethereum_types::{Address, U256};
 use evm;
 use failure::Error;
 use std::collections::{hash_map, HashMap};
+use std::fmt;
+use std::hash::Hash;
 
 #[must_use]
 #[derive(Debug, Clone)]
@@ -14,8 +20,8 @@ where
     S: LedgerState,
 {
     state: S,
-    entries: HashMap<Address, S::Entry>,
-    names: HashMap<Address, String>,
+    entries: HashMap<S::Key, S::Entry>,
+    names: HashMap<S::Key, String>,
 }
 
 impl<'a> Ledger<AccountBalance<'a>> {
@@ -45,39 +51,39 @@ where
         self.entries.values()
     }
 
-    /// Provide a readable name for an address.
-    pub fn name(&mut self, address: Address, name: impl AsRef<str>) {
-        self.names.insert(address, name.as_ref().to_string());
+    /// Provide a readable name for a key.
+    pub fn name(&mut self, key: S::Key, name: impl AsRef<str>) {
+        self.names.insert(key, name.as_ref().to_string());
     }
 
     /// Synchronize the ledger against the current state of the virtual machine.
-    pub fn sync(&mut self, address: Address) -> Result<(), Error> {
-        match self.entries.entry(address) {
+    pub fn sync(&mut self, key: S::Key) -> Result<(), Error> {
+        match self.entries.entry(key.clone()) {
             hash_map::Entry::Vacant(entry) => {
                 let mut state = self.state.new_instance();
-                self.state.sync(address, &mut state)?;
+                self.state.sync(key, &mut state)?;
                 entry.insert(state);
             }
             hash_map::Entry::Occupied(entry) => {
-                self.state.sync(address, entry.into_mut())?;
+                self.state.sync(key, entry.into_mut())?;
             }
         }
 
         Ok(())
     }
 
-    /// Sync multiple addresses.
-    pub fn sync_all(&mut self, addresses: impl IntoIterator<Item = Address>) -> Result<(), Error> {
-        for a in addresses {
-            self.sync(a)?;
+    /// Sync multiple keys.
+    pub fn sync_all(&mut self, keys: impl IntoIterator<Item = S::Key>) -> Result<(), Error> {
+        for key in keys {
+            self.sync(key)?;
         }
 
         Ok(())
     }
 
     /// Get the current entry.
-    pub fn get(&mut self, address: Address) -> Result<&S::Entry, Error> {
-        match self.entries.entry(address) {
+    pub fn get(&mut self, key: S::Key) -> Result<&S::Entry, Error> {
+        match self.entries.entry(key) {
             hash_map::Entry::Vacant(entry) => {
                 let state = self.state.new_instance();
                 Ok(entry.insert(state))
@@ -86,7 +92,7 @@ where
         }
     }
 
-    /// Go through each registered account, and verify their invariants.
+    /// Go through each registered entry, and verify their invariants.
     pub fn verify(self) -> Result<(), Error> {
         use std::fmt::Write;
 
@@ -96,9 +102,9 @@ where
         let state = self.state;
 
         // Check that all verifiable entries are matching expectations.
-        for (address, s) in self.entries {
-            if let Err(e) = state.verify(address, &s) {
-                errors.push((address, e));
+        for (key, s) in self.entries {
+            if let Err(e) = state.verify(key.clone(), &s) {
+                errors.push((key, e));
             }
         }
 
@@ -107,8 +113,8 @@ where
 
             writeln!(msg, "Errors in ledger:")?;
 
-            for (address, e) in errors {
-                writeln!(msg, "{}: {}", Self::do_address_format(&names, address), e)?;
+            for (key, e) in errors {
+                writeln!(msg, "{}: {}", key_format(&names, &key), e)?;
             }
 
             bail!("{}", msg);
@@ -117,8 +123,8 @@ where
         Ok(())
     }
 
-    /// Access the mutable state for the given address.
-    pub fn entry(&mut self, address: Address, f: impl FnOnce(&mut S::Entry)) -> Result<(), Error> {
+    /// Access the mutable state for the given key.
+    pub fn entry(&mut self, key: S::Key, f: impl FnOnce(&mut S::Entry)) -> Result<(), Error> {
         let Ledger {
             ref mut entries,
             ref state,
@@ -126,7 +132,7 @@ where
             ..
         } = *self;
 
-        let entry = match entries.entry(address) {
+        let entry = match entries.entry(key.clone()) {
             hash_map::Entry::Vacant(entry) => {
                 let mut state = state.new_instance();
                 entry.insert(state)
@@ -137,23 +143,15 @@ where
         f(entry);
 
         // verify after it has been updated.
-        if let Err(e) = state.verify(address, entry) {
-            bail!("{}: {}", Self::do_address_format(names, address), e);
+        if let Err(e) = state.verify(key.clone(), entry) {
+            bail!("{}: {}", key_format(names, &key), e);
         }
 
         Ok(())
     }
 
-    fn address_format(&self, address: Address) -> String {
-        Self::do_address_format(&self.names, address)
-    }
-
-    /// Convert an address into a human-readable name.
-    fn do_address_format(names: &HashMap<Address, String>, address: Address) -> String {
-        names
-            .get(&address)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| address.to_string())
+    fn key_format(&self, key: &S::Key) -> String {
+        key_format(&self.names, key)
     }
 }
 
@@ -161,13 +159,16 @@ impl<S> Ledger<S>
 where
     S: LedgerState<Entry = U256>,
 {
-    /// Add to the balance for the given address.
-    pub fn add<V>(&mut self, address: Address, value: V) -> Result<(), Error>
+    /// Add to the balance for the given key.
+    pub fn add<V>(&mut self, key: S::Key, value: V) -> Result<(), Error>
     where
         V: Into<U256>,
     {
         let update = {
-            let current = self.entries.entry(address).or_insert_with(U256::default);
+            let current = self
+                .entries
+                .entry(key.clone())
+                .or_insert_with(U256::default);
             let value = value.into();
 
             if let Some(update) = current.checked_add(value) {
@@ -176,26 +177,115 @@ where
             } else {
                 bail!(
                     "{}: adding {} to the account would overflow the balance",
-                    address, value
+                    key_format(&self.names, &key),
+                    value
                 );
             }
         };
 
         // verify after it has been updated.
-        if let Err(e) = self.state.verify(address, &update) {
-            bail!("{}: {}", self.address_format(address), e);
+        if let Err(e) = self.state.verify(key.clone(), &update) {
+            bail!("{}: {}", self.key_format(&key), e);
         }
 
         Ok(())
     }
 
-    /// Subtract from the balance for the given address.
-    pub fn sub<V>(&mut self, address: Address, value: V) -> Result<(), Error>
+    /// Transfer the given amount from one key to another.
+    ///
+    /// The ledger is only modified if both the subtraction and the addition succeed, after which
+    /// both entries are verified.
+    pub fn transfer<V>(&mut self, from: S::Key, to: S::Key, value: V) -> Result<(), Error>
+    where
+        V: Into<U256>,
+    {
+        let value = value.into();
+
+        if from != to {
+            let from_update = {
+                let current = self.entries.get(&from).cloned().unwrap_or_default();
+
+                match current.checked_sub(value) {
+                    Some(update) => update,
+                    None => bail!(
+                        "{}: transferring {} would set account to negative balance",
+                        self.key_format(&from),
+                        value
+                    ),
+                }
+            };
+
+            let to_update = {
+                let current = self.entries.get(&to).cloned().unwrap_or_default();
+
+                match current.checked_add(value) {
+                    Some(update) => update,
+                    None => bail!(
+                        "{}: transferring {} to the account would overflow the balance",
+                        self.key_format(&to),
+                        value
+                    ),
+                }
+            };
+
+            self.entries.insert(from.clone(), from_update);
+            self.entries.insert(to.clone(), to_update);
+        }
+
+        for key in &[from, to] {
+            let entry = self.entries.get(key).cloned().unwrap_or_default();
+
+            if let Err(e) = self.state.verify(key.clone(), &entry) {
+                bail!("{}: {}", self.key_format(key), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify that the sum of all tracked entries equals the expected total.
+    ///
+    /// This is a conservation check, like the total supply of a token contract being equal to the
+    /// sum of all balances. The expected total is typically read from the virtual machine:
+    ///
+    /// ```rust,ignore
+    /// ledger.invariant_sum(token.total_supply()?.ok()?)?;
+    /// ```
+    pub fn invariant_sum<V>(&self, expected: V) -> Result<(), Error>
+    where
+        V: Into<U256>,
+    {
+        let expected = expected.into();
+        let mut total = U256::zero();
+
+        for entry in self.entries.values() {
+            total = match total.checked_add(*entry) {
+                Some(total) => total,
+                None => bail!("sum of all entries overflows"),
+            };
+        }
+
+        if total != expected {
+            bail!(
+                "expected sum of all entries to be {}, but was {}",
+                expected,
+                total
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Subtract from the balance for the given key.
+    pub fn sub<V>(&mut self, key: S::Key, value: V) -> Result<(), Error>
     where
         V: Into<U256>,
     {
         let update = {
-            let current = self.entries.entry(address).or_insert_with(U256::default);
+            let current = self
+                .entries
+                .entry(key.clone())
+                .or_insert_with(U256::default);
             let value = value.into();
 
             if let Some(update) = current.checked_sub(value) {
@@ -204,32 +294,176 @@ where
             } else {
                 bail!(
                     "{}: subtracting {} would set account to negative balance",
-                    address, value
+                    key_format(&self.names, &key),
+                    value
                 );
             }
         };
 
         // verify after it has been updated.
-        if let Err(e) = self.state.verify(address, &update) {
-            bail!("{}: {}", self.address_format(address), e);
+        if let Err(e) = self.state.verify(key.clone(), &update) {
+            bail!("{}: {}", self.key_format(&key), e);
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> Ledger<S>
+where
+    S: LedgerState<Key = Address, Entry = U256>,
+{
+    /// Apply the transfer of value performed by the given call.
+    ///
+    /// This subtracts the gas and value of the call from the sender, and adds the value to the
+    /// receiver if the call was successful.
+    pub fn apply<T>(&mut self, call: &evm::Call<T>) -> Result<(), Error> {
+        self.sub(call.sender, call.total())?;
+
+        if call.is_ok() && !call.value.is_zero() {
+            self.add(call.to, call.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert a key into a human-readable name.
+fn key_format<K>(names: &HashMap<K, String>, key: &K) -> String
+where
+    K: Eq + Hash + fmt::Debug,
+{
+    names
+        .get(key)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("{:?}", key))
+}
+
+/// A collection of ledgers, one for each token contract.
+///
+/// This permits keeping the books of multiple assets in one structure, with a single verification
+/// step.
+#[must_use]
+pub struct MultiLedger<'a, S>
+where
+    S: LedgerState,
+{
+    /// Construct the state for the ledger of a token.
+    factory: Box<'a + Fn(Address) -> S>,
+    ledgers: HashMap<Address, Ledger<S>>,
+    names: HashMap<Address, String>,
+}
+
+impl<'a, S> MultiLedger<'a, S>
+where
+    S: LedgerState,
+{
+    /// Construct a new multi-ledger, using the given function to construct the state of the ledger
+    /// for each token.
+    pub fn new(factory: impl 'a + Fn(Address) -> S) -> Self {
+        MultiLedger {
+            factory: Box::new(factory),
+            ledgers: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Provide a readable name for a token.
+    ///
+    /// Holders are named through the ledger of each token, see `MultiLedger::ledger`.
+    pub fn name(&mut self, token: Address, name: impl AsRef<str>) {
+        self.names.insert(token, name.as_ref().to_string());
+    }
+
+    /// Access the ledger for the given token.
+    pub fn ledger(&mut self, token: Address) -> &mut Ledger<S> {
+        let MultiLedger {
+            ref factory,
+            ref mut ledgers,
+            ..
+        } = *self;
+
+        ledgers
+            .entry(token)
+            .or_insert_with(|| Ledger::new(factory(token)))
+    }
+
+    /// Synchronize the entry for the given token and holder against the virtual machine.
+    pub fn sync(&mut self, token: Address, key: S::Key) -> Result<(), Error> {
+        self.ledger(token).sync(key)
+    }
+
+    /// Synchronize multiple holders for the given token.
+    pub fn sync_all(
+        &mut self,
+        token: Address,
+        keys: impl IntoIterator<Item = S::Key>,
+    ) -> Result<(), Error> {
+        self.ledger(token).sync_all(keys)
+    }
+
+    /// Go through every ledger, and verify their invariants.
+    pub fn verify(self) -> Result<(), Error> {
+        use std::fmt::Write;
+
+        let names = self.names;
+        let mut errors = Vec::new();
+
+        for (token, ledger) in self.ledgers {
+            if let Err(e) = ledger.verify() {
+                errors.push((token, e));
+            }
+        }
+
+        if !errors.is_empty() {
+            let mut msg = String::new();
+
+            for (token, e) in errors {
+                writeln!(msg, "Token {}:\n{}", key_format(&names, &token), e)?;
+            }
+
+            bail!("{}", msg);
         }
 
         Ok(())
     }
 }
 
+impl<'a, S> MultiLedger<'a, S>
+where
+    S: LedgerState<Entry = U256>,
+{
+    /// Add to the balance of the given token for the given holder.
+    pub fn add<V>(&mut self, token: Address, key: S::Key, value: V) -> Result<(), Error>
+    where
+        V: Into<U256>,
+    {
+        self.ledger(token).add(key, value)
+    }
+
+    /// Subtract from the balance of the given token for the given holder.
+    pub fn sub<V>(&mut self, token: Address, key: S::Key, value: V) -> Result<(), Error>
+    where
+        V: Into<U256>,
+    {
+        self.ledger(token).sub(key, value)
+    }
+}
+
 /// A state that can be verified with a virtual machine.
 pub trait LedgerState {
+    /// The key identifying each entry, typically an `Address`.
+    type Key: Clone + Eq + Hash + fmt::Debug;
     type Entry;
 
     /// Construct a new instance.
     fn new_instance(&self) -> Self::Entry;
 
     /// Verify the given state.
-    fn verify(&self, address: Address, instance: &Self::Entry) -> Result<(), Error>;
+    fn verify(&self, key: Self::Key, instance: &Self::Entry) -> Result<(), Error>;
 
     /// Synchronize the given state.
-    fn sync(&self, address: Address, instance: &mut Self::Entry) -> Result<(), Error>;
+    fn sync(&self, key: Self::Key, instance: &mut Self::Entry) -> Result<(), Error>;
 }
 
 /// A ledger state checking account balances against the EVM.
@@ -237,6 +471,7 @@ pub trait LedgerState {
 pub struct AccountBalance<'a>(&'a evm::Evm);
 
 impl<'a> LedgerState for AccountBalance<'a> {
+    type Key = Address;
     type Entry = U256;
 
     fn new_instance(&self) -> U256 {
@@ -265,10 +500,47 @@ impl<'a> LedgerState for AccountBalance<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Ledger, LedgerState};
+    use super::{Ledger, LedgerState, MultiLedger};
     use ethereum_types::{Address, U256};
     use failure::Error;
 
+    #[test]
+    fn multi_ledger() {
+        let a = Address::random();
+        let token_a = Address::random();
+        let token_b = Address::random();
+
+        let mut ledger = MultiLedger::new(|token| {
+            if token == token_a {
+                Simple(0.into(), 10.into())
+            } else {
+                Simple(0.into(), 20.into())
+            }
+        });
+
+        ledger.sync(token_a, a).expect("bad sync");
+        ledger.sync(token_b, a).expect("bad sync");
+
+        ledger.add(token_a, a, 10).expect("bad invariant");
+        ledger.add(token_b, a, 20).expect("bad invariant");
+
+        ledger.verify().expect("ledger not balanced");
+    }
+
+    #[test]
+    fn transfer() {
+        let a = Address::random();
+        let b = Address::random();
+
+        let mut ledger = Ledger::new(Simple(10.into(), 10.into()));
+        ledger.sync_all(vec![a, b]).expect("bad sync");
+
+        assert!(ledger.transfer(a, b, 11).is_err());
+        // nothing should be modified by a failed transfer.
+        assert_eq!(U256::from(10), *ledger.get(a).expect("entry"));
+        assert_eq!(U256::from(10), *ledger.get(b).expect("entry"));
+    }
+
     #[test]
     fn simple_u256_ledger() {
         let mut ledger = Ledger::new(Simple(0.into(), 42.into()));
@@ -280,38 +552,91 @@ mod tests {
         ledger.add(a, 42).expect("bad invariant");
 
         ledger.verify().expect("ledger not balanced");
+    }
 
-        pub struct Simple(U256, U256);
+    #[test]
+    fn invariant_sum() {
+        let a = Address::random();
+        let b = Address::random();
 
-        impl LedgerState for Simple {
-            type Entry = U256;
+        let mut ledger = Ledger::new(Simple(10.into(), 10.into()));
+        ledger.sync_all(vec![a, b]).expect("bad sync");
 
-            fn new_instance(&self) -> U256 {
-                U256::default()
-            }
+        ledger.invariant_sum(20).expect("bad sum");
+        assert!(ledger.invariant_sum(21).is_err());
+    }
 
-            fn verify(
-                &self,
-                _address: Address,
-                expected_balance: &Self::Entry,
-            ) -> Result<(), Error> {
-                let actual_balance = self.1;
-
-                if *expected_balance != actual_balance {
-                    bail!(
-                        "expected account wei balance {}, but was {}",
-                        expected_balance,
-                        actual_balance
-                    );
-                }
+    #[test]
+    fn tranche_ledger() {
+        let a = Address::random();
+
+        let mut ledger = Ledger::new(Tranches(42.into()));
+        ledger.sync_all(vec![(a, 0), (a, 1)]).expect("bad sync");
+
+        ledger.add((a, 1), 42).expect("bad invariant");
+        assert!(ledger.add((a, 0), 1).is_err());
+
+        ledger.verify().expect("ledger not balanced");
+    }
+
+    /// A ledger state where only the tranche with index 1 has a balance.
+    pub struct Tranches(U256);
+
+    impl LedgerState for Tranches {
+        type Key = (Address, u64);
+        type Entry = U256;
 
-                Ok(())
+        fn new_instance(&self) -> U256 {
+            U256::default()
+        }
+
+        fn verify(&self, key: Self::Key, expected_balance: &Self::Entry) -> Result<(), Error> {
+            let actual_balance = if key.1 == 1 { self.0 } else { U256::zero() };
+
+            if *expected_balance != actual_balance {
+                bail!(
+                    "expected tranche balance {}, but was {}",
+                    expected_balance,
+                    actual_balance
+                );
             }
 
-            fn sync(&self, _address: Address, balance: &mut Self::Entry) -> Result<(), Error> {
-                *balance = self.0;
-                Ok(())
+            Ok(())
+        }
+
+        fn sync(&self, _key: Self::Key, balance: &mut Self::Entry) -> Result<(), Error> {
+            *balance = U256::zero();
+            Ok(())
+        }
+    }
+
+    pub struct Simple(U256, U256);
+
+    impl LedgerState for Simple {
+        type Key = Address;
+        type Entry = U256;
+
+        fn new_instance(&self) -> U256 {
+            U256::default()
+        }
+
+        fn verify(&self, _address: Address, expected_balance: &Self::Entry) -> Result<(), Error> {
+            let actual_balance = self.1;
+
+            if *expected_balance != actual_balance {
+                bail!(
+                    "expected account wei balance {}, but was {}",
+                    expected_balance,
+                    actual_balance
+                );
             }
+
+            Ok(())
+        }
+
+        fn sync(&self, _address: Address, balance: &mut Self::Entry) -> Result<(), Error> {
+            *balance = self.0;
+            Ok(())
         }
     }
 }
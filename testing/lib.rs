@@ -3,6 +3,7 @@ extern crate log;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 #[macro_use]
 #[allow(unused_imports)]
@@ -12,20 +13,24 @@ extern crate parables_derive;
 extern crate parables_test_runner;
 pub extern crate ethabi;
 pub extern crate ethcore;
-extern crate ethcore_transaction;
+pub extern crate ethcore_transaction;
 pub extern crate ethereum_types;
 extern crate evm as parity_evm;
 pub extern crate parity_bytes;
 extern crate vm as parity_vm;
 #[macro_use]
 extern crate failure;
-#[cfg(feature = "account")]
+#[cfg(any(feature = "account", feature = "rpc"))]
 extern crate crypto as rust_crypto;
 extern crate journaldb;
 extern crate kvdb;
 extern crate kvdb_memorydb;
+#[cfg(feature = "persist")]
+extern crate kvdb_rocksdb;
 #[cfg(feature = "account")]
 extern crate rand;
+#[cfg(any(feature = "account", feature = "rpc"))]
+extern crate rlp;
 #[cfg(feature = "account")]
 extern crate secp256k1;
 
@@ -37,16 +42,32 @@ pub use parables_test_runner::*;
 pub mod abi;
 #[cfg(feature = "account")]
 pub mod account;
+pub mod assertions;
 mod ast;
 pub mod call;
+mod cheatcodes;
+mod console;
+pub mod coverage;
 mod crypto;
 pub mod evm;
+pub mod fixtures;
+#[cfg(feature = "test-runner")]
+pub mod fuzz;
+pub mod gas;
 pub mod ledger;
 pub mod linker;
+#[macro_use]
 mod macros;
 mod matcher;
+pub mod mock;
+pub mod multichain;
 pub mod prelude;
+pub mod proxy;
+#[cfg(feature = "rpc")]
+pub mod rpc;
 pub mod source_map;
-mod trace;
+pub mod storage;
+pub mod trace;
+pub mod u256;
 mod utils;
 pub mod wei;
@@ -2,20 +2,21 @@ use ast;
 use ethereum_types::Address;
 use failure::{Error, ResultExt};
 use parity_evm;
-use source_map::SourceMap;
-use std::collections::HashMap;
+use serde_json;
+use source_map::{Mapping, SourceMap};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use storage;
+use utils;
 
 #[derive(Debug, Fail)]
 pub enum LinkerError {
     #[fail(display = "bad hex in section: #{}", pos)]
     HexError { pos: usize },
-    #[fail(display = "no linker item: {}", item)]
-    LinkerItemError { item: String },
-    #[fail(display = "no linker path: {}", path)]
-    LinkerPathError { path: String },
+    #[fail(display = "missing libraries to link: {}", missing)]
+    MissingLibraries { missing: String },
     #[fail(display = "failed to decode source map")]
     SourceMapDecodeError,
 }
@@ -35,6 +36,25 @@ pub struct Source {
     pub source_map: SourceMap,
     /// The decoded offsets for the given source, from program counter to instruction offset.
     pub offsets: HashMap<usize, usize>,
+    /// Index of the mapping for every program counter, built once when the source is constructed
+    /// since it's looked up for every executed instruction.
+    index: Vec<Option<u32>>,
+    /// The function of every mapping, if the AST of the source was available.
+    functions: Vec<Option<Arc<ast::Function>>>,
+}
+
+impl Source {
+    /// Find the mapping for the given program counter.
+    pub fn mapping(&self, pc: usize) -> Option<&Mapping> {
+        let index = (*self.index.get(pc)?)?;
+        self.source_map.find_mapping(index as usize)
+    }
+
+    /// Find the function containing the given program counter.
+    pub fn function(&self, pc: usize) -> Option<&Arc<ast::Function>> {
+        let index = (*self.index.get(pc)?)?;
+        self.functions.get(index as usize)?.as_ref()
+    }
 }
 
 impl fmt::Debug for Source {
@@ -52,6 +72,41 @@ pub struct AddressInfo {
     pub source: Option<Arc<Source>>,
     /// AST associated with an address.
     pub ast: Option<Arc<ast::Registry>>,
+    /// Storage layout associated with an address.
+    pub storage_layout: Option<Arc<storage::StorageLayout>>,
+}
+
+/// A location in the sources, as resolved from a program counter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The object whose code contains the program counter.
+    pub object: Object,
+    /// The file of the location, if known.
+    pub path: Option<PathBuf>,
+    /// Byte offset of the location in the file.
+    pub start: u32,
+    /// Length of the location in bytes.
+    pub length: u32,
+    /// Line of the location, starting at 1, if the file could be read.
+    pub line: Option<usize>,
+    /// The function containing the location, if known.
+    pub function: Option<String>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (self.path.as_ref(), self.line) {
+            (Some(path), Some(line)) => write!(fmt, "{}:{}", path.display(), line)?,
+            (Some(path), None) => write!(fmt, "{}:{}", path.display(), self.start)?,
+            _ => write!(fmt, "{}:{}", self.object.path, self.start)?,
+        }
+
+        if let Some(ref function) = self.function {
+            write!(fmt, ":{}", function)?;
+        }
+
+        Ok(())
+    }
 }
 
 /// hex lookup table
@@ -86,8 +141,14 @@ pub struct Linker {
     runtime_sources: HashMap<Object, Arc<Source>>,
     /// Known ASTs by file path.
     ast_by_path: HashMap<String, Arc<ast::Registry>>,
+    /// Known storage layouts by item.
+    storage_layouts: HashMap<Object, Arc<storage::StorageLayout>>,
     /// Known sources.
     source_list: Option<Arc<Vec<PathBuf>>>,
+    /// Minimal proxies (clones) and the implementation they delegate to.
+    clones: HashMap<Address, Address>,
+    /// Contents of source files, shared between clones of the linker.
+    source_cache: Arc<utils::SourceCache>,
 }
 
 impl Linker {
@@ -100,10 +161,18 @@ impl Linker {
             sources: HashMap::new(),
             runtime_sources: HashMap::new(),
             ast_by_path: HashMap::new(),
+            storage_layouts: HashMap::new(),
             source_list: None,
+            clones: HashMap::new(),
+            source_cache: Arc::new(utils::SourceCache::default()),
         }
     }
 
+    /// Access the cache of source files, used when reporting source locations.
+    pub(crate) fn source_cache(&self) -> &utils::SourceCache {
+        &self.source_cache
+    }
+
     /// Register the address for an object.
     pub fn register_object(&mut self, object: Object, address: Address) {
         self.address_to_object.insert(address, object.clone());
@@ -111,6 +180,16 @@ impl Linker {
         self.item_to_address.insert(object.item.clone(), address);
     }
 
+    /// Register that the given address is a minimal proxy delegating to `implementation`.
+    pub fn register_clone(&mut self, address: Address, implementation: Address) {
+        self.clones.insert(address, implementation);
+    }
+
+    /// Find the implementation that a clone delegates to, if the address is a clone.
+    pub fn find_clone_implementation(&self, address: Address) -> Option<Address> {
+        self.clones.get(&address).cloned()
+    }
+
     /// Find all corresponding info for the given address.
     pub fn find_runtime_info(&self, address: Address) -> AddressInfo {
         let source = self
@@ -120,7 +199,54 @@ impl Linker {
             .map(Arc::clone);
 
         let ast = self.find_ast(address);
-        AddressInfo { source, ast }
+        let storage_layout = self.find_storage_layout(address);
+
+        AddressInfo {
+            source,
+            ast,
+            storage_layout,
+        }
+    }
+
+    /// Resolve the program counter of code running at the given address to a source location.
+    ///
+    /// This requires the runtime source of the contract at the address to be registered, which
+    /// happens when it's deployed.
+    pub fn resolve_pc(&self, address: Address, pc: usize) -> Option<SourceLocation> {
+        let info = self.find_runtime_info(address);
+        let source = info.source?;
+        let mapping = source.mapping(pc)?;
+
+        let path = mapping
+            .file_index
+            .and_then(|index| self.find_file(index))
+            .map(|path| path.to_owned());
+
+        let line = path
+            .as_ref()
+            .and_then(|path| {
+                let span = (
+                    mapping.start as usize,
+                    (mapping.start + mapping.length) as usize,
+                );
+
+                self.source_cache.find_line(path, span).ok()
+            })
+            .map(|(_, line)| line + 1);
+
+        let function = source
+            .function(pc)
+            .or_else(|| info.ast.as_ref().and_then(|ast| ast.find_function(mapping)))
+            .map(|function| function.name.to_string());
+
+        Some(SourceLocation {
+            object: source.object.clone(),
+            path,
+            start: mapping.start,
+            length: mapping.length,
+            line,
+            function,
+        })
     }
 
     /// Find all AST registries.
@@ -130,6 +256,8 @@ impl Linker {
 
     /// Find a single AST.
     pub fn find_ast(&self, address: Address) -> Option<Arc<ast::Registry>> {
+        let address = self.find_clone_implementation(address).unwrap_or(address);
+
         self.address_to_path
             .get(&address)
             .and_then(|path| self.ast_by_path.get(path))
@@ -141,6 +269,29 @@ impl Linker {
         self.ast_by_path.get(&object.path).map(Arc::clone)
     }
 
+    /// Find the storage layout of the contract at the given address.
+    pub fn find_storage_layout(&self, address: Address) -> Option<Arc<storage::StorageLayout>> {
+        let address = self.find_clone_implementation(address).unwrap_or(address);
+
+        self.address_to_object
+            .get(&address)
+            .and_then(|object| self.storage_layouts.get(object))
+            .map(Arc::clone)
+    }
+
+    /// Find storage layout by corresponding object.
+    pub fn find_storage_layout_by_object(
+        &self,
+        object: &Object,
+    ) -> Option<Arc<storage::StorageLayout>> {
+        self.storage_layouts.get(object).map(Arc::clone)
+    }
+
+    /// Register the storage layout of an object.
+    pub fn register_storage_layout(&mut self, object: Object, layout: storage::StorageLayout) {
+        self.storage_layouts.insert(object, Arc::new(layout));
+    }
+
     pub fn register_source_list(&mut self, source_list: Vec<PathBuf>) {
         self.source_list = Some(Arc::new(source_list));
     }
@@ -181,6 +332,23 @@ impl Linker {
 
         let offsets = self.decode_offsets(bin)?;
 
+        let mut index = vec![None; offsets.keys().max().map(|pc| pc + 1).unwrap_or(0)];
+
+        for (pc, offset) in &offsets {
+            if *offset < source_map.mappings().len() {
+                index[*pc] = Some(*offset as u32);
+            }
+        }
+
+        let functions = match self.ast_by_path.get(path) {
+            Some(ast) => source_map
+                .mappings()
+                .iter()
+                .map(|mapping| ast.find_function(mapping).map(Arc::clone))
+                .collect(),
+            None => Vec::new(),
+        };
+
         Ok(Source {
             object: Object {
                 path: path.to_string(),
@@ -188,6 +356,8 @@ impl Linker {
             },
             source_map,
             offsets,
+            index,
+            functions,
         })
     }
 
@@ -230,7 +400,7 @@ impl Linker {
                     n += 1;
                     offset += 1;
                 }
-                Section::SwarmHash(..) => {
+                Section::Metadata(..) => {
                     // ignore
                     continue;
                 }
@@ -251,8 +421,39 @@ impl Linker {
     ///
     /// All other entries should be left preserved.
     pub fn link(&self, code: &str) -> Result<Vec<u8>, Error> {
+        self.link_with(code, &HashMap::new())
+    }
+
+    /// Decode and link the given bytecode, using the given library addresses.
+    ///
+    /// Libraries are identified by name, or by path and name like `lib/SafeMath.sol:SafeMath`.
+    /// The given addresses take precedence over the libraries registered with the linker.
+    pub fn link_with(
+        &self,
+        code: &str,
+        libraries: &HashMap<String, Address>,
+    ) -> Result<Vec<u8>, Error> {
+        self.link_inner(code, libraries, None)
+    }
+
+    /// Decode and link the bytecode of a contract declared in the given file.
+    ///
+    /// This is the same as `link`, except that missing libraries are reported as referenced by
+    /// the given file.
+    pub fn link_contract(&self, path: &str, code: &str) -> Result<Vec<u8>, Error> {
+        self.link_inner(code, &HashMap::new(), Some(path))
+    }
+
+    fn link_inner(
+        &self,
+        code: &str,
+        libraries: &HashMap<String, Address>,
+        referenced_by: Option<&str>,
+    ) -> Result<Vec<u8>, Error> {
         let mut it = Decoder::new(code);
         let mut output = Vec::new();
+        // unresolved placeholders, and the number of times they are referenced.
+        let mut missing = BTreeMap::new();
 
         while let Some(section) = it.next() {
             let section = section?;
@@ -270,7 +471,7 @@ impl Linker {
                     output.push(b);
                     continue;
                 }
-                Section::SwarmHash(bytes, _) => {
+                Section::Metadata(bytes, _) => {
                     output.extend(bytes);
                     continue;
                 }
@@ -287,19 +488,44 @@ impl Linker {
             let (path, item) = decode_linked(unlinked)?;
 
             let address = match item {
-                Some(item) => self.item_to_address.get(item).ok_or_else(|| {
-                    LinkerError::LinkerItemError {
-                        item: item.to_string(),
-                    }
-                })?,
+                Some(item) => libraries
+                    .get(&format!("{}:{}", path, item))
+                    .or_else(|| libraries.get(item))
+                    .or_else(|| self.item_to_address.get(item)),
+                // the name of the library didn't fit in the placeholder.
+                None => libraries.get(path),
+            };
+
+            match address {
+                Some(address) => output.extend(address.iter()),
                 None => {
-                    return Err(LinkerError::LinkerPathError {
-                        path: path.to_string(),
-                    }.into())
+                    let mut key = match item {
+                        Some(item) => format!("`{}` declared in `{}`", item, path),
+                        None => format!("`{}`", path),
+                    };
+
+                    if let Some(referenced_by) = referenced_by {
+                        key.push_str(&format!(", referenced by `{}`", referenced_by));
+                    }
+
+                    *missing.entry(key).or_insert(0usize) += 1;
+                    // keep going to find all missing libraries.
+                    output.extend(Address::zero().iter());
                 }
-            };
+            }
+        }
 
-            output.extend(address.iter());
+        if !missing.is_empty() {
+            let missing = missing
+                .into_iter()
+                .map(|(key, count)| match count {
+                    1 => format!("{} (1 reference)", key),
+                    n => format!("{} ({} references)", key, n),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            return Err(LinkerError::MissingLibraries { missing }.into());
         }
 
         return Ok(output);
@@ -346,14 +572,167 @@ pub enum Section<'a> {
     Instruction(u8, parity_evm::Instruction),
     /// A push instruction.
     Push(u8, Push<'a>),
-    /// Swarm hash as seen at end of contract.
-    SwarmHash(Vec<u8>, Vec<u8>),
+    /// CBOR encoded metadata as seen at end of contract, including its length.
+    Metadata(Vec<u8>, Metadata),
+}
+
+/// A value in the metadata of a contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataValue {
+    Bytes(Vec<u8>),
+    Text(String),
+    Bool(bool),
+    Uint(u64),
+}
+
+/// The metadata appended to the code of a contract by the compiler.
+///
+/// Older compilers only include a swarm hash (`bzzr0`), while newer ones include an `ipfs` or
+/// `bzzr1` hash and the version of the compiler (`solc`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub fields: Vec<(String, MetadataValue)>,
+}
+
+impl Metadata {
+    /// Find the metadata at the end of the given code, in hex.
+    ///
+    /// The metadata is a CBOR map, followed by its length as a big-endian 16-bit number. Returns
+    /// the length of the metadata section in hex characters, and the decoded metadata.
+    fn find(code: &str) -> Option<(usize, Metadata)> {
+        if code.len() < 4 || !code.is_char_boundary(code.len() - 4) {
+            return None;
+        }
+
+        let length = HexDecode(&code[code.len() - 4..])
+            .collect::<Result<Vec<u8>, BadHex>>()
+            .ok()?;
+
+        let length = ((length[0] as usize) << 8) | length[1] as usize;
+        let hex_length = (length + 2) * 2;
+
+        if length == 0 || code.len() < hex_length || !code.is_char_boundary(code.len() - hex_length)
+        {
+            return None;
+        }
+
+        let start = code.len() - hex_length;
+
+        let bytes = HexDecode(&code[start..code.len() - 4])
+            .collect::<Result<Vec<u8>, BadHex>>()
+            .ok()?;
+
+        let mut cbor = Cbor(&bytes);
+        let metadata = cbor.map()?;
+
+        if !cbor.0.is_empty() {
+            return None;
+        }
+
+        Some((hex_length, metadata))
+    }
+
+    /// Access the field with the given key.
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        self.fields.iter().find(|f| f.0 == key).map(|f| &f.1)
+    }
+
+    /// Access the swarm hash of the metadata, if present.
+    pub fn swarm_hash(&self) -> Option<&[u8]> {
+        match self.get("bzzr0").or_else(|| self.get("bzzr1")) {
+            Some(&MetadataValue::Bytes(ref bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Access the ipfs hash of the metadata, if present.
+    pub fn ipfs_hash(&self) -> Option<&[u8]> {
+        match self.get("ipfs") {
+            Some(&MetadataValue::Bytes(ref bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+/// A decoder for the subset of CBOR used in metadata.
+struct Cbor<'a>(&'a [u8]);
+
+impl<'a> Cbor<'a> {
+    /// Decode a map with text keys.
+    fn map(&mut self) -> Option<Metadata> {
+        let (major, count) = self.head()?;
+
+        if major != 5 {
+            return None;
+        }
+
+        let mut fields = Vec::new();
+
+        for _ in 0..count {
+            let key = match self.value()? {
+                MetadataValue::Text(key) => key,
+                _ => return None,
+            };
+
+            fields.push((key, self.value()?));
+        }
+
+        Some(Metadata { fields })
+    }
+
+    /// Decode a single value.
+    fn value(&mut self) -> Option<MetadataValue> {
+        let (major, argument) = self.head()?;
+
+        match major {
+            0 => Some(MetadataValue::Uint(argument)),
+            2 => Some(MetadataValue::Bytes(self.take(argument)?.to_vec())),
+            3 => {
+                let text = ::std::str::from_utf8(self.take(argument)?).ok()?;
+                Some(MetadataValue::Text(text.to_string()))
+            }
+            7 if argument == 20 => Some(MetadataValue::Bool(false)),
+            7 if argument == 21 => Some(MetadataValue::Bool(true)),
+            _ => None,
+        }
+    }
+
+    /// Decode the major type and argument of an item.
+    fn head(&mut self) -> Option<(u8, u64)> {
+        let b = *self.0.first()?;
+        self.0 = &self.0[1..];
+
+        let (major, info) = (b >> 5, b & 0x1f);
+
+        let argument = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => self.take(2)?.iter().fold(0, |a, b| (a << 8) | *b as u64),
+            26 => self.take(4)?.iter().fold(0, |a, b| (a << 8) | *b as u64),
+            27 => self.take(8)?.iter().fold(0, |a, b| (a << 8) | *b as u64),
+            _ => return None,
+        };
+
+        Some((major, argument))
+    }
+
+    fn take(&mut self, n: u64) -> Option<&'a [u8]> {
+        if (self.0.len() as u64) < n {
+            return None;
+        }
+
+        let (out, rest) = self.0.split_at(n as usize);
+        self.0 = rest;
+        Some(out)
+    }
 }
 
 #[derive(Debug)]
 pub struct Decoder<'a> {
     pos: usize,
     input: HexDecode<'a>,
+    /// The length of the metadata at the end of the input in hex characters, and its contents.
+    metadata: Option<(usize, Metadata)>,
 }
 
 impl<'a> Decoder<'a> {
@@ -361,6 +740,7 @@ impl<'a> Decoder<'a> {
         Decoder {
             pos: 0usize,
             input: HexDecode(input),
+            metadata: Metadata::find(input),
         }
     }
 }
@@ -369,13 +749,19 @@ impl<'a> Iterator for Decoder<'a> {
     type Item = Result<Section<'a>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let swarm_hash = match self.input.take_swarm_hash() {
-            Ok(swarm_hash) => swarm_hash,
-            Err(e) => return Some(Err(format_err!("{}: #{}", e, self.pos))),
+        let at_metadata = match self.metadata {
+            Some((length, _)) => self.input.0.len() == length,
+            None => false,
         };
 
-        if let Some((bytes, hash)) = swarm_hash {
-            return Some(Ok(Section::SwarmHash(bytes, hash)));
+        if at_metadata {
+            let bytes = match self.input.by_ref().collect::<Result<Vec<u8>, BadHex>>() {
+                Ok(bytes) => bytes,
+                Err(_) => return Some(Err(LinkerError::HexError { pos: self.pos }.into())),
+            };
+
+            let metadata = self.metadata.take().map(|m| m.1).unwrap_or_default();
+            return Some(Ok(Section::Metadata(bytes, metadata)));
         }
 
         let c = match self.input.next() {
@@ -432,6 +818,43 @@ impl<'a> Iterator for Decoder<'a> {
     }
 }
 
+/// A region of code reserved for an immutable variable.
+#[derive(Debug, Deserialize)]
+struct ImmutableReference {
+    start: usize,
+    length: usize,
+}
+
+/// Patch the regions reserved for immutable variables in the given runtime code in hex, with the
+/// values they have in the deployed code.
+///
+/// `references` are the `immutableReferences` emitted by the compiler, as JSON.
+pub fn patch_immutables(code: &str, references: &str, deployed: &[u8]) -> Result<String, Error> {
+    let references: HashMap<String, Vec<ImmutableReference>> = serde_json::from_str(references)
+        .map_err(|e| format_err!("bad immutable references: {}", e))?;
+
+    let mut code = code.to_string();
+
+    for (id, references) in references {
+        for r in references {
+            let end = r.start + r.length;
+
+            if end * 2 > code.len() || end > deployed.len() {
+                bail!("immutable reference `{}` out of bounds: {}..{}", id, r.start, end);
+            }
+
+            let value: String = deployed[r.start..end]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+
+            code.replace_range(r.start * 2..end * 2, &value);
+        }
+    }
+
+    Ok(code)
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct BadHex;
 
@@ -479,45 +902,6 @@ impl<'a> HexDecode<'a> {
         self.0 = rest;
         Some(out)
     }
-
-    /// Try to take swarm hash, if present.
-    fn take_swarm_hash(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
-        if self.0.len() != 86 {
-            return Ok(None);
-        }
-
-        if !self.0.starts_with("a165627a7a72305820") {
-            return Ok(None);
-        }
-
-        if !self.0.ends_with("0029") {
-            return Ok(None);
-        }
-
-        let mut bytes: Vec<u8> = Vec::new();
-        bytes.extend(b"\xa1\x65\x62\x7a\x7a\x72\x30\x58\x20");
-
-        let hash = &self.0[18..];
-        let hash = &hash[..64];
-
-        let mut decoder = HexDecode(hash);
-        let mut hash = Vec::new();
-
-        while let Some(b) = decoder.next() {
-            let b = match b {
-                Ok(b) => b,
-                Err(_) => bail!("bad hex in swarm hash"),
-            };
-
-            hash.push(b);
-        }
-
-        bytes.extend(hash.iter().cloned());
-        bytes.extend(b"\x00\x29");
-
-        self.0 = "";
-        Ok(Some((bytes, hash)))
-    }
 }
 
 impl<'a> Iterator for HexDecode<'a> {
@@ -532,7 +916,8 @@ impl<'a> Iterator for HexDecode<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::{HexDecode, Linker, Object};
+    use super::{patch_immutables, HexDecode, Linker, Metadata, MetadataValue, Object};
+    use std::collections::HashMap;
 
     extern crate hex;
 
@@ -573,6 +958,73 @@ mod tests {
         assert_eq!(linked, out);
     }
 
+    #[test]
+    fn test_link_with() {
+        let linker = Linker::new();
+        let mut libraries = HashMap::new();
+        libraries.insert("SimpleLib".to_string(), 0x342a.into());
+
+        let out = linker
+            .link_with(include_str!("tests/a.bin").trim(), &libraries)
+            .expect("bad link decode");
+
+        let linked = linker
+            .link(include_str!("tests/linked_a.bin").trim())
+            .expect("bad link decode");
+
+        assert_eq!(linked, out);
+    }
+
+    #[test]
+    fn test_missing_libraries() {
+        let linker = Linker::new();
+
+        let error = linker
+            .link(include_str!("tests/a.bin").trim())
+            .expect_err("libraries to be missing");
+
+        assert!(
+            error
+                .to_string()
+                .starts_with("missing libraries to link: `SimpleLib` declared in `SimpleLib.sol`")
+        );
+
+        let error = linker
+            .link_contract("SimpleContract.sol", include_str!("tests/a.bin").trim())
+            .expect_err("libraries to be missing");
+
+        assert!(error.to_string().starts_with(
+            "missing libraries to link: `SimpleLib` declared in `SimpleLib.sol`, referenced by \
+             `SimpleContract.sol`"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_pc() {
+        let mut linker = Linker::new();
+
+        let object = Object {
+            path: "Simple.sol".to_string(),
+            item: "Simple".to_string(),
+        };
+
+        let source = linker
+            .source("Simple.sol", "Simple", "608060405200", "0:10:0;2:3:0;4:1:0;6:2:0")
+            .expect("bad source");
+
+        linker.register_runtime_source(object.clone(), source);
+        linker.register_object(object.clone(), 0x42.into());
+
+        let location = linker.resolve_pc(0x42.into(), 4).expect("location");
+        assert_eq!(object, location.object);
+        assert_eq!((4, 1), (location.start, location.length));
+        assert_eq!(None, location.line);
+
+        // not the start of an instruction.
+        assert!(linker.resolve_pc(0x42.into(), 1).is_none());
+        assert!(linker.resolve_pc(0x43.into(), 0).is_none());
+    }
+
     #[test]
     fn test_decode_runtime_simple() {
         let linker = Linker::new();
@@ -591,6 +1043,53 @@ mod tests {
             .expect("bad decode");
     }
 
+    #[test]
+    fn test_metadata() {
+        let hash = "11".repeat(32);
+
+        // legacy swarm hash.
+        let legacy = format!("a165627a7a72305820{}0029", hash);
+        let (length, metadata) = Metadata::find(&legacy).expect("legacy metadata");
+        assert_eq!(legacy.len(), length);
+        assert_eq!(Some(&[0x11u8; 32][..]), metadata.swarm_hash());
+
+        // ipfs hash and compiler version, as emitted by solc 0.6 and later.
+        let modern = format!(
+            "6080604052a2646970667358221220{}64736f6c634300060c0033",
+            hash
+        );
+        let (length, metadata) = Metadata::find(&modern).expect("modern metadata");
+        assert_eq!(modern.len() - 10, length);
+        assert_eq!(34, metadata.ipfs_hash().map(|h| h.len()).unwrap_or_default());
+        assert_eq!(
+            Some(&MetadataValue::Bytes(vec![0, 6, 12])),
+            metadata.get("solc")
+        );
+
+        let linker = Linker::new();
+        let linked = linker.link(&modern).expect("bad link");
+        assert_eq!(hex::decode(&modern).expect("bad hex decode"), linked);
+
+        let offsets = linker.decode_offsets(&modern).expect("bad decode");
+        // two pushes and an mstore, but nothing for the metadata.
+        assert_eq!(4, offsets.len());
+
+        assert!(Metadata::find("6080604052").is_none());
+    }
+
+    #[test]
+    fn test_patch_immutables() {
+        let code = format!("7f{}60005260206000f3", "00".repeat(32));
+        let mut deployed = hex::decode(&code).expect("bad hex decode");
+        deployed[32] = 0x2a;
+
+        let patched = patch_immutables(&code, r#"{"7": [{"start": 1, "length": 32}]}"#, &deployed)
+            .expect("bad patch");
+
+        assert_eq!(hex::decode(&patched).expect("bad hex decode"), deployed);
+        assert!(patch_immutables(&code, r#"{"7": [{"start": 30, "length": 32}]}"#, &deployed).is_err());
+    }
+
     #[test]
     fn test_hex_decode() {
         let decoded = HexDecode("00112233445566778899").collect::<Vec<_>>();
@@ -1,6 +1,16 @@
 /// Include the generated contracts directory.
+///
+/// Multiple roots can be provided as a list, like `contracts!{["contracts", "lib/solmate/src"],
+/// {...}}`, where contracts in additional roots are named relative to their root.
 #[macro_export]
 macro_rules! contracts {
+    ([$($path:expr),+], {$($module:ident => $entry:expr,)*}) => {
+        #[derive(ParablesContracts)]
+        #[parables($(path = $path),+)]
+        #[parables_contract($($module = $entry,)*)]
+        struct _ParablesContracts;
+    };
+
     ($path:expr, {$($module:ident => $entry:expr,)*}) => {
         #[derive(ParablesContracts)]
         #[parables(path = $path)]
@@ -13,6 +23,150 @@ macro_rules! contracts {
     };
 }
 
+/// Include contracts from precompiled Truffle or Hardhat artifacts, instead of compiling them.
+///
+/// Contracts are named by the source they were compiled from, like
+/// `contracts_from_artifacts!{"artifacts", {token => "contracts/Token.sol:Token",}}` for Hardhat.
+#[macro_export]
+macro_rules! contracts_from_artifacts {
+    ($path:expr, {$($module:ident => $entry:expr,)*}) => {
+        #[derive(ParablesContracts)]
+        #[parables(path = $path, artifacts)]
+        #[parables_contract($($module = $entry,)*)]
+        struct _ParablesContracts;
+    };
+
+    ($($module:ident => $entry:expr,)*) => {
+        contracts_from_artifacts!{"artifacts", {$($module => $entry,)*}}
+    };
+}
+
+/// Print output from a test, which is only shown if the test fails.
+///
+/// Output printed outside of a test is written to stdout, like with `println!`.
+#[macro_export]
+macro_rules! test_println {
+    () => {
+        $crate::capture::write(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::capture::write(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Declare tests as ordinary functions marked with `#[parables::test]`, which are collected into a
+/// `register_tests` function that registers all of them with a test runner or module.
+///
+/// Tests either take no arguments, or take the value of the fixture declared at the top of the
+/// macro by reference. Functions without the attribute are left as-is, and can be used as helpers.
+///
+/// ```rust,ignore
+/// parables_tests! {
+///     fixture: Snapshot<Evm>;
+///
+///     #[parables::test]
+///     fn get_value(evm: &Evm) -> Result<()> {
+///         let contract = simple_contract::contract(evm, simple(evm)?, call());
+///         assert_eq!(U256::from(0), contract.get_value()?.output);
+///         Ok(())
+///     }
+///
+///     #[parables::test]
+///     fn wei_conversion() {
+///         assert_eq!(wei!(1000 gwei), wei!(1 szabo));
+///     }
+/// }
+///
+/// register_tests(&mut runner.module("simple"), &evm);
+/// ```
+///
+/// The `#[parables::test]` attribute has to come before any other attribute of a test, like its
+/// documentation.
+#[macro_export]
+macro_rules! parables_tests {
+    (@items [$($fixture:tt)*] [$($tests:tt)*]
+        #[parables::test] $(#[$attr:meta])*
+        fn $name:ident($($args:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        $(#[$attr])*
+        fn $name($($args)*) -> $ret $body
+
+        parables_tests!(@items [$($fixture)*] [$($tests)* ($name $($args)*)] $($rest)*);
+    };
+    (@items [$($fixture:tt)*] [$($tests:tt)*]
+        #[parables::test] $(#[$attr:meta])*
+        fn $name:ident($($args:tt)*) $body:block
+        $($rest:tt)*
+    ) => {
+        $(#[$attr])*
+        fn $name($($args)*) $body
+
+        parables_tests!(@items [$($fixture)*] [$($tests)* ($name $($args)*)] $($rest)*);
+    };
+    // items which are not tests are passed through as-is.
+    (@items [$($fixture:tt)*] [$($tests:tt)*] $item:item $($rest:tt)*) => {
+        $item
+
+        parables_tests!(@items [$($fixture)*] [$($tests)*] $($rest)*);
+    };
+    (@items [] [$($tests:tt)*]) => {
+        /// Register all tests declared with `parables_tests!`.
+        pub fn register_tests<'a, S: $crate::test_runner::Suite<'a>>(suite: &mut S) {
+            $(parables_tests!(@register suite, (), $tests);)*
+        }
+    };
+    (@items [$($fixture:tt)+] [$($tests:tt)*]) => {
+        /// Register all tests declared with `parables_tests!`, passing them the value of the
+        /// fixture.
+        pub fn register_tests<'a, S: $crate::test_runner::Suite<'a>>(
+            suite: &mut S,
+            fixture: &'a $($fixture)+,
+        ) {
+            $(parables_tests!(@register suite, fixture, $tests);)*
+        }
+    };
+    (@register $suite:ident, $fixture:tt, ($name:ident)) => {
+        $suite.test(stringify!($name), $name);
+    };
+    (@register $suite:ident, (), ($name:ident $($args:tt)+)) => {
+        compile_error!(concat!(
+            "test `",
+            stringify!($name),
+            "` takes an argument, but no fixture was declared with `fixture: <type>;`"
+        ));
+    };
+    (@register $suite:ident, $fixture:ident, ($name:ident $($args:tt)+)) => {
+        $suite.test_with(stringify!($name), $fixture, |value| $name(value));
+    };
+    (fixture: $fixture:ty; $($rest:tt)*) => {
+        parables_tests!(@items [$fixture] [] $($rest)*);
+    };
+    ($($rest:tt)*) => {
+        parables_tests!(@items [] [] $($rest)*);
+    };
+}
+
+/// Expand to the given items, since the test runner is enabled.
+///
+/// Used by generated contract code, which can't check the features of this crate itself.
+#[doc(hidden)]
+#[cfg(feature = "test-runner")]
+#[macro_export]
+macro_rules! __parables_with_test_runner {
+    ($($item:item)*) => {
+        $($item)*
+    };
+}
+
+/// Expand to nothing, since the test runner is disabled.
+#[doc(hidden)]
+#[cfg(not(feature = "test-runner"))]
+#[macro_export]
+macro_rules! __parables_with_test_runner {
+    ($($item:item)*) => {};
+}
+
 /// Helper macro for proptest! to build a closure suitable for passing in to `TestRunner::run`.
 #[macro_export]
 macro_rules! pt {
@@ -21,30 +175,247 @@ macro_rules! pt {
 }
 
 /// Convert the given argument into wei.
+///
+/// The amount can be any expression, optionally followed by a unit, like `wei!(0.05 eth)` or
+/// `wei!(balance * 2 gwei)`. Without a unit, the amount is in wei.
 #[macro_export]
 macro_rules! wei {
-    ($value:tt) => {
-        $crate::ethereum_types::U256::from($value)
+    (@unit [$($value:tt)*]) => {
+        $crate::ethereum_types::U256::from($($value)*)
     };
-    ($value:tt mwei) => {
-        $crate::wei::from_mewi($value)
+    (@unit [$($value:tt)*] wei) => {
+        $crate::ethereum_types::U256::from($($value)*)
     };
-    ($value:tt kwei) => {
-        $crate::wei::from_kewi($value)
+    (@unit [$($value:tt)*] kwei) => {
+        $crate::wei::from_kwei($($value)*)
     };
-    ($value:tt gwei) => {
-        $crate::wei::from_gwei($value)
+    (@unit [$($value:tt)*] mwei) => {
+        $crate::wei::from_mwei($($value)*)
     };
-    ($value:tt szabo) => {
-        $crate::wei::from_szabo($value)
+    (@unit [$($value:tt)*] gwei) => {
+        $crate::wei::from_gwei($($value)*)
     };
-    ($value:tt finney) => {
-        $crate::wei::from_finney($value)
+    (@unit [$($value:tt)*] szabo) => {
+        $crate::wei::from_szabo($($value)*)
     };
-    ($value:tt ether) => {
-        $crate::wei::from_ether($value)
+    (@unit [$($value:tt)*] finney) => {
+        $crate::wei::from_finney($($value)*)
     };
-    ($value:tt eth) => {
-        $crate::wei::from_ether($value)
+    (@unit [$($value:tt)*] ether) => {
+        $crate::wei::from_ether($($value)*)
     };
+    (@unit [$($value:tt)*] eth) => {
+        $crate::wei::from_ether($($value)*)
+    };
+    // munch tokens into the amount until only the unit remains.
+    (@unit [$($value:tt)*] $head:tt $($tail:tt)*) => {
+        wei!(@unit [$($value)* $head] $($tail)*)
+    };
+    ($($tokens:tt)+) => {
+        wei!(@unit [] $($tokens)+)
+    };
+}
+
+/// Assert that two `U256` values are equal.
+///
+/// On failure both values are printed in hex and in ether, with the digits which differ marked.
+/// Both arguments can be anything that converts into a `U256`.
+#[macro_export]
+macro_rules! assert_eq_u256 {
+    ($left:expr, $right:expr) => {{
+        let left = $crate::ethereum_types::U256::from($left);
+        let right = $crate::ethereum_types::U256::from($right);
+
+        if left != right {
+            panic!(
+                "assertion failed: `(left == right)`\n{}",
+                $crate::assertions::u256_diff(("left", "right"), left, right)
+            );
+        }
+    }};
+    ($left:expr, $right:expr, $($arg:tt)+) => {{
+        let left = $crate::ethereum_types::U256::from($left);
+        let right = $crate::ethereum_types::U256::from($right);
+
+        if left != right {
+            panic!(
+                "assertion failed: `(left == right)`: {}\n{}",
+                format_args!($($arg)+),
+                $crate::assertions::u256_diff(("left", "right"), left, right)
+            );
+        }
+    }};
+}
+
+/// Assert that the balance of an account in the given virtual machine is equal to the expected
+/// amount.
+///
+/// The expected amount can be anything that converts into a `U256`, like `wei!(1 ether)`.
+#[macro_export]
+macro_rules! assert_balance {
+    ($evm:expr, $address:expr, $expected:expr) => {{
+        let address = $address;
+        let expected = $crate::ethereum_types::U256::from($expected);
+
+        let balance = match $evm.balance(address) {
+            Ok(balance) => balance,
+            Err(e) => panic!("failed to query balance of {:?}: {}", address, e),
+        };
+
+        if balance != expected {
+            panic!(
+                "assertion failed: balance of {:?} is not as expected\n{}",
+                address,
+                $crate::assertions::u256_diff(("actual", "expected"), balance, expected)
+            );
+        }
+    }};
+}
+
+/// Assert that two addresses are equal.
+///
+/// On failure both addresses are printed in full, with the digits which differ marked.
+#[macro_export]
+macro_rules! assert_addr_eq {
+    ($left:expr, $right:expr) => {{
+        let left: $crate::ethereum_types::Address = $left;
+        let right: $crate::ethereum_types::Address = $right;
+
+        if left != right {
+            panic!(
+                "assertion failed: `(left == right)`\n{}",
+                $crate::assertions::address_diff(("left", "right"), left, right)
+            );
+        }
+    }};
+    ($left:expr, $right:expr, $($arg:tt)+) => {{
+        let left: $crate::ethereum_types::Address = $left;
+        let right: $crate::ethereum_types::Address = $right;
+
+        if left != right {
+            panic!(
+                "assertion failed: `(left == right)`: {}\n{}",
+                format_args!($($arg)+),
+                $crate::assertions::address_diff(("left", "right"), left, right)
+            );
+        }
+    }};
+}
+
+/// Assert that a call reverted, optionally with data matching the given matcher, like
+/// `RevertMatch::reason("not allowed")`.
+///
+/// On failure, the message includes the errors of the call, or that it succeeded.
+#[macro_export]
+macro_rules! assert_reverted {
+    ($call:expr) => {
+        if let Err(e) = $call.assert_reverted() {
+            panic!("assertion failed: {}", e);
+        }
+    };
+    ($call:expr, $matcher:expr) => {
+        if let Err(e) = $call.assert_reverted_with($matcher) {
+            panic!("assertion failed: {}", e);
+        }
+    };
+}
+
+/// Assert that the given event was emitted, and drain the matching logs.
+///
+/// An optional filter narrows down which logs match, like
+/// `assert_emitted!(evm, ev::value_updated(), |e| e.filter(Some(100.into())))`.
+/// On failure, the message lists all logs which were emitted instead.
+#[macro_export]
+macro_rules! assert_emitted {
+    ($evm:expr, $event:expr) => {
+        match $evm.logs($event).assert_emitted() {
+            Ok(logs) => logs,
+            Err(e) => panic!("assertion failed: {}", e),
+        }
+    };
+    ($evm:expr, $event:expr, $filter:expr) => {
+        match $evm.logs($event).filter($filter).assert_emitted() {
+            Ok(logs) => logs,
+            Err(e) => panic!("assertion failed: {}", e),
+        }
+    };
+}
+
+#[cfg(all(test, feature = "test-runner"))]
+mod tests {
+    use reporter::CollectingReporter;
+    use snapshot::Snapshot;
+    use std::iter;
+    use test_runner::TestRunner;
+
+    mod plain {
+        use failure::Error;
+
+        parables_tests! {
+            #[parables::test]
+            fn passes() {
+                assert_eq!(42, answer());
+            }
+
+            #[parables::test]
+            /// Documented tests are registered as well.
+            fn fails() -> Result<(), Error> {
+                Err(format_err!("broken"))
+            }
+
+            fn answer() -> u32 {
+                42
+            }
+        }
+    }
+
+    mod with_fixture {
+        use snapshot::Snapshot;
+
+        parables_tests! {
+            fixture: Snapshot<u32>;
+
+            #[parables::test]
+            fn increment(value: &mut u32) {
+                *value += 1;
+                assert_eq!(43, *value);
+            }
+
+            #[parables::test]
+            fn no_argument() {}
+        }
+    }
+
+    #[test]
+    fn test_parables_tests() {
+        let fixture = Snapshot::new(42u32);
+
+        let mut runner = TestRunner::new();
+        plain::register_tests(&mut runner.module("plain"));
+        with_fixture::register_tests(&mut runner.module("with_fixture"), &fixture);
+
+        let reporter = CollectingReporter::new();
+        runner
+            .run_with_filters(iter::empty(), &reporter)
+            .expect("tests to run");
+
+        let mut results = reporter
+            .take_results()
+            .expect("bad results")
+            .into_iter()
+            .map(|result| (result.name().to_string(), result.outcome().is_ok()))
+            .collect::<Vec<_>>();
+
+        results.sort();
+
+        assert_eq!(
+            vec![
+                (String::from("fails"), false),
+                (String::from("increment"), true),
+                (String::from("no_argument"), true),
+                (String::from("passes"), true),
+            ],
+            results
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use linker;
 use std::fmt;
+use trace;
 
 /// Matches a specific location in a solidity file.
 pub trait LocationMatcher: fmt::Display + Copy {
@@ -13,6 +14,12 @@ pub trait StatementMatcher: fmt::Display + Copy {
     fn matches_lines<'a>(&self, lines: impl IntoIterator<Item = &'a String>) -> bool;
 }
 
+/// Matches the data a call reverted with.
+pub trait RevertMatcher: fmt::Display + Copy {
+    /// Test if the given revert data matches.
+    fn matches_revert(&self, output: &[u8]) -> bool;
+}
+
 /// A default matcher.
 #[derive(Debug, Clone, Copy)]
 pub struct Matcher {
@@ -103,6 +110,61 @@ impl LocationMatcher for Matcher {
     }
 }
 
+/// Matches what a call reverted with.
+#[derive(Debug, Clone, Copy)]
+pub enum RevertMatch {
+    /// The reason given to `revert` or `require`.
+    Reason(&'static str),
+    /// The selector of a custom error.
+    Selector([u8; 4]),
+}
+
+impl fmt::Display for RevertMatch {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RevertMatch::Reason(reason) => write!(fmt, "revert with reason {:?}", reason),
+            RevertMatch::Selector(selector) => {
+                write!(fmt, "revert with error 0x")?;
+
+                for b in &selector {
+                    write!(fmt, "{:02x}", b)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl RevertMatch {
+    /// Match calls which reverted with the given reason, as in `revert("reason")`.
+    pub fn reason(reason: &'static str) -> Self {
+        RevertMatch::Reason(reason)
+    }
+
+    /// Match calls which reverted with a custom error with the given selector.
+    pub fn error_selector(selector: [u8; 4]) -> Self {
+        RevertMatch::Selector(selector)
+    }
+}
+
+impl RevertMatcher for RevertMatch {
+    fn matches_revert(&self, output: &[u8]) -> bool {
+        match *self {
+            RevertMatch::Reason(expected) => {
+                trace::revert_reason(output).as_ref().map(|s| s.as_str()) == Some(expected)
+            }
+            RevertMatch::Selector(expected) => output.len() >= 4 && output[..4] == expected[..],
+        }
+    }
+}
+
+impl RevertMatcher for &'static str {
+    fn matches_revert(&self, output: &[u8]) -> bool {
+        RevertMatch::reason(*self).matches_revert(output)
+    }
+}
+
 impl LocationMatcher for &'static str {
     fn matches_location(&self, object: Option<&linker::Object>, function: Option<&str>) -> bool {
         let mut it = self.split(":");
@@ -149,7 +211,8 @@ impl StatementMatcher for &'static str {
 
 #[cfg(test)]
 mod tests {
-    use super::LocationMatcher;
+    use super::{LocationMatcher, RevertMatch, RevertMatcher};
+    use ethabi::{self, Token};
     use linker;
 
     #[test]
@@ -181,4 +244,30 @@ mod tests {
         assert!("foo".matches_location(None, Some("foo"),));
         assert!(!"foo".matches_location(None, None));
     }
+
+    #[test]
+    fn test_revert() {
+        let mut reason = vec![0x08, 0xc3, 0x79, 0xa0];
+        reason.extend(ethabi::encode(&[Token::String("insufficient balance".to_string())]));
+
+        assert!(RevertMatch::reason("insufficient balance").matches_revert(&reason));
+        assert!("insufficient balance".matches_revert(&reason));
+        assert!(!RevertMatch::reason("not allowed").matches_revert(&reason));
+        assert!(!RevertMatch::reason("insufficient balance").matches_revert(&[]));
+
+        let error = [0x12, 0x34, 0x56, 0x78, 0x00];
+
+        assert!(RevertMatch::error_selector([0x12, 0x34, 0x56, 0x78]).matches_revert(&error));
+        assert!(!RevertMatch::error_selector([0x12, 0x34, 0x56, 0x79]).matches_revert(&error));
+        assert!(!RevertMatch::error_selector([0x08, 0xc3, 0x79, 0xa0]).matches_revert(&error[..3]));
+
+        assert_eq!(
+            "revert with reason \"insufficient balance\"",
+            RevertMatch::reason("insufficient balance").to_string()
+        );
+        assert_eq!(
+            "revert with error 0x12345678",
+            RevertMatch::error_selector([0x12, 0x34, 0x56, 0x78]).to_string()
+        );
+    }
 }
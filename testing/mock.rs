@@ -0,0 +1,433 @@
+//! Mock contracts whose behavior is programmed from the test.
+//!
+//! A mock is an account with generated code that dispatches on the calldata it receives, and
+//! returns or reverts with the programmed data. Every time an expectation is added, the code of
+//! the mock is regenerated and replaced.
+//!
+//! ```rust,ignore
+//! let oracle = oracle::mock(&evm)?;
+//! oracle.expect("price").returns(Token::Uint(100.into()))?;
+//! oracle.expect("update").reverts_with("not allowed")?;
+//! ```
+//!
+//! Calls which don't match any expectation revert with `mock: unexpected call`.
+
+use crypto::keccak256;
+use ethabi::{self, Function, Token};
+use ethereum_types::Address;
+use evm::Evm;
+use failure::Error;
+use serde_json;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Used to give every mock a unique address.
+static MOCK_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Reason to revert with if no expectation matches a call.
+const UNEXPECTED_CALL: &'static str = "mock: unexpected call";
+
+/// A mock implementing the functions of an ABI.
+pub struct Mock<'a> {
+    evm: &'a Evm,
+    /// The address of the mock.
+    pub address: Address,
+    abi: ethabi::Contract,
+    expectations: RefCell<Vec<Expectation>>,
+}
+
+impl<'a> Mock<'a> {
+    /// Set up a mock implementing the given ABI, as JSON.
+    ///
+    /// Until any expectations are added, all calls to the mock revert.
+    pub fn new(evm: &'a Evm, abi: &str) -> Result<Mock<'a>, Error> {
+        let abi: ethabi::Contract =
+            serde_json::from_str(abi).map_err(|e| format_err!("failed to parse ABI: {}", e))?;
+
+        let id = MOCK_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let hash = keccak256(format!("parables mock {}", id).as_bytes());
+
+        let mock = Mock {
+            evm,
+            address: Address::from(&hash[12..]),
+            abi,
+            expectations: RefCell::new(Vec::new()),
+        };
+
+        mock.install()?;
+        Ok(mock)
+    }
+
+    /// Program the behavior of the function with the given name.
+    ///
+    /// Later expectations take precedence over earlier ones, and expectations with arguments take
+    /// precedence over ones without.
+    pub fn expect<'m>(&'m self, name: &str) -> ExpectationBuilder<'m, 'a> {
+        ExpectationBuilder {
+            mock: self,
+            name: name.to_string(),
+            args: None,
+        }
+    }
+
+    /// Remove all expectations, causing all calls to the mock to revert.
+    pub fn clear(&self) -> Result<(), Error> {
+        self.expectations
+            .try_borrow_mut()
+            .map_err(|e| format_err!("cannot borrow expectations: {}", e))?
+            .clear();
+
+        self.install()
+    }
+
+    /// Find the function with the given name.
+    fn function(&self, name: &str) -> Result<&Function, Error> {
+        self.abi
+            .function(name)
+            .map_err(|_| format_err!("no function named `{}` in the ABI of the mock", name))
+    }
+
+    /// Add the given expectation and regenerate the code of the mock.
+    fn add(&self, expectation: Expectation) -> Result<(), Error> {
+        self.expectations
+            .try_borrow_mut()
+            .map_err(|e| format_err!("cannot borrow expectations: {}", e))?
+            .push(expectation);
+
+        self.install()
+    }
+
+    /// Generate and install the code of the mock.
+    fn install(&self) -> Result<(), Error> {
+        let code = {
+            let expectations = self
+                .expectations
+                .try_borrow()
+                .map_err(|e| format_err!("cannot borrow expectations: {}", e))?;
+
+            generate(&expectations)
+        };
+
+        self.evm.set_code(self.address, code)
+    }
+}
+
+/// Builder for an expectation on a mock.
+pub struct ExpectationBuilder<'m, 'a: 'm> {
+    mock: &'m Mock<'a>,
+    name: String,
+    args: Option<Vec<Token>>,
+}
+
+impl<'m, 'a: 'm> ExpectationBuilder<'m, 'a> {
+    /// Only match calls with the given arguments.
+    pub fn with_args(self, args: Vec<Token>) -> Self {
+        Self {
+            args: Some(args),
+            ..self
+        }
+    }
+
+    /// Return the given value.
+    pub fn returns(self, value: Token) -> Result<(), Error> {
+        self.returns_all(vec![value])
+    }
+
+    /// Return the given values, for functions with multiple outputs.
+    pub fn returns_all(self, values: Vec<Token>) -> Result<(), Error> {
+        let data = ethabi::encode(&values);
+        self.respond(Response::Return(data))
+    }
+
+    /// Revert without a reason.
+    pub fn reverts(self) -> Result<(), Error> {
+        self.respond(Response::Revert(Vec::new()))
+    }
+
+    /// Revert with the given reason.
+    pub fn reverts_with(self, reason: &str) -> Result<(), Error> {
+        self.respond(Response::Revert(revert_reason(reason)))
+    }
+
+    fn respond(self, response: Response) -> Result<(), Error> {
+        let function = self.mock.function(&self.name)?;
+
+        let input = match self.args {
+            Some(ref args) => Input::Exact(
+                function
+                    .encode_input(args)
+                    .map_err(|e| format_err!("bad arguments for `{}`: {}", self.name, e))?,
+            ),
+            None => Input::Selector(selector(function)),
+        };
+
+        self.mock.add(Expectation { input, response })
+    }
+}
+
+/// How calls are matched.
+#[derive(Debug, Clone)]
+enum Input {
+    /// Match calls with the given selector.
+    Selector([u8; 4]),
+    /// Match calls with exactly the given calldata.
+    Exact(Vec<u8>),
+}
+
+/// How to respond to a matched call.
+#[derive(Debug, Clone)]
+enum Response {
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct Expectation {
+    input: Input,
+    response: Response,
+}
+
+/// Calculate the selector of the given function.
+fn selector(function: &Function) -> [u8; 4] {
+    let types: Vec<_> = function.inputs.iter().map(|p| p.kind.to_string()).collect();
+    let signature = format!("{}({})", function.name, types.join(","));
+
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&keccak256(signature.as_bytes())[..4]);
+    selector
+}
+
+/// Encode a revert reason, like `revert("reason")` does.
+pub(crate) fn revert_reason(reason: &str) -> Vec<u8> {
+    let mut data = keccak256(b"Error(string)")[..4].to_vec();
+    data.extend(ethabi::encode(&[Token::String(reason.to_string())]));
+    data
+}
+
+/// Generate code dispatching calls according to the given expectations.
+fn generate(expectations: &[Expectation]) -> Vec<u8> {
+    let mut asm = Assembler::default();
+
+    // later expectations take precedence, and exact ones are checked before selectors.
+    let exact = expectations.iter().rev().filter(|e| match e.input {
+        Input::Exact(_) => true,
+        Input::Selector(_) => false,
+    });
+
+    let selectors = expectations.iter().rev().filter(|e| match e.input {
+        Input::Exact(_) => false,
+        Input::Selector(_) => true,
+    });
+
+    for expectation in exact.chain(selectors) {
+        let next = asm.label();
+
+        match expectation.input {
+            Input::Exact(ref data) => {
+                asm.push(data.len());
+                asm.op(op::CALLDATASIZE);
+                asm.op(op::EQ);
+                asm.op(op::ISZERO);
+                asm.jumpi(next);
+
+                for (index, chunk) in data.chunks(32).enumerate() {
+                    asm.push_bytes(&pad(chunk));
+                    asm.push(index * 32);
+                    asm.op(op::CALLDATALOAD);
+                    asm.op(op::EQ);
+                    asm.op(op::ISZERO);
+                    asm.jumpi(next);
+                }
+            }
+            Input::Selector(ref selector) => {
+                asm.jump_unless_selector(selector, next);
+            }
+        }
+
+        respond(&mut asm, &expectation.response);
+        asm.bind(next);
+    }
+
+    respond(&mut asm, &Response::Revert(revert_reason(UNEXPECTED_CALL)));
+    return asm.finish();
+
+    /// Return or revert with the given response.
+    fn respond(asm: &mut Assembler, response: &Response) {
+        match *response {
+            Response::Return(ref data) => asm.exit(data, op::RETURN),
+            Response::Revert(ref data) => asm.exit(data, op::REVERT),
+        }
+    }
+}
+
+/// Pad a chunk of at most 32 bytes with trailing zeros.
+fn pad(chunk: &[u8]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[..chunk.len()].copy_from_slice(chunk);
+    word
+}
+
+pub(crate) mod op {
+    pub const STOP: u8 = 0x00;
+    pub const DIV: u8 = 0x04;
+    pub const LT: u8 = 0x10;
+    pub const EQ: u8 = 0x14;
+    pub const ISZERO: u8 = 0x15;
+    pub const CALLDATALOAD: u8 = 0x35;
+    pub const CALLDATASIZE: u8 = 0x36;
+    pub const MSTORE: u8 = 0x52;
+    pub const JUMPI: u8 = 0x57;
+    pub const JUMPDEST: u8 = 0x5b;
+    pub const PUSH1: u8 = 0x60;
+    pub const PUSH2: u8 = 0x61;
+    pub const RETURN: u8 = 0xf3;
+    pub const REVERT: u8 = 0xfd;
+}
+
+/// A minimal assembler, with support for forward jumps to labels.
+#[derive(Default)]
+pub(crate) struct Assembler {
+    code: Vec<u8>,
+    /// Positions of labels, once bound.
+    labels: Vec<Option<usize>>,
+    /// Positions in the code to patch with the position of a label.
+    fixups: Vec<(usize, usize)>,
+}
+
+impl Assembler {
+    pub fn op(&mut self, op: u8) {
+        self.code.push(op);
+    }
+
+    /// Push the given number, using as few bytes as possible.
+    pub fn push(&mut self, value: usize) {
+        let mut bytes = [0u8; 8];
+
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = ((value as u64) >> (8 * (7 - index))) as u8;
+        }
+
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(7);
+        self.push_bytes(&bytes[start..]);
+    }
+
+    /// Push the given bytes, which must be between 1 and 32 bytes long.
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        debug_assert!(bytes.len() >= 1 && bytes.len() <= 32);
+        self.code.push(op::PUSH1 + (bytes.len() as u8 - 1));
+        self.code.extend(bytes);
+    }
+
+    /// Allocate a new label.
+    pub fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    /// Bind the label to the current position.
+    pub fn bind(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+        self.code.push(op::JUMPDEST);
+    }
+
+    /// Jump to the given label if the top of the stack is non-zero.
+    pub fn jumpi(&mut self, label: usize) {
+        self.code.push(op::PUSH2);
+        self.fixups.push((self.code.len(), label));
+        self.code.extend(&[0, 0]);
+        self.code.push(op::JUMPI);
+    }
+
+    /// Jump to the given label unless the calldata starts with the given selector.
+    pub fn jump_unless_selector(&mut self, selector: &[u8], label: usize) {
+        self.push(4);
+        self.op(op::CALLDATASIZE);
+        self.op(op::LT);
+        self.jumpi(label);
+
+        self.push_bytes(selector);
+        // shift the first word of the calldata down to the selector.
+        let mut shift = [0u8; 29];
+        shift[0] = 1;
+        self.push_bytes(&shift);
+        self.push(0);
+        self.op(op::CALLDATALOAD);
+        self.op(op::DIV);
+        self.op(op::EQ);
+        self.op(op::ISZERO);
+        self.jumpi(label);
+    }
+
+    /// Store the given data in memory, and exit with it using `RETURN` or `REVERT`.
+    pub fn exit(&mut self, data: &[u8], exit: u8) {
+        for (index, chunk) in data.chunks(32).enumerate() {
+            self.push_bytes(&pad(chunk));
+            self.push(index * 32);
+            self.op(op::MSTORE);
+        }
+
+        self.push(data.len());
+        self.push(0);
+        self.op(exit);
+    }
+
+    /// Patch all jumps and return the code.
+    pub fn finish(mut self) -> Vec<u8> {
+        for (position, label) in self.fixups {
+            let target = self.labels[label].expect("label to be bound");
+            self.code[position] = (target >> 8) as u8;
+            self.code[position + 1] = target as u8;
+        }
+
+        self.code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, op, revert_reason, Expectation, Input, Response};
+
+    #[test]
+    fn test_revert_reason() {
+        let data = revert_reason("nope");
+        assert_eq!(&[0x08, 0xc3, 0x79, 0xa0], &data[..4]);
+        assert_eq!(4 + 32 * 3, data.len());
+    }
+
+    #[test]
+    fn test_generate() {
+        let expectations = vec![
+            Expectation {
+                input: Input::Selector([1, 2, 3, 4]),
+                response: Response::Return(vec![0u8; 32]),
+            },
+            Expectation {
+                input: Input::Exact(vec![1, 2, 3, 4, 5]),
+                response: Response::Revert(Vec::new()),
+            },
+        ];
+
+        let code = generate(&expectations);
+
+        // every jump must target a jump destination.
+        let mut pc = 0;
+
+        while pc < code.len() {
+            let op = code[pc];
+
+            if op == op::PUSH2 && code.get(pc + 3) == Some(&op::JUMPI) {
+                let target = ((code[pc + 1] as usize) << 8) | code[pc + 2] as usize;
+                assert_eq!(Some(&op::JUMPDEST), code.get(target));
+            }
+
+            if op >= op::PUSH1 && op < op::PUSH1 + 32 {
+                pc += (op - op::PUSH1) as usize + 1;
+            }
+
+            pc += 1;
+        }
+
+        // the exact expectation is checked first.
+        assert_eq!(&[op::PUSH1, 5, op::CALLDATASIZE], &code[..3]);
+    }
+}
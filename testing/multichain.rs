@@ -0,0 +1,184 @@
+//! Helpers for testing contracts that span multiple chains.
+//!
+//! A `MultiChain` holds several independent `Evm` instances, each identified by a chain id, and
+//! provides utilities to relay events emitted on one chain as transactions on another.
+//!
+//! Every virtual machine uses the chain id it was added with, so transactions signed for one
+//! chain are rejected by the others.
+
+use abi;
+use ethereum_types::Address;
+use evm::Evm;
+use failure::Error;
+use std::collections::{btree_map, BTreeMap};
+
+/// A collection of virtual machines, indexed by chain id.
+#[derive(Debug, Clone)]
+pub struct MultiChain {
+    chains: BTreeMap<u64, Evm>,
+}
+
+impl MultiChain {
+    /// Construct a new, empty set of chains.
+    pub fn new() -> Self {
+        Self {
+            chains: BTreeMap::new(),
+        }
+    }
+
+    /// Add a virtual machine with the given chain id.
+    ///
+    /// The chain id of the virtual machine is changed to the given one, see `Evm::set_chain_id`.
+    pub fn add(&mut self, chain_id: u64, mut evm: Evm) -> Result<(), Error> {
+        match self.chains.entry(chain_id) {
+            btree_map::Entry::Vacant(e) => {
+                evm.set_chain_id(chain_id);
+                e.insert(evm);
+            }
+            btree_map::Entry::Occupied(_) => {
+                bail!("chain with id {} already registered", chain_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Access the virtual machine for the given chain id.
+    pub fn chain(&self, chain_id: u64) -> Result<&Evm, Error> {
+        self.chains
+            .get(&chain_id)
+            .ok_or_else(|| format_err!("no chain with id {}", chain_id))
+    }
+
+    /// Mutably access the virtual machine for the given chain id.
+    pub fn chain_mut(&mut self, chain_id: u64) -> Result<&mut Evm, Error> {
+        self.chains
+            .get_mut(&chain_id)
+            .ok_or_else(|| format_err!("no chain with id {}", chain_id))
+    }
+
+    /// Iterate over all registered chain ids, in ascending order.
+    pub fn chain_ids<'a>(&'a self) -> impl Iterator<Item = u64> + 'a {
+        self.chains.keys().cloned()
+    }
+
+    /// Iterate over all chains and their ids.
+    pub fn chains<'a>(&'a self) -> impl Iterator<Item = (u64, &'a Evm)> + 'a {
+        self.chains.iter().map(|(id, evm)| (*id, evm))
+    }
+
+    /// Relay all matching logs emitted on the `from` chain to the `to` chain.
+    ///
+    /// The logs are drained from the source chain, and `relay` is called once for each of them
+    /// with the destination virtual machine, the address that emitted the log, and the parsed log.
+    ///
+    /// Returns the number of relayed logs.
+    pub fn relay<P, F>(&self, from: u64, to: u64, log: P, mut relay: F) -> Result<usize, Error>
+    where
+        P: abi::ParseLog + abi::LogFilter,
+        F: FnMut(&Evm, Address, P::Log) -> Result<(), Error>,
+    {
+        if from == to {
+            bail!("cannot relay from chain {} to itself", from);
+        }
+
+        let source = self.chain(from)?;
+        let target = self.chain(to)?;
+
+        let logs = source.logs(log).drain_with_sender()?;
+        let count = logs.len();
+
+        for (sender, log) in logs {
+            relay(target, sender, log)
+                .map_err(|e| format_err!("failed to relay log from {} to {}: {}", from, to, e))?;
+        }
+
+        Ok(count)
+    }
+
+    /// Set the block number on all chains.
+    pub fn set_block_number(&mut self, number: u64) {
+        for evm in self.chains.values_mut() {
+            evm.set_block_number(number);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiChain;
+    use abi::ContractContext;
+    use ethcore::spec::Spec;
+    use evm::Evm;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_chain_ids() {
+        let mut chains = MultiChain::new();
+        chains.add(1, new_evm()).expect("failed to add chain");
+        chains.add(10, new_evm()).expect("failed to add chain");
+
+        assert!(chains.add(10, new_evm()).is_err());
+        assert_eq!(vec![1, 10], chains.chain_ids().collect::<Vec<_>>());
+        assert_eq!(1, chains.chain(1).expect("no chain").chain_id());
+        assert_eq!(10, chains.chain(10).expect("no chain").chain_id());
+        assert!(chains.chain(2).is_err());
+    }
+
+    #[cfg(feature = "account")]
+    #[test]
+    fn test_replay_protection() {
+        use ethcore_transaction::{Action, Transaction};
+        use ethereum_types::{Address, U256};
+        use wei;
+
+        let mut chains = MultiChain::new();
+        chains.add(1, new_evm()).expect("failed to add chain");
+        chains.add(10, new_evm()).expect("failed to add chain");
+
+        let one = chains.chain(1).expect("no chain");
+        let ten = chains.chain(10).expect("no chain");
+
+        let account = one
+            .account_from_secret(
+                "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            ).expect("bad secret");
+
+        for evm in &[one, ten] {
+            evm.add_balance(account.address, wei::from_ether(1))
+                .expect("failed to add balance");
+        }
+
+        let tx = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: U256::from(21_000),
+            action: Action::Call(Address::from(0x42)),
+            value: U256::from(1000),
+            data: Vec::new(),
+        };
+
+        let raw = account
+            .sign_transaction(&tx, 10)
+            .expect("failed to sign transaction");
+
+        // signed for the other chain.
+        assert!(one.send_raw_transaction(&raw).is_err());
+
+        let call = ten
+            .send_raw_transaction(&raw)
+            .expect("failed to send transaction");
+        assert!(call.is_ok());
+    }
+
+    /// Set up a new virtual machine.
+    fn new_evm() -> Evm {
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        Evm::new(&Spec::new_null(), context).expect("failed to set up evm")
+    }
+}
@@ -1,23 +1,37 @@
-pub use abi::Vm;
+pub use abi::{DynamicContract, RawCall, Vm};
 #[cfg(feature = "account")]
 pub use account::Account;
-pub use call::Call;
+pub use call::{Call, CallProfile};
 pub use ethabi;
 pub use ethcore::spec::Spec;
 pub use ethereum_types::*;
-pub use evm::Evm;
+pub use evm::{Evm, LogMeta};
+#[cfg(feature = "test-runner")]
+pub use capture::CaptureLogger;
+#[cfg(feature = "test-runner")]
+pub use fixture::Fixture;
+#[cfg(feature = "test-runner")]
+pub use fuzz::Fuzzer;
+#[cfg(feature = "test-runner")]
+pub use gas_snapshot::GasSnapshot;
+#[cfg(feature = "test-runner")]
+pub use invariant::Invariants;
 pub use linker::Linker;
 #[cfg(feature = "test-runner")]
-pub use reporter::{Reporter, StdoutReporter};
+pub use reporter::{JsonReporter, MultiReporter, Reporter, StdoutReporter, TapReporter, Verbosity};
 #[cfg(feature = "test-runner")]
-pub use snapshot::Snapshot;
+pub use snapshot::{Revert, Snapshot, SnapshotPool};
 #[cfg(feature = "test-runner")]
-pub use test_runner::{Suite, TestRunner};
+pub use test_runner::{Order, Suite, TestRunner};
+pub use u256;
 pub use wei;
 // re-export property testing prelude.
 pub use crypto::keccak256;
-pub use ledger::{AccountBalance, Ledger, LedgerState};
-pub use matcher::Matcher;
+pub use gas::GasProfile;
+pub use ledger::{AccountBalance, Ledger, LedgerState, MultiLedger};
+pub use matcher::{Matcher, RevertMatch};
+pub use mock::Mock;
+pub use multichain::MultiChain;
 pub use proptest::prelude::*;
 
 pub type Result<T> = ::std::result::Result<T, ::failure::Error>;
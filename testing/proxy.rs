@@ -0,0 +1,182 @@
+//! Helpers for testing upgradeable contracts behind proxies.
+//!
+//! This reads the slots standardized by EIP-1967, deploys contracts behind EIP-1167 minimal
+//! proxies, and checks that the storage layout of a contract is preserved across an upgrade:
+//!
+//! ```rust,ignore
+//! let (implementation, proxy) = proxy::deploy_behind_proxy(&evm, token::constructor(), call)?;
+//! assert_eq!(Some(implementation), proxy::minimal_proxy_implementation(&evm, proxy)?);
+//!
+//! assert_eq!(token_v1, proxy::implementation_of(&evm, upgradeable)?);
+//! proxy::assert_layout_preserved(token_v1::STORAGE_LAYOUT, token_v2::STORAGE_LAYOUT)?;
+//! ```
+
+use abi;
+use call;
+use ethereum_types::{Address, H256};
+use evm::Evm;
+use failure::Error;
+use storage::StorageLayout;
+
+/// Slot storing the address of the implementation,
+/// `keccak256("eip1967.proxy.implementation") - 1`.
+pub const IMPLEMENTATION_SLOT: H256 = H256([
+    0x36, 0x08, 0x94, 0xa1, 0x3b, 0xa1, 0xa3, 0x21, 0x06, 0x67, 0xc8, 0x28, 0x49, 0x2d, 0xb9, 0x8d,
+    0xca, 0x3e, 0x20, 0x76, 0xcc, 0x37, 0x35, 0xa9, 0x20, 0xa3, 0xca, 0x50, 0x5d, 0x38, 0x2b, 0xbc,
+]);
+
+/// Slot storing the address of the admin, `keccak256("eip1967.proxy.admin") - 1`.
+pub const ADMIN_SLOT: H256 = H256([
+    0xb5, 0x31, 0x27, 0x68, 0x4a, 0x56, 0x8b, 0x31, 0x73, 0xae, 0x13, 0xb9, 0xf8, 0xa6, 0x01, 0x6e,
+    0x24, 0x3e, 0x63, 0xb6, 0xe8, 0xee, 0x11, 0x78, 0xd6, 0xa7, 0x17, 0x85, 0x0b, 0x5d, 0x61, 0x03,
+]);
+
+/// Slot storing the address of the beacon, `keccak256("eip1967.proxy.beacon") - 1`.
+pub const BEACON_SLOT: H256 = H256([
+    0xa3, 0xf0, 0xad, 0x74, 0xe5, 0x42, 0x3a, 0xeb, 0xfd, 0x80, 0xd3, 0xef, 0x43, 0x46, 0x57, 0x83,
+    0x35, 0xa9, 0xa7, 0x2a, 0xea, 0xee, 0x59, 0xff, 0x6c, 0xb3, 0x58, 0x2b, 0x35, 0x13, 0x3d, 0x50,
+]);
+
+/// Creation code of an EIP-1167 minimal proxy, up until the implementation address.
+const MINIMAL_PROXY_PREFIX: &'static [u8] = &[
+    0x3d, 0x60, 0x2d, 0x80, 0x60, 0x0a, 0x3d, 0x39, 0x81, 0xf3, 0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d,
+    0x3d, 0x36, 0x3d, 0x73,
+];
+
+/// Length of the part of the creation code which copies the runtime code.
+const MINIMAL_PROXY_INIT: usize = 10;
+
+/// Creation code of an EIP-1167 minimal proxy, after the implementation address.
+const MINIMAL_PROXY_SUFFIX: &'static [u8] = &[
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+/// Read the address of the implementation of an EIP-1967 proxy.
+///
+/// This is the zero address if no implementation is set.
+pub fn implementation_of<VM: abi::Vm>(vm: &VM, proxy: Address) -> Result<Address, Error> {
+    address_at(vm, proxy, IMPLEMENTATION_SLOT)
+}
+
+/// Read the address of the admin of an EIP-1967 proxy.
+///
+/// This is the zero address if no admin is set.
+pub fn admin_of<VM: abi::Vm>(vm: &VM, proxy: Address) -> Result<Address, Error> {
+    address_at(vm, proxy, ADMIN_SLOT)
+}
+
+/// Read the address of the beacon of an EIP-1967 beacon proxy.
+///
+/// This is the zero address if no beacon is set.
+pub fn beacon_of<VM: abi::Vm>(vm: &VM, proxy: Address) -> Result<Address, Error> {
+    address_at(vm, proxy, BEACON_SLOT)
+}
+
+/// Read an address stored in the given slot.
+fn address_at<VM: abi::Vm>(vm: &VM, address: Address, slot: H256) -> Result<Address, Error> {
+    let value = vm.storage_at(address, slot)?;
+    Ok(Address::from_slice(&value[12..]))
+}
+
+/// Build the creation code of an EIP-1167 minimal proxy delegating to `implementation`.
+pub fn minimal_proxy_code(implementation: Address) -> Vec<u8> {
+    let mut code = Vec::new();
+    code.extend(MINIMAL_PROXY_PREFIX);
+    code.extend(implementation.iter());
+    code.extend(MINIMAL_PROXY_SUFFIX);
+    code
+}
+
+/// Extract the implementation that the given runtime code of an EIP-1167 minimal proxy delegates
+/// to, or `None` if it isn't the code of a minimal proxy.
+pub fn parse_minimal_proxy(code: &[u8]) -> Option<Address> {
+    let prefix = &MINIMAL_PROXY_PREFIX[MINIMAL_PROXY_INIT..];
+    let suffix = MINIMAL_PROXY_SUFFIX;
+
+    if code.len() != prefix.len() + 20 + suffix.len() {
+        return None;
+    }
+
+    if !code.starts_with(prefix) || !code.ends_with(suffix) {
+        return None;
+    }
+
+    Some(Address::from_slice(&code[prefix.len()..prefix.len() + 20]))
+}
+
+/// Find the implementation that the contract at the given address delegates to, if it is an
+/// EIP-1167 minimal proxy.
+pub fn minimal_proxy_implementation(evm: &Evm, proxy: Address) -> Result<Option<Address>, Error> {
+    Ok(evm.code(proxy)?.and_then(|code| parse_minimal_proxy(&code)))
+}
+
+/// Deploy a contract, and an EIP-1167 minimal proxy delegating to it.
+///
+/// Returns the address of the implementation and of the proxy, in that order.
+pub fn deploy_behind_proxy<C>(
+    evm: &Evm,
+    constructor: C,
+    call: call::Call,
+) -> Result<(Address, Address), Error>
+where
+    C: abi::ContractFunction<Output = Address> + abi::Constructor,
+{
+    let implementation = evm
+        .deploy(constructor, call.clone())?
+        .ok()
+        .map_err(|e| format_err!("{}: failed to deploy implementation: {}", C::ITEM, e))?;
+
+    let proxy = evm
+        .deploy_clone(implementation, call)
+        .map_err(|e| format_err!("{}: {}", C::ITEM, e))?;
+
+    Ok((implementation, proxy))
+}
+
+/// Check that the storage layout of an upgraded implementation preserves the state variables of
+/// the previous one.
+///
+/// The layouts are given as JSON, like the `STORAGE_LAYOUT` of contracts compiled with
+/// `#[parables(storage_layout)]`. See `StorageLayout::check_upgrade` for what is checked.
+pub fn assert_layout_preserved(previous: &str, upgraded: &str) -> Result<(), Error> {
+    let previous = StorageLayout::parse(previous)?;
+    let upgraded = StorageLayout::parse(upgraded)?;
+    upgraded.check_upgrade(&previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        minimal_proxy_code, parse_minimal_proxy, ADMIN_SLOT, BEACON_SLOT, IMPLEMENTATION_SLOT,
+        MINIMAL_PROXY_INIT,
+    };
+    use crypto::keccak256;
+    use ethereum_types::{Address, H256, U256};
+
+    #[test]
+    fn test_slots() {
+        let slot = |name: &str| {
+            let mut slot = [0u8; 32];
+            (U256::from(&keccak256(name.as_bytes())[..]) - U256::from(1)).to_big_endian(&mut slot);
+            H256(slot)
+        };
+
+        assert_eq!(slot("eip1967.proxy.implementation"), IMPLEMENTATION_SLOT);
+        assert_eq!(slot("eip1967.proxy.admin"), ADMIN_SLOT);
+        assert_eq!(slot("eip1967.proxy.beacon"), BEACON_SLOT);
+    }
+
+    #[test]
+    fn test_minimal_proxy() {
+        let implementation = Address::from(0x42);
+        let code = minimal_proxy_code(implementation);
+
+        assert_eq!(55, code.len());
+        assert_eq!(
+            Some(implementation),
+            parse_minimal_proxy(&code[MINIMAL_PROXY_INIT..])
+        );
+        assert_eq!(None, parse_minimal_proxy(&code));
+        assert_eq!(None, parse_minimal_proxy(&[]));
+    }
+}
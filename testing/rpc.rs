@@ -0,0 +1,1028 @@
+//! A JSON-RPC facade over a virtual machine.
+//!
+//! This permits pointing external tooling, like web3.js scripts or wallets, at a chain managed by
+//! parables during integration tests.
+//!
+//! Requests are served over HTTP or WebSocket, one at a time, on the thread calling
+//! `Server::serve`. Connections asking to be upgraded to WebSocket are served over it, while
+//! subscriptions are not supported.
+//!
+//! The chain does not produce blocks, so all block parameters are ignored and state is always
+//! queried at the latest block. `eth_call` and `eth_estimateGas` are run against a copy of the
+//! state, so they don't modify it or produce logs. `eth_getLogs` returns logs which have not yet
+//! been drained from the virtual machine.
+//!
+//! ```rust,ignore
+//! let server = rpc::Server::new(&evm);
+//! server.serve("127.0.0.1:8545")?;
+//! ```
+
+use call;
+use ethcore::log_entry::LogEntry;
+use ethcore_transaction::{Action, SignedTransaction, Transaction, UnverifiedTransaction};
+use ethereum_types::{Address, H256, U256};
+use evm::{Evm, Outcome};
+use failure::Error;
+use rlp;
+use serde_json::{self, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+
+/// Default amount of gas for calls which do not specify it.
+const DEFAULT_GAS: u64 = 10_000_000;
+
+/// An error that can be returned from a JSON-RPC method.
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self {
+            code: -32602,
+            message: message.into(),
+        }
+    }
+
+    fn method_not_found(method: &str) -> Self {
+        Self {
+            code: -32601,
+            message: format!("method not found: {}", method),
+        }
+    }
+
+    fn server(message: impl Into<String>) -> Self {
+        Self {
+            code: -32000,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        RpcError::server(error.to_string())
+    }
+}
+
+/// A JSON-RPC server backed by a virtual machine.
+pub struct Server<'a> {
+    evm: &'a Evm,
+    /// Receipts of transactions sent through the server, by transaction hash.
+    receipts: RefCell<HashMap<H256, Value>>,
+}
+
+impl<'a> Server<'a> {
+    /// Build a new server for the given virtual machine.
+    ///
+    /// The server reports the chain id of the virtual machine, see `Evm::set_chain_id`.
+    pub fn new(evm: &'a Evm) -> Self {
+        Self {
+            evm,
+            receipts: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Serve requests over HTTP or WebSocket on the given address.
+    ///
+    /// This blocks the current thread, serving one request at a time.
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<(), Error> {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| format_err!("failed to bind server: {}", e))?;
+
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| format_err!("failed to accept connection: {}", e))?;
+
+            if let Err(e) = self.serve_connection(stream) {
+                warn!("rpc: failed to serve connection: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serve all requests on a single connection.
+    fn serve_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        loop {
+            let mut content_length = None;
+            let mut websocket_key = None;
+            let mut line = String::new();
+
+            // request line.
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            loop {
+                line.clear();
+                reader.read_line(&mut line)?;
+
+                let header = line.trim();
+
+                if header.is_empty() {
+                    break;
+                }
+
+                let mut parts = header.splitn(2, ':');
+                let name = parts.next().unwrap_or("").trim();
+                let value = parts.next().unwrap_or("").trim();
+
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = Some(value.parse::<usize>()?);
+                }
+
+                if name.eq_ignore_ascii_case("sec-websocket-key") {
+                    websocket_key = Some(value.to_string());
+                }
+            }
+
+            if let Some(key) = websocket_key {
+                write!(
+                    writer,
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    websocket::accept_key(&key)
+                )?;
+
+                writer.flush()?;
+                return self.serve_websocket(&mut reader, &mut writer);
+            }
+
+            let mut body = vec![0u8; content_length.unwrap_or(0)];
+            reader.read_exact(&mut body)?;
+
+            let response = self.handle_message(&body)?;
+
+            write!(
+                writer,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                response.len()
+            )?;
+
+            writer.write_all(&response)?;
+            writer.flush()?;
+        }
+    }
+
+    /// Serve all requests on a WebSocket connection, once the handshake is done.
+    ///
+    /// Every text or binary message is handled as a request, and is responded to with a text
+    /// message.
+    fn serve_websocket(
+        &self,
+        reader: &mut impl Read,
+        writer: &mut impl Write,
+    ) -> Result<(), Error> {
+        let mut message = Vec::new();
+
+        loop {
+            let frame = match websocket::read_frame(reader)? {
+                Some(frame) => frame,
+                None => return Ok(()),
+            };
+
+            match frame.opcode {
+                websocket::CONTINUATION | websocket::TEXT | websocket::BINARY => {
+                    message.extend(frame.payload);
+
+                    if !frame.fin {
+                        continue;
+                    }
+
+                    let response = self.handle_message(&message)?;
+                    message.clear();
+                    websocket::write_frame(writer, websocket::TEXT, &response)?;
+                }
+                websocket::PING => {
+                    websocket::write_frame(writer, websocket::PONG, &frame.payload)?;
+                }
+                websocket::PONG => {}
+                websocket::CLOSE => {
+                    websocket::write_frame(writer, websocket::CLOSE, &frame.payload)?;
+                    return Ok(());
+                }
+                opcode => bail!("unsupported websocket opcode: {:#x}", opcode),
+            }
+        }
+    }
+
+    /// Handle a single encoded message, which is either a request or a batch of requests.
+    fn handle_message(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let response = match serde_json::from_slice::<Value>(message) {
+            Ok(request) => self.handle(&request),
+            Err(e) => error_response(
+                Value::Null,
+                RpcError {
+                    code: -32700,
+                    message: format!("parse error: {}", e),
+                },
+            ),
+        };
+
+        Ok(serde_json::to_vec(&response)?)
+    }
+
+    /// Handle a single JSON-RPC request, or a batch of requests.
+    pub fn handle(&self, request: &Value) -> Value {
+        if let Value::Array(ref requests) = *request {
+            return Value::Array(requests.iter().map(|r| self.handle(r)).collect());
+        }
+
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None => return error_response(id, RpcError::invalid_params("missing method")),
+        };
+
+        let empty = Vec::new();
+
+        let params = request
+            .get("params")
+            .and_then(Value::as_array)
+            .unwrap_or(&empty);
+
+        match self.dispatch(method, params) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => error_response(id, e),
+        }
+    }
+
+    /// Dispatch a single method.
+    fn dispatch(&self, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+        let result = match method {
+            "web3_clientVersion" => json!("parables"),
+            "net_version" => json!(self.evm.chain_id().to_string()),
+            "eth_chainId" => json!(format!("{:#x}", self.evm.chain_id())),
+            "eth_blockNumber" => json!(format!("{:#x}", self.evm.get_block_number())),
+            "eth_accounts" => json!([]),
+            "eth_gasPrice" => json!("0x0"),
+            "eth_getBalance" => {
+                let address = address_param(params, 0)?;
+                quantity(self.evm.balance(address)?)
+            }
+            "eth_getTransactionCount" => {
+                let address = address_param(params, 0)?;
+                quantity(self.evm.nonce(address)?)
+            }
+            "eth_getCode" => {
+                let address = address_param(params, 0)?;
+                data(&self.evm.code(address)?.unwrap_or_default())
+            }
+            "eth_call" => {
+                let (to, input, call) = call_param(params, 0)?;
+
+                match self.evm.query_raw(to, input, call)? {
+                    Outcome::Ok(output) => data(&output),
+                    ref outcome => return Err(outcome_error(outcome)),
+                }
+            }
+            "eth_estimateGas" => {
+                let (to, input, call) = call_param(params, 0)?;
+
+                // fail like nodes do if the call wouldn't succeed, regardless of how much gas it is
+                // given.
+                match self.evm.query_raw(to, input.clone(), call.clone())? {
+                    Outcome::Ok(_) => quantity(self.evm.estimate_gas_raw(to, input, call)?),
+                    ref outcome => return Err(outcome_error(outcome)),
+                }
+            }
+            "eth_sendTransaction" => {
+                let (to, input, call) = call_param(params, 0)?;
+                let nonce = self.evm.nonce(call.sender)?;
+
+                let action = match to {
+                    Some(address) => Action::Call(address),
+                    None => Action::Create,
+                };
+
+                let tx = Transaction {
+                    nonce,
+                    gas_price: call.gas_price,
+                    gas: call.gas,
+                    action,
+                    value: call.value,
+                    data: input,
+                };
+
+                self.transact(tx.fake_sign(call.sender.into()))?
+            }
+            "eth_sendRawTransaction" => {
+                let raw = bytes_param(params, 0)?;
+
+                let tx: UnverifiedTransaction = rlp::decode(&raw)
+                    .map_err(|e| RpcError::invalid_params(format!("bad transaction: {}", e)))?;
+
+                // transactions without a chain id can be replayed on any chain.
+                if let Some(chain_id) = tx.chain_id() {
+                    if chain_id != self.evm.chain_id() {
+                        return Err(RpcError::invalid_params(format!(
+                            "transaction is signed for chain {}, but this is chain {}",
+                            chain_id,
+                            self.evm.chain_id()
+                        )));
+                    }
+                }
+
+                let tx = SignedTransaction::new(tx)
+                    .map_err(|e| RpcError::invalid_params(format!("bad signature: {}", e)))?;
+
+                self.transact(tx)?
+            }
+            "eth_getTransactionReceipt" => {
+                let hash = hash_param(params, 0)?;
+
+                let receipts = self.receipts.borrow();
+
+                receipts.get(&hash).cloned().unwrap_or(Value::Null)
+            }
+            "eth_getLogs" => self.logs(params.get(0))?,
+            method => return Err(RpcError::method_not_found(method)),
+        };
+
+        Ok(result)
+    }
+
+    /// Run the given transaction and record its receipt.
+    fn transact(&self, tx: SignedTransaction) -> Result<Value, RpcError> {
+        let hash = tx.hash();
+        let sender = tx.sender();
+
+        let contract_address = match tx.action {
+            Action::Create => Some(self.evm.contract_address(sender, tx.nonce, &tx.data)),
+            Action::Call(_) => None,
+        };
+
+        let to = match tx.action {
+            Action::Create => None,
+            Action::Call(address) => Some(address),
+        };
+
+        let result = self.evm.transact(tx)?;
+        let block_number = format!("{:#x}", self.evm.get_block_number());
+
+        let logs = result
+            .logs()
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let mut log = log_object(entry);
+                log["logIndex"] = json!(format!("{:#x}", index));
+                log["transactionHash"] = json!(format!("{:?}", hash));
+                log["blockNumber"] = json!(block_number);
+                log
+            })
+            .collect::<Vec<_>>();
+
+        let receipt = json!({
+            "transactionHash": format!("{:?}", hash),
+            "from": format!("{:?}", sender),
+            "to": to.map(|a| format!("{:?}", a)),
+            "contractAddress": contract_address.map(|a| format!("{:?}", a)),
+            "gasUsed": quantity(result.gas_used),
+            "cumulativeGasUsed": quantity(result.gas_used),
+            "blockNumber": block_number,
+            "status": if result.is_ok() { "0x1" } else { "0x0" },
+            "logs": logs,
+        });
+
+        self.receipts.borrow_mut().insert(hash, receipt);
+        Ok(json!(format!("{:?}", hash)))
+    }
+
+    /// Collect logs matching the given filter.
+    fn logs(&self, filter: Option<&Value>) -> Result<Value, RpcError> {
+        let addresses = match filter.and_then(|f| f.get("address")) {
+            None | Some(&Value::Null) => None,
+            Some(&Value::Array(ref values)) => Some(
+                values
+                    .iter()
+                    .map(parse_address)
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            Some(value) => Some(vec![parse_address(value)?]),
+        };
+
+        let topics = match filter.and_then(|f| f.get("topics")) {
+            Some(&Value::Array(ref topics)) => topics
+                .iter()
+                .map(|topic| match *topic {
+                    Value::Null => Ok(None),
+                    Value::Array(ref values) => values
+                        .iter()
+                        .map(parse_hash)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(Some),
+                    ref value => parse_hash(value).map(|h| Some(vec![h])),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => Vec::new(),
+        };
+
+        let logs = self.evm.raw_logs()?;
+        let mut out = Vec::new();
+
+        for entry in logs.values().flat_map(|entries| entries.iter()) {
+            if let Some(ref addresses) = addresses {
+                if !addresses.contains(&entry.address) {
+                    continue;
+                }
+            }
+
+            let matches = topics.iter().enumerate().all(|(i, topic)| match *topic {
+                None => true,
+                Some(ref options) => entry
+                    .topics
+                    .get(i)
+                    .map(|t| options.contains(t))
+                    .unwrap_or(false),
+            });
+
+            if !matches {
+                continue;
+            }
+
+            out.push(log_object(entry));
+        }
+
+        Ok(Value::Array(out))
+    }
+}
+
+/// Build an error response.
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": error.code, "message": error.message},
+    })
+}
+
+/// Convert an unsuccessful outcome into an error.
+fn outcome_error<T>(outcome: &Outcome<T>) -> RpcError {
+    match *outcome {
+        Outcome::Reverted { ref errors } => RpcError {
+            code: 3,
+            message: format!("execution reverted: {}", errors),
+        },
+        Outcome::Errored { ref errors } => {
+            RpcError::server(format!("execution errored: {}", errors))
+        }
+        Outcome::Status { status } => RpcError::server(format!("execution failed: {}", status)),
+        Outcome::Ok(..) => RpcError::server("execution succeeded"),
+    }
+}
+
+/// Encode a log entry.
+fn log_object(entry: &LogEntry) -> Value {
+    json!({
+        "address": format!("{:?}", entry.address),
+        "topics": entry.topics.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>(),
+        "data": data(&entry.data),
+    })
+}
+
+/// Encode a quantity.
+fn quantity(value: U256) -> Value {
+    json!(format!("0x{:x}", value))
+}
+
+/// Encode unformatted data.
+fn data(bytes: &[u8]) -> Value {
+    let mut out = String::from("0x");
+
+    for b in bytes {
+        out.push_str(&format!("{:02x}", b));
+    }
+
+    json!(out)
+}
+
+/// Strip the hex prefix of a value.
+fn strip_hex(value: &Value) -> Result<&str, RpcError> {
+    let value = value
+        .as_str()
+        .ok_or_else(|| RpcError::invalid_params("expected string"))?;
+
+    if value.starts_with("0x") {
+        Ok(&value[2..])
+    } else {
+        Ok(value)
+    }
+}
+
+fn parse_address(value: &Value) -> Result<Address, RpcError> {
+    Address::from_str(strip_hex(value)?)
+        .map_err(|e| RpcError::invalid_params(format!("bad address: {:?}", e)))
+}
+
+fn parse_hash(value: &Value) -> Result<H256, RpcError> {
+    H256::from_str(strip_hex(value)?)
+        .map_err(|e| RpcError::invalid_params(format!("bad hash: {:?}", e)))
+}
+
+fn parse_quantity(value: &Value) -> Result<U256, RpcError> {
+    U256::from_str(strip_hex(value)?)
+        .map_err(|e| RpcError::invalid_params(format!("bad quantity: {:?}", e)))
+}
+
+fn parse_bytes(value: &Value) -> Result<Vec<u8>, RpcError> {
+    let digits = strip_hex(value)?
+        .chars()
+        .map(|c| {
+            c.to_digit(16)
+                .ok_or_else(|| RpcError::invalid_params(format!("bad data: {:?}", c)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if digits.len() % 2 != 0 {
+        return Err(RpcError::invalid_params("odd number of hex digits"));
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|d| ((d[0] << 4) | d[1]) as u8)
+        .collect())
+}
+
+fn param(params: &[Value], index: usize) -> Result<&Value, RpcError> {
+    params
+        .get(index)
+        .ok_or_else(|| RpcError::invalid_params(format!("missing parameter #{}", index)))
+}
+
+fn address_param(params: &[Value], index: usize) -> Result<Address, RpcError> {
+    parse_address(param(params, index)?)
+}
+
+fn hash_param(params: &[Value], index: usize) -> Result<H256, RpcError> {
+    parse_hash(param(params, index)?)
+}
+
+fn bytes_param(params: &[Value], index: usize) -> Result<Vec<u8>, RpcError> {
+    parse_bytes(param(params, index)?)
+}
+
+/// Parse a call object, like the one used by `eth_call`.
+fn call_param(
+    params: &[Value],
+    index: usize,
+) -> Result<(Option<Address>, Vec<u8>, call::Call), RpcError> {
+    let object = param(params, index)?
+        .as_object()
+        .ok_or_else(|| RpcError::invalid_params("expected call object"))?;
+
+    let field = |name: &str| match object.get(name) {
+        None | Some(&Value::Null) => None,
+        Some(value) => Some(value),
+    };
+
+    let sender = match field("from") {
+        Some(value) => parse_address(value)?,
+        None => Address::zero(),
+    };
+
+    let to = match field("to") {
+        Some(value) => Some(parse_address(value)?),
+        None => None,
+    };
+
+    let input = match field("data").or_else(|| field("input")) {
+        Some(value) => parse_bytes(value)?,
+        None => Vec::new(),
+    };
+
+    let mut call = call::Call::new(sender).gas(DEFAULT_GAS);
+
+    if let Some(value) = field("gas") {
+        call = call.gas(parse_quantity(value)?);
+    }
+
+    if let Some(value) = field("gasPrice") {
+        call = call.gas_price(parse_quantity(value)?);
+    }
+
+    if let Some(value) = field("value") {
+        call = call.value(parse_quantity(value)?);
+    }
+
+    Ok((to, input, call))
+}
+
+/// Minimal support for the WebSocket protocol, as specified in RFC 6455.
+mod websocket {
+    use failure::Error;
+    use rust_crypto::digest::Digest;
+    use rust_crypto::sha1::Sha1;
+    use std::io::{self, Read, Write};
+
+    /// Appended to the key of a handshake to calculate the accept key.
+    const GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+    /// The largest frame accepted, to avoid allocating arbitrary amounts of memory.
+    const MAX_PAYLOAD: u64 = 16 * 1024 * 1024;
+
+    pub const CONTINUATION: u8 = 0x0;
+    pub const TEXT: u8 = 0x1;
+    pub const BINARY: u8 = 0x2;
+    pub const CLOSE: u8 = 0x8;
+    pub const PING: u8 = 0x9;
+    pub const PONG: u8 = 0xa;
+
+    /// A single frame received from a client.
+    pub struct Frame {
+        /// If this is the last frame of a message.
+        pub fin: bool,
+        pub opcode: u8,
+        /// The unmasked payload.
+        pub payload: Vec<u8>,
+    }
+
+    /// Calculate the `Sec-WebSocket-Accept` header for the given `Sec-WebSocket-Key`.
+    pub fn accept_key(key: &str) -> String {
+        let mut sha1 = Sha1::new();
+        sha1.input(key.as_bytes());
+        sha1.input(GUID.as_bytes());
+
+        let mut hash = [0u8; 20];
+        sha1.result(&mut hash);
+        base64(&hash)
+    }
+
+    /// Read a single frame, or `None` if the connection was closed.
+    pub fn read_frame(reader: &mut impl Read) -> Result<Option<Frame>, Error> {
+        let mut header = [0u8; 2];
+
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        let masked = header[1] & 0x80 != 0;
+
+        let len = match header[1] & 0x7f {
+            126 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len)?;
+                len.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b))
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                reader.read_exact(&mut len)?;
+                len.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b))
+            }
+            len => u64::from(len),
+        };
+
+        if len > MAX_PAYLOAD {
+            bail!("frame of {} bytes is too large", len);
+        }
+
+        // frames sent by clients must be masked.
+        if !masked {
+            bail!("frame is not masked");
+        }
+
+        let mut mask = [0u8; 4];
+        reader.read_exact(&mut mask)?;
+
+        let mut payload = vec![0u8; len as usize];
+        reader.read_exact(&mut payload)?;
+
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+
+        Ok(Some(Frame {
+            fin,
+            opcode,
+            payload,
+        }))
+    }
+
+    /// Write a single, unmasked frame containing a whole message.
+    pub fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+        let mut header = vec![0x80 | opcode];
+        let len = payload.len() as u64;
+
+        if len < 126 {
+            header.push(len as u8);
+        } else if len <= 0xffff {
+            header.push(126);
+            header.extend((0..2).rev().map(|i| (len >> (i * 8)) as u8));
+        } else {
+            header.push(127);
+            header.extend((0..8).rev().map(|i| (len >> (i * 8)) as u8));
+        }
+
+        writer.write_all(&header)?;
+        writer.write_all(payload)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Encode the given bytes as base64, with padding.
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &'static [u8] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut out = String::new();
+
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, b)| n | (u32::from(*b) << (16 - i * 8)));
+
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[((n >> (18 - i * 6)) & 0x3f) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+
+        out
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{accept_key, base64, read_frame, write_frame, TEXT};
+
+        #[test]
+        fn test_accept_key() {
+            // example from RFC 6455.
+            assert_eq!(
+                "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+                accept_key("dGhlIHNhbXBsZSBub25jZQ==")
+            );
+        }
+
+        #[test]
+        fn test_base64() {
+            assert_eq!("", base64(b""));
+            assert_eq!("Zg==", base64(b"f"));
+            assert_eq!("Zm8=", base64(b"fo"));
+            assert_eq!("Zm9v", base64(b"foo"));
+            assert_eq!("Zm9vYg==", base64(b"foob"));
+        }
+
+        #[test]
+        fn test_frames() {
+            // masked "Hello" from RFC 6455.
+            let data = [
+                0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+            ];
+
+            let frame = read_frame(&mut &data[..])
+                .expect("failed to read frame")
+                .expect("no frame");
+
+            assert!(frame.fin);
+            assert_eq!(TEXT, frame.opcode);
+            assert_eq!(b"Hello".to_vec(), frame.payload);
+            assert!(read_frame(&mut &[][..]).expect("failed to read").is_none());
+
+            // frames from clients must be masked.
+            assert!(read_frame(&mut &[0x81, 0x00][..]).is_err());
+
+            let mut out = Vec::new();
+            write_frame(&mut out, TEXT, b"Hello").expect("failed to write frame");
+            assert_eq!(vec![0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f], out);
+
+            let mut out = Vec::new();
+            write_frame(&mut out, TEXT, &[0u8; 256]).expect("failed to write frame");
+            assert_eq!(&[0x81, 126, 0x01, 0x00], &out[..4]);
+            assert_eq!(4 + 256, out.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{websocket, Server};
+    use abi::{ContractContext, Vm};
+    use ethcore::spec::Spec;
+    use ethereum_types::{Address, H256, U256};
+    use evm::Evm;
+    use serde_json::{self, Value};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_handle() {
+        let mut evm = new_evm();
+        evm.set_chain_id(10);
+        evm.add_balance(Address::from(0x10), 1000u64)
+            .expect("failed to add balance");
+
+        let server = Server::new(&evm);
+
+        assert_eq!(json!("parables"), result(&server, "web3_clientVersion", json!([])));
+        assert_eq!(json!("10"), result(&server, "net_version", json!([])));
+        assert_eq!(json!("0xa"), result(&server, "eth_chainId", json!([])));
+
+        assert_eq!(
+            json!("0x3e8"),
+            result(
+                &server,
+                "eth_getBalance",
+                json!(["0x0000000000000000000000000000000000000010", "latest"]),
+            )
+        );
+
+        let response = server.handle(&json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "eth_chainId"},
+            {"jsonrpc": "2.0", "id": 2, "method": "eth_unknown"},
+        ]));
+
+        assert_eq!(json!(1), response[0]["id"]);
+        assert_eq!(json!("0xa"), response[0]["result"]);
+        assert_eq!(json!(2), response[1]["id"]);
+        assert_eq!(json!(-32601), response[1]["error"]["code"]);
+
+        let response = server.handle(&json!({"jsonrpc": "2.0", "id": 3}));
+        assert_eq!(json!(-32602), response["error"]["code"]);
+
+        let missing = error(&server, "eth_getBalance", json!([]));
+        assert_eq!(json!(-32602), missing["code"]);
+
+        let bad_data = error(&server, "eth_call", json!([{"data": "0xzz"}]));
+        assert_eq!(json!(-32602), bad_data["code"]);
+    }
+
+    #[test]
+    fn test_transactions() {
+        let evm = new_evm();
+        let server = Server::new(&evm);
+
+        let sender = Address::from(0x10);
+        let contract = Address::from(0x42);
+        let reverts = Address::from(0x43);
+
+        // SSTORE(0, 1), LOG0(0, 0) and return CALLDATASIZE.
+        evm.set_code(
+            contract,
+            vec![
+                0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x60, 0x00, 0xa0, 0x36, 0x60, 0x00, 0x52,
+                0x60, 0x20, 0x60, 0x00, 0xf3,
+            ],
+        ).expect("failed to set code");
+
+        // REVERT(0, 0)
+        evm.set_code(reverts, vec![0x60, 0x00, 0x60, 0x00, 0xfd])
+            .expect("failed to set code");
+
+        let call = json!({
+            "from": "0x0000000000000000000000000000000000000010",
+            "to": "0x0000000000000000000000000000000000000042",
+            "data": "0x010203",
+        });
+
+        let output = result(&server, "eth_call", json!([call]));
+        assert_eq!(json!(format!("0x{:064x}", 3)), output);
+
+        let gas = result(&server, "eth_estimateGas", json!([call]));
+        let gas = U256::from_str(&gas.as_str().expect("not a string")[2..]).expect("bad quantity");
+        assert!(gas > U256::from(21_000));
+
+        // neither calls nor estimates change the state.
+        assert_eq!(H256::zero(), storage(&evm, contract));
+        assert_eq!(U256::zero(), evm.nonce(sender).expect("failed to get nonce"));
+        assert_eq!(0, log_count(&evm));
+
+        let revert = json!({"to": "0x0000000000000000000000000000000000000043"});
+        assert_eq!(json!(3), error(&server, "eth_call", json!([revert]))["code"]);
+        assert_eq!(json!(3), error(&server, "eth_estimateGas", json!([revert]))["code"]);
+
+        let hash = result(&server, "eth_sendTransaction", json!([call]));
+        let receipt = result(&server, "eth_getTransactionReceipt", json!([hash]));
+
+        assert_eq!(json!("0x1"), receipt["status"]);
+        assert_eq!(1, receipt["logs"].as_array().expect("no logs").len());
+        assert_eq!(H256::from(1), storage(&evm, contract));
+        assert_eq!(U256::one(), evm.nonce(sender).expect("failed to get nonce"));
+        assert_eq!(1, log_count(&evm));
+
+        let logs = result(&server, "eth_getLogs", json!([{"address": call["to"]}]));
+        assert_eq!(1, logs.as_array().expect("no logs").len());
+
+        let unknown = format!("{:?}", H256::zero());
+        assert_eq!(
+            Value::Null,
+            result(&server, "eth_getTransactionReceipt", json!([unknown]))
+        );
+
+        fn storage(evm: &Evm, address: Address) -> H256 {
+            evm.storage_at(address, H256::zero())
+                .expect("failed to get storage")
+        }
+
+        fn log_count(evm: &Evm) -> usize {
+            evm.raw_logs()
+                .expect("failed to get logs")
+                .values()
+                .map(|entries| entries.len())
+                .sum()
+        }
+    }
+
+    #[test]
+    fn test_websocket() {
+        let mut evm = new_evm();
+        evm.set_chain_id(10);
+
+        let server = Server::new(&evm);
+
+        let request = br#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId"}"#;
+
+        let mut input = Vec::new();
+        // the request, split into a text frame and a continuation.
+        input.extend(masked(0x01, &request[..10]));
+        input.extend(masked(0x80, &request[10..]));
+        input.extend(masked(0x89, b"ping"));
+        input.extend(masked(0x88, b""));
+
+        let mut output = Vec::new();
+        server
+            .serve_websocket(&mut &input[..], &mut output)
+            .expect("failed to serve websocket");
+
+        let mut output = &output[..];
+
+        let (opcode, payload) = unmasked(&mut output);
+        assert_eq!(websocket::TEXT, opcode);
+
+        let response: Value = serde_json::from_slice(&payload).expect("bad response");
+        assert_eq!(json!({"jsonrpc": "2.0", "id": 1, "result": "0xa"}), response);
+
+        assert_eq!((websocket::PONG, b"ping".to_vec()), unmasked(&mut output));
+        assert_eq!((websocket::CLOSE, Vec::new()), unmasked(&mut output));
+        assert!(output.is_empty());
+
+        /// Encode a small frame, masked with an all-zero key.
+        fn masked(first: u8, payload: &[u8]) -> Vec<u8> {
+            let mut frame = vec![first, 0x80 | payload.len() as u8, 0, 0, 0, 0];
+            frame.extend(payload);
+            frame
+        }
+
+        /// Decode a small, unmasked frame written by the server.
+        fn unmasked(output: &mut &[u8]) -> (u8, Vec<u8>) {
+            assert_eq!(0x80, output[0] & 0x80, "expected final frame");
+            let len = output[1] as usize;
+            assert!(len < 126, "expected small, unmasked frame");
+
+            let frame = (output[0] & 0x0f, output[2..2 + len].to_vec());
+            *output = &output[2 + len..];
+            frame
+        }
+    }
+
+    /// Perform a request, expecting it to succeed.
+    fn result(server: &Server, method: &str, params: Value) -> Value {
+        let response = request(server, method, params);
+
+        match response.get("result") {
+            Some(result) => result.clone(),
+            None => panic!("request failed: {}", response),
+        }
+    }
+
+    /// Perform a request, expecting it to fail.
+    fn error(server: &Server, method: &str, params: Value) -> Value {
+        let response = request(server, method, params);
+
+        match response.get("error") {
+            Some(error) => error.clone(),
+            None => panic!("request succeeded: {}", response),
+        }
+    }
+
+    fn request(server: &Server, method: &str, params: Value) -> Value {
+        server.handle(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+    }
+
+    /// Set up a new virtual machine.
+    fn new_evm() -> Evm {
+        let context = ContractContext {
+            source_list: None,
+            sources: HashMap::new(),
+            events: Vec::new(),
+        };
+
+        Evm::new(&Spec::new_null(), context).expect("failed to set up evm")
+    }
+}
@@ -103,6 +103,11 @@ impl SourceMap {
         Ok(SourceMap { mappings })
     }
 
+    /// Access all mappings, in instruction order.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
     /// Find the mapping for a given program counter.
     pub fn find_mapping(&self, pc: usize) -> Option<&Mapping> {
         self.mappings.get(pc)
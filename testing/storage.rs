@@ -0,0 +1,766 @@
+//! Typed reads of contract storage, using the storage layout emitted by the compiler.
+//!
+//! This permits asserting on the state of a contract without it exposing a getter, and without
+//! calculating slots by hand:
+//!
+//! ```rust,ignore
+//! let owner = contract.storage().field("owner")?;
+//! let balance = contract.storage().mapping("balances", Token::Address(account))?;
+//! ```
+//!
+//! The layout is only available if contracts are compiled with `#[parables(storage_layout)]`.
+
+use abi;
+use crypto::keccak256;
+use ethabi::{self, Token};
+use ethereum_types::{Address, H256, U256};
+use failure::Error;
+use serde_json;
+use std::collections::HashMap;
+
+/// The storage layout of a contract, as emitted by the compiler.
+#[derive(Debug, Deserialize)]
+pub struct StorageLayout {
+    storage: Vec<StorageEntry>,
+    /// Types used in the layout, by identifier.
+    ///
+    /// This is `null` for contracts without any state variables.
+    #[serde(default)]
+    types: Option<HashMap<String, StorageType>>,
+}
+
+impl StorageLayout {
+    /// Parse a storage layout from JSON.
+    pub fn parse(input: &str) -> Result<StorageLayout, Error> {
+        serde_json::from_str(input)
+            .map_err(|e| format_err!("failed to parse storage layout: {}", e))
+    }
+
+    /// Check that this layout, of an upgraded contract, preserves the state variables of the
+    /// `previous` layout.
+    ///
+    /// Every state variable of the previous layout must be stored at the same slot and offset with
+    /// the same type, and state variables which were added must not overlap with storage used by
+    /// the previous layout. Variables are matched by name, so renaming one counts as removing it.
+    pub fn check_upgrade(&self, previous: &StorageLayout) -> Result<(), Error> {
+        let mut problems = Vec::new();
+
+        for old in &previous.storage {
+            let new = match self.storage.iter().find(|e| e.label == old.label) {
+                Some(new) => new,
+                None => {
+                    problems.push(format!("`{}` was removed", old.label));
+                    continue;
+                }
+            };
+
+            if new.slot != old.slot || new.offset != old.offset {
+                problems.push(format!(
+                    "`{}` moved from slot {} offset {} to slot {} offset {}",
+                    old.label, old.slot, old.offset, new.slot, new.offset
+                ));
+            }
+
+            let old_ty = &previous.ty(&old.ty)?.label;
+            let new_ty = &self.ty(&new.ty)?.label;
+
+            if old_ty != new_ty {
+                problems.push(format!(
+                    "`{}` changed type from `{}` to `{}`",
+                    old.label, old_ty, new_ty
+                ));
+            }
+        }
+
+        for new in &self.storage {
+            if previous.storage.iter().any(|e| e.label == new.label) {
+                continue;
+            }
+
+            let range = self.range(new)?;
+
+            for old in &previous.storage {
+                let old_range = previous.range(old)?;
+
+                if range.0 < old_range.1 && old_range.0 < range.1 {
+                    problems.push(format!(
+                        "`{}` overlaps with storage used by `{}`",
+                        new.label, old.label
+                    ));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            let problems = problems
+                .iter()
+                .map(|p| format!("  {}", p))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            bail!("storage layout is not preserved:\n{}", problems);
+        }
+
+        Ok(())
+    }
+
+    /// The range of bytes used by the given state variable, counting every slot as 32 bytes.
+    fn range(&self, entry: &StorageEntry) -> Result<(U256, U256), Error> {
+        let ty = self.ty(&entry.ty)?;
+
+        let size = ty
+            .number_of_bytes
+            .parse::<u64>()
+            .map_err(|e| format_err!("bad size of `{}`: {}", ty.label, e))?;
+
+        let start = parse_slot(&entry.slot)? * U256::from(32) + U256::from(entry.offset);
+        Ok((start, start + U256::from(size)))
+    }
+
+    /// Find the state variable with the given name.
+    fn entry(&self, name: &str) -> Result<&StorageEntry, Error> {
+        self.storage
+            .iter()
+            .find(|e| e.label == name)
+            .ok_or_else(|| format_err!("no state variable named `{}`", name))
+    }
+
+    /// Find the type with the given identifier.
+    fn ty(&self, id: &str) -> Result<&StorageType, Error> {
+        self.types
+            .as_ref()
+            .and_then(|types| types.get(id))
+            .ok_or_else(|| format_err!("missing type `{}` in storage layout", id))
+    }
+
+    /// Locate the value of the state variable with the given name, indexing mappings with the
+    /// given keys.
+    fn locate(&self, name: &str, keys: &[Token]) -> Result<Location, Error> {
+        let entry = self.entry(name)?;
+
+        let mut location = Location {
+            slot: parse_slot(&entry.slot)?,
+            offset: entry.offset,
+            ty: entry.ty.to_string(),
+        };
+
+        for key in keys {
+            let ty = self.ty(&location.ty)?;
+
+            let value = match (ty.encoding.as_str(), ty.value.as_ref()) {
+                ("mapping", Some(value)) => value,
+                _ => bail!("`{}` is not a mapping, but `{}`", name, ty.label),
+            };
+
+            location = Location {
+                slot: mapping_slot(location.slot, key)?,
+                offset: 0,
+                ty: value.to_string(),
+            };
+        }
+
+        Ok(location)
+    }
+
+    /// Find the state variables stored in the given slot, indexing mappings with the given keys,
+    /// which are outermost first.
+    ///
+    /// Variables packed into the same slot are only included if their bytes differ between the
+    /// `previous` word and the written `value`, unless none of them do.
+    pub fn variables(
+        &self,
+        slot: U256,
+        keys: &[U256],
+        previous: Option<U256>,
+        value: U256,
+    ) -> Vec<String> {
+        let mut all = Vec::new();
+        let mut changed = Vec::new();
+
+        for entry in &self.storage {
+            let (start, ty) = match (parse_slot(&entry.slot), self.ty(&entry.ty)) {
+                (Ok(start), Ok(ty)) => (start, ty),
+                _ => continue,
+            };
+
+            if !keys.is_empty() {
+                if start == slot {
+                    all.extend(self.index(&entry.label, &entry.ty, keys));
+                }
+
+                continue;
+            }
+
+            let size = ty.number_of_bytes.parse::<usize>().unwrap_or(32);
+
+            // values larger than a word occupy consecutive slots.
+            let slots = U256::from(((size + 31) / 32).max(1));
+
+            if slot < start || slot >= start + slots {
+                continue;
+            }
+
+            let modified = size < 32 && match previous {
+                Some(previous) => {
+                    let previous = H256::from(u256_to_bytes(previous));
+                    let value = H256::from(u256_to_bytes(value));
+                    extract(&previous, entry.offset, size) != extract(&value, entry.offset, size)
+                }
+                None => true,
+            };
+
+            if size >= 32 || modified {
+                changed.push(entry.label.to_string());
+            }
+
+            all.push(entry.label.to_string());
+        }
+
+        if keys.is_empty() && !changed.is_empty() {
+            return changed;
+        }
+
+        all
+    }
+
+    /// Format an index into the mapping with the given label and type, like `balances[0x..]`.
+    fn index(&self, label: &str, ty: &str, keys: &[U256]) -> Option<String> {
+        let mut label = label.to_string();
+        let mut ty = ty;
+
+        for key in keys {
+            let mapping = self.ty(ty).ok()?;
+
+            if mapping.encoding != "mapping" {
+                return None;
+            }
+
+            let key_label = mapping
+                .key
+                .as_ref()
+                .and_then(|key| self.ty(key).ok())
+                .map(|key| key.label.as_str());
+
+            label = format!("{}[{}]", label, format_key(key_label, *key));
+            ty = mapping.value.as_ref()?;
+        }
+
+        Some(label)
+    }
+
+    /// Decode the value at the given location.
+    fn decode<R>(&self, read: &R, location: &Location) -> Result<Token, Error>
+    where
+        R: Fn(U256) -> Result<H256, Error>,
+    {
+        let ty = self.ty(&location.ty)?;
+
+        match ty.encoding.as_str() {
+            "inplace" => {
+                if ty.members.is_some() {
+                    bail!("decoding structs is not supported: `{}`", ty.label);
+                }
+
+                if let Some(ref base) = ty.base {
+                    let length = fixed_array_length(&ty.label)?;
+                    return self.decode_array(read, location.slot, base, length);
+                }
+
+                let size = ty.size()?;
+                let word = read(location.slot)?;
+                decode_value(&ty.label, extract(&word, location.offset, size))
+            }
+            "bytes" => {
+                let data = read_bytes(read, location.slot)?;
+
+                if ty.label == "string" {
+                    let string = String::from_utf8(data)
+                        .map_err(|e| format_err!("bad utf-8 in string: {}", e))?;
+                    return Ok(Token::String(string));
+                }
+
+                Ok(Token::Bytes(data))
+            }
+            "dynamic_array" => {
+                let base = ty
+                    .base
+                    .as_ref()
+                    .ok_or_else(|| format_err!("missing base type of `{}`", ty.label))?;
+
+                let length = word_to_u256(&read(location.slot)?);
+
+                if length > U256::from(u32::max_value()) {
+                    bail!("unreasonable length of `{}`: {}", ty.label, length);
+                }
+
+                let slot = U256::from(&keccak256(&u256_to_bytes(location.slot))[..]);
+
+                match self.decode_array(read, slot, base, length.low_u64() as usize)? {
+                    Token::FixedArray(values) => Ok(Token::Array(values)),
+                    other => Ok(other),
+                }
+            }
+            "mapping" => bail!("`{}` is a mapping, and must be indexed by a key", ty.label),
+            encoding => bail!("unsupported storage encoding `{}`", encoding),
+        }
+    }
+
+    /// Decode an array of value types stored from the given slot.
+    fn decode_array<R>(
+        &self,
+        read: &R,
+        slot: U256,
+        base: &str,
+        length: usize,
+    ) -> Result<Token, Error>
+    where
+        R: Fn(U256) -> Result<H256, Error>,
+    {
+        let base_ty = self.ty(base)?;
+
+        if base_ty.encoding != "inplace" || base_ty.members.is_some() || base_ty.base.is_some() {
+            bail!("decoding arrays of `{}` is not supported", base_ty.label);
+        }
+
+        let size = base_ty.size()?;
+
+        // elements are packed into slots as long as they fit.
+        let per_slot = 32 / size;
+        let mut values = Vec::new();
+
+        for index in 0..length {
+            let slot = slot + U256::from(index / per_slot);
+            let offset = (index % per_slot) * size;
+            let word = read(slot)?;
+            values.push(decode_value(&base_ty.label, extract(&word, offset, size))?);
+        }
+
+        Ok(Token::FixedArray(values))
+    }
+}
+
+/// A state variable in the storage layout.
+#[derive(Debug, Deserialize)]
+struct StorageEntry {
+    label: String,
+    /// Offset in bytes into the slot, counting from the least significant byte.
+    offset: usize,
+    /// The slot, as a decimal number.
+    slot: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+/// A type in the storage layout.
+#[derive(Debug, Deserialize)]
+struct StorageType {
+    /// One of `inplace`, `mapping`, `dynamic_array`, or `bytes`.
+    encoding: String,
+    label: String,
+    #[serde(rename = "numberOfBytes")]
+    number_of_bytes: String,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    base: Option<String>,
+    #[serde(default)]
+    members: Option<Vec<StorageEntry>>,
+}
+
+impl StorageType {
+    /// The size of the type in bytes.
+    fn size(&self) -> Result<usize, Error> {
+        let size = self
+            .number_of_bytes
+            .parse::<usize>()
+            .map_err(|e| format_err!("bad size of `{}`: {}", self.label, e))?;
+
+        if size == 0 || size > 32 {
+            bail!("unsupported size of `{}`: {}", self.label, size);
+        }
+
+        Ok(size)
+    }
+}
+
+/// The location of a value in storage.
+#[derive(Debug)]
+struct Location {
+    slot: U256,
+    offset: usize,
+    ty: String,
+}
+
+/// A write to storage performed during a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageWrite {
+    /// The contract whose storage was written.
+    pub address: Address,
+    /// The slot that was written.
+    pub slot: U256,
+    /// The value written to the slot.
+    pub value: U256,
+    /// The state variables stored in the slot, like `owner` or `balances[0x..]`.
+    ///
+    /// This is empty if the contract wasn't compiled with a storage layout, or if the slot
+    /// couldn't be mapped back to a state variable.
+    pub variables: Vec<String>,
+}
+
+/// Typed access to the storage of a deployed contract.
+pub struct Storage<'a, VM: 'a> {
+    vm: &'a VM,
+    address: Address,
+    layout: &'static str,
+}
+
+impl<'a, VM> Storage<'a, VM>
+where
+    VM: abi::Vm,
+{
+    /// Access the storage of the contract at the given address, with the given layout as JSON.
+    pub fn new(vm: &'a VM, address: Address, layout: &'static str) -> Self {
+        Self {
+            vm,
+            address,
+            layout,
+        }
+    }
+
+    /// Read the state variable with the given name.
+    ///
+    /// Value types, strings, bytes, and arrays of value types are supported.
+    pub fn field(&self, name: &str) -> Result<Token, Error> {
+        self.read(name, &[])
+    }
+
+    /// Read the value stored under the given key of the mapping with the given name.
+    pub fn mapping(&self, name: &str, key: Token) -> Result<Token, Error> {
+        self.read(name, &[key])
+    }
+
+    /// Read the value stored under the given keys of the nested mapping with the given name.
+    pub fn nested_mapping(&self, name: &str, keys: &[Token]) -> Result<Token, Error> {
+        self.read(name, keys)
+    }
+
+    /// Calculate the slot of the state variable with the given name.
+    pub fn slot(&self, name: &str) -> Result<U256, Error> {
+        let layout = StorageLayout::parse(self.layout)?;
+        Ok(layout.locate(name, &[])?.slot)
+    }
+
+    fn read(&self, name: &str, keys: &[Token]) -> Result<Token, Error> {
+        let layout = StorageLayout::parse(self.layout)?;
+        let location = layout.locate(name, keys)?;
+
+        let read = |slot: U256| {
+            self.vm
+                .storage_at(self.address, H256::from(u256_to_bytes(slot)))
+        };
+
+        layout
+            .decode(&read, &location)
+            .map_err(|e| format_err!("failed to read `{}`: {}", name, e))
+    }
+}
+
+/// Parse the length of a fixed array from its label, like `uint256[3]`.
+fn fixed_array_length(label: &str) -> Result<usize, Error> {
+    let length = label.rfind('[').and_then(|start| {
+        label[start + 1..]
+            .trim_right_matches(']')
+            .parse::<usize>()
+            .ok()
+    });
+
+    length.ok_or_else(|| format_err!("bad fixed array `{}`", label))
+}
+
+/// Parse a decimal slot.
+fn parse_slot(slot: &str) -> Result<U256, Error> {
+    U256::from_dec_str(slot).map_err(|e| format_err!("bad slot `{}`: {:?}", slot, e))
+}
+
+/// Calculate the slot of the value stored under the given key of a mapping.
+fn mapping_slot(slot: U256, key: &Token) -> Result<U256, Error> {
+    let mut data = match *key {
+        // dynamic keys are hashed without padding.
+        Token::String(ref s) => s.as_bytes().to_vec(),
+        Token::Bytes(ref b) => b.to_vec(),
+        Token::Array(_) | Token::FixedArray(_) => bail!("arrays can't be mapping keys"),
+        ref key => ethabi::encode(&[key.clone()]),
+    };
+
+    data.extend(u256_to_bytes(slot).iter());
+    Ok(U256::from(&keccak256(&data)[..]))
+}
+
+/// Format a mapping key according to the label of its type.
+fn format_key(label: Option<&str>, key: U256) -> String {
+    let label = label.unwrap_or("");
+
+    if label == "bool" {
+        return (!key.is_zero()).to_string();
+    }
+
+    if label.starts_with("address") || label.starts_with("contract ") {
+        return format!("{:?}", Address::from(H256::from(key)));
+    }
+
+    if label.starts_with("uint") || label.starts_with("enum ") {
+        return key.to_string();
+    }
+
+    format!("{:?}", H256::from(key))
+}
+
+/// Extract a value of the given size at the given offset from a storage word.
+fn extract(word: &H256, offset: usize, size: usize) -> &[u8] {
+    let end = 32 - offset.min(32);
+    let start = end.saturating_sub(size);
+    &word[start..end]
+}
+
+/// Decode a value type with the given label.
+fn decode_value(label: &str, bytes: &[u8]) -> Result<Token, Error> {
+    if label == "bool" {
+        return Ok(Token::Bool(bytes.iter().any(|b| *b != 0)));
+    }
+
+    if label.starts_with("address") || label.starts_with("contract ") {
+        let start = bytes.len().saturating_sub(20);
+        return Ok(Token::Address(Address::from(&bytes[start..])));
+    }
+
+    if label.starts_with("uint") || label.starts_with("enum ") {
+        return Ok(Token::Uint(U256::from(bytes)));
+    }
+
+    if label.starts_with("int") {
+        let mut word = [0u8; 32];
+
+        // sign extend the value.
+        if bytes.first().map(|b| b & 0x80 != 0).unwrap_or(false) {
+            word = [0xffu8; 32];
+        }
+
+        word[32 - bytes.len()..].copy_from_slice(bytes);
+        return Ok(Token::Int(U256::from(&word[..])));
+    }
+
+    if label.starts_with("bytes") {
+        return Ok(Token::FixedBytes(bytes.to_vec()));
+    }
+
+    bail!("decoding `{}` is not supported", label)
+}
+
+/// Read `bytes` or a `string` stored at the given slot.
+fn read_bytes<R>(read: &R, slot: U256) -> Result<Vec<u8>, Error>
+where
+    R: Fn(U256) -> Result<H256, Error>,
+{
+    let word = read(slot)?;
+
+    // short values are stored in the same slot, with twice their length in the lowest byte.
+    if word[31] & 1 == 0 {
+        let length = (word[31] / 2) as usize;
+
+        if length > 31 {
+            bail!("bad length of short bytes: {}", length);
+        }
+
+        return Ok(word[..length].to_vec());
+    }
+
+    // long values store twice their length plus one, and their data from the hash of the slot.
+    let length = word_to_u256(&word) / U256::from(2);
+
+    if length > U256::from(u32::max_value()) {
+        bail!("unreasonable length of bytes: {}", length);
+    }
+
+    let length = length.low_u64() as usize;
+    let mut data = Vec::with_capacity(length);
+    let mut slot = U256::from(&keccak256(&u256_to_bytes(slot))[..]);
+
+    while data.len() < length {
+        let word = read(slot)?;
+        let remaining = (length - data.len()).min(32);
+        data.extend(word[..remaining].iter());
+        slot = slot + U256::one();
+    }
+
+    Ok(data)
+}
+
+fn word_to_u256(word: &H256) -> U256 {
+    U256::from(&word[..])
+}
+
+fn u256_to_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mapping_slot, u256_to_bytes, StorageLayout};
+    use crypto::keccak256;
+    use ethabi::Token;
+    use ethereum_types::{Address, H256, U256};
+    use failure::Error;
+    use std::collections::HashMap;
+
+    const LAYOUT: &'static str = r#"{
+        "storage": [
+            {"label": "owner", "offset": 0, "slot": "0", "type": "t_address"},
+            {"label": "paused", "offset": 20, "slot": "0", "type": "t_bool"},
+            {"label": "name", "offset": 0, "slot": "1", "type": "t_string_storage"},
+            {"label": "balances", "offset": 0, "slot": "2", "type": "t_mapping(t_address,t_uint256)"}
+        ],
+        "types": {
+            "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+            "t_bool": {"encoding": "inplace", "label": "bool", "numberOfBytes": "1"},
+            "t_string_storage": {"encoding": "bytes", "label": "string", "numberOfBytes": "32"},
+            "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+            "t_mapping(t_address,t_uint256)": {
+                "encoding": "mapping",
+                "label": "mapping(address => uint256)",
+                "numberOfBytes": "32",
+                "key": "t_address",
+                "value": "t_uint256"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_storage_layout() {
+        let layout = StorageLayout::parse(LAYOUT).expect("layout to parse");
+
+        let owner = Address::from(0x42);
+        let mut storage = HashMap::new();
+
+        // owner and paused are packed into the same slot.
+        let mut word = [0u8; 32];
+        word[11] = 1;
+        word[12..].copy_from_slice(&owner[..]);
+        storage.insert(U256::from(0), H256::from(word));
+
+        let mut word = [0u8; 32];
+        word[..5].copy_from_slice(b"hello");
+        word[31] = 10;
+        storage.insert(U256::from(1), H256::from(word));
+
+        let balance_slot = mapping_slot(U256::from(2), &Token::Address(owner)).unwrap();
+        storage.insert(balance_slot, H256::from(u256_to_bytes(U256::from(1000))));
+
+        let read = |slot: U256| -> Result<H256, Error> {
+            Ok(storage.get(&slot).cloned().unwrap_or_else(H256::zero))
+        };
+
+        let field = |name: &str, keys: &[Token]| {
+            let location = layout.locate(name, keys).unwrap();
+            layout.decode(&read, &location).unwrap()
+        };
+
+        assert_eq!(Token::Address(owner), field("owner", &[]));
+        assert_eq!(Token::Bool(true), field("paused", &[]));
+        assert_eq!(Token::String("hello".to_string()), field("name", &[]));
+        assert_eq!(
+            Token::Uint(1000.into()),
+            field("balances", &[Token::Address(owner)])
+        );
+        assert_eq!(
+            Token::Uint(0.into()),
+            field("balances", &[Token::Address(Address::from(1))])
+        );
+
+        assert!(layout.locate("missing", &[]).is_err());
+        assert!(layout.locate("owner", &[Token::Uint(1.into())]).is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        let layout = StorageLayout::parse(LAYOUT).expect("layout to parse");
+
+        let owner = Address::from(0x42);
+        let mut paused = [0u8; 32];
+        paused[11] = 1;
+        let paused = U256::from(&paused[..]);
+        let owner_word = U256::from(&H256::from(owner)[..]);
+
+        assert_eq!(
+            vec!["paused".to_string()],
+            layout.variables(0.into(), &[], Some(owner_word), owner_word | paused)
+        );
+        assert_eq!(
+            vec!["owner".to_string(), "paused".to_string()],
+            layout.variables(0.into(), &[], None, owner_word)
+        );
+        assert_eq!(
+            vec!["owner".to_string(), "paused".to_string()],
+            layout.variables(0.into(), &[], Some(paused), paused)
+        );
+        assert_eq!(
+            vec!["name".to_string()],
+            layout.variables(1.into(), &[], None, 0.into())
+        );
+        assert_eq!(
+            vec![format!("balances[{:?}]", owner)],
+            layout.variables(2.into(), &[owner_word], None, 1000.into())
+        );
+        assert!(layout.variables(1.into(), &[owner_word], None, 0.into()).is_empty());
+        assert!(layout.variables(3.into(), &[], None, 0.into()).is_empty());
+    }
+
+    #[test]
+    fn test_mapping_slot() {
+        let mut data = [0u8; 64];
+        data[31] = 1;
+        data[63] = 2;
+
+        assert_eq!(
+            U256::from(&keccak256(&data)[..]),
+            mapping_slot(U256::from(2), &Token::Uint(1.into())).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_upgrade() {
+        let previous = StorageLayout::parse(LAYOUT).expect("layout to parse");
+
+        let upgraded = StorageLayout::parse(&LAYOUT.replace(
+            r#"{"label": "balances""#,
+            r#"{"label": "fee", "offset": 0, "slot": "3", "type": "t_uint256"},
+            {"label": "balances""#,
+        )).expect("layout to parse");
+
+        assert!(upgraded.check_upgrade(&previous).is_ok());
+
+        let broken = StorageLayout::parse(&LAYOUT.replace(
+            r#"{"label": "paused", "offset": 20, "slot": "0", "type": "t_bool"},"#,
+            r#"{"label": "fee", "offset": 20, "slot": "0", "type": "t_bool"},"#,
+        ).replace(
+            r#"{"label": "name", "offset": 0, "slot": "1", "type": "t_string_storage"}"#,
+            r#"{"label": "name", "offset": 0, "slot": "3", "type": "t_uint256"}"#,
+        )).expect("layout to parse");
+
+        let error = broken.check_upgrade(&previous).unwrap_err();
+
+        assert_eq!(
+            vec![
+                "storage layout is not preserved:",
+                "  `paused` was removed",
+                "  `name` moved from slot 1 offset 0 to slot 3 offset 0",
+                "  `name` changed type from `string` to `uint256`",
+                "  `fee` overlaps with storage used by `paused`",
+            ],
+            error.to_string().lines().collect::<Vec<_>>()
+        );
+    }
+}
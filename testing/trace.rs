@@ -1,20 +1,28 @@
 use ast;
+use cheatcodes;
+use console;
+use ethabi;
+use ethcore::log_entry::LogEntry;
 use ethcore::trace;
-use ethereum_types::{H160, U256};
+use ethereum_types::{Address, H160, U256};
+use evm;
 use failure::Error;
+use gas;
 use linker;
 use matcher;
 use parity_bytes::Bytes;
 use parity_evm;
 use parity_vm;
+#[cfg(feature = "test-runner")]
+use serde_json;
 use source_map;
 use std::cmp;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
-use std::fs::File;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use utils;
+use storage;
+use wei;
 
 /// Last known frame.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -29,6 +37,69 @@ impl Default for FrameInfo {
     }
 }
 
+/// How to format `uint256` values when displaying errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    /// Format all values as raw integers.
+    Raw,
+    /// Format all values in ether, with the raw value in parenthesis.
+    Ether,
+    /// Format values which are large enough to likely be wei amounts in ether, and everything
+    /// else as raw integers.
+    Auto,
+}
+
+impl Default for ValueFormat {
+    fn default() -> Self {
+        ValueFormat::Raw
+    }
+}
+
+impl ValueFormat {
+    /// Smallest value which is considered likely to be a wei amount, 0.001 ether.
+    const AUTO_LIMIT: u64 = 1_000_000_000_000_000;
+
+    /// Display the given value according to the format.
+    pub fn display<'a>(self, value: &'a ast::Value) -> DisplayValue<'a> {
+        DisplayValue {
+            format: self,
+            value,
+        }
+    }
+}
+
+/// Helper to display a value with a given format.
+pub struct DisplayValue<'a> {
+    format: ValueFormat,
+    value: &'a ast::Value,
+}
+
+impl<'a> fmt::Display for DisplayValue<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let value = match *self.value {
+            ast::Value::Uint256(ref value) => value,
+            ref other => return other.fmt(fmt),
+        };
+
+        let ether = match self.format {
+            ValueFormat::Raw => false,
+            ValueFormat::Ether => true,
+            ValueFormat::Auto => *value >= U256::from(ValueFormat::AUTO_LIMIT),
+        };
+
+        if ether {
+            write!(
+                fmt,
+                "uint256({} ether ({}))",
+                wei::format_ether(*value),
+                value
+            )
+        } else {
+            self.value.fmt(fmt)
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ErrorKind {
     Error(parity_vm::Error),
@@ -45,15 +116,68 @@ impl ErrorKind {
     }
 }
 
+/// Selector of `Error(string)`, which is what `revert("reason")` reverts with.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Decode the reason from data reverted with by `revert("reason")` or `require(.., "reason")`.
+pub fn revert_reason(output: &[u8]) -> Option<String> {
+    if output.len() < 4 || output[..4] != ERROR_SELECTOR[..] {
+        return None;
+    }
+
+    let decoded = ethabi::decode(&[ethabi::ParamType::String], &output[4..]).ok()?;
+
+    match decoded.into_iter().next()? {
+        ethabi::Token::String(reason) => Some(reason),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Errors {
     errors: Vec<ErrorInfo>,
+    /// Data the transaction reverted with.
+    output: Vec<u8>,
 }
 
 impl Errors {
     /// Create a new root error info.
     pub fn new(errors: Vec<ErrorInfo>) -> Self {
-        Self { errors }
+        Self {
+            errors,
+            output: Vec::new(),
+        }
+    }
+
+    /// Set the data the transaction reverted with.
+    pub fn with_output(self, output: Vec<u8>) -> Self {
+        Self { output, ..self }
+    }
+
+    /// Access the data the transaction reverted with.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Decode the reason the transaction reverted with, if it reverted with a reason.
+    pub fn reason(&self) -> Option<String> {
+        revert_reason(&self.output)
+    }
+
+    /// Access the selector of the error the transaction reverted with, if any.
+    pub fn error_selector(&self) -> Option<[u8; 4]> {
+        if self.output.len() < 4 {
+            return None;
+        }
+
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&self.output[..4]);
+        Some(selector)
+    }
+
+    /// Check if the transaction reverted with data matching the given matcher.
+    pub fn is_reverted_with(&self, matcher: impl matcher::RevertMatcher) -> bool {
+        self.is_reverted() && matcher.matches_revert(&self.output)
     }
 
     /// Check if kind is reverted.
@@ -88,6 +212,10 @@ impl Errors {
 
 impl fmt::Display for Errors {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(reason) = self.reason() {
+            writeln!(fmt, "Reason: {:?}", reason)?;
+        }
+
         for (i, e) in self.errors.iter().rev().enumerate() {
             write!(fmt, "Frame #{}: {}", i, e)?;
         }
@@ -102,6 +230,10 @@ pub struct ErrorInfo {
     pub line_info: Option<LineInfo>,
     /// Local variables and their corresponding values at the time of error.
     pub variables: BTreeMap<ast::Expr, ast::Value>,
+    /// Output from `console.log` up until the time of error.
+    pub console: Vec<String>,
+    /// How to format values of variables.
+    pub value_format: ValueFormat,
 }
 
 impl ErrorInfo {
@@ -126,13 +258,31 @@ impl fmt::Display for ErrorInfo {
             },
         }
 
+        if let Some(ref line_info) = self.line_info {
+            if !line_info.docs.is_empty() {
+                writeln!(fmt, "Documentation:")?;
+
+                for line in &line_info.docs {
+                    writeln!(fmt, "  {}", line)?;
+                }
+            }
+        }
+
         if !self.variables.is_empty() {
             writeln!(fmt, "Expressions:")?;
 
             let mut it = self.variables.iter();
 
             while let Some((var, value)) = it.next() {
-                writeln!(fmt, "  {} = {}", var, value)?;
+                writeln!(fmt, "  {} = {}", var, self.value_format.display(value))?;
+            }
+        }
+
+        if !self.console.is_empty() {
+            writeln!(fmt, "Console:")?;
+
+            for line in &self.console {
+                writeln!(fmt, "  {}", line)?;
             }
         }
 
@@ -152,6 +302,8 @@ pub struct LineInfo {
     path: PathBuf,
     object: Option<linker::Object>,
     function: Option<String>,
+    /// Natspec documentation of the function.
+    docs: Vec<String>,
     line: usize,
     lines: Vec<String>,
 }
@@ -174,12 +326,35 @@ impl fmt::Display for LineInfo {
 pub struct Shared {
     // Call stack.
     call_stack: Vec<CallFrame>,
+    /// Output from `console.log` calls.
+    pub console: Vec<String>,
+    /// Cheatcodes issued during the transaction.
+    pub cheatcodes: Vec<cheatcodes::Cheatcode>,
+    /// How to format values in errors.
+    value_format: ValueFormat,
+    /// Gas used by the transaction, by function, if it is being profiled.
+    pub gas_profile: Option<gas::GasProfile>,
+    /// Gas used by the transaction, by file index and source offset, if it is being collected.
+    pub line_gas: Option<HashMap<(u32, u32), u64>>,
+    /// Writes to storage performed by calls which haven't failed.
+    pub storage_writes: Vec<storage::StorageWrite>,
+    /// All calls performed, in the order they were made.
+    pub calls: Vec<CallTrace>,
 }
 
 impl Shared {
     /// Create a new instance of shared state.
-    pub fn new() -> Self {
-        Self { call_stack: vec![] }
+    pub fn new(value_format: ValueFormat) -> Self {
+        Self {
+            call_stack: vec![],
+            console: vec![],
+            cheatcodes: vec![],
+            value_format,
+            gas_profile: None,
+            line_gas: None,
+            storage_writes: vec![],
+            calls: vec![],
+        }
     }
 
     // Decode the current statement according to its AST.
@@ -187,8 +362,6 @@ impl Shared {
     // `pc` - the current program counter.
     //
     // This will try to decode any variable assignments.
-    //
-    // NOTE: AST searching is currently not indexed correctly making it rather slow.
     fn decode_instruction(
         &mut self,
         pc: usize,
@@ -225,8 +398,16 @@ impl Shared {
             None => return Ok(()),
         };
 
+        // functions are indexed by the source, but fall back to searching the AST in case it
+        // wasn't registered when the source was constructed.
+        let function = frame
+            .source
+            .as_ref()
+            .and_then(|source| source.function(pc))
+            .or_else(|| registry.find_function(&current));
+
         // TODO: can we use current.is_jump_to_function()?
-        if let Some(function) = registry.find_function(&current) {
+        if let Some(function) = function {
             // are we in a new function?
             let replace = match last_function.as_ref() {
                 Some(last_function) => function.src != last_function.src,
@@ -276,11 +457,26 @@ impl Shared {
                 frame.variables.clear();
             }
             ref c => {
-                let mut ctx = ast::Context::new(stack, memory, &frame.call_data);
-
                 // Register an expression and the value it evaluated to.
                 if let Some((var, ty)) = registry.decode_ast(c) {
-                    let value = registry.decode_type(ty).value(&mut ctx)?;
+                    let ty = registry.decode_type(ty);
+
+                    let value = {
+                        let mut ctx = ast::Context::new(stack, memory, &frame.call_data)
+                            .with_storage(&frame.storage);
+                        ty.clone().value(&mut ctx)?
+                    };
+
+                    let resolved =
+                        frame
+                            .mappings
+                            .resolve(&mut frame.variables, &frame.storage, &var, &ty);
+
+                    let (var, value) = match resolved {
+                        Some((resolved, stored)) => (resolved, stored.unwrap_or(value)),
+                        None => (var, value),
+                    };
+
                     trace!("Set: {} = {}", var, value);
                     frame.variables.insert(var, value);
                 }
@@ -290,6 +486,102 @@ impl Shared {
         Ok(())
     }
 
+    /// Record a storage slot which has been read or written by the current call.
+    fn record_storage(&mut self, slot: U256, value: U256) {
+        if let Some(frame) = self.call_stack.last_mut() {
+            frame.storage.insert(slot, value);
+        }
+    }
+
+    /// Record the start of a call, returning its index in `calls`.
+    fn begin_call(&mut self, kind: CallKind, params: &parity_vm::ActionParams) -> usize {
+        self.calls.push(CallTrace {
+            depth: self.call_stack.len(),
+            kind,
+            address: params.address,
+            label: None,
+            input: params.data.clone().unwrap_or_else(Bytes::default),
+            value: params.value.value(),
+            output: Bytes::default(),
+            success: true,
+        });
+
+        self.calls.len() - 1
+    }
+
+    /// Record the end of the call described by the given frame.
+    fn end_call(&mut self, frame: &CallFrame, output: &[u8], success: bool) {
+        if let Some(call) = self.calls.get_mut(frame.trace_index) {
+            if frame.source.is_some() {
+                call.label = Some(frame.label());
+            }
+
+            call.output = output.to_vec();
+            call.success = success;
+        }
+    }
+
+    /// Record a write to storage by the current call, and the state variables it belongs to.
+    ///
+    /// Must be called before the write is recorded with `record_storage`, since the previous
+    /// value is used to tell apart variables which are packed into the same slot.
+    fn record_storage_write(&mut self, slot: U256, value: U256) {
+        let frame = match self.call_stack.last() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let variables = match frame.storage_layout {
+            Some(ref layout) => {
+                let (base, keys) = frame.mappings.keys(slot);
+                let previous = frame.storage.get(&slot).cloned();
+                layout.variables(base, &keys, previous, value)
+            }
+            None => vec![],
+        };
+
+        self.storage_writes.push(storage::StorageWrite {
+            address: frame.address,
+            slot,
+            value,
+            variables,
+        });
+    }
+
+    /// Attribute gas used by the instruction at `pc` to the functions currently being called,
+    /// and to the source it was compiled from.
+    fn record_gas(&mut self, pc: usize, gas: U256) {
+        if let Some(ref mut gas_profile) = self.gas_profile {
+            let stack = self
+                .call_stack
+                .iter()
+                .map(CallFrame::label)
+                .collect::<Vec<_>>();
+
+            gas_profile.add(&stack, gas.low_u64());
+        }
+
+        if let Some(ref mut line_gas) = self.line_gas {
+            let m = self
+                .call_stack
+                .last()
+                .and_then(|frame| mapping(frame.source.as_ref(), pc));
+
+            if let Some(m) = m {
+                if let Some(file_index) = m.file_index {
+                    *line_gas.entry((file_index, m.start)).or_insert(0) += gas.low_u64();
+                }
+            }
+        }
+    }
+
+    /// Record a hash calculated by the current call, which might be the slot of a mapping value.
+    fn record_preimage(&mut self, hash: U256, key: U256, base: U256) {
+        if let Some(frame) = self.call_stack.last_mut() {
+            frame.mappings.preimages.push(Preimage { hash, key, base });
+        }
+    }
+
     /// Get line info from the current program counter.
     fn line_info(
         &self,
@@ -308,12 +600,17 @@ impl Shared {
             None => return None,
         };
 
+        let docs = function.map(|f| f.docs.clone()).unwrap_or_default();
         let function = function.map(|f| f.name.to_string());
-        let file = File::open(path).expect("bad file");
+        let span = (m.start as usize, (m.start + m.length) as usize);
 
-        let (lines, line) =
-            utils::find_line(file, (m.start as usize, (m.start + m.length) as usize))
-                .expect("line from file");
+        let (lines, line) = match linker.source_cache().find_line(path, span) {
+            Ok(found) => found,
+            Err(e) => {
+                warn!("failed to find line: {}", e);
+                return None;
+            }
+        };
 
         let object = source.map(|s| s.object.clone());
 
@@ -321,6 +618,7 @@ impl Shared {
             path: path.to_owned(),
             object,
             function,
+            docs,
             line,
             lines,
         })
@@ -370,8 +668,28 @@ impl<'a> trace::Tracer for Tracer<'a> {
     ) {
         let mut shared = self.shared.lock().expect("lock poisoned");
 
+        if params.code_address == console::console_address() {
+            if let Some(line) = params.data.as_ref().and_then(|d| console::decode(d)) {
+                debug!("console.log: {}", line);
+                shared.console.push(line);
+            }
+        }
+
+        // cheatcodes issued by the call are reverted with it.
+        let first_cheatcode = shared.cheatcodes.len();
+
+        if params.code_address == cheatcodes::cheatcode_address() {
+            let cheat = cheatcodes::decode(params.data.as_ref().map(|d| &d[..]).unwrap_or(&[]));
+            debug!("cheatcode: {:?}", cheat);
+            shared.cheatcodes.push(cheat);
+        }
+
         let mut frame = CallFrame::from(self.linker.find_runtime_info(params.code_address));
         frame.call_data = params.data.clone().unwrap_or_else(Bytes::default);
+        frame.address = params.address;
+        frame.first_write = shared.storage_writes.len();
+        frame.first_cheatcode = first_cheatcode;
+        frame.trace_index = shared.begin_call(CallKind::Call, params);
 
         debug!(
             ">> {:03}: Prepare Trace Call: {:?} (address: {:?}, call_type: {:?})",
@@ -387,13 +705,23 @@ impl<'a> trace::Tracer for Tracer<'a> {
         let ast = source
             .as_ref()
             .and_then(|s| self.linker.find_ast_by_object(&s.object));
+        let storage_layout = source
+            .as_ref()
+            .and_then(|s| self.linker.find_storage_layout_by_object(&s.object));
 
         let info = CallFrame {
             frame_info: FrameInfo::None,
             source,
             ast,
             call_data: params.data.clone().unwrap_or_else(Bytes::default),
+            address: params.address,
+            storage_layout,
             variables: HashMap::new(),
+            storage: HashMap::new(),
+            mappings: Mappings::default(),
+            first_write: shared.storage_writes.len(),
+            first_cheatcode: shared.cheatcodes.len(),
+            trace_index: shared.begin_call(CallKind::Create, params),
             function: None,
         };
 
@@ -405,9 +733,14 @@ impl<'a> trace::Tracer for Tracer<'a> {
         shared.call_stack.push(info);
     }
 
-    fn done_trace_call(&mut self, _gas_used: U256, _output: &[u8]) {
+    fn done_trace_call(&mut self, _gas_used: U256, output: &[u8]) {
         let mut shared = self.shared.lock().expect("lock poisoned");
         let info = shared.call_stack.pop();
+
+        if let Some(ref info) = info {
+            shared.end_call(info, output, true);
+        }
+
         let source = info.as_ref().and_then(|s| s.source.as_ref());
 
         debug!("!! {:03}: Trace Call: {:?}", self.depth, source);
@@ -417,6 +750,11 @@ impl<'a> trace::Tracer for Tracer<'a> {
     fn done_trace_create(&mut self, _gas_used: U256, _code: &[u8], address: H160) {
         let mut shared = self.shared.lock().expect("lock poisoned");
         let info = shared.call_stack.pop();
+
+        if let Some(ref info) = info {
+            shared.end_call(info, &[], true);
+        }
+
         let source = info.as_ref().and_then(|s| s.source.as_ref());
 
         debug!(
@@ -430,13 +768,22 @@ impl<'a> trace::Tracer for Tracer<'a> {
     fn done_trace_failed(&mut self, error: &parity_vm::Error) {
         let mut shared = self.shared.lock().expect("lock poisoned");
 
+        let frame = shared.call_stack.pop().expect("call frame missing");
+        shared.end_call(&frame, &[], false);
+
         let CallFrame {
             source,
             variables,
             function,
             frame_info,
+            first_write,
+            first_cheatcode,
             ..
-        } = shared.call_stack.pop().expect("call frame missing");
+        } = frame;
+
+        // writes and cheatcodes performed by the failed call and its children are reverted.
+        shared.storage_writes.truncate(first_write);
+        shared.cheatcodes.truncate(first_cheatcode);
 
         debug!(
             "!! {:03}: Trace Failed Call: {:?} ({})",
@@ -444,6 +791,8 @@ impl<'a> trace::Tracer for Tracer<'a> {
         );
 
         let variables: BTreeMap<_, _> = variables.into_iter().collect();
+        let console = shared.console.clone();
+        let value_format = shared.value_format;
 
         match frame_info {
             FrameInfo::Some(pc) => {
@@ -454,12 +803,16 @@ impl<'a> trace::Tracer for Tracer<'a> {
                     kind: ErrorKind::Error(error.clone()),
                     line_info,
                     variables,
+                    console,
+                    value_format,
                 })
             }
             FrameInfo::None => self.errors.push(ErrorInfo {
                 kind: ErrorKind::Error(error.clone()),
                 line_info: None,
                 variables,
+                console,
+                value_format,
             }),
         }
     }
@@ -509,6 +862,8 @@ pub struct VmTracer<'a> {
     shared: &'a Mutex<Shared>,
     /// Statements which have been visited.
     visited_statements: HashSet<ast::Src>,
+    /// Gas accounting for the instruction being executed, for every depth of calls.
+    gas: Vec<PendingGas>,
 }
 
 impl<'a> VmTracer<'a> {
@@ -527,6 +882,7 @@ impl<'a> VmTracer<'a> {
             last: None,
             shared,
             visited_statements: HashSet::new(),
+            gas: Vec::new(),
         }
     }
 }
@@ -534,15 +890,67 @@ impl<'a> VmTracer<'a> {
 impl<'a> trace::VMTracer for VmTracer<'a> {
     type Output = VmTracerOutput;
 
-    fn trace_next_instruction(&mut self, pc: usize, instruction: u8, _current_gas: U256) -> bool {
+    fn trace_next_instruction(&mut self, pc: usize, instruction: u8, current_gas: U256) -> bool {
         self.pc = pc;
         self.instruction = parity_evm::Instruction::from_u8(instruction);
+
+        if self.gas.is_empty() {
+            self.gas.push(PendingGas::default());
+        }
+
+        if let Some(gas) = self.gas.last_mut() {
+            gas.pc = pc;
+            gas.before = current_gas;
+            gas.children = U256::zero();
+        }
+
         true
     }
 
-    fn trace_executed(&mut self, _gas_used: U256, stack_push: &[U256], mem: &[u8]) {
+    fn trace_executed(&mut self, gas_left: U256, stack_push: &[U256], mem: &[u8]) {
         let mut shared = self.shared.lock().expect("poisoned lock");
 
+        if let Some(gas) = self.gas.last_mut() {
+            // gas used by the instruction, including any calls it performs.
+            let used = saturating_sub(gas.before, gas_left);
+            gas.total = gas.total + used;
+            shared.record_gas(gas.pc, saturating_sub(used, gas.children));
+            gas.children = U256::zero();
+        }
+
+        {
+            let mut it = self.stack.iter().rev();
+
+            match self.instruction {
+                Some(parity_evm::Instruction::SLOAD) => {
+                    if let (Some(slot), Some(value)) = (it.next(), stack_push.first()) {
+                        shared.record_storage(*slot, *value);
+                    }
+                }
+                Some(parity_evm::Instruction::SSTORE) => {
+                    if let (Some(slot), Some(value)) = (it.next(), it.next()) {
+                        shared.record_storage_write(*slot, *value);
+                        shared.record_storage(*slot, *value);
+                    }
+                }
+                // hashes of two words are how the slots of mapping values are calculated.
+                Some(parity_evm::Instruction::SHA3) => {
+                    let offset = it.next().map(|v| v.low_u64() as usize);
+                    let size = it.next().map(|v| v.low_u64() as usize);
+
+                    if let (Some(offset), Some(64), Some(hash)) = (offset, size, stack_push.first())
+                    {
+                        if offset + 64 <= mem.len() {
+                            let key = U256::from(&mem[offset..offset + 32]);
+                            let base = U256::from(&mem[offset + 32..offset + 64]);
+                            shared.record_preimage(*hash, key, base);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
         if let Err(e) = shared.decode_instruction(
             self.pc,
             &self.stack,
@@ -574,8 +982,17 @@ impl<'a> trace::VMTracer for VmTracer<'a> {
         // trace!("{:<24}= {:?}", "", self.stack);
     }
 
-    fn prepare_subtrace(&mut self, _code: &[u8]) {}
-    fn done_subtrace(&mut self) {}
+    fn prepare_subtrace(&mut self, _code: &[u8]) {
+        self.gas.push(PendingGas::default());
+    }
+
+    fn done_subtrace(&mut self) {
+        if let Some(child) = self.gas.pop() {
+            if let Some(parent) = self.gas.last_mut() {
+                parent.children = parent.children + child.total;
+            }
+        }
+    }
 
     fn drain(self) -> Option<Self::Output> {
         let visited_statements = self.visited_statements;
@@ -583,26 +1000,333 @@ impl<'a> trace::VMTracer for VmTracer<'a> {
     }
 }
 
+/// Gas accounting for the instruction being executed at one depth of calls.
+#[derive(Debug, Default)]
+struct PendingGas {
+    /// Program counter of the instruction.
+    pc: usize,
+    /// Gas left before the instruction was executed.
+    before: U256,
+    /// Gas used by calls performed by the instruction.
+    children: U256,
+    /// Total gas used by all instructions at this depth.
+    total: U256,
+}
+
+/// Subtract without underflowing.
+fn saturating_sub(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        U256::zero()
+    }
+}
+
+/// The kind of a call in a trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    Create,
+}
+
+/// A call performed during a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallTrace {
+    /// Depth of the call, where the transaction itself is at depth 0.
+    pub depth: usize,
+    pub kind: CallKind,
+    /// Address of the contract called or created.
+    pub address: Address,
+    /// The contract and function called, like `Token::transfer`, if the source is known.
+    pub label: Option<String>,
+    pub input: Bytes,
+    pub value: U256,
+    /// Output of the call, which is empty for failed calls and contract creations.
+    pub output: Bytes,
+    /// If the call succeeded.
+    pub success: bool,
+}
+
+impl CallTrace {
+    /// Test if two calls are equivalent.
+    ///
+    /// Calls to known contracts are compared by contract and function instead of address, so
+    /// that the same call to different deployments of a contract is considered the same.
+    fn is_equivalent(&self, other: &CallTrace) -> bool {
+        let target = match (self.label.as_ref(), other.label.as_ref()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.address == other.address,
+        };
+
+        target
+            && self.depth == other.depth
+            && self.kind == other.kind
+            && self.input == other.input
+            && self.value == other.value
+            && self.output == other.output
+            && self.success == other.success
+    }
+}
+
+impl fmt::Display for CallTrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            CallKind::Call => write!(fmt, "call ")?,
+            CallKind::Create => write!(fmt, "create ")?,
+        }
+
+        match self.label {
+            Some(ref label) => write!(fmt, "{} ({:?})", label, self.address)?,
+            None => write!(fmt, "{:?}", self.address)?,
+        }
+
+        write!(fmt, "(0x")?;
+        write_hex(fmt, &self.input)?;
+        write!(fmt, ")")?;
+
+        if !self.value.is_zero() {
+            write!(fmt, " value {}", self.value)?;
+        }
+
+        if !self.success {
+            return write!(fmt, " -> failed");
+        }
+
+        write!(fmt, " -> 0x")?;
+        write_hex(fmt, &self.output)
+    }
+}
+
+/// The first point where two transactions diverge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The calls at the given index differ, where `None` means that the transaction performed
+    /// fewer calls.
+    Call {
+        index: usize,
+        a: Option<CallTrace>,
+        b: Option<CallTrace>,
+    },
+    /// The storage writes at the given index differ, where `None` means that the transaction
+    /// performed fewer writes.
+    StorageWrite {
+        index: usize,
+        a: Option<storage::StorageWrite>,
+        b: Option<storage::StorageWrite>,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Divergence::Call {
+                index,
+                ref a,
+                ref b,
+            } => {
+                writeln!(fmt, "Call #{} differs:", index)?;
+
+                for (name, call) in &[("a", a), ("b", b)] {
+                    match **call {
+                        Some(ref call) => writeln!(
+                            fmt,
+                            "  {}: {:indent$}{}",
+                            name,
+                            "",
+                            call,
+                            indent = call.depth * 2
+                        )?,
+                        None => writeln!(fmt, "  {}: <no call>", name)?,
+                    }
+                }
+            }
+            Divergence::StorageWrite {
+                index,
+                ref a,
+                ref b,
+            } => {
+                writeln!(fmt, "Storage write #{} differs:", index)?;
+
+                for (name, write) in &[("a", a), ("b", b)] {
+                    match **write {
+                        Some(ref write) => {
+                            write!(fmt, "  {}: ", name)?;
+
+                            if write.variables.is_empty() {
+                                write!(fmt, "slot {}", write.slot)?;
+                            } else {
+                                write!(fmt, "{}", write.variables.join(", "))?;
+                            }
+
+                            writeln!(fmt, " = {} ({:?})", write.value, write.address)?;
+                        }
+                        None => writeln!(fmt, "  {}: <no write>", name)?,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare the calls and storage writes of two transactions, and find the first point where they
+/// diverge.
+///
+/// This is useful to compare the behavior of a contract before and after it has been changed, or
+/// for two different inputs. Calls are compared first, then storage writes.
+pub fn diff<A, B>(a: &evm::Call<A>, b: &evm::Call<B>) -> Option<Divergence> {
+    diff_traces(
+        (a.calls(), a.storage_writes()),
+        (b.calls(), b.storage_writes()),
+    )
+}
+
+/// Compare the calls and storage writes of two transactions.
+fn diff_traces(
+    a: (&[CallTrace], &[storage::StorageWrite]),
+    b: (&[CallTrace], &[storage::StorageWrite]),
+) -> Option<Divergence> {
+    let ((a_calls, a_writes), (b_calls, b_writes)) = (a, b);
+
+    for index in 0..a_calls.len().max(b_calls.len()) {
+        let (a, b) = (a_calls.get(index), b_calls.get(index));
+
+        let same = match (a, b) {
+            (Some(a), Some(b)) => a.is_equivalent(b),
+            _ => false,
+        };
+
+        if !same {
+            return Some(Divergence::Call {
+                index,
+                a: a.cloned(),
+                b: b.cloned(),
+            });
+        }
+    }
+
+    for index in 0..a_writes.len().max(b_writes.len()) {
+        let (a, b) = (a_writes.get(index), b_writes.get(index));
+
+        // writes to known variables are compared by name instead of by address.
+        let same = match (a, b) {
+            (Some(a), Some(b)) => {
+                a.slot == b.slot
+                    && a.value == b.value
+                    && a.variables == b.variables
+                    && (!a.variables.is_empty() || a.address == b.address)
+            }
+            _ => false,
+        };
+
+        if !same {
+            return Some(Divergence::StorageWrite {
+                index,
+                a: a.cloned(),
+                b: b.cloned(),
+            });
+        }
+    }
+
+    None
+}
+
+/// Convert the calls, storage writes and logs of a transaction into JSON documents, named by the
+/// file they are written to as artifacts of a failing test.
+#[cfg(feature = "test-runner")]
+pub(crate) fn artifacts(
+    calls: &[CallTrace],
+    storage_writes: &[storage::StorageWrite],
+    logs: &[LogEntry],
+) -> Vec<(&'static str, serde_json::Value)> {
+    let calls = calls
+        .iter()
+        .map(|call| {
+            json!({
+                "depth": call.depth,
+                "kind": match call.kind {
+                    CallKind::Call => "call",
+                    CallKind::Create => "create",
+                },
+                "address": format!("{:?}", call.address),
+                "label": call.label,
+                "input": hex(&call.input),
+                "value": format!("0x{:x}", call.value),
+                "output": hex(&call.output),
+                "success": call.success,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let storage_writes = storage_writes
+        .iter()
+        .map(|write| {
+            json!({
+                "address": format!("{:?}", write.address),
+                "slot": format!("0x{:x}", write.slot),
+                "value": format!("0x{:x}", write.value),
+                "variables": write.variables,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let logs = logs
+        .iter()
+        .map(|log| {
+            json!({
+                "address": format!("{:?}", log.address),
+                "topics": log.topics.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>(),
+                "data": hex(&log.data),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    vec![
+        ("trace.json", json!(calls)),
+        ("state_diff.json", json!(storage_writes)),
+        ("logs.json", json!(logs)),
+    ]
+}
+
+/// Format the given bytes as hex, prefixed with `0x`.
+#[cfg(feature = "test-runner")]
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::from("0x");
+
+    for b in bytes {
+        out.push_str(&format!("{:02x}", b));
+    }
+
+    out
+}
+
+/// Write the given bytes as hex.
+fn write_hex(fmt: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    for b in bytes {
+        write!(fmt, "{:02x}", b)?;
+    }
+
+    Ok(())
+}
+
 /// Find the corresponding mapping for a source and program counter.
 fn mapping<'a>(
     source: Option<&'a Arc<linker::Source>>,
     pc: usize,
 ) -> Option<&'a source_map::Mapping> {
-    let linker::Source {
-        ref source_map,
-        ref offsets,
-        ..
-    } = match source {
-        Some(source) => source.as_ref(),
-        None => return None,
-    };
-
-    let offset = match offsets.get(&pc) {
-        Some(offset) => *offset,
-        None => return None,
-    };
+    match source {
+        Some(source) => source.mapping(pc),
+        None => None,
+    }
+}
 
-    source_map.find_mapping(offset)
+/// A hash of a key and a slot, which is how the slots of mapping values are calculated.
+#[derive(Debug)]
+struct Preimage {
+    hash: U256,
+    key: U256,
+    base: U256,
 }
 
 /// Information about the current call.
@@ -616,12 +1340,120 @@ pub struct CallFrame {
     pub ast: Option<Arc<ast::Registry>>,
     /// Input data for the current call frame.
     pub call_data: Bytes,
+    /// Address whose storage is used by the call.
+    pub address: Address,
+    /// Storage layout of the code being executed.
+    pub storage_layout: Option<Arc<storage::StorageLayout>>,
     // named variables and their stack offsets.
     variables: HashMap<ast::Expr, ast::Value>,
+    // storage slots observed during the call, used to decode values in storage.
+    storage: HashMap<U256, U256>,
+    // index accesses into mappings.
+    mappings: Mappings,
+    // index of the first storage write performed by the call.
+    first_write: usize,
+    // index of the first cheatcode issued by the call.
+    first_cheatcode: usize,
+    // index of the call in the calls performed by the transaction.
+    trace_index: usize,
     // Function call stack.
     function: Option<Arc<ast::Function>>,
 }
 
+/// Index accesses into mappings, and the information needed to resolve them.
+#[derive(Debug, Default)]
+struct Mappings {
+    // hashes of a key and a slot calculated during the call, in the order they were calculated.
+    preimages: Vec<Preimage>,
+    // index accesses which have been resolved, and the slots they refer to.
+    resolved: HashMap<ast::Expr, (ast::Expr, U256)>,
+}
+
+impl Mappings {
+    /// Follow the hashes calculated during the call back from the given slot, to the slot of the
+    /// mapping it belongs to and the keys used to index it, outermost first.
+    fn keys(&self, slot: U256) -> (U256, Vec<U256>) {
+        let mut slot = slot;
+        let mut keys = Vec::new();
+
+        while let Some(preimage) = self.preimages.iter().rev().find(|p| p.hash == slot) {
+            keys.push(preimage.key);
+            slot = preimage.base;
+        }
+
+        keys.reverse();
+        (slot, keys)
+    }
+
+    /// Resolve an index access into a mapping to the key that was used and the storage slot it
+    /// refers to.
+    ///
+    /// The slot is found by matching the value of the index against the hashes calculated during
+    /// the call, and the value is read from the slot if it has been observed.
+    fn resolve(
+        &mut self,
+        variables: &mut HashMap<ast::Expr, ast::Value>,
+        storage: &HashMap<U256, U256>,
+        var: &ast::Expr,
+        ty: &ast::Type,
+    ) -> Option<(ast::Expr, Option<ast::Value>)> {
+        let (base, index) = match *var {
+            ast::Expr::IndexAccess { ref key, ref value } => (key.as_ref(), value.as_ref()),
+            _ => return None,
+        };
+
+        let index = variables.get(index)?.clone();
+        let key = index.to_word()?;
+
+        let (base_expr, base_slot) = match self.resolved.get(base) {
+            Some(&(ref expr, slot)) => (expr.clone(), Some(slot)),
+            None => (base.clone(), None),
+        };
+
+        let slot = self
+            .preimages
+            .iter()
+            .rev()
+            .find(|p| p.key == key && base_slot.map(|base| base == p.base).unwrap_or(true))?
+            .hash;
+
+        let resolved = ast::Expr::IndexAccess {
+            key: Box::new(base_expr.clone()),
+            value: Box::new(ast::Expr::Resolved {
+                value: index.to_string(),
+            }),
+        };
+
+        let value = storage.get(&slot).and_then(|word| ty.from_word(*word));
+
+        // the mapping itself is redundant once one of its values is known.
+        for expr in &[base, &base_expr] {
+            if let Some(&ast::Value::Mapping(..)) = variables.get(*expr) {
+                variables.remove(*expr);
+            }
+        }
+
+        self.resolved.insert(var.clone(), (resolved.clone(), slot));
+        Some((resolved, value))
+    }
+}
+
+impl CallFrame {
+    /// Label of the frame in gas profiles, which is the contract and function being called.
+    fn label(&self) -> String {
+        let object = self
+            .source
+            .as_ref()
+            .map(|s| s.object.item.as_str())
+            .unwrap_or("?");
+
+        match self.function {
+            Some(ref function) => format!("{}::{}", object, function.name),
+            None => object.to_string(),
+        }
+    }
+}
+
 impl From<linker::AddressInfo> for CallFrame {
     fn from(info: linker::AddressInfo) -> Self {
         Self {
@@ -629,8 +1461,162 @@ impl From<linker::AddressInfo> for CallFrame {
             source: info.source,
             ast: info.ast,
             call_data: Bytes::default(),
+            address: Address::default(),
+            storage_layout: info.storage_layout,
             variables: HashMap::new(),
+            storage: HashMap::new(),
+            mappings: Mappings::default(),
+            first_write: 0,
+            first_cheatcode: 0,
+            trace_index: 0,
             function: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_traces, CallKind, CallTrace, Divergence, Mappings, Preimage};
+    use ast::{Expr, Type, Value};
+    use ethereum_types::{Address, H256, U256};
+    use std::collections::HashMap;
+    use storage::StorageWrite;
+
+    #[test]
+    fn test_resolve_mapping() {
+        let ident = |identifier: &str| Expr::Identifier {
+            identifier: identifier.to_string(),
+        };
+
+        let owner = Address::from(0x42);
+        let slot = U256::from(0x1234);
+
+        let balances = ident("balances");
+        let sender = Expr::MemberAccess {
+            key: Box::new(ident("msg")),
+            value: "sender".to_string(),
+        };
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            balances.clone(),
+            Value::Mapping(Type::Address, Type::Uint256),
+        );
+        variables.insert(sender.clone(), Value::Address(owner));
+
+        let mut storage = HashMap::new();
+        storage.insert(slot, U256::from(100));
+
+        let mut mappings = Mappings::default();
+        mappings.preimages.push(Preimage {
+            hash: slot,
+            key: U256::from(&H256::from(owner)[..]),
+            base: U256::from(3),
+        });
+
+        let var = Expr::IndexAccess {
+            key: Box::new(balances.clone()),
+            value: Box::new(sender.clone()),
+        };
+
+        let (resolved, value) = mappings
+            .resolve(&mut variables, &storage, &var, &Type::Uint256)
+            .expect("mapping to resolve");
+
+        assert_eq!(
+            format!("balances[address({:?})]", owner),
+            resolved.to_string()
+        );
+        assert_eq!(Some(Value::Uint256(100.into())), value);
+        assert!(!variables.contains_key(&balances));
+
+        let other = Expr::IndexAccess {
+            key: Box::new(balances),
+            value: Box::new(ident("unknown")),
+        };
+
+        assert!(mappings
+            .resolve(&mut variables, &storage, &other, &Type::Uint256)
+            .is_none());
+    }
+
+    #[test]
+    fn test_mapping_keys() {
+        let mut mappings = Mappings::default();
+
+        // allowances[1][2], stored in slot 4.
+        mappings.preimages.push(Preimage {
+            hash: U256::from(100),
+            key: U256::from(1),
+            base: U256::from(4),
+        });
+
+        mappings.preimages.push(Preimage {
+            hash: U256::from(200),
+            key: U256::from(2),
+            base: U256::from(100),
+        });
+
+        assert_eq!(
+            (U256::from(4), vec![U256::from(1), U256::from(2)]),
+            mappings.keys(U256::from(200))
+        );
+        assert_eq!((U256::from(4), vec![]), mappings.keys(U256::from(4)));
+    }
+
+    #[test]
+    fn test_diff_traces() {
+        let call = |depth, label: &str, address, output: &[u8]| CallTrace {
+            depth,
+            kind: CallKind::Call,
+            address: Address::from(address),
+            label: Some(label.to_string()),
+            input: vec![1, 2, 3, 4],
+            value: U256::zero(),
+            output: output.to_vec(),
+            success: true,
+        };
+
+        let write = |variable: &str, value: u64| StorageWrite {
+            address: Address::from(0x10),
+            slot: U256::from(1),
+            value: U256::from(value),
+            variables: vec![variable.to_string()],
+        };
+
+        // the same calls to different deployments are equivalent.
+        let a = vec![call(0, "Token::transfer", 0x10, &[1])];
+        let b = vec![call(0, "Token::transfer", 0x20, &[1])];
+
+        assert_eq!(None, diff_traces((&a, &[]), (&b, &[])));
+
+        let b = vec![
+            call(0, "Token::transfer", 0x10, &[1]),
+            call(1, "Hook::notify", 0x30, &[]),
+        ];
+
+        assert_eq!(
+            Some(Divergence::Call {
+                index: 1,
+                a: None,
+                b: Some(b[1].clone()),
+            }),
+            diff_traces((&a, &[]), (&b, &[]))
+        );
+
+        let a_writes = vec![write("totalSupply", 100)];
+        let b_writes = vec![write("totalSupply", 200)];
+
+        let divergence = diff_traces((&a, &a_writes), (&a, &b_writes)).expect("divergence");
+
+        let address = Address::from(0x10);
+
+        assert_eq!(
+            format!(
+                "Storage write #0 differs:\n  a: totalSupply = 100 ({:?})\n  b: totalSupply = 200 ({:?})\n",
+                address, address
+            ),
+            divergence.to_string()
+        );
+    }
+}
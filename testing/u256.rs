@@ -0,0 +1,120 @@
+//! Checked arithmetic helpers for `U256`.
+//!
+//! These are useful when computing expected values for fee or interest math in assertions, where
+//! a silent overflow would make the assertion meaningless.
+
+use ethereum_types::{U256, U512};
+
+/// Calculate the given percentage of a value, rounding down.
+///
+/// Returns `None` if the calculation overflows.
+pub fn checked_pct(value: U256, percent: impl Into<U256>) -> Option<U256> {
+    mul_div(value, percent.into(), 100.into())
+}
+
+/// Calculate the given number of basis points (1/100th of a percent) of a value, rounding down.
+///
+/// Returns `None` if the calculation overflows.
+pub fn checked_bps(value: U256, bps: impl Into<U256>) -> Option<U256> {
+    mul_div(value, bps.into(), 10_000.into())
+}
+
+/// Calculate `a * b / denominator` with full precision, rounding down.
+///
+/// The intermediate product is permitted to exceed 256 bits. Returns `None` if the denominator is
+/// zero, or if the result doesn't fit in 256 bits.
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+
+    let result = U512::from(a) * U512::from(b) / U512::from(denominator);
+    narrow(result)
+}
+
+/// Calculate `a * b / denominator` with full precision, rounding up.
+///
+/// Returns `None` if the denominator is zero, or if the result doesn't fit in 256 bits.
+pub fn mul_div_up(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+
+    let product = U512::from(a) * U512::from(b);
+    let denominator = U512::from(denominator);
+    let mut result = product / denominator;
+
+    if !(product % denominator).is_zero() {
+        result = result + U512::one();
+    }
+
+    narrow(result)
+}
+
+/// Add two values, saturating at the maximum value.
+pub fn saturating_add(a: U256, b: U256) -> U256 {
+    a.checked_add(b).unwrap_or_else(U256::max_value)
+}
+
+/// Subtract two values, saturating at zero.
+pub fn saturating_sub(a: U256, b: U256) -> U256 {
+    a.checked_sub(b).unwrap_or_else(U256::zero)
+}
+
+/// Multiply two values, saturating at the maximum value.
+pub fn saturating_mul(a: U256, b: U256) -> U256 {
+    a.checked_mul(b).unwrap_or_else(U256::max_value)
+}
+
+/// Narrow a 512-bit value into 256 bits, if it fits.
+fn narrow(value: U512) -> Option<U256> {
+    let mut bytes = [0u8; 64];
+    value.to_big_endian(&mut bytes);
+
+    if bytes[..32].iter().any(|b| *b != 0) {
+        return None;
+    }
+
+    Some(U256::from(&bytes[32..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pct() {
+        assert_eq!(Some(U256::from(25)), checked_pct(100.into(), 25));
+        assert_eq!(Some(U256::from(3)), checked_bps(10_000.into(), 3));
+        assert_eq!(Some(U256::max_value()), checked_pct(U256::max_value(), 100));
+        assert_eq!(None, checked_pct(U256::max_value(), 101));
+    }
+
+    #[test]
+    fn test_mul_div() {
+        let max = U256::max_value();
+
+        assert_eq!(Some(max), mul_div(max, max, max));
+        assert_eq!(Some(U256::from(3)), mul_div(10.into(), 1.into(), 3.into()));
+        assert_eq!(
+            Some(U256::from(4)),
+            mul_div_up(10.into(), 1.into(), 3.into())
+        );
+        assert_eq!(
+            Some(U256::from(5)),
+            mul_div_up(10.into(), 1.into(), 2.into())
+        );
+        assert_eq!(None, mul_div(1.into(), 1.into(), 0.into()));
+        assert_eq!(None, mul_div(max, 2.into(), 1.into()));
+    }
+
+    #[test]
+    fn test_saturating() {
+        let max = U256::max_value();
+
+        assert_eq!(max, saturating_add(max, 1.into()));
+        assert_eq!(U256::zero(), saturating_sub(1.into(), 2.into()));
+        assert_eq!(max, saturating_mul(max, 2.into()));
+        assert_eq!(U256::from(6), saturating_mul(2.into(), 3.into()));
+    }
+}
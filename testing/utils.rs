@@ -1,60 +1,120 @@
 use failure::Error;
-use std::io::Read;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 const NL: u8 = '\n' as u8;
 
-/// Find the line for a given span.
-pub fn find_line(reader: impl Read, span: (usize, usize)) -> Result<(Vec<String>, usize), Error> {
-    let mut out_line = None;
-    let mut line = 0usize;
-    let mut current = 0usize;
-    let mut buf: Vec<u8> = Vec::new();
+/// A source file, with the offsets of its lines.
+#[derive(Debug)]
+pub struct SourceFile {
+    content: String,
+    /// Byte offset of the start of every line.
+    line_starts: Vec<usize>,
+}
 
-    let start = span.0;
-    let end = span.1;
+impl SourceFile {
+    /// Build a source file from its content.
+    pub fn new(content: String) -> Self {
+        let mut line_starts = vec![0];
 
-    let mut it = reader.bytes();
+        for (index, b) in content.bytes().enumerate() {
+            if b == NL {
+                line_starts.push(index + 1);
+            }
+        }
 
-    let mut lines = Vec::new();
+        Self {
+            content,
+            line_starts,
+        }
+    }
 
-    while let Some(b) = it.next() {
-        let b = b.map_err(|e| format_err!("failed to read byte: {}", e))?;
+    /// Access the content of the file.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
 
-        match b {
-            NL => {}
-            _ => {
-                buf.push(b);
-                continue;
-            }
-        }
+    /// Find the lines for a given span, and the index of the first line.
+    pub fn find_line(&self, span: (usize, usize)) -> (Vec<String>, usize) {
+        let (start, end) = span;
 
-        current += buf.len() + 1usize;
+        let first = self.line_of(start);
+        let last = self.line_of(end.saturating_sub(1)).max(first);
 
-        if current > start {
-            lines.push(
-                ::std::str::from_utf8(&buf)
-                    .map_err(|e| format_err!("bad utf-8 line: {}", e))?
-                    .to_string(),
-            );
+        let lines = (first..=last).map(|line| self.line(line).to_string()).collect();
+        (lines, first)
+    }
 
-            if out_line.is_none() {
-                out_line = Some(line);
-            }
+    /// Find the index of the line containing the given byte offset.
+    fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
         }
+    }
+
+    /// Access the line with the given index, without its line ending.
+    fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line].min(self.content.len());
+
+        let end = match self.line_starts.get(line + 1) {
+            Some(end) => end - 1,
+            None => self.content.len(),
+        };
 
-        if current >= end {
-            return Ok((lines, out_line.unwrap_or(0usize)));
+        &self.content[start..end]
+    }
+}
+
+/// An in-memory cache of source files, shared by everything which reports source locations.
+#[derive(Debug, Default)]
+pub struct SourceCache {
+    files: Mutex<HashMap<PathBuf, Arc<SourceFile>>>,
+}
+
+impl SourceCache {
+    /// Access the given file, reading it if it's not cached.
+    pub fn file(&self, path: &Path) -> Result<Arc<SourceFile>, Error> {
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|_| format_err!("source cache poisoned"))?;
+
+        if let Some(file) = files.get(path) {
+            return Ok(Arc::clone(file));
         }
 
-        line += 1;
-        buf.clear();
+        let content = fs::read_to_string(path)
+            .map_err(|e| format_err!("{}: failed to read: {}", path.display(), e))?;
+
+        let file = Arc::new(SourceFile::new(content));
+        files.insert(path.to_owned(), Arc::clone(&file));
+        Ok(file)
+    }
+
+    /// Find the lines for a given span in the given file, and the index of the first line.
+    pub fn find_line(&self, path: &Path, span: (usize, usize)) -> Result<(Vec<String>, usize), Error> {
+        Ok(self.file(path)?.find_line(span))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceFile;
 
-    lines.push(
-        ::std::str::from_utf8(&buf)
-            .map_err(|e| format_err!("bad utf-8 line: {}", e))?
-            .to_string(),
-    );
+    #[test]
+    fn test_find_line() {
+        let file = SourceFile::new("first\nsecond\nthird\n".to_string());
 
-    Ok((lines, out_line.unwrap_or(0usize)))
+        assert_eq!((vec!["first".to_string()], 0), file.find_line((0, 5)));
+        assert_eq!((vec!["second".to_string()], 1), file.find_line((6, 12)));
+        assert_eq!(
+            (vec!["second".to_string(), "third".to_string()], 1),
+            file.find_line((8, 15))
+        );
+        assert_eq!((vec!["third".to_string()], 2), file.find_line((13, 13)));
+        assert_eq!((vec!["".to_string()], 3), file.find_line((19, 19)));
+    }
 }
@@ -1,6 +1,7 @@
 // Parts borrowed from: https://github.com/paritytech/sol-rs/blob/master/solaris/src/wei.rs
 
 use ethereum_types::U256;
+use failure::Error;
 use std::fmt;
 
 macro_rules! default_impl {
@@ -16,13 +17,7 @@ macro_rules! default_impl {
 macro_rules! convert {
     ($name:ident, $exp:expr) => {
         pub fn $name(value: impl IntoU256 + fmt::Display) -> U256 {
-            let (value, n) = value.into_u256();
-
-            if $exp < n {
-                panic!("illegal literal value {}", value);
-            }
-
-            value * U256::from(10).pow(($exp - n).into())
+            from_units(value, $exp)
         }
     };
 }
@@ -34,6 +29,119 @@ convert!(from_gwei, 9usize);
 convert!(from_mwei, 6usize);
 convert!(from_kwei, 3usize);
 
+/// Convert an amount of a token with the given number of decimals into its base units.
+///
+/// For example, `from_units(1.5, 6)` is `1500000` base units of a token with 6 decimals.
+pub fn from_units(value: impl IntoU256 + fmt::Display, decimals: usize) -> U256 {
+    let (value, n) = value.into_u256();
+
+    if decimals < n {
+        panic!("illegal literal value {}", value);
+    }
+
+    value * U256::from(10).pow((decimals - n).into())
+}
+
+/// Format an amount of base units of a token with the given number of decimals, without trailing
+/// zeros in the fraction.
+///
+/// For example, `to_units(1500000.into(), 6)` is `"1.5"`.
+pub fn to_units(value: U256, decimals: usize) -> String {
+    let unit = U256::from(10).pow(decimals.into());
+    let (whole, fraction) = (value / unit, value % unit);
+
+    if fraction.is_zero() {
+        return whole.to_string();
+    }
+
+    let fraction = format!("{:0>width$}", fraction.to_string(), width = decimals);
+    format!("{}.{}", whole, fraction.trim_right_matches('0'))
+}
+
+/// Parse an amount with an optional unit, like `1.5 ether` or `250 gwei`, into wei.
+///
+/// Without a unit, the amount is in wei. Fractional amounts must be exactly representable in wei.
+pub fn parse(input: &str) -> Result<U256, Error> {
+    let mut parts = input.split_whitespace();
+
+    let amount = match parts.next() {
+        Some(amount) => amount,
+        None => bail!("empty amount"),
+    };
+
+    let decimals = match parts.next() {
+        None => 0,
+        Some(unit) => match unit_decimals(unit) {
+            Some(decimals) => decimals,
+            None => bail!("unknown unit `{}` in amount: {}", unit, input),
+        },
+    };
+
+    if let Some(rest) = parts.next() {
+        bail!("unexpected `{}` in amount: {}", rest, input);
+    }
+
+    parse_decimal(amount, decimals).map_err(|e| format_err!("bad amount `{}`: {}", input, e))
+}
+
+/// Get the number of decimals for the given unit.
+fn unit_decimals(unit: &str) -> Option<usize> {
+    let decimals = match unit.to_lowercase().as_str() {
+        "wei" => 0,
+        "kwei" => 3,
+        "mwei" => 6,
+        "gwei" => 9,
+        "szabo" => 12,
+        "finney" => 15,
+        "ether" | "eth" => 18,
+        _ => return None,
+    };
+
+    Some(decimals)
+}
+
+/// Parse a decimal number, scaling it up by the given number of decimals.
+fn parse_decimal(value: &str, decimals: usize) -> Result<U256, Error> {
+    let (whole, fraction) = match value.find('.') {
+        Some(index) => (&value[..index], &value[index + 1..]),
+        None => (value, ""),
+    };
+
+    if whole.is_empty() && fraction.is_empty() {
+        bail!("missing digits");
+    }
+
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        bail!("not a decimal number");
+    }
+
+    let fraction = fraction.trim_right_matches('0');
+
+    if fraction.len() > decimals {
+        bail!("more than {} decimals", decimals);
+    }
+
+    let digits = format!(
+        "{}{}{}",
+        whole,
+        fraction,
+        "0".repeat(decimals - fraction.len())
+    );
+
+    let digits = digits.trim_left_matches('0');
+
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+
+    U256::from_dec_str(digits).map_err(|_| format_err!("amount out of range"))
+}
+
+/// Format the given amount of wei in ether, without trailing zeros in the fraction.
+pub fn format_ether(value: U256) -> String {
+    to_units(value, 18)
+}
+
 /// Local conversion trait for converting to U256.
 pub trait IntoU256 {
     /// Convert into U256.
@@ -49,6 +157,7 @@ default_impl!(i16);
 default_impl!(i32);
 default_impl!(i64);
 default_impl!(usize);
+default_impl!(U256);
 
 impl IntoU256 for f32 {
     fn into_u256(self) -> (U256, usize) {
@@ -65,6 +174,30 @@ impl IntoU256 for f32 {
     }
 }
 
+impl IntoU256 for f64 {
+    fn into_u256(self) -> (U256, usize) {
+        // the shortest representation which round-trips is exact for literals like `0.05`.
+        let repr = self.to_string();
+
+        let (whole, fraction) = match repr.find('.') {
+            Some(index) => (&repr[..index], &repr[index + 1..]),
+            None => (repr.as_str(), ""),
+        };
+
+        let digits = format!("{}{}", whole, fraction);
+        let digits = digits.trim_left_matches('0');
+
+        if digits.is_empty() {
+            return (U256::zero(), 0);
+        }
+
+        match U256::from_dec_str(digits) {
+            Ok(value) => (value, fraction.len()),
+            Err(_) => panic!("illegal literal value {}", self),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,4 +222,48 @@ mod tests {
         assert_eq!(from_mwei(1004), from_gwei(1.004));
         assert_eq!(from_kwei(1004), from_mwei(1.004));
     }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(from_ether(1), parse("1 ether").expect("bad parse"));
+        assert_eq!(from_finney(1500), parse("1.5 ether").expect("bad parse"));
+        assert_eq!(from_gwei(250), parse("250 gwei").expect("bad parse"));
+        assert_eq!(from_szabo(50), parse(".05 finney").expect("bad parse"));
+        assert_eq!(U256::from(42), parse("42").expect("bad parse"));
+        assert_eq!(U256::from(42), parse("42 wei").expect("bad parse"));
+
+        assert!(parse("").is_err());
+        assert!(parse("1.5 wei").is_err());
+        assert!(parse("1 foo").is_err());
+        assert!(parse("-1 ether").is_err());
+    }
+
+    #[test]
+    fn test_macro() {
+        let balance = from_ether(1);
+
+        assert_eq!(from_finney(50), wei!(0.05 eth));
+        assert_eq!(from_ether(2), wei!(balance * 2));
+        assert_eq!(from_gwei(4), wei!(2 * 2 gwei));
+        assert_eq!(from_kwei(1), wei!(1 kwei));
+        assert_eq!(from_mwei(1), wei!(1 mwei));
+        assert_eq!(U256::from(42), wei!(42));
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(U256::from(1500000), from_units(1.5, 6));
+        assert_eq!(U256::from(100000000), from_units(1, 8));
+        assert_eq!("1.5", to_units(1500000.into(), 6));
+        assert_eq!("0.00000001", to_units(1.into(), 8));
+        assert_eq!("42", to_units(42.into(), 0));
+    }
+
+    #[test]
+    fn test_format_ether() {
+        assert_eq!("0", format_ether(0.into()));
+        assert_eq!("1", format_ether(from_ether(1)));
+        assert_eq!("1.004", format_ether(from_finney(1004)));
+        assert_eq!("0.000000000000000001", format_ether(1.into()));
+    }
 }